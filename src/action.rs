@@ -0,0 +1,897 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use indicatif::DecimalBytes;
+use rupes::FileId;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::locale::{self, Message};
+use crate::output;
+use crate::sidecar;
+use crate::syslog;
+
+/// What to do with the redundant copies in a group of duplicate files
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Delete,
+    Hardlink,
+    Symlink,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Delete => "delete",
+            Operation::Hardlink => "hardlink",
+            Operation::Symlink => "symlink",
+        }
+    }
+}
+
+/// A single action on a duplicate file, `source`, whose content is kept at `target`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannedAction {
+    /// The stable id of the group of duplicates the action is for
+    #[serde(default)]
+    pub group: String,
+    pub operation: Operation,
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub size: u64,
+    pub hash: String,
+    pub reason: String,
+    /// Sidecars of `source` taken along to `target` when it's deleted, see [`sidecar::carry`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<PathBuf>,
+}
+
+/// A reviewable list of actions written by `rupes plan` and executed by `rupes apply`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    /// See [`output::SCHEMA_VERSION`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub algorithm: String,
+    /// The directories scanned, `rupes apply` refuses actions outside them as a scan does
+    #[serde(default)]
+    pub directories: Vec<PathBuf>,
+    pub actions: Vec<PlannedAction>,
+}
+
+/// A single line of the action log
+#[derive(Serialize)]
+struct LogEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u32>,
+    timestamp: f64,
+    group: String,
+    operation: &'static str,
+    source: String,
+    target: String,
+    bytes_freed: u64,
+    result: String,
+}
+
+/// Append-only audit trail of every action taken, written as one JSON object per line
+pub struct ActionLog {
+    writer: BufWriter<File>,
+    /// See [`output::SCHEMA_VERSION`]
+    version: u32,
+}
+
+impl ActionLog {
+    /// Append to the log at `path`, writing entries in schema `version`
+    pub fn open(path: &Path, version: u32) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(ActionLog {
+            writer: BufWriter::new(file),
+            version,
+        })
+    }
+
+    fn record(
+        &mut self,
+        action: &PlannedAction,
+        bytes_freed: u64,
+        result: &Result<()>,
+    ) -> Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs_f64())
+            .unwrap_or_default();
+
+        let entry = LogEntry {
+            schema_version: output::versioned(self.version),
+            timestamp,
+            group: action.group.clone(),
+            operation: action.operation.name(),
+            source: action.source.display().to_string(),
+            target: action.target.display().to_string(),
+            bytes_freed,
+            result: match result {
+                Ok(()) => "ok".to_string(),
+                Err(error) if is_protected(error) => format!("skipped: {}", error),
+                Err(error) => format!("error: {}", error),
+            },
+        };
+
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+/// Temporary sibling path used to build a link before it replaces the duplicate
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".rupes-tmp");
+
+    path.with_file_name(file_name)
+}
+
+/// Create a link at a temporary path and rename it over `path`, so a failure never loses the duplicate
+fn replace_with_link(path: &Path, link: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let temporary = temporary_path(path);
+
+    link(&temporary)?;
+
+    if let Err(error) = fs::rename(&temporary, path) {
+        let _ = fs::remove_file(&temporary);
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
+/// Clear the immutable and append-only flags of `path`, set with chattr, if it has either
+#[cfg(target_os = "linux")]
+fn clear_immutable(path: &Path) -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+    const FS_APPEND_FL: libc::c_int = 0x20;
+
+    let file = File::open(path)?;
+    let mut flags: libc::c_int = 0;
+
+    // SAFETY: the file descriptor is open for as long as `file` lives, and both ioctls take a pointer to an int
+    unsafe {
+        if libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) != 0 {
+            return Err(Error::last_os_error());
+        }
+        if flags & (FS_IMMUTABLE_FL | FS_APPEND_FL) == 0 {
+            return Ok(());
+        }
+
+        flags &= !(FS_IMMUTABLE_FL | FS_APPEND_FL);
+        if libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Clear what keeps `path` from being deleted or replaced, the read-only attribute on Windows and the immutable flag on
+/// Linux
+fn clear_readonly(path: &Path) -> Result<()> {
+    #[cfg(windows)]
+    {
+        let mut permissions = fs::symlink_metadata(path)?.permissions();
+        if permissions.readonly() {
+            permissions.set_readonly(false);
+            fs::set_permissions(path, permissions)?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    clear_immutable(path)?;
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    let _ = path;
+
+    Ok(())
+}
+
+/// Whether `error` comes from a file that can't be changed, because it's immutable, read-only or not ours to change,
+/// which skips the file rather than counting as a failure
+fn is_protected(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+    )
+}
+
+fn perform(
+    operation: Operation,
+    kept: &Path,
+    duplicate: &Path,
+    force_readonly: bool,
+) -> Result<()> {
+    // Deleting or replacing it would take the kept copy with it
+    if same_file(kept, duplicate) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} is the same file as {}, the copy kept",
+                duplicate.display(),
+                kept.display()
+            ),
+        ));
+    }
+    if force_readonly {
+        // Anything still in the way shows up when performing the action
+        let _ = clear_readonly(duplicate);
+    }
+
+    match operation {
+        Operation::Delete => fs::remove_file(duplicate),
+        Operation::Hardlink => replace_with_link(duplicate, |link| fs::hard_link(kept, link)),
+        Operation::Symlink => {
+            let original = kept.canonicalize()?;
+            replace_with_link(duplicate, |link| symlink(&original, link))
+        }
+    }
+}
+
+/// Perform a planned action, then take the sidecars of a deleted duplicate along to the copy that's kept
+fn perform_planned(action: &PlannedAction, force_readonly: bool) -> Result<()> {
+    perform(
+        action.operation,
+        &action.target,
+        &action.source,
+        force_readonly,
+    )?;
+    if action.operation == Operation::Delete {
+        sidecar::carry(&action.sidecars, &action.source, &action.target);
+    }
+    Ok(())
+}
+
+/// Plan `operation` for every path but the first in `paths`, which is the copy that is kept for `kept_reason`, such as
+/// `newest copy` with --keep newest
+///
+/// Paths that are the kept copy itself, through a hardlink or a symlink followed, are left alone, acting on them would
+/// lose it.
+pub fn plan(
+    operation: Operation,
+    size: u64,
+    hash: &str,
+    paths: &[PathBuf],
+    kept_reason: &str,
+) -> Vec<PlannedAction> {
+    let Some((kept, duplicates)) = paths.split_first() else {
+        return Vec::new();
+    };
+
+    duplicates
+        .iter()
+        .filter(|duplicate| !same_file(kept, duplicate))
+        .map(|duplicate| PlannedAction {
+            group: output::group_id(hash),
+            operation,
+            source: duplicate.to_path_buf(),
+            target: kept.to_path_buf(),
+            size,
+            hash: hash.to_string(),
+            reason: format!("identical content, kept copy: {}", kept_reason),
+            sidecars: Vec::new(),
+        })
+        .collect()
+}
+
+/// Find the sidecars of every duplicate `planned` deletes, and drop the actions on those sidecars themselves, since
+/// they're taken along with their duplicate instead
+pub fn take_sidecars(planned: &mut Vec<Vec<PlannedAction>>) {
+    let mut carried = HashSet::new();
+    for action in planned
+        .iter_mut()
+        .flatten()
+        .filter(|action| action.operation == Operation::Delete)
+    {
+        action.sidecars = sidecar::sidecars(&action.source);
+        carried.extend(action.sidecars.iter().cloned());
+    }
+
+    for actions in planned.iter_mut() {
+        actions.retain(|action| {
+            !carried.contains(&action.source) && !carried.contains(&action.target)
+        });
+    }
+    planned.retain(|actions| !actions.is_empty());
+}
+
+/// Directories holding the operating system, which actions are refused in unless --allow-system is given
+#[cfg(not(windows))]
+const SYSTEM_DIRECTORIES: &[&str] = &[
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/proc",
+    "/sbin",
+    "/sys",
+    "/usr",
+    "/System",
+    "/Library",
+    "/private/etc",
+];
+#[cfg(windows)]
+const SYSTEM_DIRECTORIES: &[&str] = &[
+    r"C:\Windows",
+    r"C:\Program Files",
+    r"C:\Program Files (x86)",
+    r"C:\ProgramData",
+];
+
+/// `path` with every symlink resolved, or as it is if it can't be
+fn canonical(path: &Path) -> PathBuf {
+    let Ok(canonical) = fs::canonicalize(path) else {
+        return path.to_path_buf();
+    };
+
+    // Windows canonicalizes to verbatim \\?\C:\ paths, which don't match the usual spelling of paths
+    #[cfg(windows)]
+    if let Some(stripped) = canonical
+        .to_str()
+        .and_then(|canonical| canonical.strip_prefix(r"\\?\"))
+    {
+        return PathBuf::from(stripped);
+    }
+
+    canonical
+}
+
+/// Where `path` really is, with symlinks in the directories leading to it resolved but not the file itself, since
+/// that's what an action on it changes
+fn real_location(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if parent.as_os_str().is_empty() => {
+            canonical(Path::new(".")).join(name)
+        }
+        (Some(parent), Some(name)) => canonical(parent).join(name),
+        _ => canonical(path),
+    }
+}
+
+/// Whether `a` and `b` are the same file, a single path however it's spelled, or a hardlink or symlink to the other
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    let id = |path: &Path| fs::metadata(path).ok().as_ref().and_then(FileId::of);
+    match (id(a), id(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => canonical(a) == canonical(b),
+    }
+}
+
+/// Whether `directory` is the root of a filesystem, such as / or C:\, or in one of the [`SYSTEM_DIRECTORIES`]
+pub fn is_system_directory(directory: &Path) -> bool {
+    let directory = canonical(directory);
+
+    directory.parent().is_none()
+        || SYSTEM_DIRECTORIES
+            .iter()
+            .any(|system| directory.starts_with(system))
+}
+
+/// Whether `path` really is inside one of `roots`, rather than reached through a symlink to somewhere else
+pub fn is_within(path: &Path, roots: &[PathBuf]) -> bool {
+    let path = real_location(path);
+
+    roots.iter().any(|root| path.starts_with(canonical(root)))
+}
+
+/// Whether `path` is inside a directory holding one of the `markers` files, which opts everything in it out of actions
+pub fn is_opted_out(path: &Path, markers: &[OsString]) -> bool {
+    !markers.is_empty()
+        && path
+            .ancestors()
+            .skip(1)
+            .any(|directory| markers.iter().any(|marker| directory.join(marker).exists()))
+}
+
+/// What every action is checked against before it's taken, by a scan, `rupes plan` and `rupes apply` alike
+pub struct Guard<'a> {
+    /// The directories scanned, nothing outside them is acted on
+    pub roots: &'a [PathBuf],
+    /// Names of the files opting their directory out of actions, as given to --skip-marker
+    pub markers: &'a [OsString],
+    /// Whether acting in system directories is allowed, with --allow-system
+    pub allow_system: bool,
+}
+
+impl Guard<'_> {
+    /// Refuse to act at all when one of the roots is a system directory, unless that's allowed
+    pub fn check_roots(&self) -> Result<()> {
+        if self.allow_system {
+            return Ok(());
+        }
+        let Some(system) = self.roots.iter().find(|root| is_system_directory(root)) else {
+            return Ok(());
+        };
+
+        eprintln!(
+            "{}",
+            locale::text(Message::RefuseSystemRoot, &[("path", &system.display())])
+        );
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Can't act on system directories",
+        ))
+    }
+
+    /// Why `action` is refused, if it is
+    ///
+    /// Its sidecars are checked like its source, and have to be next to it, a hand-edited plan could list any file.
+    fn refusal(&self, action: &PlannedAction) -> Option<String> {
+        if let Some(reason) = self.refusal_of(&action.source) {
+            return Some(reason);
+        }
+
+        action.sidecars.iter().find_map(|sidecar| {
+            if sidecar.file_name().is_none() || sidecar.parent() != action.source.parent() {
+                Some(locale::text(
+                    Message::StraySidecar,
+                    &[("path", &sidecar.display())],
+                ))
+            } else {
+                self.refusal_of(sidecar).map(|reason| {
+                    locale::text(
+                        Message::RefusedSidecar,
+                        &[("path", &sidecar.display()), ("reason", &reason)],
+                    )
+                })
+            }
+        })
+    }
+
+    /// Why acting on the file at `path` is refused, if it is
+    fn refusal_of(&self, path: &Path) -> Option<String> {
+        if !self.allow_system
+            && real_location(path)
+                .parent()
+                .is_some_and(is_system_directory)
+        {
+            Some(locale::text(Message::InSystemDirectory, &[]))
+        } else if !is_within(path, self.roots) {
+            Some(locale::text(Message::OutsideScanned, &[]))
+        } else if is_opted_out(path, self.markers) {
+            Some(locale::text(Message::OptedOut, &[]))
+        } else if config::protects(path) {
+            Some(locale::text(
+                Message::ProtectedByConfig,
+                &[("config", &config::DIRECTORY_CONFIG)],
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The error `action` is recorded as failing with when it's refused, if it is
+    pub fn refused(&self, action: &PlannedAction) -> Option<Error> {
+        self.refusal(action)
+            .map(|reason| Error::new(ErrorKind::InvalidInput, reason))
+    }
+
+    /// Whether `action` may be taken, saying why not when it may not
+    pub fn allows(&self, action: &PlannedAction) -> bool {
+        let Some(reason) = self.refusal(action) else {
+            return true;
+        };
+
+        let refused = match action.operation {
+            Operation::Delete => Message::RefuseDelete,
+            Operation::Hardlink => Message::RefuseHardlink,
+            Operation::Symlink => Message::RefuseSymlink,
+        };
+        eprintln!(
+            "{}",
+            locale::text(
+                refused,
+                &[("path", &action.source.display()), ("reason", &reason)],
+            )
+        );
+        false
+    }
+}
+
+/// The directory `path` is summarised under, the one directly inside the root it was found in
+fn top_level(path: &Path, roots: &[PathBuf]) -> PathBuf {
+    for root in roots {
+        if let Ok(relative) = path.strip_prefix(root) {
+            let mut components = relative.components();
+            return match (components.next(), components.next()) {
+                (Some(first), Some(_)) => root.join(first),
+                _ => root.clone(),
+            };
+        }
+    }
+
+    path.parent().unwrap_or(path).to_path_buf()
+}
+
+/// Write how many files the planned actions change and how much space they free, in total and for each top-level
+/// directory of `roots`, the ones freeing the most first
+pub fn write_summary(
+    out: &mut impl Write,
+    planned: &[Vec<PlannedAction>],
+    roots: &[PathBuf],
+) -> Result<()> {
+    let actions: Vec<&PlannedAction> = planned.iter().flatten().collect();
+    let Some(first) = actions.first() else {
+        return Ok(());
+    };
+
+    let mut by_directory: HashMap<PathBuf, (usize, u64)> = HashMap::new();
+    for action in &actions {
+        let totals = by_directory
+            .entry(top_level(&action.source, roots))
+            .or_default();
+        totals.0 += 1;
+        totals.1 += action.size;
+    }
+    let mut by_directory: Vec<_> = by_directory.into_iter().collect();
+    by_directory.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(&b.0)));
+
+    let summary = match first.operation {
+        Operation::Delete => Message::DeleteSummary,
+        Operation::Hardlink => Message::HardlinkSummary,
+        Operation::Symlink => Message::SymlinkSummary,
+    };
+    let bytes: u64 = actions.iter().map(|action| action.size).sum();
+    writeln!(
+        out,
+        "{}",
+        locale::text(
+            summary,
+            &[("files", &actions.len()), ("bytes", &DecimalBytes(bytes))]
+        )
+    )?;
+
+    for (directory, (files, bytes)) in by_directory {
+        writeln!(
+            out,
+            "{}",
+            locale::text(
+                Message::SummaryDirectory,
+                &[
+                    ("bytes", &format!("{:>12}", DecimalBytes(bytes).to_string())),
+                    ("files", &format!("{:>6}", files)),
+                    ("directory", &directory.display()),
+                ],
+            )
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Whether a failed hardlink means no more links to the kept copy can be made, so every other duplicate of its group
+/// would fail the same way
+///
+/// That is the case on filesystems without hardlinks, such as FAT and exFAT, and once the kept copy has as many links as
+/// the filesystem allows.
+fn no_more_links(error: &Error) -> bool {
+    if error.kind() == ErrorKind::Unsupported {
+        return true;
+    }
+
+    #[cfg(unix)]
+    let codes = [libc::EPERM, libc::EOPNOTSUPP, libc::ENOTSUP, libc::EMLINK];
+    // ERROR_INVALID_FUNCTION, ERROR_NOT_SUPPORTED and ERROR_TOO_MANY_LINKS, as CreateHardLinkW reports them
+    #[cfg(windows)]
+    let codes = [1, 50, 1142];
+    #[cfg(not(any(unix, windows)))]
+    let codes: [i32; 0] = [];
+
+    error
+        .raw_os_error()
+        .is_some_and(|code| codes.contains(&code))
+}
+
+/// Write what each planned action would do, for --dry-run
+pub fn write_dry_run(out: &mut impl Write, planned: &[Vec<PlannedAction>]) -> Result<()> {
+    for action in planned.iter().flatten() {
+        let would = match action.operation {
+            Operation::Delete => Message::WouldDelete,
+            Operation::Hardlink => Message::WouldHardlink,
+            Operation::Symlink => Message::WouldSymlink,
+        };
+        writeln!(
+            out,
+            "{}",
+            locale::text(
+                would,
+                &[
+                    ("path", &action.source.display()),
+                    ("target", &action.target.display()),
+                    ("bytes", &DecimalBytes(action.size)),
+                ],
+            )
+        )?;
+    }
+    Ok(())
+}
+
+/// What the actions of a run did, counted by [`execute_group`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    pub succeeded: usize,
+    pub bytes_freed: u64,
+}
+
+impl Outcome {
+    /// Add the outcome of another group
+    pub fn add(&mut self, other: Outcome) {
+        self.succeeded += other.succeeded;
+        self.bytes_freed += other.bytes_freed;
+    }
+}
+
+/// Write how many of the `planned` actions of `operation` succeeded and the space they freed
+pub fn write_outcome(
+    out: &mut impl Write,
+    operation: Operation,
+    planned: usize,
+    outcome: &Outcome,
+) -> Result<()> {
+    let summary = match operation {
+        Operation::Delete => Message::DeletedSummary,
+        Operation::Hardlink => Message::HardlinkedSummary,
+        Operation::Symlink => Message::SymlinkedSummary,
+    };
+    writeln!(
+        out,
+        "{}",
+        locale::text(
+            summary,
+            &[
+                ("files", &outcome.succeeded),
+                ("planned", &planned),
+                ("bytes", &DecimalBytes(outcome.bytes_freed)),
+            ]
+        )
+    )
+}
+
+/// Perform the actions planned for one group, returning how many succeeded and the space they freed
+///
+/// Like [`execute`], failures don't stop the remaining actions, except for hardlinks the kept copy can't take any more
+/// of. The rest of that group is then skipped with a single message, and the run carries on with the next group.
+pub fn execute_group(
+    actions: &[PlannedAction],
+    force_readonly: bool,
+    mut log: Option<&mut ActionLog>,
+) -> Result<Outcome> {
+    let mut outcome = Outcome::default();
+
+    for (index, action) in actions.iter().enumerate() {
+        let result = perform_planned(action, force_readonly);
+        let skip_rest =
+            action.operation == Operation::Hardlink && result.as_ref().is_err_and(no_more_links);
+
+        if record(action, result, log.as_deref_mut())? {
+            outcome.add(Outcome {
+                succeeded: 1,
+                bytes_freed: action.size,
+            });
+        }
+
+        let skipped = actions.len() - index - 1;
+        if skip_rest && skipped > 0 {
+            eprintln!(
+                "{}",
+                locale::text(
+                    Message::SkippingHardlinks,
+                    &[("files", &skipped), ("target", &action.target.display())],
+                )
+            );
+            break;
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Perform a planned action, returning whether it succeeded
+///
+/// Failures are reported and logged rather than returned, so the remaining actions can still be processed. With
+/// `force_readonly`, read-only and immutable duplicates are made writable first.
+pub fn execute(
+    action: &PlannedAction,
+    force_readonly: bool,
+    log: Option<&mut ActionLog>,
+) -> Result<bool> {
+    let result = perform_planned(action, force_readonly);
+    record(action, result, log)
+}
+
+/// Report and log the outcome of an action, returning whether it succeeded
+pub fn record(
+    action: &PlannedAction,
+    result: Result<()>,
+    log: Option<&mut ActionLog>,
+) -> Result<bool> {
+    let bytes_freed = match &result {
+        Ok(()) => {
+            let done = match action.operation {
+                Operation::Delete => "Deleted",
+                Operation::Hardlink => "Hardlinked",
+                Operation::Symlink => "Symlinked",
+            };
+            syslog::info(&format!(
+                "{} {}, a copy of {}",
+                done,
+                action.source.display(),
+                action.target.display()
+            ));
+            action.size
+        }
+        Err(error) if is_protected(error) => {
+            let message = locale::text(
+                Message::SkippedProtected,
+                &[("path", &action.source.display()), ("error", error)],
+            );
+            eprintln!("{}", message);
+            syslog::log(syslog::Level::Warning, &message);
+            0
+        }
+        Err(error) => {
+            let failed = match action.operation {
+                Operation::Delete => Message::FailedDelete,
+                Operation::Hardlink => Message::FailedHardlink,
+                Operation::Symlink => Message::FailedSymlink,
+            };
+            let message = locale::text(
+                failed,
+                &[("path", &action.source.display()), ("error", error)],
+            );
+            eprintln!("{}", message);
+            syslog::log(syslog::Level::Error, &message);
+            0
+        }
+    };
+
+    if let Some(log) = log {
+        log.record(action, bytes_freed, &result)?;
+    }
+
+    Ok(result.is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exhausted_links() {
+        assert!(no_more_links(&Error::from(ErrorKind::Unsupported)));
+        assert!(!no_more_links(&Error::from(ErrorKind::NotFound)));
+
+        #[cfg(unix)]
+        {
+            assert!(no_more_links(&Error::from_raw_os_error(libc::EMLINK)));
+            assert!(no_more_links(&Error::from_raw_os_error(libc::EPERM)));
+            assert!(!no_more_links(&Error::from_raw_os_error(libc::EXDEV)));
+        }
+    }
+
+    #[test]
+    fn protected_files_are_skipped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paths = [dir.path().join("kept"), dir.path().join("duplicate")];
+        let actions = plan(Operation::Delete, 3, "abc", &paths, "first path");
+        let mut log =
+            ActionLog::open(&dir.path().join("actions.log"), output::SCHEMA_VERSION).unwrap();
+
+        let denied = Error::from(ErrorKind::PermissionDenied);
+        assert!(!record(&actions[0], Err(denied), Some(&mut log)).unwrap());
+        assert!(!record(
+            &actions[0],
+            Err(Error::from(ErrorKind::NotFound)),
+            Some(&mut log)
+        )
+        .unwrap());
+        drop(log);
+
+        let logged = fs::read_to_string(dir.path().join("actions.log")).unwrap();
+        let results: Vec<String> = logged
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["result"].to_string()
+            })
+            .collect();
+        assert!(results[0].starts_with("\"skipped: "));
+        assert!(results[1].starts_with("\"error: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn system_directories() {
+        assert!(is_system_directory(Path::new("/")));
+        assert!(is_system_directory(Path::new("/usr")));
+        assert!(is_system_directory(Path::new("/etc/ssh/")));
+        assert!(!is_system_directory(Path::new("/home/user/photos")));
+        assert!(!is_system_directory(Path::new("/usrdata")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn within_roots() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let outside = tempfile::TempDir::new().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+        let roots = [dir.path().to_path_buf()];
+
+        assert!(is_within(&dir.path().join("a.jpg"), &roots));
+        assert!(is_within(&dir.path().join("link"), &roots));
+        assert!(!is_within(&dir.path().join("link/a.jpg"), &roots));
+        assert!(!is_within(&outside.path().join("a.jpg"), &roots));
+    }
+
+    #[test]
+    fn kept_copy_itself_refused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kept = dir.path().join("kept");
+        fs::write(&kept, "only copy").unwrap();
+        fs::hard_link(&kept, dir.path().join("link")).unwrap();
+
+        for duplicate in [kept.clone(), dir.path().join("link")] {
+            assert!(perform(Operation::Delete, &kept, &duplicate, false).is_err());
+        }
+        assert!(kept.exists());
+        assert!(dir.path().join("link").exists());
+    }
+
+    #[test]
+    fn summary_by_top_level() {
+        let roots = [PathBuf::from("/data"), PathBuf::from("/backup")];
+        let paths = [
+            "/data/photos/kept.jpg",
+            "/data/photos/2020/a.jpg",
+            "/data/photos/b.jpg",
+            "/data/c.jpg",
+            "/backup/old/d.jpg",
+        ]
+        .map(PathBuf::from);
+        let planned = vec![plan(Operation::Delete, 100, "abc", &paths, "first path")];
+
+        assert_eq!(top_level(&paths[1], &roots), PathBuf::from("/data/photos"));
+        assert_eq!(top_level(&paths[3], &roots), PathBuf::from("/data"));
+        assert_eq!(
+            top_level(Path::new("/elsewhere/e.jpg"), &roots),
+            PathBuf::from("/elsewhere")
+        );
+
+        let mut out = Vec::new();
+        write_summary(&mut out, &planned, &roots).unwrap();
+        let summary = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = summary.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("4 files, freeing 400 B"));
+        assert!(lines[1].ends_with("2 files  /data/photos"));
+        assert!(lines[2].ends_with("1 files  /backup/old"));
+        assert!(lines[3].ends_with("1 files  /data"));
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::{self, Error, Result};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use console::Term;
+use indicatif::DecimalBytes;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use rupes::{FileEntry, HashAlgorithm, ScanOptions};
+
+/// Bytes read per second, as a human readable rate
+fn rate(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+    format!("{}/s", DecimalBytes((bytes as f64 / seconds) as u64))
+}
+
+/// Pick files to benchmark with until they add up to `sample_size` bytes
+fn sample(files: &[FileEntry], sample_size: u64) -> (Vec<FileEntry>, u64) {
+    let mut sample = Vec::new();
+    let mut total = 0;
+
+    for file in files {
+        if total >= sample_size {
+            break;
+        }
+
+        total += file.size;
+        sample.push(file.clone());
+    }
+
+    (sample, total)
+}
+
+fn thread_pool(threads: usize) -> Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(Error::other)
+}
+
+fn hash_all(files: &[FileEntry], algorithm: HashAlgorithm) -> Result<()> {
+    files
+        .par_iter()
+        .try_for_each(|file| algorithm.hash_file(&file.path).map(|_| ()))
+}
+
+/// Measure discovery, reading and hashing speed on the storage behind `directory`
+pub fn run(directory: PathBuf, sample_size: u64) -> Result<()> {
+    let term = Term::stdout();
+
+    let options = ScanOptions {
+        recursive: true,
+        ..ScanOptions::default()
+    };
+
+    let start = Instant::now();
+    let files = rupes::find_files(&directory, &options)?;
+    let elapsed = start.elapsed();
+
+    term.write_line(&format!(
+        "Discovery: {} files in {:.2?} ({:.0} files/s)",
+        files.len(),
+        elapsed,
+        files.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    ))?;
+
+    let (sample, sample_bytes) = sample(&files, sample_size);
+    if sample.is_empty() {
+        term.write_line("No files to benchmark hashing with")?;
+        return Ok(());
+    }
+
+    // The first read comes from storage, later passes are likely served from the page cache
+    let start = Instant::now();
+    sample
+        .par_iter()
+        .try_for_each(|file| io::copy(&mut File::open(&file.path)?, &mut io::sink()).map(|_| ()))?;
+    let elapsed = start.elapsed();
+
+    term.write_line(&format!(
+        "Reading: {} in {} files in {:.2?} ({})",
+        DecimalBytes(sample_bytes),
+        sample.len(),
+        elapsed,
+        rate(sample_bytes, elapsed)
+    ))?;
+    term.write_line("")?;
+
+    let single_thread = thread_pool(1)?;
+
+    term.write_line("Hashing throughput on one thread (--hash)")?;
+    for algorithm in HashAlgorithm::value_variants() {
+        let start = Instant::now();
+        single_thread.install(|| hash_all(&sample, *algorithm))?;
+        let elapsed = start.elapsed();
+
+        term.write_line(&format!(
+            "{:>10}  {}",
+            algorithm.name(),
+            rate(sample_bytes, elapsed)
+        ))?;
+    }
+    term.write_line("")?;
+
+    let max_threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut thread_counts: Vec<usize> = (0..)
+        .map(|power| 1 << power)
+        .take_while(|&threads| threads < max_threads)
+        .collect();
+    thread_counts.push(max_threads);
+
+    let algorithm = HashAlgorithm::default();
+    term.write_line(&format!(
+        "Scaling with thread count using {} (--threads)",
+        algorithm
+    ))?;
+    for threads in thread_counts {
+        let pool = thread_pool(threads)?;
+
+        let start = Instant::now();
+        pool.install(|| hash_all(&sample, algorithm))?;
+        let elapsed = start.elapsed();
+
+        term.write_line(&format!(
+            "{:>10}  {}",
+            if threads == 1 {
+                "1 thread".to_string()
+            } else {
+                format!("{} threads", threads)
+            },
+            rate(sample_bytes, elapsed)
+        ))?;
+    }
+
+    Ok(())
+}
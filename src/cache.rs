@@ -0,0 +1,487 @@
+//! Hashes kept between scans, so files that haven't changed since they were last hashed aren't read again
+//!
+//! A file is taken to be unchanged while its size and modification time are the same as when it was hashed. The cache
+//! is a text file of one `algorithm size modified digest path` line per file, separated by tabs, which can be moved
+//! to another machine scanning the same files under a different directory with [`HashCache::under`] and
+//! [`HashCache::import`].
+
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{self, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use rayon::prelude::*;
+
+use crate::hash::{Digest, HashAlgorithm};
+
+/// First line of every cache file, naming its columns
+const HEADER: &str = "# rupes hash cache: algorithm, size, modified, digest, path";
+
+/// Start of the line recording how the cache was used by the last scan
+const LAST_SCAN: &str = "# last scan:";
+
+/// The hash of a file as it was when it was hashed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedHash {
+    pub algorithm: HashAlgorithm,
+    pub size: u64,
+    /// Modification time, in nanoseconds since the Unix epoch
+    pub modified: u64,
+    pub digest: Digest,
+}
+
+/// How often a scan found the hashes it needed in the cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Files whose hashes were taken from the cache
+    pub hits: u64,
+    /// Files that had to be hashed, because they weren't cached yet or changed since
+    pub misses: u64,
+    /// Bytes in the files whose hashes were taken from the cache, which didn't have to be read
+    pub bytes_saved: u64,
+}
+
+impl CacheStats {
+    /// Percentage of lookups answered by the cache
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 * 100.0 / lookups as f64,
+        }
+    }
+
+    fn parse(line: &str) -> Option<CacheStats> {
+        let mut stats = CacheStats::default();
+        for field in line.strip_prefix(LAST_SCAN)?.split_whitespace() {
+            let (name, value) = field.split_once('=')?;
+            let value = value.parse().ok()?;
+            match name {
+                "hits" => stats.hits = value,
+                "misses" => stats.misses = value,
+                "bytes_saved" => stats.bytes_saved = value,
+                _ => {}
+            }
+        }
+        Some(stats)
+    }
+}
+
+/// Hashes of files by absolute path, shared by every thread hashing them
+#[derive(Debug, Default)]
+pub struct HashCache {
+    entries: Mutex<HashMap<PathBuf, CachedHash>>,
+    /// Whether anything was added since the cache was read, so saving it can be skipped
+    changed: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bytes_saved: AtomicU64,
+    /// How the cache was used by the scan that last saved it
+    last_scan: Option<CacheStats>,
+}
+
+/// Modification time of a file in nanoseconds since the Unix epoch, if the platform and filesystem record one after it
+pub(crate) fn modified(metadata: &Metadata) -> Option<u64> {
+    let since = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since.as_nanos()).ok()
+}
+
+/// Whether two modification times are the same, as far as the coarser of them goes
+///
+/// Filesystems and network shares keep times to different precisions, from nanoseconds to whole seconds, so a time
+/// recorded on one machine can read back truncated on another.
+pub(crate) fn same_time(a: u64, b: u64) -> bool {
+    let precision = |time: u64| {
+        let mut precision = 1;
+        while precision < 1_000_000_000 && time.is_multiple_of(precision * 10) {
+            precision *= 10;
+        }
+        precision
+    };
+    let precision = precision(a).max(precision(b));
+
+    a / precision == b / precision
+}
+
+/// `path` made absolute without touching the filesystem, so the same file is found from any working directory
+fn key(path: &Path) -> PathBuf {
+    path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, CachedHash)> {
+    let mut fields = line.splitn(5, '\t');
+    let algorithm = fields.next()?.parse().ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let modified = fields.next()?.parse().ok()?;
+    let digest = fields.next()?.parse().ok()?;
+    let path = PathBuf::from(fields.next()?);
+
+    Some((
+        path,
+        CachedHash {
+            algorithm,
+            size,
+            modified,
+            digest,
+        },
+    ))
+}
+
+impl HashCache {
+    /// Read the cache in the file at `path`, an empty cache if there's no file there yet
+    pub fn open(path: &Path) -> Result<HashCache> {
+        match File::open(path) {
+            Ok(file) => HashCache::read(BufReader::new(file)).map_err(|error| {
+                Error::new(error.kind(), format!("{}: {}", path.display(), error))
+            }),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(HashCache::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Read a cache written by [`HashCache::write`]
+    pub fn read(reader: impl BufRead) -> Result<HashCache> {
+        let mut entries = HashMap::new();
+        let mut last_scan = None;
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.starts_with(LAST_SCAN) {
+                last_scan = CacheStats::parse(&line);
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (path, hash) = parse_line(&line).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("line {} isn't a cached hash", number + 1),
+                )
+            })?;
+            entries.insert(path, hash);
+        }
+
+        Ok(HashCache {
+            entries: Mutex::new(entries),
+            last_scan,
+            ..HashCache::default()
+        })
+    }
+
+    /// Write every entry, sorted by path so caches can be compared
+    ///
+    /// Paths that aren't valid UTF-8 or hold a line break can't be written, their files are hashed again next time.
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        let entries = self.entries.lock().unwrap();
+        let mut paths: Vec<_> = entries.keys().collect();
+        paths.sort();
+
+        writeln!(writer, "{}", HEADER)?;
+        if let Some(stats) = self.stats().or(self.last_scan) {
+            writeln!(
+                writer,
+                "{} hits={} misses={} bytes_saved={}",
+                LAST_SCAN, stats.hits, stats.misses, stats.bytes_saved
+            )?;
+        }
+        for path in paths {
+            let Some(name) = path.to_str().filter(|name| !name.contains(['\n', '\r'])) else {
+                continue;
+            };
+            let hash = &entries[path];
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                hash.algorithm, hash.size, hash.modified, hash.digest, name
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    /// Write the cache to the file at `path` if it was used or changed since it was read, replacing the file in one step
+    /// so a scan stopped halfway leaves the previous cache whole
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.unchanged(path) {
+            return Ok(());
+        }
+
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        self.write(BufWriter::new(File::create(&partial)?))?;
+        fs::rename(&partial, path)?;
+        self.changed.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Like [`HashCache::save`], but truncating and writing the file at `path` itself, for --sandbox which lets rupes
+    /// write its output files and nothing else beside them, at the cost of a scan stopped halfway losing the cache
+    pub fn save_in_place(&self, path: &Path) -> Result<()> {
+        if self.unchanged(path) {
+            return Ok(());
+        }
+
+        self.write(BufWriter::new(File::create(path)?))?;
+        self.changed.store(false, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Whether the file at `path` already holds everything [`HashCache::save`] would write
+    fn unchanged(&self, path: &Path) -> bool {
+        !self.changed.load(Ordering::Relaxed) && self.stats().is_none() && path.exists()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How the cache has been used since it was read, `None` if nothing was looked up in it
+    pub fn stats(&self) -> Option<CacheStats> {
+        let stats = CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            bytes_saved: self.bytes_saved.load(Ordering::Relaxed),
+        };
+        (stats.hits + stats.misses > 0).then_some(stats)
+    }
+
+    /// How the cache was used by the last scan that saved it, if it was saved by one
+    pub fn last_scan(&self) -> Option<CacheStats> {
+        self.last_scan
+    }
+
+    /// Drop the entries of files that were removed or changed since they were hashed, returning how many were dropped
+    ///
+    /// Files that can't be looked at for another reason, such as a permission error, keep their entries.
+    pub fn prune(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let stale: Vec<PathBuf> = entries
+            .par_iter()
+            .filter(|(path, hash)| match fs::metadata(path) {
+                Ok(metadata) => {
+                    !metadata.is_file()
+                        || metadata.len() != hash.size
+                        || !modified(&metadata).is_some_and(|time| same_time(hash.modified, time))
+                }
+                Err(error) => error.kind() == ErrorKind::NotFound,
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in &stale {
+            entries.remove(path);
+        }
+        if !stale.is_empty() {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+
+        stale.len()
+    }
+
+    /// The digest of the file at `path` made with `algorithm`, if it was cached and the file is still as `metadata`
+    /// describes it
+    pub fn get(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        metadata: &Metadata,
+    ) -> Option<Digest> {
+        let digest = modified(metadata).and_then(|modified| {
+            let entries = self.entries.lock().unwrap();
+            let hash = entries.get(&key(path))?;
+
+            (hash.algorithm == algorithm
+                && hash.size == metadata.len()
+                && same_time(hash.modified, modified))
+            .then(|| hash.digest.clone())
+        });
+
+        if digest.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            self.bytes_saved
+                .fetch_add(metadata.len(), Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        digest
+    }
+
+    /// Remember `digest` as the hash of the file at `path`, `metadata` being how the file was before it was read
+    ///
+    /// Files without a modification time are left out, there'd be no telling when they change.
+    pub fn insert(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        metadata: &Metadata,
+        digest: Digest,
+    ) {
+        let Some(modified) = modified(metadata) else {
+            return;
+        };
+
+        self.entries.lock().unwrap().insert(
+            key(path),
+            CachedHash {
+                algorithm,
+                size: metadata.len(),
+                modified,
+                digest,
+            },
+        );
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// The entries of files in `directory`, with paths relative to it, to be imported elsewhere
+    pub fn under(&self, directory: &Path) -> HashCache {
+        let directory = key(directory);
+        let entries = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(path, hash)| {
+                let relative = path.strip_prefix(&directory).ok()?;
+                Some((relative.to_path_buf(), hash.clone()))
+            })
+            .collect();
+
+        HashCache {
+            entries: Mutex::new(entries),
+            changed: AtomicBool::new(true),
+            ..HashCache::default()
+        }
+    }
+
+    /// Add every entry of `other`, with relative paths taken to be in `directory`, returning how many were added
+    ///
+    /// Entries of `other` replace those already cached for the same files.
+    pub fn import(&self, other: HashCache, directory: &Path) -> usize {
+        let directory = key(directory);
+        let imported = other.entries.into_inner().unwrap();
+        let count = imported.len();
+
+        let mut entries = self.entries.lock().unwrap();
+        for (path, hash) in imported {
+            entries.insert(directory.join(path), hash);
+        }
+        if count > 0 {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_files_only() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "content").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        let digest = Digest::from_bytes(&[1, 2, 3]);
+
+        let cache = HashCache::default();
+        cache.insert(&file, HashAlgorithm::Sha256, &metadata, digest.clone());
+        assert_eq!(
+            cache.get(&file, HashAlgorithm::Sha256, &metadata),
+            Some(digest.clone())
+        );
+        assert_eq!(cache.get(&file, HashAlgorithm::Blake3, &metadata), None);
+
+        fs::write(&file, "changed content").unwrap();
+        let changed = fs::metadata(&file).unwrap();
+        assert_eq!(cache.get(&file, HashAlgorithm::Sha256, &changed), None);
+
+        assert_eq!(
+            cache.stats(),
+            Some(CacheStats {
+                hits: 1,
+                misses: 2,
+                bytes_saved: 7
+            })
+        );
+
+        let path = dir.path().join("cache");
+        cache.save(&path).unwrap();
+        let read = HashCache::open(&path).unwrap();
+        assert_eq!(read.last_scan(), cache.stats());
+        assert_eq!(read.stats(), None);
+        assert_eq!(
+            read.get(&file, HashAlgorithm::Sha256, &metadata),
+            Some(digest)
+        );
+
+        assert_eq!(read.prune(), 1);
+        assert!(read.is_empty());
+        assert!(HashCache::open(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn moved_between_directories() {
+        let cache = HashCache::read(
+            "# comment\nsha256\t3\t10\t010203\t/volume1/share/a\nsha256\t3\t10\t010203\t/elsewhere/b\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        let exported = cache.under(Path::new("/volume1/share"));
+        let mut written = Vec::new();
+        exported.write(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.ends_with("sha256\t3\t10\t010203\ta\n"));
+
+        let other = HashCache::default();
+        let exported = HashCache::read(written.as_bytes()).unwrap();
+        assert_eq!(other.import(exported, Path::new("/mnt/share")), 1);
+        assert!(other
+            .entries
+            .lock()
+            .unwrap()
+            .contains_key(Path::new("/mnt/share/a")));
+
+        assert!(same_time(
+            1_700_000_000_123_456_789,
+            1_700_000_000_123_456_700
+        ));
+        assert!(same_time(
+            1_700_000_000_123_456_789,
+            1_700_000_000_000_000_000
+        ));
+        assert!(!same_time(
+            1_700_000_000_123_456_789,
+            1_700_000_000_123_456_000 + 7_000
+        ));
+        assert!(!same_time(
+            1_700_000_000_000_000_000,
+            1_700_000_001_000_000_000
+        ));
+
+        assert!(HashCache::read("sha256\tlarge\t10\t01\t/a\n".as_bytes()).is_err());
+    }
+}
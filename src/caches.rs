@@ -0,0 +1,76 @@
+//! Well-known cache directories, left out of scans by --skip-caches
+//!
+//! Their content is rebuilt at will by the programs that own them, so duplicates in them are meaningless, and they
+//! change between scans far more than anything else.
+
+use std::env;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+/// Cache directories of the current user and the system, found from the environment, whether or not they exist
+pub fn directories() -> Vec<PathBuf> {
+    let var = |name: &str| {
+        env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let home = var("HOME").or_else(|| var("USERPROFILE"));
+    let cargo = var("CARGO_HOME").or_else(|| home.as_ref().map(|home| home.join(".cargo")));
+
+    let mut directories = Vec::new();
+    directories.extend(var("PIP_CACHE_DIR"));
+    directories.extend(var("npm_config_cache"));
+    if let Some(cargo) = cargo {
+        directories.push(cargo.join("registry"));
+        directories.push(cargo.join("git"));
+    }
+
+    if cfg!(windows) {
+        if let Some(local) = var("LOCALAPPDATA") {
+            directories.extend([
+                local.join("Temp"),
+                local.join("pip\\Cache"),
+                local.join("npm-cache"),
+                local.join("Microsoft\\Windows\\INetCache"),
+                local.join("CrashDumps"),
+            ]);
+        }
+        directories.extend(var("TEMP"));
+        return directories;
+    }
+
+    directories
+        .extend(var("XDG_CACHE_HOME").or_else(|| home.as_ref().map(|home| home.join(".cache"))));
+    if let Some(home) = &home {
+        directories.extend([
+            home.join("Library/Caches"),
+            home.join(".npm/_cacache"),
+            home.join(".gradle/caches"),
+        ]);
+    }
+    directories.push(PathBuf::from("/var/cache"));
+
+    directories
+}
+
+/// Names of the cache directories browsers and Electron apps keep inside each of their profiles
+pub fn names() -> Regex {
+    Regex::new("^(cache2|Code Cache|GPUCache|GrShaderCache|ShaderCache|DawnCache)$").unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browser_caches() {
+        let names = names();
+        assert!(names.is_match("cache2"));
+        assert!(names.is_match("Code Cache"));
+        assert!(!names.is_match("cache"));
+        assert!(!names.is_match("Code Cache Notes"));
+
+        assert!(!directories().is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+//! Partial duplicate detection with content-defined chunking
+//!
+//! Files are split into variable sized chunks at boundaries chosen by a rolling gear hash of their content, so an
+//! insertion or deletion only changes the chunks around it. Files that share many chunks share much of their content,
+//! even when they aren't identical, such as a log that has been appended to or a re-exported video.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Result};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::walk::FileEntry;
+
+/// Chunks are never smaller than this, except at the end of a file
+pub const MIN_CHUNK: usize = 2 * 1024;
+/// Target average chunk size, must be a power of two
+pub const AVERAGE_CHUNK: usize = 8 * 1024;
+/// Chunks are never larger than this
+pub const MAX_CHUNK: usize = 64 * 1024;
+
+/// Chunks found in more than this many files, such as runs of zeros, are ignored when pairing files
+const MAX_CHUNK_SHARERS: usize = 64;
+
+/// Chunk boundaries are where the top bits of the gear hash are all zero, on average once every `AVERAGE_CHUNK` bytes
+const BOUNDARY_MASK: u64 = !0 << (64 - AVERAGE_CHUNK.trailing_zeros());
+
+/// Pseudo-random value for each byte used by the gear hash, generated with splitmix64
+const GEAR: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x5275_7065_7343_4443;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// A piece of a file, identified by a hash of its content
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Chunk {
+    pub hash: u128,
+    pub len: u32,
+}
+
+fn finish_chunk(hasher: &mut blake3::Hasher, len: usize, chunks: &mut Vec<Chunk>) {
+    let hash = hasher.finalize();
+    let hash = u128::from_le_bytes(hash.as_bytes()[..16].try_into().unwrap());
+
+    chunks.push(Chunk {
+        hash,
+        len: len as u32,
+    });
+    hasher.reset();
+}
+
+/// Split the content of `reader` into content-defined chunks
+pub fn chunk_reader(reader: &mut impl Read) -> Result<Vec<Chunk>> {
+    let mut chunks = Vec::new();
+    let mut buffer = vec![0; 64 * 1024];
+
+    let mut hasher = blake3::Hasher::new();
+    let mut gear: u64 = 0;
+    let mut len = 0;
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        let mut start = 0;
+        for (i, &byte) in buffer[..read].iter().enumerate() {
+            gear = (gear << 1).wrapping_add(GEAR[byte as usize]);
+            len += 1;
+
+            if (len >= MIN_CHUNK && gear & BOUNDARY_MASK == 0) || len >= MAX_CHUNK {
+                hasher.update(&buffer[start..=i]);
+                finish_chunk(&mut hasher, len, &mut chunks);
+
+                start = i + 1;
+                gear = 0;
+                len = 0;
+            }
+        }
+
+        hasher.update(&buffer[start..read]);
+    }
+
+    if len > 0 {
+        finish_chunk(&mut hasher, len, &mut chunks);
+    }
+
+    Ok(chunks)
+}
+
+/// Split the content of the file at `path` into content-defined chunks
+pub fn chunk_file(path: &Path) -> Result<Vec<Chunk>> {
+    chunk_reader(&mut File::open(path)?)
+}
+
+/// Two files that share part of their content
+#[derive(Debug)]
+pub struct SimilarPair {
+    pub first: FileEntry,
+    pub second: FileEntry,
+    /// Total size of the chunks found in both files
+    pub shared_bytes: u64,
+    /// Fraction of the two files' combined content that is shared, between 0 and 1
+    pub similarity: f64,
+}
+
+/// Find pairs of `files` at least `threshold` (between 0 and 1) similar, calling `progress` as each file is chunked
+///
+/// Pairs with identical content are left out, since they are exact duplicates rather than partial ones. Pairs are
+/// returned from most to least similar.
+pub fn find_similar(
+    files: Vec<FileEntry>,
+    threshold: f64,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<SimilarPair>> {
+    let chunked: Vec<(FileEntry, Vec<Chunk>)> = files
+        .into_par_iter()
+        .map(|file| {
+            let chunks = chunk_file(&file.path)?;
+            progress(&file);
+            Ok((file, chunks))
+        })
+        .collect::<Result<_>>()?;
+
+    // Which files each distinct chunk appears in
+    let mut sharers: HashMap<Chunk, Vec<usize>> = HashMap::new();
+    for (index, (_, chunks)) in chunked.iter().enumerate() {
+        let distinct: HashSet<&Chunk> = chunks.iter().collect();
+        for chunk in distinct {
+            sharers.entry(*chunk).or_default().push(index);
+        }
+    }
+
+    let mut shared_bytes: HashMap<(usize, usize), u64> = HashMap::new();
+    for (chunk, files) in &sharers {
+        if files.len() < 2 || files.len() > MAX_CHUNK_SHARERS {
+            continue;
+        }
+
+        for (i, &first) in files.iter().enumerate() {
+            for &second in &files[i + 1..] {
+                *shared_bytes.entry((first, second)).or_default() += chunk.len as u64;
+            }
+        }
+    }
+
+    let mut pairs: Vec<SimilarPair> = shared_bytes
+        .into_iter()
+        .filter_map(|((first, second), shared)| {
+            let (first, first_chunks) = &chunked[first];
+            let (second, second_chunks) = &chunked[second];
+
+            if first_chunks == second_chunks {
+                return None;
+            }
+
+            let similarity = (shared * 2) as f64 / (first.size + second.size) as f64;
+            if similarity < threshold {
+                return None;
+            }
+
+            let (first, second) = if first.path <= second.path {
+                (first, second)
+            } else {
+                (second, first)
+            };
+
+            Some(SimilarPair {
+                first: first.clone(),
+                second: second.clone(),
+                shared_bytes: shared,
+                similarity,
+            })
+        })
+        .collect();
+
+    pairs.sort_by(|a, b| {
+        b.similarity
+            .total_cmp(&a.similarity)
+            .then_with(|| a.first.path.cmp(&b.first.path))
+            .then_with(|| a.second.path.cmp(&b.second.path))
+    });
+
+    Ok(pairs)
+}
+
+/// Block sizes block-level deduplication works with: 4 KiB, the blocks bees deduplicates on Btrfs, and 128 KiB, the
+/// default record size of ZFS datasets
+pub const DEDUP_BLOCKS: [usize; 2] = [4 * 1024, 128 * 1024];
+
+/// Hashes of the fixed size blocks of everything read through it, each file starting a new block as filesystems do
+struct BlockHashing<R> {
+    inner: R,
+    block_size: usize,
+    hasher: blake3::Hasher,
+    filled: usize,
+    blocks: Vec<u64>,
+}
+
+impl<R: Read> BlockHashing<R> {
+    fn new(inner: R, block_size: usize) -> Self {
+        BlockHashing {
+            inner,
+            block_size,
+            hasher: blake3::Hasher::new(),
+            filled: 0,
+            blocks: Vec::new(),
+        }
+    }
+
+    fn finish_block(&mut self) {
+        let hash = self.hasher.finalize();
+        self.blocks
+            .push(u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap()));
+        self.hasher.reset();
+        self.filled = 0;
+    }
+
+    /// The hashes of every block, the last one possibly shorter
+    fn finish(mut self) -> Vec<u64> {
+        if self.filled > 0 {
+            self.finish_block();
+        }
+        self.blocks
+    }
+}
+
+impl<R: Read> Read for BlockHashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        let mut rest = &buf[..read];
+        while !rest.is_empty() {
+            let take = rest.len().min(self.block_size - self.filled);
+            self.hasher.update(&rest[..take]);
+            self.filled += take;
+            rest = &rest[take..];
+            if self.filled == self.block_size {
+                self.finish_block();
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+/// How much of some files would be left once deduplicated, by each way of deduplicating them
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DedupEstimate {
+    /// Total size of the files
+    pub total: u64,
+    /// Size of their distinct blocks, for each of [`DEDUP_BLOCKS`]
+    pub unique_blocks: [u64; 2],
+    /// Size of their distinct content-defined chunks, as stored by deduplicating backup tools
+    pub unique_chunks: u64,
+}
+
+/// Estimate what block-level deduplication would keep of `files`, calling `progress` as each file is read
+///
+/// Blocks are compared by a 64 bit hash of their content, plenty for an estimate. The hashes of every block and chunk
+/// are kept while counting, about 50 bytes for every 8 KiB of the files.
+pub fn estimate_dedup(
+    files: Vec<FileEntry>,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<DedupEstimate> {
+    let read: Vec<(u64, [Vec<u64>; 2], Vec<Chunk>)> = files
+        .into_par_iter()
+        .map(|file| {
+            let mut large = BlockHashing::new(File::open(&file.path)?, DEDUP_BLOCKS[1]);
+            let mut small = BlockHashing::new(&mut large, DEDUP_BLOCKS[0]);
+            let chunks = chunk_reader(&mut small)?;
+            let blocks = [small.finish(), large.finish()];
+            progress(&file);
+            Ok((file.size, blocks, chunks))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut estimate = DedupEstimate::default();
+    let mut seen_blocks: [HashSet<u64>; 2] = Default::default();
+    let mut seen_chunks: HashSet<Chunk> = HashSet::new();
+    for (size, blocks, chunks) in read {
+        estimate.total += size;
+
+        for (i, block_size) in DEDUP_BLOCKS.into_iter().enumerate() {
+            for (index, &block) in blocks[i].iter().enumerate() {
+                if seen_blocks[i].insert(block) {
+                    estimate.unique_blocks[i] +=
+                        (size - (index * block_size) as u64).min(block_size as u64);
+                }
+            }
+        }
+        for chunk in chunks {
+            if seen_chunks.insert(chunk) {
+                estimate.unique_chunks += chunk.len as u64;
+            }
+        }
+    }
+
+    Ok(estimate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn dedup_estimate() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let block = |seed: u8| -> Vec<u8> {
+            (0..DEDUP_BLOCKS[0])
+                .map(|i| (i * 31 % 251) as u8 ^ seed)
+                .collect()
+        };
+
+        // Two files sharing their first two 4 KiB blocks, the second ending in a short block of its own
+        let first = [block(1), block(2), block(3)].concat();
+        let second = [block(1), block(2), vec![9; 100]].concat();
+        let mut files = Vec::new();
+        for (name, content) in [("first", &first), ("second", &second)] {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            files.push(FileEntry {
+                path,
+                size: content.len() as u64,
+                id: None,
+            });
+        }
+
+        let estimate = estimate_dedup(files, |_| {}).unwrap();
+        assert_eq!(estimate.total, (first.len() + second.len()) as u64);
+        assert_eq!(estimate.unique_blocks[0], first.len() as u64 + 100);
+        // Each file fits in a single record, and they differ
+        assert_eq!(estimate.unique_blocks[1], estimate.total);
+        assert!(estimate.unique_chunks <= estimate.total);
+    }
+}
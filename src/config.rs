@@ -0,0 +1,757 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use regex::Regex;
+
+use rupes::walk::DirectoryOverrides;
+use rupes::ScanOptions;
+
+/// Commands taking the scan options, which the configuration applies to
+const CONFIGURED: &[&[&str]] = &[
+    &[],
+    &["plan"],
+    &["hash"],
+    &["missing"],
+    &["verify-backup"],
+    &["config", "show"],
+    &["service", "run"],
+    &["cache", "export"],
+    &["cache", "import"],
+    &["cache", "prune"],
+    &["cache", "stats"],
+];
+
+/// Options that can't be configured, either because they choose the configuration or make no sense as a default
+const UNCONFIGURABLE: &[&str] = &["help", "version", "config", "profile"];
+
+/// Values given to options by one source of settings, such as a profile
+struct Layer {
+    source: String,
+    values: Vec<(String, Vec<String>)>,
+}
+
+/// Settings read before parsing the command line, applied to it as defaults so anything given there wins
+pub struct Settings {
+    pub path: Option<PathBuf>,
+    pub found: bool,
+    pub profile: Option<String>,
+    layers: Vec<Layer>,
+}
+
+impl Settings {
+    /// The values an option defaults to and where they come from, the last source setting it wins
+    fn resolved(&self) -> HashMap<&str, (&[String], &str)> {
+        let mut resolved = HashMap::new();
+        for layer in &self.layers {
+            for (id, values) in &layer.values {
+                resolved.insert(id.as_str(), (values.as_slice(), layer.source.as_str()));
+            }
+        }
+        resolved
+    }
+}
+
+/// Where the configuration file is read from when neither --config nor $RUPES_CONFIG is given
+pub fn default_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(|data| PathBuf::from(data).join("rupes\\config.toml"));
+    }
+
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|config| !config.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("rupes/config.toml"))
+}
+
+/// The configuration file chosen with --config or $RUPES_CONFIG, or the one read by default
+pub fn path(args: &[OsString]) -> Option<PathBuf> {
+    raw_value(args, "config")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("RUPES_CONFIG").map(PathBuf::from))
+        .or_else(default_path)
+}
+
+/// Options that can be configured, with the name they're given in the configuration file
+fn configurable(command: &Command) -> impl Iterator<Item = (String, &Arg)> {
+    command
+        .get_arguments()
+        .filter(|arg| !UNCONFIGURABLE.contains(&arg.get_id().as_str()))
+        .map(|arg| {
+            let name = match arg.get_long() {
+                Some(long) => long.to_string(),
+                None => arg.get_id().as_str().replace('_', "-"),
+            };
+            (name, arg)
+        })
+}
+
+fn is_flag(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::SetTrue)
+}
+
+/// The last value given to `--name` on the command line, found before it's parsed
+fn raw_value(args: &[OsString], name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut value = None;
+
+    let mut args = args.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == flag {
+            value = args.next().map(|value| value.into_owned());
+        } else if let Some(given) = arg
+            .strip_prefix(&flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            value = Some(given.to_string());
+        }
+    }
+
+    value
+}
+
+/// Command line values for a TOML value, arrays giving an option several values
+fn toml_values(value: &toml::Value) -> Option<Vec<String>> {
+    match value {
+        toml::Value::String(value) => Some(vec![value.clone()]),
+        toml::Value::Integer(value) => Some(vec![value.to_string()]),
+        toml::Value::Float(value) => Some(vec![value.to_string()]),
+        toml::Value::Boolean(value) => Some(vec![value.to_string()]),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                toml::Value::Array(_) | toml::Value::Table(_) => None,
+                value => toml_values(value).map(|mut values| values.remove(0)),
+            })
+            .collect(),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => None,
+    }
+}
+
+fn table_layer(
+    command: &Command,
+    table: &toml::Table,
+    source: String,
+    path: &Path,
+) -> Result<Layer> {
+    let mut values = Vec::new();
+
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        let arg = configurable(command)
+            .find(|(configured, _)| *configured == name)
+            .map(|(_, arg)| arg)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} has an unknown option '{}'", path.display(), key),
+                )
+            })?;
+        let value = toml_values(value).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} gives '{}' a value that isn't a string, number, boolean or list of them",
+                    path.display(),
+                    key
+                ),
+            )
+        })?;
+
+        values.push((arg.get_id().to_string(), value));
+    }
+
+    Ok(Layer { source, values })
+}
+
+/// Options set with environment variables named after them, like RUPES_EXCLUDE_DOTS=1
+fn environment_layer(command: &Command) -> Layer {
+    let mut values = Vec::new();
+
+    for (_, arg) in configurable(command) {
+        let variable = format!("RUPES_{}", arg.get_id().as_str().to_ascii_uppercase());
+        let Some(value) = env::var_os(&variable) else {
+            continue;
+        };
+        let value = value.to_string_lossy().into_owned();
+
+        let value = if is_flag(arg) {
+            match value.to_ascii_lowercase().as_str() {
+                "1" | "yes" | "on" | "true" => "true".to_string(),
+                "" | "0" | "no" | "off" | "false" => "false".to_string(),
+                _ => value,
+            }
+        } else {
+            value
+        };
+
+        values.push((arg.get_id().to_string(), vec![value]));
+    }
+
+    Layer {
+        source: "environment".to_string(),
+        values,
+    }
+}
+
+/// Read the configuration file, the profile chosen from it and the environment, to be applied to `command`
+pub fn load(command: &Command, args: &[OsString]) -> Result<Settings> {
+    let path = path(args);
+    let profile = raw_value(args, "profile").or_else(|| env::var("RUPES_PROFILE").ok());
+
+    let content = match &path {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(content) => Some(content),
+            Err(error) if error.kind() == ErrorKind::NotFound => None,
+            Err(error) => return Err(error),
+        },
+        None => None,
+    };
+
+    let mut layers = Vec::new();
+    if let (Some(path), Some(content)) = (&path, &content) {
+        let mut table: toml::Table = content.parse().map_err(|error| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), error),
+            )
+        })?;
+        let profiles = table.remove("profile");
+
+        layers.push(table_layer(
+            command,
+            &table,
+            "config file".to_string(),
+            path,
+        )?);
+
+        if let Some(profile) = &profile {
+            let settings = profiles
+                .as_ref()
+                .and_then(|profiles| profiles.get(profile))
+                .and_then(|settings| settings.as_table())
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("{} has no [profile.{}]", path.display(), profile),
+                    )
+                })?;
+            layers.push(table_layer(
+                command,
+                settings,
+                format!("profile {}", profile),
+                path,
+            )?);
+        }
+    } else if let Some(profile) = &profile {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "Profile '{}' was chosen, but there's no configuration file to read it from",
+                profile
+            ),
+        ));
+    }
+
+    layers.push(environment_layer(command));
+
+    Ok(Settings {
+        found: content.is_some(),
+        path,
+        profile,
+        layers,
+    })
+}
+
+/// Make the settings the defaults of every command taking the scan options
+pub fn apply(mut command: Command, settings: &Settings) -> Command {
+    let resolved = settings.resolved();
+
+    for path in CONFIGURED {
+        command = with_defaults(command, path, &resolved);
+    }
+
+    command
+}
+
+fn with_defaults(
+    command: Command,
+    path: &[&str],
+    resolved: &HashMap<&str, (&[String], &str)>,
+) -> Command {
+    if let Some((name, rest)) = path.split_first() {
+        if command.find_subcommand(name).is_none() {
+            return command;
+        }
+        return command.mut_subcommand(*name, |subcommand| {
+            with_defaults(subcommand, rest, resolved)
+        });
+    }
+
+    let ids: Vec<_> = configurable(&command)
+        .map(|(_, arg)| arg.get_id().to_string())
+        .filter(|id| resolved.contains_key(id.as_str()))
+        .collect();
+
+    ids.into_iter().fold(command, |command, id| {
+        let (values, _) = resolved[id.as_str()];
+        command.mut_arg(id, |arg| arg.default_values(values.to_vec()))
+    })
+}
+
+/// A TOML basic string, written on one line whatever it holds
+fn quoted(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for character in value.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            character if character.is_control() => {
+                quoted.push_str(&format!("\\u{:04X}", character as u32))
+            }
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A value as it would be written in the configuration file
+fn toml_literal(arg: &Arg, value: &str) -> String {
+    if is_flag(arg) || value.parse::<i64>().is_ok() || value.parse::<f64>().is_ok() {
+        value.to_string()
+    } else {
+        quoted(value)
+    }
+}
+
+/// Print every option `config show` was given as it resolved, with where its value comes from
+pub fn show(
+    out: &mut impl Write,
+    command: &Command,
+    matches: &ArgMatches,
+    settings: &Settings,
+) -> Result<()> {
+    match &settings.path {
+        Some(path) if settings.found => writeln!(out, "# Configuration file: {}", path.display())?,
+        Some(path) => writeln!(out, "# Configuration file: {} (not found)", path.display())?,
+        None => writeln!(
+            out,
+            "# Configuration file: none, neither $XDG_CONFIG_HOME nor $HOME is set"
+        )?,
+    }
+    writeln!(
+        out,
+        "# Profile: {}",
+        settings.profile.as_deref().unwrap_or("none")
+    )?;
+    writeln!(out)?;
+
+    let resolved = settings.resolved();
+
+    for (name, arg) in configurable(command) {
+        let id = arg.get_id().as_str();
+        let values: Vec<String> = match matches.get_raw(id) {
+            Some(values) => values
+                .map(|value| toml_literal(arg, &value.to_string_lossy()))
+                .collect(),
+            None => {
+                writeln!(out, "# {} is not set", name)?;
+                continue;
+            }
+        };
+
+        let source = match matches.value_source(id) {
+            Some(ValueSource::CommandLine) => "command line",
+            _ => resolved.get(id).map_or("default", |(_, source)| *source),
+        };
+
+        let value = if matches!(arg.get_action(), ArgAction::Append) {
+            format!("[{}]", values.join(", "))
+        } else {
+            values.join(", ")
+        };
+
+        writeln!(out, "{:<44} # {}", format!("{} = {}", name, value), source)?;
+    }
+
+    Ok(())
+}
+
+/// Wrap `text` into comment lines of at most 100 characters
+fn comment(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut line = String::from("#");
+
+    for word in text.split_whitespace() {
+        if line.len() + word.len() >= 100 {
+            lines.push(std::mem::replace(&mut line, String::from("#")));
+        }
+        line.push(' ');
+        line.push_str(word);
+    }
+    lines.push(line);
+
+    lines.join("\n")
+}
+
+/// A configuration file listing every option with its help, all commented out
+fn starter(command: &Command) -> String {
+    let mut config = String::from(
+        "# Defaults for rupes, overridden by RUPES_<OPTION> environment variables and the command line. Options are\n\
+         # named as on the command line, `rupes config show` prints the settings a scan would use and where they\n\
+         # come from.\n",
+    );
+
+    for (name, arg) in configurable(command) {
+        let defaults: Vec<_> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| toml_literal(arg, &value.to_string_lossy()))
+            .collect();
+
+        let value = if matches!(arg.get_action(), ArgAction::Append) {
+            format!("[{}]", defaults.join(", "))
+        } else if let Some(default) = defaults.first() {
+            default.clone()
+        } else if is_flag(arg) {
+            "false".to_string()
+        } else {
+            let placeholder = arg
+                .get_value_names()
+                .and_then(|names| names.first())
+                .map_or(name.to_ascii_uppercase(), |name| name.to_string());
+            quoted(&placeholder)
+        };
+
+        config.push('\n');
+        if let Some(help) = arg.get_help() {
+            config.push_str(&comment(&help.to_string()));
+            config.push('\n');
+        }
+        config.push_str(&format!("# {} = {}\n", name, value));
+    }
+
+    config.push_str(
+        "\n# Profiles are chosen with --profile NAME or $RUPES_PROFILE, and override the settings above\n\
+         # [profile.photos]\n\
+         # preset = [\"images\"]\n\
+         # recursive = true\n",
+    );
+
+    config
+}
+
+/// Write a starter configuration to `path`, refusing to replace an existing one unless `force` is given
+pub fn init(command: &Command, path: &Path, force: bool) -> Result<()> {
+    if !force && path.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, add --force to replace it",
+                path.display()
+            ),
+        ));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, starter(command))
+}
+
+/// Name of the files inside a scanned tree that tighten the scan for their directory and everything below it
+pub const DIRECTORY_CONFIG: &str = ".rupes.toml";
+
+/// Options a directory configuration can set, none of them able to widen the scan
+const DIRECTORY_KEYS: &[&str] = &[
+    "exclude",
+    "exclude-dir",
+    "exclude-glob",
+    "exclude-dots",
+    "min",
+    "max",
+    "skip-marker",
+    "protect",
+];
+
+/// Reads every [`DIRECTORY_CONFIG`] found while walking
+pub fn directory_overrides() -> DirectoryOverrides {
+    DirectoryOverrides {
+        file_name: DIRECTORY_CONFIG,
+        read: read_directory_config,
+    }
+}
+
+fn read_directory_table(path: &Path) -> Result<toml::Table> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+
+    let table: toml::Table = fs::read_to_string(path)?
+        .parse()
+        .map_err(|error| invalid(format!("{}: {}", path.display(), error)))?;
+
+    for key in table.keys() {
+        if !DIRECTORY_KEYS.contains(&key.replace('_', "-").as_str()) {
+            return Err(invalid(format!(
+                "{} has an option '{}' that can't be set for a directory, only {} can",
+                path.display(),
+                key,
+                DIRECTORY_KEYS.join(", ")
+            )));
+        }
+    }
+
+    Ok(table)
+}
+
+/// The options for the directory holding the configuration at `path`, tightening `options` in force above it
+///
+/// Patterns and markers are added to those already given, sizes only narrow the range allowed.
+fn read_directory_config(path: &Path, options: &ScanOptions) -> Result<ScanOptions> {
+    let table = read_directory_table(path)?;
+    let invalid = |key: &str, expected: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} gives '{}' a value that isn't {}",
+                path.display(),
+                key,
+                expected
+            ),
+        )
+    };
+    let strings = |key: &str, value: &toml::Value| -> Result<Vec<String>> {
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        values
+            .into_iter()
+            .map(|value| value.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid(key, "a string or list of strings"))
+    };
+    let patterns = |key: &str, value: &toml::Value| -> Result<Vec<Regex>> {
+        strings(key, value)?
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|error| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{}: {}", path.display(), error),
+                    )
+                })
+            })
+            .collect()
+    };
+    let size = |key: &str, value: &toml::Value| -> Result<u64> {
+        value
+            .as_integer()
+            .and_then(|size| u64::try_from(size).ok())
+            .ok_or_else(|| invalid(key, "a number of bytes"))
+    };
+
+    let mut options = options.clone();
+    for (key, value) in &table {
+        match key.replace('_', "-").as_str() {
+            "exclude" => options.exclude.extend(patterns(key, value)?),
+            "exclude-dir" => options.exclude_dirs.extend(patterns(key, value)?),
+            "exclude-glob" => {
+                let globs = strings(key, value)?
+                    .iter()
+                    .map(|glob| {
+                        rupes::glob_pattern(glob).map_err(|error| {
+                            Error::new(
+                                ErrorKind::InvalidData,
+                                format!("{}: {}", path.display(), error),
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                options.exclude.extend(globs.iter().cloned());
+                options.exclude_dirs.extend(globs);
+            }
+            "exclude-dots" => {
+                options.exclude_dots |= value.as_bool().ok_or_else(|| invalid(key, "a boolean"))?
+            }
+            "min" => options.min = options.min.max(Some(size(key, value)?)),
+            "max" => {
+                let max = size(key, value)?;
+                options.max = Some(options.max.map_or(max, |given| given.min(max)));
+            }
+            "skip-marker" => options
+                .skip_markers
+                .extend(strings(key, value)?.into_iter().map(OsString::from)),
+            // Only matters to actions, see `protects`
+            _ => {}
+        }
+    }
+
+    Ok(options)
+}
+
+/// Whether a directory configuration at or above `path` protects it from actions, with `protect = true`
+///
+/// A configuration that can't be read protects its directory too, rather than risk acting on what it meant to keep.
+pub fn protects(path: &Path) -> bool {
+    path.ancestors().skip(1).any(|directory| {
+        let config = directory.join(DIRECTORY_CONFIG);
+        config.is_file()
+            && read_directory_table(&config).map_or(true, |table| {
+                table.get("protect").and_then(toml::Value::as_bool) == Some(true)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("rupes")
+            .arg(
+                Arg::new("recursive")
+                    .long("recursive")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("min").long("min"))
+            .arg(
+                Arg::new("exclude_dir")
+                    .long("exclude-dir")
+                    .action(ArgAction::Append),
+            )
+            .arg(Arg::new("profile").long("profile"))
+    }
+
+    #[test]
+    fn raw_values() {
+        let args: Vec<OsString> = [
+            "rupes",
+            "--profile",
+            "a",
+            "--profile=b",
+            "--",
+            "--profile",
+            "c",
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        assert_eq!(raw_value(&args, "profile").as_deref(), Some("b"));
+        assert_eq!(raw_value(&args, "config"), None);
+    }
+
+    #[test]
+    fn layered_defaults() {
+        let table: toml::Table =
+            "recursive = true\nmin = 10\nexclude_dir = ['node_modules', 'target']"
+                .parse()
+                .unwrap();
+        let profile: toml::Table = "min = 20".parse().unwrap();
+        let path = Path::new("config.toml");
+
+        let settings = Settings {
+            path: Some(path.to_path_buf()),
+            found: true,
+            profile: Some("big".to_string()),
+            layers: vec![
+                table_layer(&command(), &table, "config file".to_string(), path).unwrap(),
+                table_layer(&command(), &profile, "profile big".to_string(), path).unwrap(),
+            ],
+        };
+
+        let matches = apply(command(), &settings)
+            .try_get_matches_from(["rupes", "--exclude-dir", "cache"])
+            .unwrap();
+        assert!(matches.get_flag("recursive"));
+        assert_eq!(matches.get_one::<String>("min").unwrap(), "20");
+        let excluded: Vec<_> = matches.get_many::<String>("exclude_dir").unwrap().collect();
+        assert_eq!(excluded, ["cache"]);
+
+        let mut shown = Vec::new();
+        show(&mut shown, &command(), &matches, &settings).unwrap();
+        let shown = String::from_utf8(shown).unwrap();
+        assert!(shown.contains("# Profile: big"));
+        assert!(shown.contains("min = 20"));
+        assert!(shown
+            .lines()
+            .any(|line| line.starts_with("min = 20") && line.ends_with("# profile big")));
+        assert!(shown
+            .lines()
+            .any(|line| line.starts_with("recursive = true") && line.ends_with("# config file")));
+        assert!(shown
+            .lines()
+            .any(|line| line.starts_with("exclude-dir = [\"cache\"]")
+                && line.ends_with("# command line")));
+
+        let unknown: toml::Table = "recursve = true".parse().unwrap();
+        assert!(table_layer(&command(), &unknown, "config file".to_string(), path).is_err());
+    }
+
+    #[test]
+    fn starter_parses() {
+        let starter = starter(&command());
+        assert!(starter.contains("# recursive = false\n"));
+        assert!(starter.contains("# exclude-dir = []\n"));
+        assert_eq!(quoted("a\n\"b\"\\"), r#""a\n\"b\"\\""#);
+
+        let uncommented: String = starter
+            .lines()
+            .take_while(|line| !line.starts_with("# Profiles"))
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| line.contains(" = "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let table: toml::Table = uncommented.parse().unwrap();
+        assert!(table_layer(
+            &command(),
+            &table,
+            "config file".to_string(),
+            Path::new("config.toml")
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn directory_configs_only_tighten() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = dir.path().join(DIRECTORY_CONFIG);
+        let given = ScanOptions {
+            min: Some(10),
+            max: Some(1000),
+            exclude_dots: true,
+            ..ScanOptions::default()
+        };
+
+        fs::write(
+            &config,
+            "exclude = '\\.log$'\nexclude-dots = false\nmin = 5\nmax = 100\nskip-marker = ['.keep']\n",
+        )
+        .unwrap();
+        let options = read_directory_config(&config, &given).unwrap();
+        assert_eq!(options.exclude.len(), 1);
+        assert!(options.exclude_dots);
+        assert_eq!(options.min, Some(10));
+        assert_eq!(options.max, Some(100));
+        assert_eq!(options.skip_markers, [OsString::from(".keep")]);
+        assert!(!protects(&dir.path().join("file")));
+
+        fs::write(&config, "protect = true\n").unwrap();
+        assert!(protects(&dir.path().join("sub/file")));
+
+        fs::write(&config, "recursive = false\n").unwrap();
+        assert!(read_directory_config(&config, &given).is_err());
+        assert!(protects(&dir.path().join("file")));
+    }
+}
@@ -0,0 +1,210 @@
+//! PDFs and office documents compared by the text in them, since re-saving or re-exporting a document changes nearly
+//! every byte of the file but not what it says
+//!
+//! Text is read from PDFs with pdftotext and from the XML inside docx and odt files with unzip, or the programs named by
+//! the `RUPES_PDFTOTEXT` and `RUPES_UNZIP` environment variables.
+
+use std::io::Result;
+use std::path::Path;
+use std::process::Stdio;
+
+use rupes::{ContentComparator, Digest, HashComparator};
+
+use crate::tool::{PDFTOTEXT, UNZIP};
+
+/// How the text of a document is stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Pdf,
+    /// Word's zipped XML, the text is in word/document.xml
+    Docx,
+    /// OpenDocument's zipped XML, the text is in content.xml
+    Odt,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "pdf" => Some(Kind::Pdf),
+        "docx" => Some(Kind::Docx),
+        "odt" => Some(Kind::Odt),
+        _ => None,
+    }
+}
+
+/// Text of the XML of a document, without its markup, paragraphs, line breaks and tabs separated by spaces
+fn xml_text(xml: &str) -> String {
+    let mut text = String::with_capacity(xml.len() / 4);
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&unescaped(&rest[..start]));
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+
+        let name = rest[start + 1..start + end]
+            .trim_start_matches('/')
+            .split([' ', '/'])
+            .next()
+            .unwrap_or_default();
+        if matches!(
+            name,
+            "w:p"
+                | "w:br"
+                | "w:tab"
+                | "w:cr"
+                | "text:p"
+                | "text:h"
+                | "text:s"
+                | "text:tab"
+                | "text:line-break"
+        ) {
+            text.push(' ');
+        }
+        rest = &rest[start + end + 1..];
+    }
+
+    text
+}
+
+/// `text` with the XML entities in it replaced by the characters they stand for
+fn unescaped(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let character = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => name
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| name.strip_prefix('#').map(str::parse))
+                .and_then(|code| char::from_u32(code.ok()?)),
+        });
+
+        match (character, entity) {
+            (Some(character), Some((_, end))) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+
+    unescaped
+}
+
+/// The text of the document at `path`, with every run of whitespace turned into a single space, `None` if it can't be
+/// read from the file
+fn text(path: &Path, kind: Kind) -> Result<Option<String>> {
+    let output = match kind {
+        Kind::Pdf => {
+            PDFTOTEXT.output(|command| command.arg(path).arg("-").stderr(Stdio::null()))?
+        }
+        Kind::Docx | Kind::Odt => UNZIP.output(|command| {
+            command
+                .arg("-p")
+                .arg(path)
+                .arg(if kind == Kind::Docx {
+                    "word/document.xml"
+                } else {
+                    "content.xml"
+                })
+                .stderr(Stdio::null())
+        })?,
+    };
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let extracted = String::from_utf8_lossy(&output.stdout);
+    let text = match kind {
+        Kind::Pdf => extracted.into_owned(),
+        Kind::Docx | Kind::Odt => xml_text(&extracted),
+    };
+
+    Ok(Some(text.split_whitespace().collect::<Vec<_>>().join(" ")))
+}
+
+/// Compares PDF, docx and odt files by their text, and every other file like `comparator` does
+///
+/// The key of a document holds the length of its text rather than its size. Documents without any text, such as
+/// scanned pages, and those the tools can't read are compared like other files.
+#[derive(Clone, Debug, Default)]
+pub struct DocumentComparator {
+    pub comparator: HashComparator,
+}
+
+impl ContentComparator for DocumentComparator {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        let Some(kind) = kind(path) else {
+            return self.comparator.key(path, size);
+        };
+
+        let text = self
+            .comparator
+            .retry
+            .run(|| match &self.comparator.read_limit {
+                Some(limit) => limit.run(|| text(path, kind)),
+                None => text(path, kind),
+            })?;
+        match text.filter(|text| !text.is_empty()) {
+            Some(text) => Ok((
+                text.len() as u64,
+                self.comparator
+                    .algorithm
+                    .digest_reader(&mut text.as_bytes())?,
+            )),
+            None => self.comparator.key(path, size),
+        }
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_text() {
+        let docx = r#"<w:document><w:body><w:p><w:r><w:t>Quarterly</w:t></w:r><w:r><w:t xml:space="preserve"> rep</w:t></w:r><w:r><w:t>ort</w:t></w:r></w:p><w:p><w:r><w:t>Profit &amp; loss</w:t></w:r></w:p></w:body></w:document>"#;
+        let odt = r#"<office:text><text:h text:outline-level="1">Quarterly report</text:h><text:p>Profit &#38; loss</text:p></office:text>"#;
+
+        let normalized = |xml| {
+            xml_text(xml)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(normalized(docx), "Quarterly report Profit & loss");
+        assert_eq!(normalized(odt), normalized(docx));
+
+        assert_eq!(
+            unescaped("&lt;&#x41;&#66;&gt; &unknown; & done"),
+            "<AB> &unknown; & done"
+        );
+        assert_eq!(kind(Path::new("Report.PDF")), Some(Kind::Pdf));
+        assert_eq!(kind(Path::new("report.doc")), None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+use rupes::{walk, ExcludedDirectories, ScanOptions};
+
+/// Whether `directory` holds nothing but files in `removed` and other such directories
+///
+/// Hollow directories below it are collected into `hollow`, only the outermost of them unless `directory` is a root,
+/// which is never reported itself. Directories skipped by `options` count as content, since what they hold isn't
+/// known.
+fn is_hollow(
+    directory: &Path,
+    root: bool,
+    removed: &HashSet<PathBuf>,
+    options: &ScanOptions,
+    excluded: &ExcludedDirectories,
+    hollow: &mut Vec<PathBuf>,
+) -> Result<bool> {
+    let mut hollow_children = Vec::new();
+    let mut only_removed = true;
+
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        let walked = entry.file_type()?.is_dir()
+            && !walk::excludes_name(&name, options)
+            && walk::includes_directory(&name, options)
+            && !excluded.contains(&path)
+            && !walk::is_marked(&path, options);
+
+        if walked
+            && is_hollow(
+                &path,
+                false,
+                removed,
+                &*walk::directory_options(&path, options)?,
+                excluded,
+                hollow,
+            )?
+        {
+            hollow_children.push(path);
+        } else if walked || !removed.contains(&path) {
+            only_removed = false;
+        }
+    }
+
+    // Removing a hollow directory removes the hollow directories inside it too
+    if root || !only_removed {
+        hollow.extend(hollow_children);
+    }
+
+    Ok(only_removed)
+}
+
+/// Directories under `roots` that are empty, or would be once the files in `removed` are gone
+pub fn find(
+    roots: &[PathBuf],
+    removed: &HashSet<PathBuf>,
+    options: &ScanOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut hollow = Vec::new();
+    let excluded = ExcludedDirectories::new(&options.exclude_paths);
+
+    for root in roots {
+        is_hollow(root, true, removed, options, &excluded, &mut hollow)?;
+    }
+
+    hollow.sort();
+    Ok(hollow)
+}
@@ -0,0 +1,222 @@
+//! Commands run for every group of duplicates with --exec, such as `tag-duplicates {kept} {dupes...}`
+//!
+//! The command is split into words like a shell would, with single and double quotes, but isn't run by one: every
+//! placeholder becomes exactly one argument, or one per duplicate for `{dupes...}`, whatever characters the paths hold.
+
+use std::io::{Error, Result};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use crate::output;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Kept,
+    Size,
+    Waste,
+    Hash,
+    Id,
+    Group,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Word {
+    Pieces(Vec<Piece>),
+    /// `{dupes...}`, an argument for each duplicate
+    Dupes,
+}
+
+/// A command run for every group, with the placeholders `{kept}`, the path of the copy kept, `{dupes...}`, the paths of
+/// the others as arguments of their own, `{size}` and `{waste}` in bytes, `{hash}`, `{id}` and `{group}` as with
+/// --format-template
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupCommand {
+    words: Vec<Word>,
+}
+
+/// Split `command` into words on whitespace outside of quotes, dropping the quotes
+fn split_words(command: &str) -> std::result::Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+
+    for character in command.chars() {
+        match (quote, character) {
+            (Some(open), _) if character == open => quote = None,
+            (Some(_), _) => word.get_or_insert_with(String::new).push(character),
+            (None, '\'' | '"') => {
+                quote = Some(character);
+                word.get_or_insert_with(String::new);
+            }
+            (None, _) if character.is_whitespace() => words.extend(word.take()),
+            (None, _) => word.get_or_insert_with(String::new).push(character),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unterminated quote in '{}'", command));
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+impl FromStr for GroupCommand {
+    type Err = String;
+
+    fn from_str(command: &str) -> std::result::Result<Self, Self::Err> {
+        let mut words = Vec::new();
+
+        for word in split_words(command)? {
+            if word == "{dupes...}" {
+                words.push(Word::Dupes);
+                continue;
+            }
+
+            let mut pieces = Vec::new();
+            let mut rest = word.as_str();
+            while let Some(start) = rest.find('{') {
+                let end = rest[start..]
+                    .find('}')
+                    .map(|end| start + end)
+                    .ok_or_else(|| format!("unclosed placeholder in '{}'", word))?;
+                if start > 0 {
+                    pieces.push(Piece::Text(rest[..start].to_string()));
+                }
+                pieces.push(match &rest[start + 1..end] {
+                    "kept" => Piece::Kept,
+                    "size" => Piece::Size,
+                    "waste" => Piece::Waste,
+                    "hash" => Piece::Hash,
+                    "id" => Piece::Id,
+                    "group" => Piece::Group,
+                    "dupes..." => return Err("{dupes...} has to be an argument of its own".to_string()),
+                    name => {
+                        return Err(format!(
+                            "unknown placeholder {{{}}}, expected one of {{kept}}, {{dupes...}}, {{size}}, {{waste}}, {{hash}}, {{id}} or {{group}}",
+                            name
+                        ))
+                    }
+                });
+                rest = &rest[end + 1..];
+            }
+            if !rest.is_empty() {
+                pieces.push(Piece::Text(rest.to_string()));
+            }
+            words.push(Word::Pieces(pieces));
+        }
+
+        if !matches!(words.first(), Some(Word::Pieces(_))) {
+            return Err("the command to run has to come first".to_string());
+        }
+        Ok(GroupCommand { words })
+    }
+}
+
+/// Everything a [`GroupCommand`] is given about a group
+pub struct ExecGroup<'a> {
+    pub group: usize,
+    pub hash: &'a str,
+    pub size: u64,
+    pub waste: u64,
+    /// The copy kept first, then its duplicates
+    pub paths: &'a [PathBuf],
+}
+
+impl GroupCommand {
+    /// The program and arguments run for `group`
+    fn arguments(&self, group: &ExecGroup) -> Vec<std::ffi::OsString> {
+        let mut arguments = Vec::new();
+
+        for word in &self.words {
+            let pieces = match word {
+                Word::Dupes => {
+                    arguments.extend(group.paths[1..].iter().map(|path| path.into()));
+                    continue;
+                }
+                Word::Pieces(pieces) => pieces,
+            };
+
+            let mut argument = std::ffi::OsString::new();
+            for piece in pieces {
+                match piece {
+                    Piece::Text(text) => argument.push(text),
+                    Piece::Kept => argument.push(&group.paths[0]),
+                    Piece::Size => argument.push(group.size.to_string()),
+                    Piece::Waste => argument.push(group.waste.to_string()),
+                    Piece::Hash => argument.push(group.hash),
+                    Piece::Id => argument.push(output::group_id(group.hash)),
+                    Piece::Group => argument.push(group.group.to_string()),
+                }
+            }
+            arguments.push(argument);
+        }
+
+        arguments
+    }
+
+    /// Run the command for `group`, waiting for it to finish, an error when it can't be started or fails
+    pub fn run(&self, group: &ExecGroup) -> Result<()> {
+        let arguments = self.arguments(group);
+        let status = Command::new(&arguments[0])
+            .args(&arguments[1..])
+            .stdin(Stdio::null())
+            .status()
+            .map_err(|error| {
+                Error::new(
+                    error.kind(),
+                    format!("Couldn't run {}: {}", arguments[0].to_string_lossy(), error),
+                )
+            })?;
+
+        if !status.success() {
+            return Err(Error::other(format!(
+                "{} failed for group {}, {}",
+                arguments[0].to_string_lossy(),
+                group.group,
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_become_arguments() {
+        let command: GroupCommand = "tag --size={size} 'kept copy' {kept} {dupes...}"
+            .parse()
+            .unwrap();
+        let paths = [
+            PathBuf::from("/photos/a b.jpg"),
+            PathBuf::from("/x/a.jpg"),
+            PathBuf::from("/y/a.jpg"),
+        ];
+        let arguments = command.arguments(&ExecGroup {
+            group: 1,
+            hash: "abc",
+            size: 5,
+            waste: 10,
+            paths: &paths,
+        });
+
+        assert_eq!(
+            arguments,
+            [
+                "tag",
+                "--size=5",
+                "kept copy",
+                "/photos/a b.jpg",
+                "/x/a.jpg",
+                "/y/a.jpg"
+            ]
+        );
+        assert!("tag {dupes...},".parse::<GroupCommand>().is_err());
+        assert!("tag {kep}".parse::<GroupCommand>().is_err());
+        assert!("tag 'open".parse::<GroupCommand>().is_err());
+    }
+}
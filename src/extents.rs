@@ -0,0 +1,151 @@
+//! Detecting files that already share their storage
+//!
+//! Copy-on-write filesystems such as Btrfs and XFS can store the content of several files in the same extents, after a
+//! reflink copy or an offline deduplication pass. Those files are duplicates in name only, removing one reclaims
+//! nothing.
+
+use std::io::Result;
+use std::path::Path;
+
+use crate::walk::FileEntry;
+
+/// A contiguous piece of a file mapped to a location on disk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extent {
+    /// Offset of the extent within the file
+    pub logical: u64,
+    /// Offset of the extent on the device
+    pub physical: u64,
+    pub length: u64,
+}
+
+#[cfg(target_os = "linux")]
+mod fiemap {
+    use std::fs::File;
+    use std::io::{Error, Result};
+    use std::os::fd::AsRawFd;
+    use std::path::Path;
+
+    use super::Extent;
+
+    /// `_IOWR('f', 11, struct fiemap)`
+    const FS_IOC_FIEMAP: u32 = 0xC020_660B;
+    const FIEMAP_FLAG_SYNC: u32 = 0x1;
+
+    const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    /// Flags of extents whose physical location isn't meaningful, such as delayed allocations and inline data
+    const FIEMAP_EXTENT_UNRELIABLE: u32 = 0x2 | 0x4 | 0x8 | 0x80 | 0x100 | 0x200;
+
+    /// Extents requested per call
+    const BATCH: usize = 128;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct FiemapExtent {
+        fe_logical: u64,
+        fe_physical: u64,
+        fe_length: u64,
+        fe_reserved64: [u64; 2],
+        fe_flags: u32,
+        fe_reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    struct Fiemap {
+        fm_start: u64,
+        fm_length: u64,
+        fm_flags: u32,
+        fm_mapped_extents: u32,
+        fm_extent_count: u32,
+        fm_reserved: u32,
+        fm_extents: [FiemapExtent; BATCH],
+    }
+
+    pub fn extents(path: &Path) -> Result<Option<Vec<Extent>>> {
+        let file = File::open(path)?;
+        let mut extents = Vec::new();
+        let mut start = 0;
+
+        loop {
+            let mut map = Fiemap {
+                fm_start: start,
+                fm_length: u64::MAX - start,
+                fm_flags: FIEMAP_FLAG_SYNC,
+                fm_mapped_extents: 0,
+                fm_extent_count: BATCH as u32,
+                fm_reserved: 0,
+                fm_extents: [FiemapExtent::default(); BATCH],
+            };
+
+            // SAFETY: `map` is a valid fiemap with room for `fm_extent_count` extents
+            if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut map) } != 0 {
+                let error = Error::last_os_error();
+                return match error.raw_os_error() {
+                    Some(libc::EOPNOTSUPP) | Some(libc::ENOTTY) => Ok(None),
+                    _ => Err(error),
+                };
+            }
+
+            let mapped = &map.fm_extents[..map.fm_mapped_extents as usize];
+            for extent in mapped {
+                if extent.fe_flags & FIEMAP_EXTENT_UNRELIABLE != 0 {
+                    return Ok(None);
+                }
+
+                extents.push(Extent {
+                    logical: extent.fe_logical,
+                    physical: extent.fe_physical,
+                    length: extent.fe_length,
+                });
+            }
+
+            match mapped.last() {
+                Some(last) if last.fe_flags & FIEMAP_EXTENT_LAST == 0 => {
+                    start = last.fe_logical + last.fe_length;
+                }
+                _ => return Ok(Some(extents)),
+            }
+        }
+    }
+}
+
+/// The extents the content of the file at `path` is stored in, or `None` if the filesystem can't tell
+#[cfg(target_os = "linux")]
+pub fn extents(path: &Path) -> Result<Option<Vec<Extent>>> {
+    fiemap::extents(path)
+}
+
+/// The extents the content of the file at `path` is stored in, or `None` if the filesystem can't tell
+#[cfg(not(target_os = "linux"))]
+pub fn extents(_path: &Path) -> Result<Option<Vec<Extent>>> {
+    Ok(None)
+}
+
+/// The paths of one physical file, hardlinks to each other
+type Links = Vec<FileEntry>;
+
+/// Gather physical files, as returned by [`crate::group::by_physical_file`], whose content is stored in the very same
+/// extents
+///
+/// Hardlinks are the same physical file, so only distinct physical files are compared. Files whose extents are unknown
+/// or empty are never considered to share storage.
+pub fn by_shared_extents(physical: Vec<Links>) -> Vec<Vec<Links>> {
+    let mut shared: Vec<(Option<Vec<Extent>>, Vec<Links>)> = Vec::new();
+
+    for links in physical {
+        let extents = extents(&links[0].path)
+            .ok()
+            .flatten()
+            .filter(|extents| !extents.is_empty());
+
+        match shared
+            .iter_mut()
+            .find(|(known, _)| extents.is_some() && *known == extents)
+        {
+            Some((_, files)) => files.push(links),
+            None => shared.push((extents, vec![links])),
+        }
+    }
+
+    shared.into_iter().map(|(_, files)| files).collect()
+}
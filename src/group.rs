@@ -0,0 +1,1399 @@
+//! Grouping files with identical content
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::cache::HashCache;
+use crate::hash::{Digest, HashAlgorithm};
+use crate::limit::ReadLimit;
+use crate::retry::RetryPolicy;
+use crate::storage::StorageMap;
+use crate::text::{self, NormalizedText};
+use crate::walk::{FileEntry, FileId};
+use crate::xattr;
+
+/// A set of two or more files with identical content, sorted by path
+#[derive(Debug)]
+pub struct DuplicateGroup<K> {
+    /// The key all of the files share, as computed by the [`ContentComparator`]
+    pub key: K,
+    pub files: Vec<FileEntry>,
+}
+
+/// Decides whether two files have identical content
+///
+/// Files are considered identical when their keys are equal, so implementations can define equality however suits
+/// their domain: by hash, by a hash of normalized content, by decoded pixels, and so on.
+pub trait ContentComparator: Sync {
+    /// Value that is equal for two files exactly when their content is considered identical
+    type Key: Eq + Hash + Send;
+
+    /// Compute the key of the file at `path`, which is `size` bytes long
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key>;
+
+    /// How many files on the device `path` is stored on to compare at once, `None` to leave it to the thread pool
+    ///
+    /// Asked once per device with one of its files, so a spinning disk can be read a file at a time while an SSD next
+    /// to it is read on every thread.
+    fn read_depth(&self, _path: &Path) -> Option<usize> {
+        None
+    }
+
+    /// Whether files this comparator considers identical always have the same size, so [`find_duplicates`] can leave
+    /// out files whose size no other file has without computing their keys
+    fn same_size(&self) -> bool {
+        false
+    }
+
+    /// Whether the file at `path` only matches files starting with the same bytes and reading them is much cheaper than
+    /// its key, so [`find_duplicates`] can first compare the first [`PARTIAL_HASH`] bytes of files of the same size
+    ///
+    /// Only asked of comparators whose [`ContentComparator::same_size`] is true.
+    fn same_start(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Called with the error reading the file at `path`, returning an error fails the comparison while `Ok` leaves the
+    /// file out of it
+    ///
+    /// By default only files that vanished since they were found are left out, such as those deleted by another
+    /// process.
+    fn skip_unreadable(&self, _path: &Path, error: Error) -> Result<()> {
+        skip_vanished(error)
+    }
+}
+
+/// Leave out files that vanished since they were found, failing with any other `error`, the default of
+/// [`ContentComparator::skip_unreadable`]
+pub fn skip_vanished(error: Error) -> Result<()> {
+    if error.kind() == ErrorKind::NotFound {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// Bytes at the start of files of the same size [`find_duplicates`] compares before computing their keys, for
+/// comparators that allow it
+pub const PARTIAL_HASH: u64 = 4 * 1024;
+
+impl<K> DuplicateGroup<K> {
+    /// The files of this group gathered by the physical file behind them, see [`by_physical_file`]
+    pub fn physical_files(&self) -> Vec<Vec<FileEntry>> {
+        by_physical_file(self.files.clone())
+    }
+}
+
+/// The default comparator: files are identical when both their sizes and content hashes match
+#[derive(Clone, Debug, Default)]
+pub struct HashComparator {
+    pub algorithm: HashAlgorithm,
+    /// Files of at least this many bytes are hashed with [`HashAlgorithm::digest_file_parallel`]
+    pub parallel_threshold: Option<u64>,
+    /// How reads that fail with transient errors are retried
+    pub retry: RetryPolicy,
+    /// Shared with other comparators to cap how many files are read at once, reads are unlimited without one
+    pub read_limit: Option<Arc<ReadLimit>>,
+    /// Compare files a few at a time on the devices it says would be slowed down by more
+    pub storage: Option<Arc<StorageMap>>,
+    /// Reuse the hashes of files that haven't changed since they were cached, and cache every file hashed
+    pub cache: Option<Arc<HashCache>>,
+    /// Likewise with hashes kept in the extended attributes of each file, see [`xattr`](crate::xattr)
+    pub xattrs: bool,
+}
+
+impl ContentComparator for HashComparator {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        if self.cache.is_none() && !self.xattrs {
+            return self.hash(path, size);
+        }
+
+        // Taken before reading, so a file changed while it's hashed is hashed again next time
+        let metadata = fs::metadata(path)?;
+        if let Some(hash) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get(path, self.algorithm, &metadata))
+        {
+            return Ok((size, hash));
+        }
+        let kept = self
+            .xattrs
+            .then(|| xattr::read(path, self.algorithm, &metadata))
+            .flatten();
+
+        let (size, hash) = match kept {
+            Some(hash) => (size, hash),
+            None => {
+                let key = self.hash(path, size)?;
+                if self.xattrs {
+                    // Files that can't be written to are hashed again next time
+                    let _ = xattr::write(path, self.algorithm, &metadata, &key.1);
+                }
+                key
+            }
+        };
+        if let Some(cache) = &self.cache {
+            cache.insert(path, self.algorithm, &metadata, hash.clone());
+        }
+        Ok((size, hash))
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.storage.as_ref()?.read_depth(path)
+    }
+
+    fn same_size(&self) -> bool {
+        true
+    }
+
+    /// A cached hash is found without reading the file at all
+    fn same_start(&self, _path: &Path) -> bool {
+        self.cache.is_none() && !self.xattrs
+    }
+}
+
+impl HashComparator {
+    fn hash(&self, path: &Path, size: u64) -> Result<(u64, Digest)> {
+        let parallel = self
+            .parallel_threshold
+            .is_some_and(|threshold| size >= threshold);
+
+        let hash = self.retry.run(|| match (&self.read_limit, parallel) {
+            (None, true) => self.algorithm.digest_file_parallel(path),
+            (None, false) => self.algorithm.digest_file(path),
+            // The file is mapped into memory and read by every thread, it takes one slot for all of it
+            (Some(limit), true) => limit.run(|| self.algorithm.digest_file_parallel(path)),
+            (Some(limit), false) => self
+                .algorithm
+                .digest_reader(&mut limit.reader(File::open(path)?)),
+        })?;
+
+        Ok((size, hash))
+    }
+}
+
+/// A fast, probabilistic comparator: files are identical when their sizes and the hashes of their first and last
+/// `sample` bytes match
+///
+/// Only reads a small part of each file, so it can't detect differences in the middle of files larger than twice the
+/// sample. Matches should be confirmed, for example with [`confirm_duplicates`] and a [`HashComparator`], before
+/// anything is done with them.
+#[derive(Clone, Debug)]
+pub struct QuickComparator {
+    pub algorithm: HashAlgorithm,
+    pub sample: u64,
+    /// How reads that fail with transient errors are retried
+    pub retry: RetryPolicy,
+    /// Shared with other comparators to cap how many files are read at once, the samples of a file taking one slot
+    pub read_limit: Option<Arc<ReadLimit>>,
+    /// Compare files a few at a time on the devices it says would be slowed down by more
+    pub storage: Option<Arc<StorageMap>>,
+}
+
+impl Default for QuickComparator {
+    fn default() -> Self {
+        QuickComparator {
+            algorithm: HashAlgorithm::default(),
+            sample: 64 * 1024,
+            retry: RetryPolicy::default(),
+            read_limit: None,
+            storage: None,
+        }
+    }
+}
+
+impl ContentComparator for QuickComparator {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        self.retry.run(|| match &self.read_limit {
+            Some(limit) => limit.run(|| self.sampled_key(path, size)),
+            None => self.sampled_key(path, size),
+        })
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.storage.as_ref()?.read_depth(path)
+    }
+
+    fn same_size(&self) -> bool {
+        true
+    }
+}
+
+impl QuickComparator {
+    fn sampled_key(&self, path: &Path, size: u64) -> Result<(u64, Digest)> {
+        let mut file = File::open(path)?;
+
+        // Small files are read in full, so their hashes are exact
+        if size <= self.sample * 2 {
+            return Ok((size, self.algorithm.digest_reader(&mut file)?));
+        }
+
+        let mut head = Vec::new();
+        file.by_ref().take(self.sample).read_to_end(&mut head)?;
+
+        let mut tail = Vec::new();
+        file.seek(SeekFrom::End(-(self.sample as i64)))?;
+        file.take(self.sample).read_to_end(&mut tail)?;
+
+        let hash = self
+            .algorithm
+            .digest_reader(&mut head.as_slice().chain(tail.as_slice()))?;
+        Ok((size, hash))
+    }
+}
+
+/// Uses hashes computed elsewhere, such as on another machine, for the paths it knows and hashes every other file
+///
+/// The precomputed hashes must have been made with the same algorithm as `comparator` for keys to match.
+#[derive(Debug, Default)]
+pub struct PrecomputedComparator {
+    pub comparator: HashComparator,
+    pub hashes: HashMap<PathBuf, Digest>,
+}
+
+impl ContentComparator for PrecomputedComparator {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        match self.hashes.get(path) {
+            Some(hash) => Ok((size, hash.clone())),
+            None => self.comparator.key(path, size),
+        }
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+
+    fn same_size(&self) -> bool {
+        self.comparator.same_size()
+    }
+
+    /// Files known by their hash may not be readable here at all
+    fn same_start(&self, path: &Path) -> bool {
+        !self.hashes.contains_key(path) && self.comparator.same_start(path)
+    }
+}
+
+/// Compares text files by their content with line endings, byte order marks and optionally trailing whitespace
+/// normalized, see [`NormalizedText`], and every other file like `comparator` does
+///
+/// The key of a text file holds the length of its normalized content rather than its size, so a copy saved with CRLF
+/// line endings matches the original saved with LF ones. Files are taken for text when their first
+/// [`TEXT_SNIFF`] bytes have no NUL in them.
+#[derive(Clone, Debug, Default)]
+pub struct TextComparator {
+    pub comparator: HashComparator,
+    /// Also drop the spaces and tabs ending each line
+    pub trailing_whitespace: bool,
+}
+
+/// How many bytes of a file are looked at to tell whether it's text
+pub const TEXT_SNIFF: usize = 8 * 1024;
+
+impl ContentComparator for TextComparator {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        let read = || {
+            let mut reader = BufReader::with_capacity(TEXT_SNIFF, File::open(path)?);
+            if !text::is_text(reader.fill_buf()?) {
+                return Ok(None);
+            }
+            let mut normalized = NormalizedText::new(reader, self.trailing_whitespace);
+            let hash = self.comparator.algorithm.digest_reader(&mut normalized)?;
+            Ok(Some((normalized.length(), hash)))
+        };
+
+        let key = self
+            .comparator
+            .retry
+            .run(|| match &self.comparator.read_limit {
+                Some(limit) => limit.run(read),
+                None => read(),
+            })?;
+        match key {
+            Some(key) => Ok(key),
+            None => self.comparator.key(path, size),
+        }
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+}
+
+/// Gather `files` that are hardlinks to the same physical file, keeping the order they were found in
+///
+/// Files without a [`FileId`] are assumed to be distinct physical files.
+pub fn by_physical_file(files: Vec<FileEntry>) -> Vec<Vec<FileEntry>> {
+    let mut physical: Vec<Vec<FileEntry>> = Vec::new();
+    let mut index_of: HashMap<FileId, usize> = HashMap::new();
+
+    for file in files {
+        match file.id {
+            Some(id) => match index_of.get(&id) {
+                Some(&index) => physical[index].push(file),
+                None => {
+                    index_of.insert(id, physical.len());
+                    physical.push(vec![file]);
+                }
+            },
+            None => physical.push(vec![file]),
+        }
+    }
+
+    physical
+}
+
+/// Gather physical files by the device they are stored on, keeping the order they were found in
+///
+/// Files without a [`FileId`] are all gathered together, as if they were on one device.
+fn by_device(physical: Vec<Vec<FileEntry>>) -> Vec<(Option<u64>, VecDeque<Vec<FileEntry>>)> {
+    let mut devices: Vec<(Option<u64>, VecDeque<Vec<FileEntry>>)> = Vec::new();
+
+    for links in physical {
+        let device = links[0].id.map(|id| id.device);
+        match devices.iter_mut().find(|(known, _)| *known == device) {
+            Some((_, files)) => files.push_back(links),
+            None => devices.push((device, VecDeque::from([links]))),
+        }
+    }
+
+    devices
+}
+
+/// CRC-32C of the first [`PARTIAL_HASH`] bytes of the physical file behind `links`, read through the first link still
+/// there, `None` when every one of them is gone
+fn partial_hash(links: &[FileEntry]) -> Result<Option<u32>> {
+    for link in links {
+        let mut start = Vec::with_capacity(PARTIAL_HASH as usize);
+        match File::open(&link.path)
+            .and_then(|file| file.take(PARTIAL_HASH).read_to_end(&mut start))
+        {
+            Ok(_) => return Ok(Some(crc32c::crc32c(&start))),
+            Err(error) if error.kind() == ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(None)
+}
+
+/// Split physical files of a single size by the start of their content, see [`ContentComparator::same_start`],
+/// dropping and passing to `progress` those no other file starts like
+fn by_start(
+    same_size: Vec<Vec<FileEntry>>,
+    progress: &(impl Fn(&FileEntry) + Sync),
+) -> Vec<Vec<Vec<FileEntry>>> {
+    let hashed: Vec<(Result<Option<u32>>, Vec<FileEntry>)> = same_size
+        .into_par_iter()
+        .map(|links| (partial_hash(&links), links))
+        .collect();
+
+    // Files that couldn't be read are left for their keys, retried and reported as the comparator does, and could
+    // match any of the others
+    if hashed.iter().any(|(start, _)| start.is_err()) {
+        return vec![hashed.into_iter().map(|(_, links)| links).collect()];
+    }
+
+    let mut starts: HashMap<u32, Vec<Vec<FileEntry>>> = HashMap::new();
+    for (start, links) in hashed {
+        match start {
+            Ok(Some(start)) => starts.entry(start).or_default().push(links),
+            _ => links.iter().for_each(progress),
+        }
+    }
+    starts
+        .into_values()
+        .filter(|same| {
+            let unmatched = same.iter().map(Vec::len).sum::<usize>() < 2;
+            if unmatched {
+                same.iter().flatten().for_each(progress);
+            }
+            !unmatched
+        })
+        .collect()
+}
+
+/// The physical files of `files` that may have duplicates by `comparator`, leaving out and passing to `progress` those
+/// whose size no other file has, and for comparators that allow it those whose first [`PARTIAL_HASH`] bytes no other
+/// file of their size starts with
+///
+/// Hardlinks to the same file are kept together, and count as files of the same size.
+fn candidates<C: ContentComparator>(
+    files: Vec<FileEntry>,
+    comparator: &C,
+    progress: &(impl Fn(&FileEntry) + Sync),
+) -> Vec<Vec<FileEntry>> {
+    let physical = by_physical_file(files);
+    if !comparator.same_size() {
+        return physical;
+    }
+
+    let mut by_size: HashMap<u64, Vec<Vec<FileEntry>>> = HashMap::new();
+    for links in physical {
+        by_size.entry(links[0].size).or_default().push(links);
+    }
+
+    let (alone, shared): (Vec<_>, Vec<_>) = by_size
+        .into_iter()
+        .partition(|(_, same)| same.iter().map(Vec::len).sum::<usize>() < 2);
+    alone
+        .iter()
+        .flat_map(|(_, same)| same.iter().flatten())
+        .for_each(progress);
+
+    shared
+        .into_par_iter()
+        .flat_map_iter(|(size, same)| {
+            let by_content = size > PARTIAL_HASH
+                && same.len() >= 2
+                && same
+                    .iter()
+                    .all(|links| comparator.same_start(&links[0].path));
+            if by_content {
+                by_start(same, progress)
+            } else {
+                vec![same]
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+/// Reorder physical files so consecutive ones alternate between the devices they are stored on
+///
+/// Threads hash contiguous runs of files, interleaving them keeps every disk of a multi-device scan busy rather than
+/// working through one device at a time. The order of files on each device is kept.
+fn interleave_by_device(
+    mut devices: Vec<(Option<u64>, VecDeque<Vec<FileEntry>>)>,
+) -> Vec<Vec<FileEntry>> {
+    let mut interleaved = Vec::new();
+    while !devices.is_empty() {
+        for (_, files) in devices.iter_mut() {
+            interleaved.extend(files.pop_front());
+        }
+        devices.retain(|(_, files)| !files.is_empty());
+    }
+
+    interleaved
+}
+
+/// Group `files` whose content `comparator` considers identical, calling `progress` as each file is processed
+///
+/// Only groups of two or more files are returned, in no particular order. Hardlinks to the same physical file are only
+/// read once, every one of their paths is attributed the key computed for it. Files that no longer exist, removed by
+/// another process since they were found, are left out, `progress` is still called for them so totals add up.
+///
+/// When the comparator limits the [read depth](ContentComparator::read_depth) of a device, its files are compared
+/// from a queue of their own by that many threads, leaving the other threads to the other devices.
+pub fn find_duplicates<C: ContentComparator>(
+    files: Vec<FileEntry>,
+    comparator: &C,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<DuplicateGroup<C::Key>>> {
+    let files_by_key: Mutex<HashMap<C::Key, Vec<FileEntry>>> = Mutex::default();
+
+    let compare = |mut links: Vec<FileEntry>| {
+        let key = loop {
+            let Some(first) = links.first() else {
+                return Ok(());
+            };
+
+            match comparator.key(&first.path, first.size) {
+                Ok(key) => break key,
+                // Another link to the same file may still be there
+                Err(error) => {
+                    comparator.skip_unreadable(&first.path, error)?;
+                    progress(&links.remove(0))
+                }
+            }
+        };
+
+        links.iter().for_each(&progress);
+
+        files_by_key
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .extend(links);
+
+        Ok::<(), Error>(())
+    };
+
+    let devices = by_device(candidates(files, comparator, &progress));
+    let depths: Vec<Option<usize>> = devices
+        .iter()
+        .map(|(_, files)| comparator.read_depth(&files[0][0].path))
+        .collect();
+
+    if depths.iter().all(Option::is_none) {
+        interleave_by_device(devices)
+            .into_par_iter()
+            .try_for_each(compare)?;
+    } else {
+        let failure: Mutex<Option<Error>> = Mutex::default();
+
+        rayon::scope(|scope| {
+            for ((_, files), depth) in devices.into_iter().zip(depths) {
+                let readers = depth
+                    .unwrap_or_else(rayon::current_num_threads)
+                    .clamp(1, files.len());
+                let queue = Arc::new(Mutex::new(files));
+
+                for _ in 0..readers {
+                    let queue = queue.clone();
+                    let (compare, failure) = (&compare, &failure);
+
+                    scope.spawn(move |_| loop {
+                        if failure.lock().unwrap().is_some() {
+                            return;
+                        }
+                        let Some(links) = queue.lock().unwrap().pop_front() else {
+                            return;
+                        };
+
+                        if let Err(error) = compare(links) {
+                            failure.lock().unwrap().get_or_insert(error);
+                            return;
+                        }
+                    });
+                }
+            }
+        });
+
+        if let Some(error) = failure.into_inner().unwrap() {
+            return Err(error);
+        }
+    }
+
+    let groups = files_by_key
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .filter(|(_, files)| files.len() >= 2)
+        .map(|(key, mut files)| {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            DuplicateGroup { key, files }
+        })
+        .collect();
+
+    Ok(groups)
+}
+
+/// Split `groups` further by a second `comparator`, keeping only the files it also considers identical
+///
+/// Useful for confirming matches found by one comparator with another, such as an independent hash. Groups left with
+/// fewer than two files are dropped.
+pub fn confirm_duplicates<K: Clone + Send, C: ContentComparator>(
+    groups: Vec<DuplicateGroup<K>>,
+    comparator: &C,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<DuplicateGroup<K>>> {
+    let mut confirmed = Vec::new();
+
+    for group in groups {
+        for split in find_duplicates(group.files, comparator, &progress)? {
+            confirmed.push(DuplicateGroup {
+                key: group.key.clone(),
+                files: split.files,
+            });
+        }
+    }
+
+    Ok(confirmed)
+}
+
+/// Bytes of every file [`compare_bytes`] reads and compares at a time
+const COMPARE_BLOCK: usize = 64 * 1024;
+
+/// Most files of one size [`compare_bytes`] keeps open between blocks, those of larger sizes are opened again for each
+const MAX_OPEN_FILES: usize = 64;
+
+/// A physical file compared by [`compare_bytes`], with the block of it read last
+struct Compared {
+    links: Vec<FileEntry>,
+    file: Option<File>,
+    block: Vec<u8>,
+}
+
+impl Compared {
+    /// Read the `len` bytes at `offset`, `false` when the file is gone, has become shorter since it was found, or is
+    /// left out by `skip`
+    fn read_block(
+        &mut self,
+        offset: u64,
+        len: usize,
+        keep_open: bool,
+        retry: &RetryPolicy,
+        skip: &(impl Fn(&Path, Error) -> Result<()> + Sync),
+        progress: &(impl Fn(&FileEntry) + Sync),
+    ) -> Result<bool> {
+        loop {
+            let Some(first) = self.links.first() else {
+                return Ok(false);
+            };
+
+            let read = retry.run(|| {
+                let mut file = match self.file.take() {
+                    Some(file) => file,
+                    None => {
+                        let mut file = File::open(&first.path)?;
+                        file.seek(SeekFrom::Start(offset))?;
+                        file
+                    }
+                };
+                self.block.resize(len, 0);
+                file.read_exact(&mut self.block)?;
+                if keep_open {
+                    self.file = Some(file);
+                }
+                Ok(())
+            });
+
+            match read {
+                Ok(()) => return Ok(true),
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => {
+                    self.links.drain(..).for_each(|file| progress(&file));
+                }
+                // Another link to the same file may still be there
+                Err(error) => {
+                    skip(&first.path, error)?;
+                    progress(&self.links.remove(0))
+                }
+            }
+        }
+    }
+}
+
+/// Split the physical files of `size` bytes in `physical` into those with identical content, reading them a block at
+/// a time and leaving out each file as soon as it differs from all the others
+fn compare_size(
+    size: u64,
+    physical: Vec<Vec<FileEntry>>,
+    retry: &RetryPolicy,
+    skip: &(impl Fn(&Path, Error) -> Result<()> + Sync),
+    progress: &(impl Fn(&FileEntry) + Sync),
+) -> Result<Vec<DuplicateGroup<u64>>> {
+    let keep_open = physical.len() <= MAX_OPEN_FILES;
+    let mut classes: Vec<Vec<Compared>> = vec![physical
+        .into_iter()
+        .map(|links| Compared {
+            links,
+            file: None,
+            block: Vec::new(),
+        })
+        .collect()];
+    let mut identical = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        classes.retain(|class| {
+            let duplicated = class.iter().map(|file| file.links.len()).sum::<usize>() >= 2;
+            if !duplicated {
+                class.iter().flat_map(|file| &file.links).for_each(progress);
+            }
+            duplicated
+        });
+        if classes.is_empty() || offset >= size {
+            break;
+        }
+
+        let len = (size - offset).min(COMPARE_BLOCK as u64) as usize;
+        let mut split = Vec::new();
+        for mut class in classes {
+            // Hardlinks to a single file, there's nothing to compare them with
+            if class.len() == 1 {
+                identical.push(class);
+                continue;
+            }
+
+            let read: Vec<bool> = class
+                .par_iter_mut()
+                .map(|file| file.read_block(offset, len, keep_open, retry, skip, progress))
+                .collect::<Result<_>>()?;
+
+            let mut parts: Vec<Vec<Compared>> = Vec::new();
+            for (file, read) in class.into_iter().zip(read) {
+                if !read {
+                    continue;
+                }
+                match parts.iter_mut().find(|part| part[0].block == file.block) {
+                    Some(part) => part.push(file),
+                    None => parts.push(vec![file]),
+                }
+            }
+            split.extend(parts);
+        }
+
+        classes = split;
+        offset += len as u64;
+    }
+
+    identical.extend(classes);
+    Ok(identical
+        .into_iter()
+        .map(|class| {
+            let mut files: Vec<FileEntry> = class.into_iter().flat_map(|file| file.links).collect();
+            files.iter().for_each(progress);
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            DuplicateGroup { key: size, files }
+        })
+        .collect())
+}
+
+/// Group `files` with identical content by comparing their bytes directly, without hashing them, calling `progress` as
+/// each file is processed
+///
+/// Files of the same size are read side by side a block at a time, each one left out as soon as it differs from the
+/// others, so differing files usually cost a single block. Cheaper than hashing when files of a size are few, and no
+/// digest of any file is ever computed. Groups are keyed by their size, and otherwise returned as by
+/// [`find_duplicates`].
+pub fn compare_bytes(
+    files: Vec<FileEntry>,
+    retry: &RetryPolicy,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<DuplicateGroup<u64>>> {
+    compare_bytes_skipping(files, retry, |_, error| skip_vanished(error), progress)
+}
+
+/// Like [`compare_bytes`], passing the error reading a file to `skip`, which leaves the file out by returning `Ok`, as
+/// [`ContentComparator::skip_unreadable`] does for keys
+pub fn compare_bytes_skipping(
+    files: Vec<FileEntry>,
+    retry: &RetryPolicy,
+    skip: impl Fn(&Path, Error) -> Result<()> + Sync,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<DuplicateGroup<u64>>> {
+    let mut by_size: HashMap<u64, Vec<Vec<FileEntry>>> = HashMap::new();
+    for links in by_physical_file(files) {
+        by_size.entry(links[0].size).or_default().push(links);
+    }
+
+    let groups: Vec<Vec<DuplicateGroup<u64>>> = by_size
+        .into_par_iter()
+        .map(|(size, physical)| compare_size(size, physical, retry, &skip, &progress))
+        .collect::<Result<_>>()?;
+
+    Ok(groups.into_iter().flatten().collect())
+}
+
+/// Number of groups [`DuplicateFinder::run_streaming`] holds before waiting for the consumer to catch up
+const STREAM_CAPACITY: usize = 64;
+
+/// Called by [`DuplicateFinder`] as each file is processed
+type FileProgress = Arc<dyn Fn(&FileEntry) + Send + Sync>;
+
+/// Finds duplicates among a set of files, either all at once or streaming each group as soon as it is confirmed
+///
+/// Work runs on the global rayon thread pool unless another is given with [`DuplicateFinder::thread_pool`] or
+/// [`DuplicateFinder::max_parallelism`].
+pub struct DuplicateFinder<C> {
+    files: Vec<FileEntry>,
+    comparator: C,
+    pool: Option<Arc<ThreadPool>>,
+    progress: Option<FileProgress>,
+}
+
+impl<C: ContentComparator + Send + Sync + 'static> DuplicateFinder<C>
+where
+    C::Key: 'static,
+{
+    pub fn new(files: Vec<FileEntry>, comparator: C) -> Self {
+        DuplicateFinder {
+            files,
+            comparator,
+            pool: None,
+            progress: None,
+        }
+    }
+
+    /// Call `progress` as each file is processed, from whichever thread processed it
+    pub fn progress(mut self, progress: impl Fn(&FileEntry) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Compare files on `pool`, so embedding applications can share one pool between rupes and their own work
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Compare files on a thread pool of its own with at most `threads` threads
+    pub fn max_parallelism(self, threads: usize) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(Error::other)?;
+
+        Ok(self.thread_pool(Arc::new(pool)))
+    }
+
+    /// Find every group at once, like [`find_duplicates`]
+    pub fn run(self) -> Result<Vec<DuplicateGroup<C::Key>>> {
+        let progress = self.progress.unwrap_or_else(|| Arc::new(|_| {}));
+        let find = || find_duplicates(self.files, &self.comparator, |file| progress(file));
+        match &self.pool {
+            Some(pool) => pool.install(find),
+            None => find(),
+        }
+    }
+
+    /// Yield each group as soon as every file of its size has been compared, in no particular order
+    ///
+    /// Files are compared on the pool given with [`DuplicateFinder::thread_pool`], or the global one, from a background
+    /// thread, which pauses while the consumer is more than a few groups behind so memory use stays bounded. Since only
+    /// files of the same size are compared to each other, this suits comparators that never consider files of
+    /// different sizes identical. Dropping the iterator stops the search, the first error ends it.
+    pub fn run_streaming(self) -> impl Iterator<Item = Result<DuplicateGroup<C::Key>>> {
+        let (sender, receiver) = mpsc::sync_channel(STREAM_CAPACITY);
+
+        thread::spawn(move || {
+            let progress = self.progress.unwrap_or_else(|| Arc::new(|_| {}));
+            let mut by_size: HashMap<u64, Vec<FileEntry>> = HashMap::new();
+            for file in self.files {
+                by_size.entry(file.size).or_default().push(file);
+            }
+
+            let search = || {
+                by_size
+                    .into_values()
+                    .filter(|files| {
+                        if files.len() < 2 {
+                            files.iter().for_each(|file| progress(file));
+                        }
+                        files.len() >= 2
+                    })
+                    .par_bridge()
+                    .try_for_each(|files| {
+                        for group in
+                            find_duplicates(files, &self.comparator, |file| progress(file))?
+                        {
+                            // The consumer has gone away, there's no one left to tell
+                            sender
+                                .send(Ok(group))
+                                .map_err(|_| Error::from(ErrorKind::BrokenPipe))?;
+                        }
+
+                        Ok::<(), Error>(())
+                    })
+            };
+
+            let result = match &self.pool {
+                Some(pool) => pool.install(search),
+                None => search(),
+            };
+
+            if let Err(error) = result {
+                let _ = sender.send(Err(error));
+            }
+        });
+
+        receiver.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Keys files by the part of their name before the first '-', without reading them
+    struct NamePrefix;
+
+    impl ContentComparator for NamePrefix {
+        type Key = String;
+
+        fn key(&self, path: &Path, _size: u64) -> Result<Self::Key> {
+            let name = path.file_name().unwrap().to_string_lossy();
+            Ok(name.split('-').next().unwrap().to_string())
+        }
+    }
+
+    /// Keys files by the last character of their name, without reading them
+    struct LastCharacter;
+
+    impl ContentComparator for LastCharacter {
+        type Key = char;
+
+        fn key(&self, path: &Path, _size: u64) -> Result<Self::Key> {
+            Ok(path.to_string_lossy().chars().last().unwrap())
+        }
+    }
+
+    /// Records the paths another comparator was asked to key
+    struct Recording<'a, C> {
+        inner: C,
+        keyed: &'a Mutex<Vec<String>>,
+    }
+
+    impl<C: ContentComparator> ContentComparator for Recording<'_, C> {
+        type Key = C::Key;
+
+        fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+            self.keyed.lock().unwrap().push(path.display().to_string());
+            self.inner.key(path, size)
+        }
+
+        fn same_size(&self) -> bool {
+            self.inner.same_size()
+        }
+
+        fn same_start(&self, path: &Path) -> bool {
+            self.inner.same_start(path)
+        }
+    }
+
+    fn files(names: &[&str]) -> Vec<FileEntry> {
+        names
+            .iter()
+            .map(|name| FileEntry {
+                path: PathBuf::from(name),
+                size: 1,
+                id: None,
+            })
+            .collect()
+    }
+
+    fn names<K>(group: &DuplicateGroup<K>) -> Vec<String> {
+        group
+            .files
+            .iter()
+            .map(|file| file.path.display().to_string())
+            .collect()
+    }
+
+    /// Keys files like [`NamePrefix`], except those named gone-..., which have been removed
+    struct Removed;
+
+    impl ContentComparator for Removed {
+        type Key = String;
+
+        fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+            if path.to_string_lossy().starts_with("gone") {
+                return Err(Error::from(ErrorKind::NotFound));
+            }
+            NamePrefix.key(path, size)
+        }
+    }
+
+    #[test]
+    fn drops_removed_files() {
+        let processed = Mutex::new(0);
+        let groups = find_duplicates(files(&["a-1", "gone-1", "a-2", "gone-2"]), &Removed, |_| {
+            *processed.lock().unwrap() += 1
+        })
+        .unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(names(&groups[0]), ["a-1", "a-2"]);
+        assert_eq!(*processed.lock().unwrap(), 4);
+    }
+
+    /// Keys files like [`NamePrefix`], except those named locked-..., which can't be read, and records those it skips
+    #[derive(Default)]
+    struct Locked {
+        skip: bool,
+        skipped: Mutex<Vec<PathBuf>>,
+    }
+
+    impl ContentComparator for Locked {
+        type Key = String;
+
+        fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+            if path.to_string_lossy().starts_with("locked") {
+                return Err(Error::from(ErrorKind::PermissionDenied));
+            }
+            NamePrefix.key(path, size)
+        }
+
+        fn skip_unreadable(&self, path: &Path, error: Error) -> Result<()> {
+            if !self.skip {
+                return Err(error);
+            }
+            self.skipped.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unreadable_files_skipped_when_asked() {
+        let listed = || files(&["a-1", "locked-1", "a-2"]);
+        assert_eq!(
+            find_duplicates(listed(), &Locked::default(), |_| {})
+                .unwrap_err()
+                .kind(),
+            ErrorKind::PermissionDenied
+        );
+
+        let skipping = Locked {
+            skip: true,
+            ..Locked::default()
+        };
+        let groups = find_duplicates(listed(), &skipping, |_| {}).unwrap();
+        assert_eq!(names(&groups[0]), ["a-1", "a-2"]);
+        assert_eq!(
+            *skipping.skipped.lock().unwrap(),
+            [PathBuf::from("locked-1")]
+        );
+    }
+
+    #[test]
+    fn groups_by_key_and_drops_singletons() {
+        let mut groups = find_duplicates(
+            files(&["b-2", "a-1", "b-1", "c-1", "a-2", "a-3"]),
+            &NamePrefix,
+            |_| {},
+        )
+        .unwrap();
+        groups.sort_by(|a, b| a.key.cmp(&b.key));
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(names(&groups[0]), ["a-1", "a-2", "a-3"]);
+        assert_eq!(names(&groups[1]), ["b-1", "b-2"]);
+    }
+
+    #[test]
+    fn progress_called_for_every_file() {
+        let count = Mutex::new(0);
+        find_duplicates(files(&["a-1", "a-2", "b-1"]), &NamePrefix, |_| {
+            *count.lock().unwrap() += 1
+        })
+        .unwrap();
+
+        assert_eq!(count.into_inner().unwrap(), 3);
+    }
+
+    #[test]
+    fn keys_only_files_sharing_size_and_start() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let large = |start: u8| {
+            let mut content = vec![start; PARTIAL_HASH as usize];
+            content.extend(b"end");
+            content
+        };
+        let contents = [
+            ("unique-size", b"unique".to_vec()),
+            ("small-1", b"abc".to_vec()),
+            ("small-2", b"abd".to_vec()),
+            ("large-1", large(b'a')),
+            ("large-2", large(b'a')),
+            ("other-start", large(b'b')),
+        ];
+        let files: Vec<FileEntry> = contents
+            .iter()
+            .map(|(name, content)| {
+                let path = dir.path().join(name);
+                fs::write(&path, content).unwrap();
+                FileEntry {
+                    path,
+                    size: content.len() as u64,
+                    id: None,
+                }
+            })
+            .collect();
+
+        let keyed = Mutex::new(Vec::new());
+        let progressed = Mutex::new(0);
+        let groups = find_duplicates(
+            files,
+            &Recording {
+                inner: HashComparator::default(),
+                keyed: &keyed,
+            },
+            |_| *progressed.lock().unwrap() += 1,
+        )
+        .unwrap();
+
+        let mut keyed: Vec<String> = keyed
+            .into_inner()
+            .unwrap()
+            .iter()
+            .map(|path| {
+                Path::new(path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        keyed.sort();
+        // Small files are too short for their start to tell anything their hash doesn't
+        assert_eq!(keyed, ["large-1", "large-2", "small-1", "small-2"]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 2);
+        assert_eq!(progressed.into_inner().unwrap(), 6);
+    }
+
+    #[test]
+    fn hardlinks_keyed_once() {
+        let mut files = files(&["a-1", "a-2", "a-3", "b-1"]);
+        let id = FileId {
+            device: 1,
+            inode: 2,
+        };
+        files[0].id = Some(id);
+        files[2].id = Some(id);
+
+        let keyed = Mutex::new(Vec::new());
+        let progressed = Mutex::new(0);
+        let groups = find_duplicates(
+            files,
+            &Recording {
+                inner: NamePrefix,
+                keyed: &keyed,
+            },
+            |_| *progressed.lock().unwrap() += 1,
+        )
+        .unwrap();
+
+        let mut keyed = keyed.into_inner().unwrap();
+        keyed.sort();
+        assert_eq!(keyed, ["a-1", "a-2", "b-1"]);
+        assert_eq!(progressed.into_inner().unwrap(), 4);
+        assert_eq!(names(&groups[0]), ["a-1", "a-2", "a-3"]);
+    }
+
+    #[test]
+    fn devices_interleaved() {
+        let mut files = files(&["a", "b", "c", "d", "e"]);
+        for (inode, (file, device)) in files.iter_mut().zip([1, 1, 1, 2, 2]).enumerate() {
+            file.id = Some(FileId {
+                device,
+                inode: inode as u64,
+            });
+        }
+
+        let order: Vec<_> = interleave_by_device(by_device(by_physical_file(files)))
+            .iter()
+            .map(|links| links[0].path.display().to_string())
+            .collect();
+        assert_eq!(order, ["a", "d", "b", "e", "c"]);
+    }
+
+    /// Keys files like [`NamePrefix`], reading one device a file at a time and recording how many were read at once
+    struct OneAtATime {
+        reading: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    impl ContentComparator for OneAtATime {
+        type Key = String;
+
+        fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+            let slow = path.to_string_lossy().ends_with("-slow");
+            if slow {
+                let reading = self.reading.fetch_add(1, Ordering::SeqCst) + 1;
+                self.most.fetch_max(reading, Ordering::SeqCst);
+                thread::sleep(std::time::Duration::from_millis(5));
+                self.reading.fetch_sub(1, Ordering::SeqCst);
+            }
+            NamePrefix.key(path, size)
+        }
+
+        fn read_depth(&self, path: &Path) -> Option<usize> {
+            path.to_string_lossy().ends_with("-slow").then_some(1)
+        }
+    }
+
+    #[test]
+    fn devices_read_at_their_depth() {
+        let names_on = [
+            "a-1-slow", "a-2-slow", "b-1-slow", "b-2-slow", "a-3", "b-3", "c-1",
+        ];
+        let mut files = files(&names_on);
+        for (inode, file) in files.iter_mut().enumerate() {
+            file.id = Some(FileId {
+                device: file.path.to_string_lossy().ends_with("-slow") as u64,
+                inode: inode as u64,
+            });
+        }
+
+        let comparator = OneAtATime {
+            reading: AtomicUsize::new(0),
+            most: AtomicUsize::new(0),
+        };
+        let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let mut groups = pool
+            .install(|| find_duplicates(files, &comparator, |_| {}))
+            .unwrap();
+        groups.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let groups: Vec<_> = groups.iter().map(names).collect();
+        assert_eq!(
+            groups,
+            [
+                vec!["a-1-slow", "a-2-slow", "a-3"],
+                vec!["b-1-slow", "b-2-slow", "b-3"]
+            ]
+        );
+        assert_eq!(comparator.most.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn streaming_compares_within_sizes() {
+        let mut files = files(&["b-2", "a-1", "b-1", "c-1", "a-2", "a-3", "c-2"]);
+        files[5].size = 2;
+        files[6].size = 2;
+
+        let mut batch = DuplicateFinder::new(files.clone(), NamePrefix)
+            .run()
+            .unwrap();
+        let mut streamed = DuplicateFinder::new(files, NamePrefix)
+            .run_streaming()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        batch.sort_by(|a, b| a.key.cmp(&b.key));
+        streamed.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let batch: Vec<_> = batch.iter().map(names).collect();
+        assert_eq!(
+            batch,
+            [
+                vec!["a-1", "a-2", "a-3"],
+                vec!["b-1", "b-2"],
+                vec!["c-1", "c-2"]
+            ]
+        );
+
+        // a-3 and c-2 are the only files of their size with their prefix, so never match when streaming
+        let streamed: Vec<_> = streamed.iter().map(names).collect();
+        assert_eq!(streamed, [vec!["a-1", "a-2"], vec!["b-1", "b-2"]]);
+    }
+
+    #[test]
+    fn streaming_stops_when_dropped() {
+        let streamed: Vec<_> =
+            DuplicateFinder::new(files(&["a-1", "a-2", "b-1", "b-2"]), NamePrefix)
+                .run_streaming()
+                .take(1)
+                .collect();
+
+        assert_eq!(streamed.len(), 1);
+    }
+
+    /// Records the number of threads in the pool each file was compared on
+    struct PoolSize(Mutex<Vec<usize>>);
+
+    impl ContentComparator for PoolSize {
+        type Key = ();
+
+        fn key(&self, _path: &Path, _size: u64) -> Result<Self::Key> {
+            self.0.lock().unwrap().push(rayon::current_num_threads());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn caller_thread_pool() {
+        let comparator = Arc::new(PoolSize(Mutex::default()));
+
+        struct Shared(Arc<PoolSize>);
+        impl ContentComparator for Shared {
+            type Key = ();
+
+            fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+                self.0.key(path, size)
+            }
+        }
+
+        DuplicateFinder::new(files(&["a", "b"]), Shared(comparator.clone()))
+            .max_parallelism(3)
+            .unwrap()
+            .run()
+            .unwrap();
+        let streamed: Vec<_> = DuplicateFinder::new(files(&["c", "d"]), Shared(comparator.clone()))
+            .max_parallelism(5)
+            .unwrap()
+            .run_streaming()
+            .collect();
+        assert_eq!(streamed.len(), 1);
+
+        let mut pool_sizes = comparator.0.lock().unwrap().clone();
+        pool_sizes.sort();
+        assert_eq!(pool_sizes, [3, 3, 5, 5]);
+    }
+
+    #[test]
+    fn confirming_splits_groups() {
+        let groups =
+            find_duplicates(files(&["a-1", "a-2", "a-x1", "a-y"]), &NamePrefix, |_| {}).unwrap();
+        let groups = confirm_duplicates(groups, &LastCharacter, |_| {}).unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].key, "a");
+        assert_eq!(names(&groups[0]), ["a-1", "a-x1"]);
+    }
+
+    #[test]
+    fn compares_bytes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let content = vec![7; COMPARE_BLOCK + 10];
+        let mut late = content.clone();
+        late[COMPARE_BLOCK + 5] = 8;
+        let mut early = content.clone();
+        early[0] = 8;
+
+        let mut entries = Vec::new();
+        for (name, content) in [
+            ("a", &content),
+            ("b", &content),
+            ("c", &late),
+            ("d", &early),
+            ("e", &late),
+            ("gone", &content),
+        ] {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            entries.push(FileEntry {
+                size: content.len() as u64,
+                path,
+                id: None,
+            });
+        }
+        fs::remove_file(dir.path().join("gone")).unwrap();
+
+        let processed = Mutex::new(0);
+        let mut groups = compare_bytes(entries, &RetryPolicy::default(), |_| {
+            *processed.lock().unwrap() += 1
+        })
+        .unwrap();
+        groups.sort_by(|a, b| a.files[0].path.cmp(&b.files[0].path));
+
+        let names: Vec<Vec<String>> = groups
+            .iter()
+            .map(|group| {
+                group
+                    .files
+                    .iter()
+                    .map(|file| {
+                        file.path
+                            .file_name()
+                            .unwrap()
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(names, [["a", "b"], ["c", "e"]]);
+        assert_eq!(groups[0].key, content.len() as u64);
+        assert_eq!(*processed.lock().unwrap(), 6);
+    }
+}
@@ -0,0 +1,321 @@
+//! Hashing file content
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Error, ErrorKind, Read, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use xxhash_rust::xxh3::Xxh3;
+
+/// Algorithm used to hash file content
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Sha1,
+    Md5,
+    Blake3,
+    Crc32c,
+    Xxh3,
+}
+
+/// Adapts the CRC32C checksum to [`io::Write`] so files can be copied into it
+#[derive(Default)]
+struct Crc32cHasher(u32);
+
+impl io::Write for Crc32cHasher {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0 = crc32c::crc32c_append(self.0, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Likewise for XXH3, whose hasher only takes slices
+#[derive(Default)]
+struct Xxh3Hasher(Xxh3);
+
+impl io::Write for Xxh3Hasher {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The digest of some content, kept as bytes and only hex encoded when displayed
+///
+/// Digests of up to 32 bytes, which is every algorithm but SHA-512, are stored inline without allocating. Digests
+/// order the same way as their hex encodings.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Digest {
+    Inline { len: u8, bytes: [u8; 32] },
+    Long(Box<[u8]>),
+}
+
+impl Digest {
+    pub fn from_bytes(digest: &[u8]) -> Digest {
+        if digest.len() > 32 {
+            return Digest::Long(digest.into());
+        }
+
+        let mut bytes = [0; 32];
+        bytes[..digest.len()].copy_from_slice(digest);
+        Digest::Inline {
+            len: digest.len() as u8,
+            bytes,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Digest::Inline { len, bytes } => &bytes[..*len as usize],
+            Digest::Long(bytes) => bytes,
+        }
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.as_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self)
+    }
+}
+
+impl FromStr for Digest {
+    type Err = Error;
+
+    /// Parse a hex encoded digest, in either case
+    fn from_str(hex: &str) -> Result<Self> {
+        let bytes = base16ct::mixed::decode_vec(hex).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Not a hex encoded digest: {}", hex),
+            )
+        })?;
+
+        Ok(Digest::from_bytes(&bytes))
+    }
+}
+
+/// Hash the rest of `reader` with a RustCrypto hasher
+fn digest<D: sha2::Digest + io::Write>(reader: &mut impl Read) -> Result<Digest> {
+    let mut hasher = D::new();
+
+    io::copy(reader, &mut hasher)?;
+
+    Ok(Digest::from_bytes(&hasher.finalize()))
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Crc32c => "crc32c",
+            HashAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+
+    /// Hash the content of the file at `path`, hex encoded
+    pub fn hash_file(&self, path: &Path) -> Result<String> {
+        Ok(self.digest_file(path)?.to_string())
+    }
+
+    /// Hash everything left in `reader`, hex encoded
+    pub fn hash_reader(&self, reader: &mut impl Read) -> Result<String> {
+        Ok(self.digest_reader(reader)?.to_string())
+    }
+
+    /// Like [`HashAlgorithm::hash_file_parallel`], hex encoded
+    pub fn hash_file_parallel(&self, path: &Path) -> Result<String> {
+        Ok(self.digest_file_parallel(path)?.to_string())
+    }
+
+    /// Hash the content of the file at `path`
+    pub fn digest_file(&self, path: &Path) -> Result<Digest> {
+        self.digest_reader(&mut File::open(path)?)
+    }
+
+    /// Hash everything left in `reader`
+    pub fn digest_reader(&self, reader: &mut impl Read) -> Result<Digest> {
+        match self {
+            HashAlgorithm::Sha256 => digest::<Sha256>(reader),
+            HashAlgorithm::Sha512 => digest::<Sha512>(reader),
+            HashAlgorithm::Sha1 => digest::<Sha1>(reader),
+            HashAlgorithm::Md5 => digest::<Md5>(reader),
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+
+                io::copy(reader, &mut hasher)?;
+
+                Ok(Digest::from_bytes(hasher.finalize().as_bytes()))
+            }
+            HashAlgorithm::Crc32c => {
+                let mut hasher = Crc32cHasher::default();
+
+                io::copy(reader, &mut hasher)?;
+
+                Ok(Digest::from_bytes(&hasher.0.to_be_bytes()))
+            }
+            HashAlgorithm::Xxh3 => {
+                let mut hasher = Xxh3Hasher::default();
+
+                io::copy(reader, &mut hasher)?;
+
+                Ok(Digest::from_bytes(&hasher.0.digest().to_be_bytes()))
+            }
+        }
+    }
+
+    /// Hash the content of the file at `path` using every thread of the current rayon pool
+    ///
+    /// Only BLAKE3 can split a single file across threads, other algorithms hash the file the same way as
+    /// [`HashAlgorithm::digest_file`]. Worthwhile for very large files, where one thread would otherwise do all the
+    /// work.
+    pub fn digest_file_parallel(&self, path: &Path) -> Result<Digest> {
+        match self {
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+
+                hasher.update_mmap_rayon(path)?;
+
+                Ok(Digest::from_bytes(hasher.finalize().as_bytes()))
+            }
+            _ => self.digest_file(path),
+        }
+    }
+
+    /// A different algorithm from this one, for confirming matches with an independent digest
+    pub fn independent(&self) -> HashAlgorithm {
+        match self {
+            HashAlgorithm::Blake3 => HashAlgorithm::Sha256,
+            _ => HashAlgorithm::Blake3,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "md5" => Ok(HashAlgorithm::Md5),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "crc32c" => Ok(HashAlgorithm::Crc32c),
+            "xxh3" => Ok(HashAlgorithm::Xxh3),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unsupported hashing algorithm: {}", name),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(algorithm: HashAlgorithm, content: &[u8]) -> String {
+        algorithm.hash_reader(&mut &content[..]).unwrap()
+    }
+
+    #[test]
+    fn known_digests() {
+        assert_eq!(
+            hash(HashAlgorithm::Md5, b"abc"),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Sha1, b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hash(HashAlgorithm::Crc32c, b""), "00000000");
+        assert_eq!(hash(HashAlgorithm::Crc32c, b"123456789"), "e3069283");
+        assert_eq!(hash(HashAlgorithm::Xxh3, b"abc"), "78af5f94892f3950");
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Md5,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Crc32c,
+            HashAlgorithm::Xxh3,
+        ] {
+            assert_eq!(
+                algorithm.name().parse::<HashAlgorithm>().unwrap(),
+                algorithm
+            );
+            assert_ne!(algorithm.independent(), algorithm);
+        }
+
+        assert!("sha3".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn digests() {
+        for algorithm in [
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Crc32c,
+            HashAlgorithm::Xxh3,
+        ] {
+            let digest = algorithm.digest_reader(&mut &b"abc"[..]).unwrap();
+            assert_eq!(digest.to_string(), hash(algorithm, b"abc"));
+            assert_eq!(digest.to_string().parse::<Digest>().unwrap(), digest);
+            assert_eq!(
+                digest.to_string().to_uppercase().parse::<Digest>().unwrap(),
+                digest
+            );
+        }
+
+        let (low, high) = (
+            Digest::from_bytes(&[0x0f, 0xff]),
+            Digest::from_bytes(&[0xf0, 0x00]),
+        );
+        assert!(low < high);
+        assert!(low.to_string() < high.to_string());
+        assert!(matches!(low, Digest::Inline { .. }));
+        assert!("xyz".parse::<Digest>().is_err());
+    }
+}
@@ -0,0 +1,467 @@
+use std::fs::File;
+use std::io::{Error, IsTerminal, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use indicatif::ProgressBar;
+
+use rupes::{ContentComparator, Digest, PrecomputedComparator, QuickComparator, TextComparator};
+
+use crate::documents::DocumentComparator;
+use crate::locale::{self, Message};
+use crate::mail::MailComparator;
+
+/// A file being read, abandoned once skipped
+type Reading = (PathBuf, Arc<AtomicBool>);
+
+/// Requests made with the keyboard during a scan, shared with every hashing thread
+#[derive(Default)]
+pub struct Controls {
+    paused: AtomicBool,
+    quit: AtomicBool,
+    /// Files being read, in the order they were started, the first is the one shown and skipped
+    reading: Mutex<Vec<Reading>>,
+    /// Files left out of the results, after being skipped or because of quitting
+    pub left_out: AtomicUsize,
+    skipped: Mutex<Vec<PathBuf>>,
+    progress: Mutex<Option<ProgressBar>>,
+}
+
+impl Controls {
+    /// Show the hotkeys on `progress`, the bar of the step currently running
+    pub fn attach(&self, progress: &ProgressBar) {
+        progress.set_message(self.message());
+        *self.progress.lock().unwrap() = Some(progress.clone());
+    }
+
+    pub fn quitting(&self) -> bool {
+        self.quit.load(Ordering::Relaxed)
+    }
+
+    /// The files skipped with the hotkey, in the order they were
+    pub fn skipped(&self) -> Vec<PathBuf> {
+        self.skipped.lock().unwrap().clone()
+    }
+
+    /// The hotkeys, followed by the file they skip
+    fn message(&self) -> String {
+        match self.reading.lock().unwrap().first() {
+            Some((path, _)) => format!("{}  {}", self.hint(), path.display()),
+            None => self.hint(),
+        }
+    }
+
+    fn refresh(&self) {
+        if let Some(progress) = self.progress.lock().unwrap().as_ref() {
+            progress.set_message(self.message());
+        }
+    }
+
+    /// Start reading the file at `path`, returning the flag set when it's skipped
+    fn start_reading(&self, path: &Path) -> Arc<AtomicBool> {
+        let abandoned = Arc::new(AtomicBool::new(false));
+        self.reading
+            .lock()
+            .unwrap()
+            .push((path.to_path_buf(), abandoned.clone()));
+        self.refresh();
+        abandoned
+    }
+
+    /// Done reading the file `abandoned` was returned for, recording it if it was skipped
+    fn done_reading(&self, abandoned: &Arc<AtomicBool>) {
+        let mut reading = self.reading.lock().unwrap();
+        if let Some(index) = reading
+            .iter()
+            .position(|(_, flag)| Arc::ptr_eq(flag, abandoned))
+        {
+            let (path, _) = reading.remove(index);
+            if abandoned.load(Ordering::Relaxed) {
+                self.skipped.lock().unwrap().push(path);
+            }
+        }
+        drop(reading);
+        self.refresh();
+    }
+
+    fn hint(&self) -> String {
+        if self.paused.load(Ordering::Relaxed) {
+            locale::text(Message::PausedHint, &[])
+        } else {
+            locale::text(Message::HotkeyHint, &[])
+        }
+    }
+
+    fn press(&self, key: u8) {
+        match key {
+            b'p' => {
+                self.paused.fetch_xor(true, Ordering::Relaxed);
+            }
+            b's' => {
+                if let Some((_, abandoned)) = self.reading.lock().unwrap().first() {
+                    abandoned.store(true, Ordering::Relaxed);
+                }
+            }
+            b'q' => {
+                self.quit.store(true, Ordering::Relaxed);
+                self.paused.store(false, Ordering::Relaxed);
+            }
+            _ => return,
+        }
+
+        self.refresh();
+    }
+
+    /// Block while paused
+    fn pause_point(&self) {
+        while self.paused.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// A key that no other file has, so a file that wasn't compared is never reported as a duplicate
+    fn not_compared(&self, size: u64) -> (u64, Digest) {
+        let n = self.left_out.fetch_add(1, Ordering::Relaxed) as u64;
+        // No algorithm has 8 byte digests, so these never match a file that was hashed
+        (size, Digest::from_bytes(&n.to_le_bytes()))
+    }
+}
+
+/// Reads that wait while paused and stop when the file is skipped or the scan is quit
+struct ControlledReader<'a, R> {
+    reader: R,
+    controls: &'a Controls,
+    abandoned: &'a AtomicBool,
+}
+
+impl<R: Read> Read for ControlledReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.controls.pause_point();
+
+        if self.controls.quitting() || self.abandoned.load(Ordering::Relaxed) {
+            return Err(Error::other(Abandoned));
+        }
+
+        self.reader.read(buf)
+    }
+}
+
+/// Marks reads given up on with a hotkey, rather than failed
+#[derive(Debug)]
+struct Abandoned;
+
+impl std::fmt::Display for Abandoned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "skipped")
+    }
+}
+
+impl std::error::Error for Abandoned {}
+
+/// A comparator that obeys the hotkeys, files skipped or left when quitting get keys of their own
+pub struct Controlled<'a, C> {
+    pub comparator: &'a C,
+    pub controls: &'a Controls,
+}
+
+impl ContentComparator for Controlled<'_, PrecomputedComparator> {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        self.controls.pause_point();
+        if self.controls.quitting() {
+            return Ok(self.controls.not_compared(size));
+        }
+
+        let hashing = &self.comparator.comparator;
+        let parallel = hashing
+            .parallel_threshold
+            .is_some_and(|threshold| size >= threshold);
+        if self.comparator.hashes.contains_key(path) || parallel {
+            return self.comparator.key(path, size);
+        }
+
+        let abandoned = self.controls.start_reading(path);
+        let hashed = hashing.retry.run(|| {
+            let file = File::open(path)?;
+            match &hashing.read_limit {
+                Some(limit) => hashing.algorithm.digest_reader(&mut ControlledReader {
+                    reader: limit.reader(file),
+                    controls: self.controls,
+                    abandoned: &abandoned,
+                }),
+                None => hashing.algorithm.digest_reader(&mut ControlledReader {
+                    reader: file,
+                    controls: self.controls,
+                    abandoned: &abandoned,
+                }),
+            }
+        });
+        self.controls.done_reading(&abandoned);
+
+        match hashed {
+            Ok(hash) => Ok((size, hash)),
+            Err(error) if error.get_ref().is_some_and(|inner| inner.is::<Abandoned>()) => {
+                Ok(self.controls.not_compared(size))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+
+    fn same_size(&self) -> bool {
+        self.comparator.same_size()
+    }
+
+    fn same_start(&self, path: &Path) -> bool {
+        self.comparator.same_start(path)
+    }
+}
+
+impl ContentComparator for Controlled<'_, QuickComparator> {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        self.controls.pause_point();
+        if self.controls.quitting() {
+            return Ok(self.controls.not_compared(size));
+        }
+
+        self.comparator.key(path, size)
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+
+    fn same_size(&self) -> bool {
+        self.comparator.same_size()
+    }
+}
+
+impl ContentComparator for Controlled<'_, TextComparator> {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        self.controls.pause_point();
+        if self.controls.quitting() {
+            return Ok(self.controls.not_compared(size));
+        }
+
+        self.comparator.key(path, size)
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+}
+
+impl ContentComparator for Controlled<'_, DocumentComparator> {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        self.controls.pause_point();
+        if self.controls.quitting() {
+            return Ok(self.controls.not_compared(size));
+        }
+
+        self.comparator.key(path, size)
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+}
+
+impl ContentComparator for Controlled<'_, MailComparator> {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        self.controls.pause_point();
+        if self.controls.quitting() {
+            return Ok(self.controls.not_compared(size));
+        }
+
+        self.comparator.key(path, size)
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::io::{Error, Result};
+    use std::mem::MaybeUninit;
+
+    /// Switch the terminal on stdin to reading single keys without echoing them, returning the settings to restore
+    pub fn raw() -> Result<libc::termios> {
+        let mut original = MaybeUninit::<libc::termios>::uninit();
+
+        // SAFETY: tcgetattr fills in the termios it's given when it succeeds
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+                return Err(Error::last_os_error());
+            }
+            original.assume_init()
+        };
+
+        // Ctrl-C arrives as a key too, so the terminal can be restored before exiting
+        let mut keys = original;
+        keys.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        keys.c_cc[libc::VMIN] = 1;
+        keys.c_cc[libc::VTIME] = 0;
+        restore(&keys)?;
+
+        Ok(original)
+    }
+
+    pub fn restore(settings: &libc::termios) -> Result<()> {
+        // SAFETY: `settings` is a valid termios
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, settings) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Wait up to `timeout_ms` for a key, returning it if one was pressed
+    pub fn key(timeout_ms: i32) -> Option<u8> {
+        let mut poll = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // SAFETY: `poll` is a single valid pollfd, and `key` has room for the one byte read
+        unsafe {
+            if libc::poll(&mut poll, 1, timeout_ms) <= 0 {
+                return None;
+            }
+
+            let mut key = 0u8;
+            (libc::read(libc::STDIN_FILENO, (&mut key as *mut u8).cast(), 1) == 1).then_some(key)
+        }
+    }
+}
+
+/// Listens for hotkeys on the terminal until dropped, which puts the terminal back the way it was
+pub struct Hotkeys {
+    pub controls: Arc<Controls>,
+    done: Arc<AtomicBool>,
+    listener: Option<thread::JoinHandle<()>>,
+}
+
+impl Hotkeys {
+    /// Start listening, if stdin is a terminal that supports it
+    #[cfg(unix)]
+    pub fn listen() -> Option<Hotkeys> {
+        if !std::io::stdin().is_terminal() {
+            return None;
+        }
+        let original = terminal::raw().ok()?;
+
+        let controls = Arc::new(Controls::default());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let listener = {
+            let controls = controls.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    match terminal::key(100) {
+                        // Ctrl-C
+                        Some(3) => {
+                            let _ = terminal::restore(&original);
+                            std::process::exit(130);
+                        }
+                        Some(key) => controls.press(key.to_ascii_lowercase()),
+                        None => {}
+                    }
+                }
+
+                let _ = terminal::restore(&original);
+            })
+        };
+
+        Some(Hotkeys {
+            controls,
+            done,
+            listener: Some(listener),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen() -> Option<Hotkeys> {
+        None
+    }
+}
+
+impl Drop for Hotkeys {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(listener) = self.listener.take() {
+            let _ = listener.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skipping_abandons_the_file_shown() {
+        let controls = Controls::default();
+        let shown = controls.start_reading(Path::new("/big"));
+        let other = controls.start_reading(Path::new("/small"));
+        assert!(controls.message().ends_with("  /big"));
+
+        let mut reader = ControlledReader {
+            reader: &b"content"[..],
+            controls: &controls,
+            abandoned: &shown,
+        };
+        let mut still_read = ControlledReader {
+            reader: &b"content"[..],
+            controls: &controls,
+            abandoned: &other,
+        };
+
+        let mut buf = [0; 3];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+
+        controls.press(b's');
+        let error = reader.read(&mut buf).unwrap_err();
+        assert!(error.get_ref().unwrap().is::<Abandoned>());
+        assert_eq!(still_read.read(&mut buf).unwrap(), 3);
+
+        controls.done_reading(&shown);
+        assert!(controls.message().ends_with("  /small"));
+        controls.done_reading(&other);
+        assert_eq!(controls.message(), controls.hint());
+        assert_eq!(controls.skipped(), [PathBuf::from("/big")]);
+    }
+
+    #[test]
+    fn quitting_resumes() {
+        let controls = Controls::default();
+        controls.press(b'p');
+        assert_eq!(controls.hint(), locale::text(Message::PausedHint, &[]));
+
+        controls.press(b'q');
+        assert!(controls.quitting());
+        assert_eq!(controls.hint(), locale::text(Message::HotkeyHint, &[]));
+        controls.pause_point();
+
+        let key = controls.not_compared(3);
+        assert_ne!(key, controls.not_compared(3));
+        assert_eq!(controls.left_out.load(Ordering::Relaxed), 2);
+    }
+}
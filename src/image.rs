@@ -0,0 +1,501 @@
+//! Files duplicated across the layers of container images, read from OCI image layouts and `docker save` archives
+//!
+//! Every layer is a tar archive, usually compressed, and files copied again by a later build step are stored again in
+//! the layer of that step. Layers are read as they are, without unpacking them, and their files given virtual paths
+//! made of the image, the start of the layer's digest and the path in the layer, such as `app.tar@3f1c9a2b4d5e/usr/lib/
+//! libssl.so.3`. Compressed layers are decompressed with gzip or zstd, or the programs named by the `RUPES_GZIP` and
+//! `RUPES_ZSTD` environment variables.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::thread;
+
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use rupes::{Digest, HashAlgorithm};
+
+use crate::tool::{GZIP, ZSTD};
+
+/// Size of the blocks tar archives are made of
+const BLOCK: usize = 512;
+
+/// How deep image indexes are followed into the indexes they list
+const MAX_INDEX_DEPTH: usize = 4;
+
+/// A file found in a layer of an image
+#[derive(Debug)]
+pub struct LayerFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub hash: Digest,
+}
+
+/// Call `member` with the path, size, position and content of every regular file in the tar archive read by `reader`
+///
+/// Long names, from both GNU and PAX headers, are supported. Whatever `member` doesn't read of the content is skipped.
+fn read_tar(
+    mut reader: impl Read,
+    mut member: impl FnMut(&str, u64, u64, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let mut header = [0; BLOCK];
+    let mut position: u64 = 0;
+    let mut long_name: Option<String> = None;
+
+    loop {
+        if read_block(&mut reader, &mut header)? == 0 || header.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+        position += BLOCK as u64;
+
+        let size = header_size(&header)?;
+        let padded = size.div_ceil(BLOCK as u64) * BLOCK as u64;
+        let mut content = (&mut reader).take(size);
+
+        match header[156] {
+            // GNU long name, and PAX extended header, both naming the member after them
+            b'L' | b'x' => {
+                let mut data = Vec::new();
+                content.read_to_end(&mut data)?;
+                long_name = if header[156] == b'L' {
+                    Some(field(&data))
+                } else {
+                    pax_path(&data).or(long_name)
+                };
+            }
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| header_name(&header));
+                member(&name, size, position, &mut content)?;
+            }
+            _ => long_name = None,
+        }
+
+        io::copy(&mut content, &mut io::sink())?;
+        io::copy(&mut (&mut reader).take(padded - size), &mut io::sink())?;
+        position += padded;
+    }
+}
+
+/// Fill `block`, returning 0 at the end of the archive
+fn read_block(reader: &mut impl Read, block: &mut [u8; BLOCK]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < BLOCK {
+        match reader.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(0),
+            0 => {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Truncated tar archive",
+                ))
+            }
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// A NUL terminated header field
+fn field(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn header_name(header: &[u8; BLOCK]) -> String {
+    let name = field(&header[..100]);
+    let prefix = field(&header[345..500]);
+    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+        format!("{}/{}", prefix, name)
+    } else {
+        name
+    }
+}
+
+/// The size of a member, in octal or, for large members, in base-256
+fn header_size(header: &[u8; BLOCK]) -> Result<u64> {
+    let size = &header[124..136];
+    if size[0] & 0x80 != 0 {
+        return Ok(size[1..]
+            .iter()
+            .fold(0, |size, &byte| size << 8 | byte as u64));
+    }
+
+    let octal = field(size);
+    let octal = octal.trim_matches(|character: char| character == ' ' || character == '\0');
+    if octal.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(octal, 8).map_err(|error| Error::new(ErrorKind::InvalidData, error))
+}
+
+/// The path given by the records of a PAX extended header, each `<length> <key>=<value>\n`
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&byte| byte == b' ')?;
+        let length: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..length)?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return Some(
+                String::from_utf8_lossy(path.strip_suffix(b"\n").unwrap_or(path)).into_owned(),
+            );
+        }
+        rest = &rest[length..];
+    }
+    None
+}
+
+/// Reads the output of a decompressing program, failing at the end if the program did
+struct ChildOutput(Child);
+
+impl Read for ChildOutput {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self
+            .0
+            .stdout
+            .as_mut()
+            .map_or(Ok(0), |stdout| stdout.read(buf))?;
+        if read == 0 && !buf.is_empty() {
+            let status = self.0.wait()?;
+            if !status.success() {
+                return Err(Error::other(format!(
+                    "Decompressing a layer failed, {}",
+                    status
+                )));
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// The content of `blob`, decompressed by gzip or zstd when it's compressed with one of them
+fn decompressed(mut blob: impl Read + Send + 'static) -> Result<Box<dyn Read>> {
+    let mut magic = Vec::with_capacity(4);
+    (&mut blob).take(4).read_to_end(&mut magic)?;
+
+    let tool = match magic[..] {
+        [0x1f, 0x8b, ..] => Some(GZIP),
+        [0x28, 0xb5, 0x2f, 0xfd] => Some(ZSTD),
+        _ => None,
+    };
+    let mut blob = Cursor::new(magic).chain(blob);
+    let Some(tool) = tool else {
+        return Ok(Box::new(blob));
+    };
+
+    let mut child = tool.spawn(|command| {
+        command
+            .arg("-dc")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+    })?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Stops early, with a broken pipe, when the layer isn't read to its end
+    thread::spawn(move || io::copy(&mut blob, &mut stdin));
+
+    Ok(Box::new(ChildOutput(child)))
+}
+
+/// Where a blob of an image is stored
+#[derive(Clone, Debug)]
+enum Blob {
+    File(PathBuf),
+    /// A member of a tar archive, starting `offset` bytes into it
+    Member {
+        archive: PathBuf,
+        offset: u64,
+        size: u64,
+    },
+}
+
+impl Blob {
+    fn open(&self) -> Result<Box<dyn Read + Send>> {
+        match self {
+            Blob::File(path) => Ok(Box::new(BufReader::new(File::open(path)?))),
+            Blob::Member {
+                archive,
+                offset,
+                size,
+            } => {
+                let mut file = File::open(archive)?;
+                file.seek(SeekFrom::Start(*offset))?;
+                Ok(Box::new(BufReader::new(file).take(*size)))
+            }
+        }
+    }
+
+    fn read(&self) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.open()?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+}
+
+/// A layer of an image, known by its digest, or the name of its file in archives without digests
+#[derive(Clone, Debug)]
+struct Layer {
+    id: String,
+    blob: Blob,
+}
+
+impl Layer {
+    /// The start of the layer's digest, as `docker history` shows it, or its name when it has none
+    fn short_id(&self) -> String {
+        let digest = self
+            .id
+            .split([':', '/'])
+            .find(|part| part.len() >= 12 && part.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        match digest {
+            Some(digest) => digest[..12].to_string(),
+            None => self.id.trim_end_matches("/layer.tar").to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    digest: String,
+}
+
+/// An OCI image index or image manifest, listing the manifests or the layers of an image
+#[derive(Deserialize)]
+struct OciDocument {
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+}
+
+/// An image in the manifest.json of a `docker save` archive
+#[derive(Deserialize)]
+struct DockerImage {
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// The path of the blob with `digest` in an OCI image layout
+fn blob_path(digest: &str) -> Result<String> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .filter(|(algorithm, hex)| !algorithm.contains('/') && !hex.contains('/'))
+        .ok_or_else(|| invalid(format!("'{}' isn't a digest", digest)))?;
+    Ok(format!("blobs/{}/{}", algorithm, hex))
+}
+
+/// The layers of every image in the OCI image layout whose files `blob` finds by path
+fn oci_layers(blob: &impl Fn(&str) -> Result<Blob>) -> Result<Vec<Layer>> {
+    let mut layers = Vec::new();
+    let mut documents = vec![("index.json".to_string(), 0)];
+
+    while let Some((path, depth)) = documents.pop() {
+        let content = blob(&path)?
+            .read()
+            .map_err(|error| Error::new(error.kind(), format!("Can't read {}: {}", path, error)))?;
+        let document: OciDocument = serde_json::from_slice(&content)
+            .map_err(|error| invalid(format!("{} isn't an OCI manifest: {}", path, error)))?;
+
+        for manifest in document
+            .manifests
+            .iter()
+            .filter(|_| depth < MAX_INDEX_DEPTH)
+        {
+            documents.push((blob_path(&manifest.digest)?, depth + 1));
+        }
+        for layer in document.layers {
+            layers.push(Layer {
+                blob: blob(&blob_path(&layer.digest)?)?,
+                id: layer.digest,
+            });
+        }
+    }
+
+    Ok(layers)
+}
+
+/// The layers of the image at `image`, an OCI image layout directory, or a tar archive of one or of `docker save`
+fn layers(image: &Path) -> Result<Vec<Layer>> {
+    if image.is_dir() {
+        return oci_layers(&|path| Ok(Blob::File(image.join(path))));
+    }
+
+    let mut members: HashMap<String, (u64, u64)> = HashMap::new();
+    read_tar(
+        BufReader::new(File::open(image)?),
+        |name, size, offset, _| {
+            members.insert(name.trim_start_matches("./").to_string(), (offset, size));
+            Ok(())
+        },
+    )?;
+    let blob = |path: &str| match members.get(path) {
+        Some(&(offset, size)) => Ok(Blob::Member {
+            archive: image.to_path_buf(),
+            offset,
+            size,
+        }),
+        None => Err(invalid(format!("{} has no {}", image.display(), path))),
+    };
+
+    if !members.contains_key("manifest.json") {
+        return oci_layers(&blob);
+    }
+    let images: Vec<DockerImage> = serde_json::from_slice(&blob("manifest.json")?.read()?)
+        .map_err(|error| {
+            invalid(format!(
+                "{} has an invalid manifest.json: {}",
+                image.display(),
+                error
+            ))
+        })?;
+    images
+        .into_iter()
+        .flat_map(|image| image.layers)
+        .map(|layer| {
+            Ok(Layer {
+                blob: blob(&layer)?,
+                id: layer,
+            })
+        })
+        .collect()
+}
+
+/// Hash every regular file of `layer`, other than empty files and whiteouts, naming them after `image`
+fn layer_files(image: &Path, layer: &Layer, algorithm: HashAlgorithm) -> Result<Vec<LayerFile>> {
+    let prefix = format!("{}@{}", image.display(), layer.short_id());
+    let mut files = Vec::new();
+
+    read_tar(
+        decompressed(layer.blob.open()?)?,
+        |name, size, _, mut content| {
+            let name = name.trim_start_matches("./").trim_start_matches('/');
+            let whiteout = Path::new(name)
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(".wh."));
+            if size > 0 && !whiteout {
+                files.push(LayerFile {
+                    path: PathBuf::from(format!("{}/{}", prefix, name)),
+                    size,
+                    hash: algorithm.digest_reader(&mut content)?,
+                });
+            }
+            Ok(())
+        },
+    )?;
+
+    Ok(files)
+}
+
+/// The files of every layer of `images`, a layer shared by several images being read only once, since it's stored once
+pub fn files(images: &[PathBuf], algorithm: HashAlgorithm) -> Result<Vec<LayerFile>> {
+    let mut seen = HashSet::new();
+    let mut layers = Vec::new();
+    for image in images {
+        for layer in layers_of(image)? {
+            if seen.insert(layer.id.clone()) {
+                layers.push((image, layer));
+            }
+        }
+    }
+
+    let files: Vec<Vec<LayerFile>> = layers
+        .par_iter()
+        .map(|(image, layer)| layer_files(image, layer, algorithm))
+        .collect::<Result<_>>()?;
+    Ok(files.into_iter().flatten().collect())
+}
+
+fn layers_of(image: &Path) -> Result<Vec<Layer>> {
+    layers(image)
+        .map_err(|error| Error::new(error.kind(), format!("{}: {}", image.display(), error)))
+}
+
+/// Group `files` with the same size and hash, ordered by size then path, each group's paths sorted
+pub fn duplicates(files: Vec<LayerFile>) -> Vec<(u64, Vec<PathBuf>)> {
+    let mut by_content: HashMap<(u64, Digest), Vec<PathBuf>> = HashMap::new();
+    for file in files {
+        by_content
+            .entry((file.size, file.hash))
+            .or_default()
+            .push(file.path);
+    }
+
+    let mut groups: Vec<(u64, Vec<PathBuf>)> = by_content
+        .into_iter()
+        .filter(|(_, paths)| paths.len() >= 2)
+        .map(|((size, _), mut paths)| {
+            paths.sort();
+            (size, paths)
+        })
+        .collect();
+    groups.sort();
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tar archive of `members`, with a GNU long name header for names that don't fit a header
+    fn tar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        fn header(name: &str, size: usize, kind: u8) -> [u8; BLOCK] {
+            let mut header = [0; BLOCK];
+            header[..name.len().min(100)].copy_from_slice(&name.as_bytes()[..name.len().min(100)]);
+            header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+            header[156] = kind;
+            header
+        }
+        fn padded(archive: &mut Vec<u8>, data: &[u8]) {
+            archive.extend_from_slice(data);
+            archive.resize(archive.len().div_ceil(BLOCK) * BLOCK, 0);
+        }
+
+        let mut archive = Vec::new();
+        for (name, content) in members {
+            if name.len() > 100 {
+                archive.extend_from_slice(&header("././@LongLink", name.len() + 1, b'L'));
+                padded(&mut archive, format!("{}\0", name).as_bytes());
+            }
+            archive.extend_from_slice(&header(name, content.len(), b'0'));
+            padded(&mut archive, content);
+        }
+        archive.extend_from_slice(&[0; BLOCK * 2]);
+        archive
+    }
+
+    #[test]
+    fn tar_members() {
+        let long = format!("usr/{}/lib.so", "nested/".repeat(20));
+        let archive = tar(&[("etc/hosts", b"localhost"), (&long, b"library")]);
+
+        let mut members = Vec::new();
+        read_tar(&archive[..], |name, size, offset, content| {
+            let mut data = String::new();
+            content.read_to_string(&mut data)?;
+            members.push((name.to_string(), size, offset, data));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            members,
+            [
+                ("etc/hosts".to_string(), 9, 512, "localhost".to_string()),
+                (long, 7, 2560, "library".to_string())
+            ]
+        );
+        assert_eq!(
+            pax_path(b"27 path=usr/share/doc/file\n"),
+            Some("usr/share/doc/file".to_string())
+        );
+    }
+}
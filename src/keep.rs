@@ -0,0 +1,245 @@
+//! Choosing the copy of each group to keep, with the policies of --keep or an external program of your own given to
+//! --keep-command
+//!
+//! The program is run as a separate process once per group, nothing is interpreted by rupes itself: .lua scripts are
+//! handed to the `lua` command and WebAssembly modules built for WASI to `wasmtime run`, which must be installed, and
+//! anything else is executed directly. It reads a line per file from stdin, `<mtime>\t<size>\t<path>` with the modification time
+//! in seconds since the Unix epoch, in the order the copies would otherwise be kept, and prints the number of the line
+//! to keep, counting from 1, or nothing to keep the first.
+
+use std::cmp::Reverse;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rupes::FileEntry;
+
+use crate::tool::{Tool, LUA, WASMTIME};
+
+/// Which copy of each group --keep keeps
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// The first path, once ordered by --prefer-dir
+    First,
+    /// The most recently modified copy
+    Newest,
+    /// The least recently modified copy
+    Oldest,
+    /// The copy under this directory, like a --prefer-dir given after the others
+    PathPrefix(PathBuf),
+}
+
+impl FromStr for KeepPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<Self, Self::Err> {
+        match policy {
+            "first" => Ok(KeepPolicy::First),
+            "newest" => Ok(KeepPolicy::Newest),
+            "oldest" => Ok(KeepPolicy::Oldest),
+            _ => match policy.strip_prefix("path-prefix=") {
+                Some(directory) if !directory.is_empty() => {
+                    Ok(KeepPolicy::PathPrefix(PathBuf::from(directory)))
+                }
+                _ => Err(format!(
+                    "unknown keep policy '{}', expected first, newest, oldest or path-prefix=DIRECTORY",
+                    policy
+                )),
+            },
+        }
+    }
+}
+
+impl KeepPolicy {
+    /// The directory the copy kept should be under
+    pub fn prefix(&self) -> Option<&Path> {
+        match self {
+            KeepPolicy::PathPrefix(directory) => Some(directory),
+            _ => None,
+        }
+    }
+
+    /// Why the copy it keeps is kept, when it's for its modification time
+    pub fn reason(&self) -> Option<&'static str> {
+        match self {
+            KeepPolicy::Newest => Some("newest copy"),
+            KeepPolicy::Oldest => Some("oldest copy"),
+            _ => None,
+        }
+    }
+
+    /// The index of the copy of `files` to keep among those ranked lowest by `rank`, the earliest of those modified at
+    /// the same time
+    ///
+    /// Copies whose modification time can't be read are never kept for it over one whose time can.
+    pub fn choose(&self, files: &[FileEntry], rank: impl Fn(&FileEntry) -> usize) -> Option<usize> {
+        let modified = |file: &FileEntry| -> Option<SystemTime> {
+            file.path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        let ranked = files.iter().enumerate();
+
+        match self {
+            KeepPolicy::Newest => ranked
+                .min_by_key(|(index, file)| (rank(file), Reverse(modified(file)), *index))
+                .map(|(index, _)| index),
+            KeepPolicy::Oldest => ranked
+                .min_by_key(|(index, file)| {
+                    let modified = modified(file);
+                    (rank(file), modified.is_none(), modified, *index)
+                })
+                .map(|(index, _)| index),
+            KeepPolicy::First | KeepPolicy::PathPrefix(_) => ranked
+                .min_by_key(|(index, file)| (rank(file), *index))
+                .map(|(index, _)| index),
+        }
+    }
+}
+
+/// The interpreter the extension of `program` calls for, with the arguments it's given before the program, `None`
+/// when it's executed directly
+fn interpreter(program: &Path) -> Option<(Tool, &'static [&'static str])> {
+    match program.extension().and_then(|extension| extension.to_str()) {
+        Some("lua") => Some((LUA, &[])),
+        Some("wasm") => Some((WASMTIME, &["run"])),
+        _ => None,
+    }
+}
+
+/// The external command running `program`, through the interpreter its extension calls for if it's a script
+fn command(program: &Path) -> Command {
+    let Some((interpreter, args)) = interpreter(program) else {
+        return Command::new(program);
+    };
+
+    let mut command = interpreter.command();
+    command.args(args).arg(program);
+    command
+}
+
+/// What the program is told about `files`, a line each
+fn describe(files: &[FileEntry]) -> String {
+    let mut lines = String::new();
+    for file in files {
+        let modified = file
+            .path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |elapsed| elapsed.as_secs());
+        lines.push_str(&format!(
+            "{}\t{}\t{}\n",
+            modified,
+            file.size,
+            file.path.display()
+        ));
+    }
+    lines
+}
+
+/// Run `program` to ask which of `files` to keep, `None` when it leaves the first
+pub fn choose(program: &Path, files: &[FileEntry]) -> Result<Option<usize>> {
+    let failed =
+        |reason: String| Error::other(format!("--keep-command {} {}", program.display(), reason));
+
+    let mut child = command(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|error| match interpreter(program) {
+            Some((interpreter, _)) if error.kind() == ErrorKind::NotFound => {
+                interpreter.not_found(error)
+            }
+            _ => failed(format!("couldn't be run: {}", error)),
+        })?;
+    // A program that chooses without reading every line closes its end early
+    let written = child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(describe(files).as_bytes());
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(failed(format!("failed, {}", output.status)));
+    }
+    if let Err(error) = written {
+        if error.kind() != ErrorKind::BrokenPipe {
+            return Err(error);
+        }
+    }
+
+    let printed = String::from_utf8_lossy(&output.stdout);
+    let printed = printed.trim();
+    if printed.is_empty() {
+        return Ok(None);
+    }
+    match printed.parse::<usize>() {
+        Ok(number) if (1..=files.len()).contains(&number) => Ok(Some(number - 1)),
+        _ => Err(failed(format!(
+            "printed '{}', expected a number from 1 to {}",
+            printed,
+            files.len()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpreters() {
+        let lua = command(Path::new("keep.lua"));
+        assert_eq!(lua.get_args().collect::<Vec<_>>(), ["keep.lua"]);
+        let wasm = command(Path::new("keep.wasm"));
+        assert_eq!(wasm.get_args().collect::<Vec<_>>(), ["run", "keep.wasm"]);
+        assert_eq!(command(Path::new("./keep")).get_program(), "./keep");
+
+        let files = [FileEntry {
+            path: PathBuf::from("/nonexistent/a"),
+            size: 3,
+            id: None,
+        }];
+        assert_eq!(describe(&files), "0\t3\t/nonexistent/a\n");
+    }
+
+    #[test]
+    fn policies() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let files: Vec<FileEntry> = ["old", "new", "gone"]
+            .iter()
+            .map(|name| FileEntry {
+                path: dir.path().join(name),
+                size: 4,
+                id: None,
+            })
+            .collect();
+        std::fs::write(&files[0].path, "same").unwrap();
+        std::fs::write(&files[1].path, "same").unwrap();
+        let old = std::fs::File::options()
+            .write(true)
+            .open(&files[0].path)
+            .unwrap();
+        old.set_modified(UNIX_EPOCH).unwrap();
+
+        let unranked = |_: &FileEntry| 0;
+        assert_eq!(KeepPolicy::First.choose(&files, unranked), Some(0));
+        assert_eq!(KeepPolicy::Newest.choose(&files, unranked), Some(1));
+        assert_eq!(KeepPolicy::Oldest.choose(&files, unranked), Some(0));
+        // The rank comes first, so --prefer-dir still decides which copies the policy chooses from
+        let last_preferred = |file: &FileEntry| (file.path != files[2].path) as usize;
+        assert_eq!(KeepPolicy::Newest.choose(&files, last_preferred), Some(2));
+
+        assert_eq!(
+            "path-prefix=/photos".parse(),
+            Ok(KeepPolicy::PathPrefix(PathBuf::from("/photos")))
+        );
+        assert!("largest".parse::<KeepPolicy>().is_err());
+        assert!("path-prefix=".parse::<KeepPolicy>().is_err());
+    }
+}
@@ -0,0 +1,42 @@
+//! The duplicate detection engine behind the rupes command line tool
+//!
+//! Files are discovered with [`find_files`] and grouped by content with [`find_duplicates`], which is generic over a
+//! [`ContentComparator`] so the definition of "identical" can be swapped out. The default, [`HashComparator`],
+//! considers two files identical when their sizes and hashes match, and only hashes the files whose size and first
+//! [`group::PARTIAL_HASH`] bytes another file shares. [`Scanner`] does both in a single call, with one progress
+//! callback for the whole scan.
+//!
+//! Parallel work runs on the rayon thread pool the functions are called from, so wrapping a call in
+//! [`rayon::ThreadPool::install`] keeps it on a pool of your own. [`DuplicateFinder`] takes a pool directly, since its
+//! streaming search carries on in the background after returning.
+
+pub mod cache;
+pub mod chunks;
+pub mod extents;
+pub mod group;
+pub mod hash;
+pub mod limit;
+pub mod paths;
+pub mod retry;
+pub mod scanner;
+pub mod storage;
+pub mod text;
+pub mod walk;
+pub mod xattr;
+
+pub use cache::HashCache;
+pub use group::{
+    compare_bytes, compare_bytes_skipping, confirm_duplicates, find_duplicates, skip_vanished,
+    ContentComparator, DuplicateFinder, DuplicateGroup, HashComparator, PrecomputedComparator,
+    QuickComparator, TextComparator,
+};
+pub use hash::{Digest, HashAlgorithm};
+pub use limit::ReadLimit;
+pub use paths::FileList;
+pub use retry::RetryPolicy;
+pub use scanner::{Scan, ScanProgress, Scanner};
+pub use storage::{Storage, StorageMap};
+pub use walk::{
+    discover, discover_roots, find_files, glob_pattern, Discovery, DiscoveryProgress,
+    ExcludedDirectories, FileEntry, FileId, ScanOptions,
+};
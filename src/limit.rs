@@ -0,0 +1,110 @@
+//! Capping how many reads are in flight at once, independently of how many threads hash what was read
+
+use std::io::{BufReader, Read, Result};
+use std::sync::{Condvar, Mutex};
+
+/// Bytes read per slot taken by [`ReadLimit::reader`], large enough that a spinning disk mostly reads sequentially
+const CHUNK: usize = 1024 * 1024;
+
+/// Lets at most a given number of reads run at once, shared by every thread reading files
+///
+/// Threads wait for a slot only while reading, so hashing what was read carries on on every thread while a slow
+/// disk serves a few reads at a time.
+#[derive(Debug)]
+pub struct ReadLimit {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl ReadLimit {
+    /// Allow `reads` reads at once, at least one
+    pub fn new(reads: usize) -> Self {
+        ReadLimit {
+            available: Mutex::new(reads.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Call `read` once a slot is free, holding the slot until it returns
+    pub fn run<T>(&self, read: impl FnOnce() -> T) -> T {
+        let mut available = self
+            .freed
+            .wait_while(self.available.lock().unwrap(), |available| *available == 0)
+            .unwrap();
+        *available -= 1;
+        drop(available);
+
+        let result = read();
+
+        *self.available.lock().unwrap() += 1;
+        self.freed.notify_one();
+
+        result
+    }
+
+    /// Read `reader` in large chunks, taking a slot for each of them
+    pub fn reader<'a, R: Read + 'a>(&'a self, reader: R) -> impl Read + 'a {
+        BufReader::with_capacity(
+            CHUNK,
+            Limited {
+                reader,
+                limit: self,
+            },
+        )
+    }
+}
+
+struct Limited<'a, R> {
+    reader: R,
+    limit: &'a ReadLimit,
+}
+
+impl<R: Read> Read for Limited<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.limit.run(|| self.reader.read(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn caps_concurrent_reads() {
+        let limit = ReadLimit::new(2);
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    limit.run(|| {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(10));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                });
+            }
+        });
+
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn reads_everything() {
+        let limit = ReadLimit::new(1);
+        let content = vec![7u8; CHUNK * 2 + 5];
+
+        let mut read = Vec::new();
+        limit
+            .reader(content.as_slice())
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, content);
+    }
+}
@@ -0,0 +1,890 @@
+use std::env;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+/// Languages the progress labels, prompts and summaries are translated into
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+impl Lang {
+    /// The language of a locale such as de_DE.UTF-8, if its messages are translated
+    pub fn from_locale(locale: &str) -> Option<Lang> {
+        let language = locale
+            .split(['_', '.', '@', '-'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Lang::En),
+            "de" => Some(Lang::De),
+            "fr" => Some(Lang::Fr),
+            "es" => Some(Lang::Es),
+            _ => None,
+        }
+    }
+
+    /// The language of the first locale set in LC_ALL, LC_MESSAGES or LANG, as gettext looks them up
+    pub fn from_environment() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|variable| env::var(variable).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| Lang::from_locale(&locale))
+            .unwrap_or(Lang::En)
+    }
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+/// Choose the language of messages, the one given to --lang or else the one of the environment, and whether they're
+/// limited to plain ASCII with --ascii
+pub fn select(lang: Option<Lang>, ascii: bool) {
+    let _ = LANG.set(lang.unwrap_or_else(Lang::from_environment));
+    ASCII.store(ascii, Ordering::Relaxed);
+}
+
+/// Whether output is limited to plain ASCII, for terminals that can't show anything else
+pub fn ascii() -> bool {
+    ASCII.load(Ordering::Relaxed)
+}
+
+fn current() -> Lang {
+    *LANG.get().unwrap_or(&Lang::En)
+}
+
+/// A message shown while scanning or in the summary after it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Message {
+    ScanningFiles,
+    DiscoveryProgress,
+    FindingDuplicates,
+    DoubleChecking,
+    Verifying,
+    FindingSimilar,
+    ComparingVideos,
+    ReadingMailboxes,
+    EstimatingDedup,
+    HashingFiles,
+    HashingObjects,
+    HashingRemote,
+    ConfirmHashing,
+    HashingEstimate,
+    Cancelled,
+    NoFiles,
+    Truncated,
+    QuitEarly,
+    Skipped,
+    QuickMode,
+    Took,
+    TotalWasted,
+    WastedShare,
+    HotkeyHint,
+    PausedHint,
+    DeleteSummary,
+    HardlinkSummary,
+    SymlinkSummary,
+    ConfirmActions,
+    ActionsCancelled,
+    DeletedSummary,
+    HardlinkedSummary,
+    SymlinkedSummary,
+    DryRun,
+    SameDirectory,
+    CacheUse,
+    ReviewGroup,
+    ReviewKeys,
+    SkippedUnreadable,
+    DanglingSymlink,
+    DanglingSymlinks,
+    Vanished,
+    NoPlanAction,
+    NoQuickPlans,
+    NoByteComparePlans,
+    NoQuickActions,
+    AppliedSummary,
+    WouldDelete,
+    WouldHardlink,
+    WouldSymlink,
+    SummaryDirectory,
+    RefuseSystemRoot,
+    RefuseDelete,
+    RefuseHardlink,
+    RefuseSymlink,
+    InSystemDirectory,
+    OutsideScanned,
+    OptedOut,
+    ProtectedByConfig,
+    StraySidecar,
+    RefusedSidecar,
+    SkippingHardlinks,
+    SkippedProtected,
+    FailedDelete,
+    FailedHardlink,
+    FailedSymlink,
+    FailedMove,
+    FreeShort,
+    FreeActing,
+    StaleHashes,
+    StaleHashesLeft,
+    SidecarMoved,
+    SidecarDeleted,
+    SidecarLeft,
+    NoCoverageSources,
+    AllCovered,
+    ServiceWritten,
+    CacheHashes,
+    CacheSize,
+    CacheLastScan,
+    CacheNoScan,
+    ConfigWritten,
+    BackedUp,
+    NotBackedUp,
+}
+
+impl Message {
+    /// Every message, for checking the catalog
+    #[cfg(test)]
+    const ALL: [Message; 84] = [
+        Message::ScanningFiles,
+        Message::DiscoveryProgress,
+        Message::FindingDuplicates,
+        Message::DoubleChecking,
+        Message::Verifying,
+        Message::FindingSimilar,
+        Message::ComparingVideos,
+        Message::ReadingMailboxes,
+        Message::EstimatingDedup,
+        Message::HashingFiles,
+        Message::HashingObjects,
+        Message::HashingRemote,
+        Message::ConfirmHashing,
+        Message::HashingEstimate,
+        Message::Cancelled,
+        Message::NoFiles,
+        Message::Truncated,
+        Message::QuitEarly,
+        Message::Skipped,
+        Message::QuickMode,
+        Message::Took,
+        Message::TotalWasted,
+        Message::WastedShare,
+        Message::HotkeyHint,
+        Message::PausedHint,
+        Message::DeleteSummary,
+        Message::HardlinkSummary,
+        Message::SymlinkSummary,
+        Message::ConfirmActions,
+        Message::ActionsCancelled,
+        Message::DeletedSummary,
+        Message::HardlinkedSummary,
+        Message::SymlinkedSummary,
+        Message::DryRun,
+        Message::SameDirectory,
+        Message::CacheUse,
+        Message::ReviewGroup,
+        Message::ReviewKeys,
+        Message::SkippedUnreadable,
+        Message::DanglingSymlink,
+        Message::DanglingSymlinks,
+        Message::Vanished,
+        Message::NoPlanAction,
+        Message::NoQuickPlans,
+        Message::NoByteComparePlans,
+        Message::NoQuickActions,
+        Message::AppliedSummary,
+        Message::WouldDelete,
+        Message::WouldHardlink,
+        Message::WouldSymlink,
+        Message::SummaryDirectory,
+        Message::RefuseSystemRoot,
+        Message::RefuseDelete,
+        Message::RefuseHardlink,
+        Message::RefuseSymlink,
+        Message::InSystemDirectory,
+        Message::OutsideScanned,
+        Message::OptedOut,
+        Message::ProtectedByConfig,
+        Message::StraySidecar,
+        Message::RefusedSidecar,
+        Message::SkippingHardlinks,
+        Message::SkippedProtected,
+        Message::FailedDelete,
+        Message::FailedHardlink,
+        Message::FailedSymlink,
+        Message::FailedMove,
+        Message::FreeShort,
+        Message::FreeActing,
+        Message::StaleHashes,
+        Message::StaleHashesLeft,
+        Message::SidecarMoved,
+        Message::SidecarDeleted,
+        Message::SidecarLeft,
+        Message::NoCoverageSources,
+        Message::AllCovered,
+        Message::ServiceWritten,
+        Message::CacheHashes,
+        Message::CacheSize,
+        Message::CacheLastScan,
+        Message::CacheNoScan,
+        Message::ConfigWritten,
+        Message::BackedUp,
+        Message::NotBackedUp,
+    ];
+
+    /// The message in English, German, French and Spanish, in the order of [`Lang`], with {named} placeholders
+    fn catalog(self) -> [&'static str; 4] {
+        match self {
+            Message::ScanningFiles => [
+                "Scanning files",
+                "Dateien werden gesucht",
+                "Recherche des fichiers",
+                "Buscando archivos",
+            ],
+            Message::DiscoveryProgress => [
+                "{directories} directories, {files} files, {bytes} ({rate} files/s)",
+                "{directories} Verzeichnisse, {files} Dateien, {bytes} ({rate} Dateien/s)",
+                "{directories} dossiers, {files} fichiers, {bytes} ({rate} fichiers/s)",
+                "{directories} carpetas, {files} archivos, {bytes} ({rate} archivos/s)",
+            ],
+            Message::FindingDuplicates => [
+                "Finding duplicates",
+                "Duplikate werden gesucht",
+                "Recherche des doublons",
+                "Buscando duplicados",
+            ],
+            Message::DoubleChecking => [
+                "Double checking duplicates",
+                "Duplikate werden nochmals geprüft",
+                "Nouvelle vérification des doublons",
+                "Verificando de nuevo los duplicados",
+            ],
+            Message::Verifying => [
+                "Comparing duplicates byte by byte",
+                "Duplikate werden Byte für Byte verglichen",
+                "Comparaison des doublons octet par octet",
+                "Comparando los duplicados byte a byte",
+            ],
+            Message::FindingSimilar => [
+                "Finding similar files",
+                "Ähnliche Dateien werden gesucht",
+                "Recherche des fichiers similaires",
+                "Buscando archivos similares",
+            ],
+            Message::ComparingVideos => [
+                "Comparing videos",
+                "Videos werden verglichen",
+                "Comparaison des vidéos",
+                "Comparando vídeos",
+            ],
+            Message::ReadingMailboxes => [
+                "Reading mailboxes",
+                "Postfächer werden gelesen",
+                "Lecture des boîtes aux lettres",
+                "Leyendo buzones",
+            ],
+            Message::EstimatingDedup => [
+                "Estimating block-level deduplication",
+                "Deduplizierung auf Blockebene wird geschätzt",
+                "Estimation de la déduplication par blocs",
+                "Estimando la deduplicación por bloques",
+            ],
+            Message::HashingFiles => [
+                "Hashing files",
+                "Dateien werden gehasht",
+                "Calcul des empreintes des fichiers",
+                "Calculando el hash de los archivos",
+            ],
+            Message::HashingObjects => [
+                "Hashing objects in S3",
+                "Objekte in S3 werden gehasht",
+                "Calcul des empreintes des objets S3",
+                "Calculando el hash de los objetos en S3",
+            ],
+            Message::HashingRemote => [
+                "Hashing files on {destination}:{path}",
+                "Dateien auf {destination}:{path} werden gehasht",
+                "Calcul des empreintes des fichiers sur {destination}:{path}",
+                "Calculando el hash de los archivos en {destination}:{path}",
+            ],
+            Message::ConfirmHashing => [
+                "About to hash {files} files, {bytes}{estimate}. Continue? [y/N] ",
+                "Gleich werden {files} Dateien gehasht, {bytes}{estimate}. Fortfahren? [j/N] ",
+                "{files} fichiers vont être lus, {bytes}{estimate}. Continuer ? [o/N] ",
+                "Se van a leer {files} archivos, {bytes}{estimate}. ¿Continuar? [s/N] ",
+            ],
+            Message::HashingEstimate => [
+                ", which should take about {duration}",
+                ", was etwa {duration} dauern sollte",
+                ", ce qui devrait prendre environ {duration}",
+                ", lo que debería tardar unos {duration}",
+            ],
+            Message::Cancelled => [
+                "Cancelled, no files were hashed",
+                "Abgebrochen, es wurden keine Dateien gehasht",
+                "Annulé, aucun fichier n'a été lu",
+                "Cancelado, no se leyó ningún archivo",
+            ],
+            Message::NoFiles => [
+                "No files to scan, rupes will now exit",
+                "Keine Dateien zu durchsuchen, rupes wird beendet",
+                "Aucun fichier à analyser, rupes va s'arrêter",
+                "No hay archivos que analizar, rupes terminará",
+            ],
+            Message::Truncated => [
+                "Truncated: discovery stopped at the --max-files or --max-bytes limit, these results only cover a sample of the files",
+                "Gekürzt: die Suche endete an der Grenze von --max-files oder --max-bytes, diese Ergebnisse umfassen nur einen Teil der Dateien",
+                "Tronqué : la recherche s'est arrêtée à la limite de --max-files ou --max-bytes, ces résultats ne couvrent qu'une partie des fichiers",
+                "Truncado: la búsqueda se detuvo en el límite de --max-files o --max-bytes, estos resultados solo cubren una parte de los archivos",
+            ],
+            Message::QuitEarly => [
+                "Quit early: {files} files weren't compared, these results are partial",
+                "Vorzeitig beendet: {files} Dateien wurden nicht verglichen, diese Ergebnisse sind unvollständig",
+                "Arrêt anticipé : {files} fichiers n'ont pas été comparés, ces résultats sont partiels",
+                "Salida anticipada: {files} archivos no se compararon, estos resultados son parciales",
+            ],
+            Message::Skipped => [
+                "Skipped {files} files, they're left out of these results",
+                "{files} Dateien übersprungen, sie fehlen in diesen Ergebnissen",
+                "{files} fichiers ignorés, ils ne figurent pas dans ces résultats",
+                "{files} archivos omitidos, no aparecen en estos resultados",
+            ],
+            Message::QuickMode => [
+                "Quick mode: only sizes and the first and last 64 KiB of each file were compared, these duplicates are probable rather than confirmed",
+                "Schnellmodus: nur die Größen und die ersten und letzten 64 KiB jeder Datei wurden verglichen, diese Duplikate sind wahrscheinlich, aber nicht bestätigt",
+                "Mode rapide : seuls les tailles et les premiers et derniers 64 Kio de chaque fichier ont été comparés, ces doublons sont probables mais pas confirmés",
+                "Modo rápido: solo se compararon los tamaños y los primeros y últimos 64 KiB de cada archivo, estos duplicados son probables pero no están confirmados",
+            ],
+            Message::Took => [
+                "Took {duration} to complete",
+                "Fertig nach {duration}",
+                "Terminé en {duration}",
+                "Completado en {duration}",
+            ],
+            Message::TotalWasted => [
+                "{bytes} total wasted space",
+                "{bytes} verschwendeter Speicherplatz insgesamt",
+                "{bytes} d'espace gaspillé au total",
+                "{bytes} de espacio desperdiciado en total",
+            ],
+            Message::WastedShare => [
+                "Duplicates account for {percent}% of scanned bytes, removing them shrinks the files from {scanned} to {remaining}",
+                "Duplikate machen {percent} % der durchsuchten Bytes aus, ohne sie schrumpfen die Dateien von {scanned} auf {remaining}",
+                "Les doublons représentent {percent} % des octets analysés, les supprimer réduit les fichiers de {scanned} à {remaining}",
+                "Los duplicados suponen el {percent} % de los bytes analizados, eliminarlos reduce los archivos de {scanned} a {remaining}",
+            ],
+            Message::HotkeyHint => [
+                "p pause, s skip the file shown, q quit with partial results",
+                "p Pause, s angezeigte Datei überspringen, q mit Teilergebnissen beenden",
+                "p pause, s ignorer le fichier affiché, q quitter avec des résultats partiels",
+                "p pausa, s omitir el archivo mostrado, q salir con resultados parciales",
+            ],
+            Message::PausedHint => [
+                "paused, p to resume",
+                "pausiert, p zum Fortsetzen",
+                "en pause, p pour reprendre",
+                "en pausa, p para continuar",
+            ],
+            Message::DeleteSummary => [
+                "About to delete {files} files, freeing {bytes}",
+                "Gleich werden {files} Dateien gelöscht, das gibt {bytes} frei",
+                "{files} fichiers vont être supprimés, libérant {bytes}",
+                "Se van a eliminar {files} archivos, liberando {bytes}",
+            ],
+            Message::HardlinkSummary => [
+                "About to replace {files} files with hardlinks, freeing {bytes}",
+                "Gleich werden {files} Dateien durch Hardlinks ersetzt, das gibt {bytes} frei",
+                "{files} fichiers vont être remplacés par des liens physiques, libérant {bytes}",
+                "Se van a reemplazar {files} archivos por enlaces duros, liberando {bytes}",
+            ],
+            Message::SymlinkSummary => [
+                "About to replace {files} files with symlinks, freeing {bytes}",
+                "Gleich werden {files} Dateien durch symbolische Links ersetzt, das gibt {bytes} frei",
+                "{files} fichiers vont être remplacés par des liens symboliques, libérant {bytes}",
+                "Se van a reemplazar {files} archivos por enlaces simbólicos, liberando {bytes}",
+            ],
+            Message::ConfirmActions => [
+                "Go ahead? [y/N] ",
+                "Fortfahren? [j/N] ",
+                "Continuer ? [o/N] ",
+                "¿Continuar? [s/N] ",
+            ],
+            Message::ActionsCancelled => [
+                "Cancelled, no files were changed",
+                "Abgebrochen, es wurden keine Dateien geändert",
+                "Annulé, aucun fichier n'a été modifié",
+                "Cancelado, no se modificó ningún archivo",
+            ],
+            Message::DeletedSummary => [
+                "Deleted {files} of {planned} files, freeing {bytes}",
+                "{files} von {planned} Dateien gelöscht, das gab {bytes} frei",
+                "{files} fichiers sur {planned} supprimés, libérant {bytes}",
+                "Se eliminaron {files} de {planned} archivos, liberando {bytes}",
+            ],
+            Message::HardlinkedSummary => [
+                "Replaced {files} of {planned} files with hardlinks, freeing {bytes}",
+                "{files} von {planned} Dateien durch Hardlinks ersetzt, das gab {bytes} frei",
+                "{files} fichiers sur {planned} remplacés par des liens physiques, libérant {bytes}",
+                "Se reemplazaron {files} de {planned} archivos por enlaces duros, liberando {bytes}",
+            ],
+            Message::SymlinkedSummary => [
+                "Replaced {files} of {planned} files with symlinks, freeing {bytes}",
+                "{files} von {planned} Dateien durch symbolische Links ersetzt, das gab {bytes} frei",
+                "{files} fichiers sur {planned} remplacés par des liens symboliques, libérant {bytes}",
+                "Se reemplazaron {files} de {planned} archivos por enlaces simbólicos, liberando {bytes}",
+            ],
+            Message::DryRun => [
+                "Dry run, no files were changed",
+                "Probelauf, es wurden keine Dateien geändert",
+                "Simulation, aucun fichier n'a été modifié",
+                "Simulación, no se modificó ningún archivo",
+            ],
+            Message::SameDirectory => [
+                "Scanned {path} once, as {first}, they are the same directory, such as through a bind mount",
+                "{path} wurde nur einmal durchsucht, als {first}, es ist dasselbe Verzeichnis, etwa über einen Bind-Mount",
+                "{path} n'a été parcouru qu'une fois, en tant que {first}, c'est le même répertoire, par exemple via un montage lié",
+                "{path} se recorrió una sola vez, como {first}, es el mismo directorio, por ejemplo mediante un montaje enlazado",
+            ],
+            Message::CacheUse => [
+                "Hash cache: {hits} hashes reused, {misses} computed, {bytes} not read",
+                "Hash-Cache: {hits} Hashes wiederverwendet, {misses} berechnet, {bytes} nicht gelesen",
+                "Cache d'empreintes : {hits} empreintes réutilisées, {misses} calculées, {bytes} non lus",
+                "Caché de hashes: {hits} hashes reutilizados, {misses} calculados, {bytes} sin leer",
+            ],
+            Message::ReviewGroup => [
+                "Group {group} of {groups}, {operation} the copies marked [x], keeping the first",
+                "Gruppe {group} von {groups}, {operation} für die mit [x] markierten Kopien, die erste bleibt",
+                "Groupe {group} sur {groups}, {operation} des copies marquées [x], la première est conservée",
+                "Grupo {group} de {groups}, {operation} de las copias marcadas [x], se conserva la primera",
+            ],
+            Message::ReviewKeys => [
+                "up/down move, space mark, a mark all, n mark none, c keep another copy, enter next, b back, q done leaving the groups not shown alone, esc cancel",
+                "hoch/runter bewegen, Leertaste markieren, a alle, n keine, c andere Kopie behalten, Enter weiter, b zurück, q fertig, nicht gezeigte Gruppen bleiben unberührt, Esc abbrechen",
+                "haut/bas déplacer, espace marquer, a tout, n aucune, c garder une autre copie, entrée suivant, b retour, q terminer sans toucher aux groupes non vus, échap annuler",
+                "arriba/abajo mover, espacio marcar, a todas, n ninguna, c conservar otra copia, intro siguiente, b atrás, q terminar sin tocar los grupos no vistos, esc cancelar",
+            ],
+            Message::SkippedUnreadable => [
+                "Skipped {count} files and directories that couldn't be read: {reasons}",
+                "{count} Dateien und Verzeichnisse übersprungen, die nicht gelesen werden konnten: {reasons}",
+                "{count} fichiers et dossiers illisibles ignorés : {reasons}",
+                "Se omitieron {count} archivos y carpetas que no se pudieron leer: {reasons}",
+            ],
+            Message::DanglingSymlink => [
+                "dangling symlink",
+                "verwaister symbolischer Link",
+                "lien symbolique brisé",
+                "enlace simbólico roto",
+            ],
+            Message::DanglingSymlinks => [
+                "dangling symlinks",
+                "verwaiste symbolische Links",
+                "liens symboliques brisés",
+                "enlaces simbólicos rotos",
+            ],
+            Message::Vanished => [
+                "vanished",
+                "verschwunden",
+                "disparus",
+                "desaparecidos",
+            ],
+            Message::NoPlanAction => [
+                "Please specify an action to plan with --delete, --hardlink or --symlink",
+                "Bitte eine Aktion für den Plan angeben, mit --delete, --hardlink oder --symlink",
+                "Indiquez une action à planifier avec --delete, --hardlink ou --symlink",
+                "Indica una acción que planificar con --delete, --hardlink o --symlink",
+            ],
+            Message::NoQuickPlans => [
+                "Plans can't be made from --quick matches, their hashes only cover part of each file",
+                "Aus Treffern von --quick lassen sich keine Pläne erstellen, ihre Hashes decken nur einen Teil jeder Datei ab",
+                "Impossible de planifier à partir des correspondances de --quick, leurs empreintes ne couvrent qu'une partie de chaque fichier",
+                "No se pueden hacer planes con coincidencias de --quick, sus hashes solo cubren una parte de cada archivo",
+            ],
+            Message::NoByteComparePlans => [
+                "Plans can't be made with --no-hash, apply checks files against the hashes they record",
+                "Mit --no-hash lassen sich keine Pläne erstellen, apply prüft die Dateien anhand der darin festgehaltenen Hashes",
+                "Impossible de planifier avec --no-hash, apply vérifie les fichiers à l'aide des empreintes enregistrées",
+                "No se pueden hacer planes con --no-hash, apply comprueba los archivos con los hashes que guardan",
+            ],
+            Message::NoQuickActions => [
+                "Refusing to act on probable --quick matches, add --double-check to confirm them first",
+                "Wahrscheinliche Treffer von --quick werden nicht bearbeitet, zuerst mit --double-check bestätigen",
+                "Refus d'agir sur des correspondances probables de --quick, ajoutez --double-check pour les confirmer d'abord",
+                "No se actuará sobre coincidencias probables de --quick, añade --double-check para confirmarlas primero",
+            ],
+            Message::AppliedSummary => [
+                "Applied {applied} of {planned} planned actions",
+                "{applied} von {planned} geplanten Aktionen ausgeführt",
+                "{applied} actions planifiées sur {planned} appliquées",
+                "Se aplicaron {applied} de {planned} acciones planificadas",
+            ],
+            Message::WouldDelete => [
+                "Would delete {path}, a copy of {target}, freeing {bytes}",
+                "Würde {path} löschen, eine Kopie von {target}, das gäbe {bytes} frei",
+                "Supprimerait {path}, une copie de {target}, libérant {bytes}",
+                "Se eliminaría {path}, una copia de {target}, liberando {bytes}",
+            ],
+            Message::WouldHardlink => [
+                "Would hardlink {path}, a copy of {target}, freeing {bytes}",
+                "Würde {path} durch einen Hardlink ersetzen, eine Kopie von {target}, das gäbe {bytes} frei",
+                "Remplacerait {path} par un lien physique, une copie de {target}, libérant {bytes}",
+                "Se reemplazaría {path} por un enlace duro, una copia de {target}, liberando {bytes}",
+            ],
+            Message::WouldSymlink => [
+                "Would symlink {path}, a copy of {target}, freeing {bytes}",
+                "Würde {path} durch einen symbolischen Link ersetzen, eine Kopie von {target}, das gäbe {bytes} frei",
+                "Remplacerait {path} par un lien symbolique, une copie de {target}, libérant {bytes}",
+                "Se reemplazaría {path} por un enlace simbólico, una copia de {target}, liberando {bytes}",
+            ],
+            Message::SummaryDirectory => [
+                "{bytes}  {files} files  {directory}",
+                "{bytes}  {files} Dateien  {directory}",
+                "{bytes}  {files} fichiers  {directory}",
+                "{bytes}  {files} archivos  {directory}",
+            ],
+            Message::RefuseSystemRoot => [
+                "Refusing to act on files in {path}, a system directory, add --allow-system if that's really intended",
+                "Dateien in {path} werden nicht bearbeitet, es ist ein Systemverzeichnis, --allow-system hinzufügen, wenn das wirklich gewollt ist",
+                "Refus d'agir sur les fichiers de {path}, un dossier système, ajoutez --allow-system si c'est vraiment voulu",
+                "No se actuará sobre los archivos de {path}, una carpeta del sistema, añade --allow-system si es realmente lo que quieres",
+            ],
+            Message::RefuseDelete => [
+                "Refusing to delete {path}, {reason}",
+                "{path} wird nicht gelöscht, {reason}",
+                "Refus de supprimer {path}, {reason}",
+                "No se eliminará {path}, {reason}",
+            ],
+            Message::RefuseHardlink => [
+                "Refusing to hardlink {path}, {reason}",
+                "{path} wird nicht durch einen Hardlink ersetzt, {reason}",
+                "Refus de remplacer {path} par un lien physique, {reason}",
+                "No se reemplazará {path} por un enlace duro, {reason}",
+            ],
+            Message::RefuseSymlink => [
+                "Refusing to symlink {path}, {reason}",
+                "{path} wird nicht durch einen symbolischen Link ersetzt, {reason}",
+                "Refus de remplacer {path} par un lien symbolique, {reason}",
+                "No se reemplazará {path} por un enlace simbólico, {reason}",
+            ],
+            Message::InSystemDirectory => [
+                "it's in a system directory, add --allow-system if that's really intended",
+                "es liegt in einem Systemverzeichnis, --allow-system hinzufügen, wenn das wirklich gewollt ist",
+                "il est dans un dossier système, ajoutez --allow-system si c'est vraiment voulu",
+                "está en una carpeta del sistema, añade --allow-system si es realmente lo que quieres",
+            ],
+            Message::OutsideScanned => [
+                "it's outside the scanned directories",
+                "es liegt außerhalb der durchsuchten Verzeichnisse",
+                "il est en dehors des dossiers analysés",
+                "está fuera de las carpetas analizadas",
+            ],
+            Message::OptedOut => [
+                "a skip marker opts its directory out",
+                "eine Markierungsdatei nimmt sein Verzeichnis aus",
+                "un marqueur exclut son dossier",
+                "un marcador excluye su carpeta",
+            ],
+            Message::ProtectedByConfig => [
+                "a {config} protects its directory",
+                "eine {config} schützt sein Verzeichnis",
+                "un {config} protège son dossier",
+                "un {config} protege su carpeta",
+            ],
+            Message::StraySidecar => [
+                "its sidecar {path} isn't next to it",
+                "seine Begleitdatei {path} liegt nicht daneben",
+                "son fichier annexe {path} n'est pas à côté de lui",
+                "su archivo complementario {path} no está junto a él",
+            ],
+            Message::RefusedSidecar => [
+                "its sidecar {path} can't be taken along, {reason}",
+                "seine Begleitdatei {path} kann nicht mitgenommen werden, {reason}",
+                "son fichier annexe {path} ne peut pas l'accompagner, {reason}",
+                "su archivo complementario {path} no puede acompañarlo, {reason}",
+            ],
+            Message::SkippingHardlinks => [
+                "Skipping {files} more hardlinks to {target}, its filesystem can't link it any further",
+                "{files} weitere Hardlinks auf {target} übersprungen, sein Dateisystem erlaubt keine weiteren",
+                "{files} liens physiques de plus vers {target} ignorés, son système de fichiers ne peut plus en créer",
+                "Se omiten {files} enlaces duros más a {target}, su sistema de archivos no admite más",
+            ],
+            Message::SkippedProtected => [
+                "Skipped {path}, it's read-only or protected: {error}",
+                "{path} übersprungen, es ist schreibgeschützt oder geschützt: {error}",
+                "{path} ignoré, il est en lecture seule ou protégé : {error}",
+                "Se omitió {path}, es de solo lectura o está protegido: {error}",
+            ],
+            Message::FailedDelete => [
+                "Failed to delete {path}: {error}",
+                "{path} konnte nicht gelöscht werden: {error}",
+                "Échec de la suppression de {path} : {error}",
+                "No se pudo eliminar {path}: {error}",
+            ],
+            Message::FailedHardlink => [
+                "Failed to hardlink {path}: {error}",
+                "{path} konnte nicht durch einen Hardlink ersetzt werden: {error}",
+                "Échec du remplacement de {path} par un lien physique : {error}",
+                "No se pudo reemplazar {path} por un enlace duro: {error}",
+            ],
+            Message::FailedSymlink => [
+                "Failed to symlink {path}: {error}",
+                "{path} konnte nicht durch einen symbolischen Link ersetzt werden: {error}",
+                "Échec du remplacement de {path} par un lien symbolique : {error}",
+                "No se pudo reemplazar {path} por un enlace simbólico: {error}",
+            ],
+            Message::FailedMove => [
+                "Failed to move {path}: {error}",
+                "{path} konnte nicht verschoben werden: {error}",
+                "Échec du déplacement de {path} : {error}",
+                "No se pudo mover {path}: {error}",
+            ],
+            Message::FreeShort => [
+                "Only {bytes} can be reclaimed, short of the {goal} asked for",
+                "Nur {bytes} lassen sich freigeben, weniger als die verlangten {goal}",
+                "Seuls {bytes} peuvent être récupérés, moins que les {goal} demandés",
+                "Solo se pueden recuperar {bytes}, menos de los {goal} pedidos",
+            ],
+            Message::FreeActing => [
+                "Acting on {groups} of {found} groups to reclaim {bytes}",
+                "{groups} von {found} Gruppen werden bearbeitet, um {bytes} freizugeben",
+                "Action sur {groups} groupes sur {found} pour récupérer {bytes}",
+                "Se actuará sobre {groups} de {found} grupos para recuperar {bytes}",
+            ],
+            Message::StaleHashes => [
+                "Dropped {hashes} stale hashes from {cache}",
+                "{hashes} veraltete Hashes aus {cache} entfernt",
+                "{hashes} empreintes obsolètes retirées de {cache}",
+                "Se quitaron {hashes} hashes obsoletos de {cache}",
+            ],
+            Message::StaleHashesLeft => [
+                "Dropped {hashes} stale hashes from {cache}, {left} are left",
+                "{hashes} veraltete Hashes aus {cache} entfernt, {left} bleiben",
+                "{hashes} empreintes obsolètes retirées de {cache}, il en reste {left}",
+                "Se quitaron {hashes} hashes obsoletos de {cache}, quedan {left}",
+            ],
+            Message::SidecarMoved => [
+                "Moved {path}, a sidecar of {kept}",
+                "{path} verschoben, eine Begleitdatei von {kept}",
+                "{path} déplacé, un fichier annexe de {kept}",
+                "Se movió {path}, un archivo complementario de {kept}",
+            ],
+            Message::SidecarDeleted => [
+                "Deleted {path}, a sidecar of {kept}",
+                "{path} gelöscht, eine Begleitdatei von {kept}",
+                "{path} supprimé, un fichier annexe de {kept}",
+                "Se eliminó {path}, un archivo complementario de {kept}",
+            ],
+            Message::SidecarLeft => [
+                "Left {path}, {kept} already has a different sidecar",
+                "{path} bleibt, {kept} hat bereits eine andere Begleitdatei",
+                "{path} laissé en place, {kept} a déjà un autre fichier annexe",
+                "Se dejó {path}, {kept} ya tiene otro archivo complementario",
+            ],
+            Message::NoCoverageSources => [
+                "Please give the directory to check, then the directories or manifests to look for its files in",
+                "Bitte das zu prüfende Verzeichnis angeben, dann die Verzeichnisse oder Manifeste, in denen seine Dateien gesucht werden",
+                "Indiquez le dossier à vérifier, puis les dossiers ou manifestes où chercher ses fichiers",
+                "Indica la carpeta que comprobar y luego las carpetas o manifiestos donde buscar sus archivos",
+            ],
+            Message::AllCovered => [
+                "Verified: every one of the {files} files in {directory} has a copy",
+                "Geprüft: jede der {files} Dateien in {directory} hat eine Kopie",
+                "Vérifié : chacun des {files} fichiers de {directory} a une copie",
+                "Verificado: cada uno de los {files} archivos de {directory} tiene una copia",
+            ],
+            Message::ServiceWritten => [
+                "Wrote a service to {path}, start it with `{command}`",
+                "Dienst nach {path} geschrieben, starten mit `{command}`",
+                "Service écrit dans {path}, démarrez-le avec `{command}`",
+                "Se escribió un servicio en {path}, inícialo con `{command}`",
+            ],
+            Message::CacheHashes => [
+                "Hashes: {hashes}",
+                "Hashes: {hashes}",
+                "Empreintes : {hashes}",
+                "Hashes: {hashes}",
+            ],
+            Message::CacheSize => [
+                "Size on disk: {size}",
+                "Größe auf der Festplatte: {size}",
+                "Taille sur le disque : {size}",
+                "Tamaño en disco: {size}",
+            ],
+            Message::CacheLastScan => [
+                "Last scan: {hits} of {hashes} hashes from the cache ({rate}%), {saved} not read",
+                "Letzter Scan: {hits} von {hashes} Hashes aus dem Cache ({rate} %), {saved} nicht gelesen",
+                "Dernière analyse : {hits} empreintes sur {hashes} tirées du cache ({rate} %), {saved} non lus",
+                "Último análisis: {hits} de {hashes} hashes desde la caché ({rate} %), {saved} sin leer",
+            ],
+            Message::CacheNoScan => [
+                "Last scan: none yet",
+                "Letzter Scan: noch keiner",
+                "Dernière analyse : aucune pour l'instant",
+                "Último análisis: ninguno todavía",
+            ],
+            Message::ConfigWritten => [
+                "Wrote a starter configuration to {path}",
+                "Startkonfiguration nach {path} geschrieben",
+                "Configuration de départ écrite dans {path}",
+                "Se escribió una configuración inicial en {path}",
+            ],
+            Message::BackedUp => [
+                "Backed up, safe to delete locally: {files} files, {bytes}",
+                "Gesichert, kann lokal gelöscht werden: {files} Dateien, {bytes}",
+                "Sauvegardés, supprimables localement : {files} fichiers, {bytes}",
+                "Con copia de seguridad, se pueden eliminar localmente: {files} archivos, {bytes}",
+            ],
+            Message::NotBackedUp => [
+                "Not backed up yet: {files} files, {bytes}",
+                "Noch nicht gesichert: {files} Dateien, {bytes}",
+                "Pas encore sauvegardés : {files} fichiers, {bytes}",
+                "Sin copia de seguridad todavía: {files} archivos, {bytes}",
+            ],
+        }
+    }
+}
+
+fn fill(template: &str, values: &[(&str, &dyn Display)]) -> String {
+    let mut text = template.to_string();
+    for (name, value) in values {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// `text` with accented letters spelled without their accents, and anything else that isn't ASCII replaced by '?'
+fn to_ascii(text: &str) -> String {
+    let mut ascii = String::with_capacity(text.len());
+    for character in text.chars() {
+        let plain = match character {
+            character if character.is_ascii() => {
+                ascii.push(character);
+                continue;
+            }
+            'ä' => "ae",
+            'ö' => "oe",
+            'ü' => "ue",
+            'Ä' => "Ae",
+            'Ö' => "Oe",
+            'Ü' => "Ue",
+            'ß' => "ss",
+            'à' | 'á' | 'â' => "a",
+            'ç' => "c",
+            'é' | 'è' | 'ê' | 'ë' => "e",
+            'É' => "E",
+            'í' | 'î' | 'ï' => "i",
+            'ñ' => "n",
+            'ó' | 'ô' => "o",
+            'ú' | 'ù' | 'û' => "u",
+            'µ' => "u",
+            '¿' | '¡' => "",
+            _ => "?",
+        };
+        ascii.push_str(plain);
+    }
+    ascii
+}
+
+/// `message` in the selected language, with each {name} placeholder replaced by its value
+pub fn text(message: Message, values: &[(&str, &dyn Display)]) -> String {
+    let text = fill(message.catalog()[current() as usize], values);
+    if ascii() {
+        to_ascii(&text)
+    } else {
+        text
+    }
+}
+
+/// A rough duration, in words for English and as hours:minutes:seconds otherwise
+pub fn duration(duration: Duration) -> String {
+    match current() {
+        Lang::En => indicatif::HumanDuration(duration).to_string(),
+        _ => indicatif::FormattedDuration(duration).to_string(),
+    }
+}
+
+/// Whether the answer to a [y/N] prompt is yes, in English or the selected language
+pub fn is_yes(answer: &str) -> bool {
+    let answer = answer.trim().to_lowercase();
+    let localized: &[&str] = match current() {
+        Lang::En => &[],
+        Lang::De => &["j", "ja"],
+        Lang::Fr => &["o", "oui"],
+        Lang::Es => &["s", "si", "sí"],
+    };
+
+    matches!(answer.as_str(), "y" | "yes") || localized.contains(&answer.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(template: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = template
+            .split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn translations_match() {
+        for message in Message::ALL {
+            let [english, translations @ ..] = message.catalog();
+            for translation in translations {
+                assert_eq!(
+                    placeholders(english),
+                    placeholders(translation),
+                    "{:?}",
+                    message
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn locales() {
+        assert_eq!(Lang::from_locale("de_DE.UTF-8"), Some(Lang::De));
+        assert_eq!(Lang::from_locale("fr_CA"), Some(Lang::Fr));
+        assert_eq!(Lang::from_locale("es"), Some(Lang::Es));
+        assert_eq!(Lang::from_locale("C.UTF-8"), Some(Lang::En));
+        assert_eq!(Lang::from_locale("ja_JP.UTF-8"), None);
+
+        assert_eq!(
+            fill(
+                Message::catalog(Message::Skipped)[Lang::De as usize],
+                &[("files", &3)]
+            ),
+            "3 Dateien übersprungen, sie fehlen in diesen Ergebnissen"
+        );
+    }
+
+    #[test]
+    fn ascii_only() {
+        assert_eq!(
+            to_ascii("Duplikate werden nochmals geprüft"),
+            "Duplikate werden nochmals geprueft"
+        );
+        assert_eq!(to_ascii("¿Continuar? [s/N]"), "Continuar? [s/N]");
+        assert_eq!(to_ascii("Took 12.5µs"), "Took 12.5us");
+        assert_eq!(to_ascii("日本"), "??");
+
+        for message in Message::ALL {
+            for text in message.catalog() {
+                assert!(to_ascii(text).is_ascii());
+            }
+        }
+    }
+}
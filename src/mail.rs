@@ -0,0 +1,316 @@
+//! Mail messages compared by what was sent rather than the bytes of the files keeping them
+//!
+//! Every copy of a message delivered to a mailbox gets headers of its own on the way, such as Received and
+//! Delivered-To, and mail clients add status headers when it's read or flagged. Those headers are dropped before
+//! comparing, and line endings normalized, so the copies match while messages with different senders, recipients, dates
+//! or Message-IDs don't.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Result};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use rupes::{ContentComparator, Digest, FileEntry, HashAlgorithm, HashComparator};
+
+/// Headers added while a message is delivered or stored, rather than by its sender
+const TRANSIT_HEADERS: [&str; 21] = [
+    "arc-authentication-results",
+    "arc-message-signature",
+    "arc-seal",
+    "authentication-results",
+    "content-length",
+    "delivered-to",
+    "envelope-to",
+    "lines",
+    "received",
+    "received-spf",
+    "return-path",
+    "status",
+    "x-keywords",
+    "x-mozilla-keys",
+    "x-mozilla-status",
+    "x-mozilla-status2",
+    "x-original-to",
+    "x-spam-flag",
+    "x-spam-score",
+    "x-spam-status",
+    "x-status",
+];
+
+/// Whether the file at `path` is a message of its own, in the cur or new directory of a maildir or saved as .eml
+pub fn is_message(path: &Path) -> bool {
+    let in_maildir = path
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|directory| directory == "cur" || directory == "new");
+    let eml = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("eml"));
+
+    in_maildir || eml
+}
+
+/// `message` without its transit headers, its headers unfolded and their names lowercased, with LF line endings and
+/// no blank lines at its end
+fn normalized(message: &[u8]) -> Vec<u8> {
+    let mut lines = message
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+
+    let mut headers: Vec<Vec<u8>> = Vec::new();
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        match (line[0], headers.last_mut()) {
+            (b' ' | b'\t', Some(header)) => {
+                header.push(b' ');
+                header.extend_from_slice(line.trim_ascii());
+            }
+            _ => headers.push(line.to_vec()),
+        }
+    }
+
+    let mut normalized = Vec::with_capacity(message.len());
+    for header in &headers {
+        let (name, value) = match header.iter().position(|&byte| byte == b':') {
+            Some(colon) => (&header[..colon], &header[colon + 1..]),
+            None => (&header[..], &[][..]),
+        };
+        let name = name.trim_ascii().to_ascii_lowercase();
+        if TRANSIT_HEADERS
+            .iter()
+            .any(|transit| transit.as_bytes() == name)
+        {
+            continue;
+        }
+        normalized.extend_from_slice(&name);
+        normalized.extend_from_slice(b": ");
+        normalized.extend_from_slice(value.trim_ascii());
+        normalized.push(b'\n');
+    }
+
+    normalized.push(b'\n');
+    for line in lines {
+        normalized.extend_from_slice(line);
+        normalized.push(b'\n');
+    }
+    while normalized.ends_with(b"\n\n") {
+        normalized.pop();
+    }
+
+    normalized
+}
+
+/// The value of the message's Subject header, unfolded but left MIME encoded
+fn subject(message: &[u8]) -> String {
+    let mut subject: Option<String> = None;
+    for line in message.split(|&byte| byte == b'\n') {
+        let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line));
+        if line.is_empty() {
+            break;
+        }
+        match &mut subject {
+            Some(subject) if line.starts_with([' ', '\t']) => {
+                subject.push(' ');
+                subject.push_str(line.trim());
+            }
+            Some(_) => break,
+            None => {
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("subject") {
+                        subject = Some(value.trim().to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    subject.unwrap_or_default()
+}
+
+/// Compares mail messages by their content without transit headers, see [`is_message`], and every other file like
+/// `comparator` does
+///
+/// The key of a message holds the length of its normalized content rather than its size.
+#[derive(Clone, Debug, Default)]
+pub struct MailComparator {
+    pub comparator: HashComparator,
+}
+
+impl ContentComparator for MailComparator {
+    type Key = (u64, Digest);
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        if !is_message(path) {
+            return self.comparator.key(path, size);
+        }
+
+        let read = || {
+            let mut message = Vec::new();
+            File::open(path)?.read_to_end(&mut message)?;
+            Ok(normalized(&message))
+        };
+        let message = self
+            .comparator
+            .retry
+            .run(|| match &self.comparator.read_limit {
+                Some(limit) => limit.run(read),
+                None => read(),
+            })?;
+
+        Ok((
+            message.len() as u64,
+            self.comparator
+                .algorithm
+                .digest_reader(&mut message.as_slice())?,
+        ))
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+}
+
+/// A message kept in an mbox file
+#[derive(Clone, Debug)]
+pub struct MboxMessage {
+    pub mailbox: PathBuf,
+    /// Position of the message in the mailbox, the first one being 1
+    pub number: usize,
+    pub subject: String,
+}
+
+/// Call `message` with every message of the mbox file read by `reader`, with the ">From " quoting of its lines undone
+fn mbox_messages(reader: impl BufRead, mut message: impl FnMut(&[u8])) -> Result<()> {
+    let mut current: Option<Vec<u8>> = None;
+    let mut previous_blank = true;
+
+    for line in reader.split(b'\n') {
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        if previous_blank && line.starts_with(b"From ") {
+            if let Some(finished) = current.replace(Vec::new()) {
+                message(&finished);
+            }
+        } else if let Some(current) = &mut current {
+            let quoted = line.iter().take_while(|&&byte| byte == b'>').count();
+            let unquoted = if quoted > 0 && line[quoted..].starts_with(b"From ") {
+                &line[1..]
+            } else {
+                &line[..]
+            };
+            current.extend_from_slice(unquoted);
+            current.push(b'\n');
+        }
+        previous_blank = line.is_empty();
+    }
+    if let Some(finished) = current {
+        message(&finished);
+    }
+
+    Ok(())
+}
+
+/// Whether the file at `path` is an mbox file, starting with the "From " line of its first message
+fn is_mbox(path: &Path) -> bool {
+    let mut start = [0; 5];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut start))
+        .is_ok()
+        && &start == b"From "
+}
+
+/// Messages appearing more than once in the mbox files among `files`, in the same file or in different ones, calling
+/// `progress` as each file is looked at
+///
+/// Groups are sorted by their first message, and their messages by mailbox and number.
+pub fn duplicate_messages(
+    files: Vec<FileEntry>,
+    algorithm: HashAlgorithm,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<Vec<MboxMessage>>> {
+    let hashed: Vec<Vec<(Digest, MboxMessage)>> = files
+        .into_par_iter()
+        .filter(|file| !is_message(&file.path))
+        .map(|file| {
+            let mut messages = Vec::new();
+            if is_mbox(&file.path) {
+                let mut hashing = Ok(());
+                mbox_messages(BufReader::new(File::open(&file.path)?), |message| {
+                    let digest = algorithm.digest_reader(&mut normalized(message).as_slice());
+                    match digest {
+                        Ok(digest) => messages.push((
+                            digest,
+                            MboxMessage {
+                                mailbox: file.path.clone(),
+                                number: messages.len() + 1,
+                                subject: subject(message),
+                            },
+                        )),
+                        Err(error) => hashing = Err(error),
+                    }
+                })?;
+                hashing?;
+            }
+            progress(&file);
+            Ok(messages)
+        })
+        .collect::<Result<_>>()?;
+
+    let mut by_digest: HashMap<Digest, Vec<MboxMessage>> = HashMap::new();
+    for (digest, message) in hashed.into_iter().flatten() {
+        by_digest.entry(digest).or_default().push(message);
+    }
+
+    let mut groups: Vec<Vec<MboxMessage>> = by_digest
+        .into_values()
+        .filter(|messages| messages.len() >= 2)
+        .collect();
+    for group in &mut groups {
+        group.sort_by(|a, b| (&a.mailbox, a.number).cmp(&(&b.mailbox, b.number)));
+    }
+    groups.sort_by(|a, b| (&a[0].mailbox, a[0].number).cmp(&(&b[0].mailbox, b[0].number)));
+
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transit_headers_dropped() {
+        let delivered = b"Return-Path: <ann@example.com>\r\nReceived: from mx1\r\n\tby mail.example.com\r\nFrom: Ann <ann@example.com>\r\nSubject: Lunch\r\n on Friday\r\nMessage-ID: <1@example.com>\r\n\r\nSee you there\r\n";
+        let stored = b"from: Ann <ann@example.com>\nsubject: Lunch on Friday\nStatus: RO\nMessage-ID: <1@example.com>\n\nSee you there\n\n";
+        assert_eq!(normalized(delivered), normalized(stored));
+        assert_eq!(subject(delivered), "Lunch on Friday");
+
+        let resent = b"From: Ann <ann@example.com>\nSubject: Lunch on Friday\nMessage-ID: <2@example.com>\n\nSee you there\n";
+        assert_ne!(normalized(resent), normalized(stored));
+    }
+
+    #[test]
+    fn mbox() {
+        let mbox = b"From ann@example.com Mon Jan  1 00:00:00 2024\nSubject: One\n\n>From the start\nFrom here\n\nFrom bob@example.com Mon Jan  1 00:00:00 2024\nSubject: Two\n\nbody\n";
+        let mut messages = Vec::new();
+        mbox_messages(&mbox[..], |message| messages.push(message.to_vec())).unwrap();
+
+        assert_eq!(
+            messages,
+            [
+                b"Subject: One\n\nFrom the start\nFrom here\n\n".to_vec(),
+                b"Subject: Two\n\nbody\n".to_vec()
+            ]
+        );
+        assert!(is_message(Path::new(
+            "Mail/INBOX/cur/1700000000.M1P2.host:2,S"
+        )));
+        assert!(!is_message(Path::new("Mail/INBOX")));
+    }
+}
@@ -1,32 +1,297 @@
-use clap::Parser;
-use std::path::{Path, PathBuf};
-
-use std::fs::{read_dir, File};
+mod action;
+mod bench;
+mod caches;
+mod config;
+mod documents;
+mod empty;
+mod exec;
+mod hotkeys;
+mod image;
+mod keep;
+mod locale;
+mod mail;
+mod manifest;
+mod output;
+mod preset;
+mod recheck;
+mod remote;
+mod report;
+mod review;
+mod s3;
+mod sandbox;
+mod score;
+mod service;
+mod sidecar;
+mod skipped;
+mod snapshot;
+mod status;
+mod syslog;
+mod tool;
+mod video;
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::path::{Component, Path, PathBuf};
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
 use std::io;
+use std::io::Write;
+use std::sync::{Arc, OnceLock};
 
-use std::collections::HashMap;
-use std::io::{Error, ErrorKind};
 use std::io::Result;
-use std::sync::Mutex;
-
-use md5::Md5;
-use sha2::{Digest, Sha256};
+use std::io::{Error, ErrorKind};
 
 use console::{style, Term};
-use indicatif::{DecimalBytes, ProgressBar, ProgressStyle};
+use indicatif::{DecimalBytes, MultiProgress, ProgressBar, ProgressStyle};
 
 use std::time::{Duration, Instant};
 
+use rayon::prelude::*;
 use regex::Regex;
 
-use rayon::prelude::*;
+use rupes::xattr;
+use rupes::{
+    ContentComparator, Digest, Discovery, DuplicateGroup, FileEntry, FileList, HashAlgorithm,
+    HashCache, HashComparator, PrecomputedComparator, QuickComparator, ReadLimit, RetryPolicy,
+    ScanOptions, Storage, StorageMap, TextComparator,
+};
+
+use action::{ActionLog, Operation, Plan, PlannedAction};
+use documents::DocumentComparator;
+use exec::GroupCommand;
+use hotkeys::{Controlled, Controls, Hotkeys};
+use keep::KeepPolicy;
+use locale::{Lang, Message};
+use mail::MailComparator;
+use output::{Count, GroupOrder, OutputFormat, Template};
+use preset::Preset;
+use remote::{HashedFile, Remote};
+use s3::S3Location;
+use skipped::{Skipped, Tolerant};
 
 /// A program to determine number of duplicate files (matching size and hashes) in a directory
+#[derive(Parser)]
+#[clap(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a JSON plan of the actions that would be taken to stdout, without performing them
+    Plan {
+        #[clap(flatten)]
+        args: Args,
+    },
+
+    /// Execute a plan written by `rupes plan`, after re-verifying the hashes of every file involved
+    Apply {
+        /// Plan file to execute
+        plan: PathBuf,
+
+        /// Record every action taken to this file, one JSON object per line
+        #[clap(long)]
+        log: Option<PathBuf>,
+
+        /// Clear the read-only attribute or immutable flag of duplicates before acting on them, rather than skipping them
+        #[clap(long, default_value_t = false)]
+        force_readonly: bool,
+
+        /// Allow acting under / or C:\, or system directories such as /usr and /etc
+        #[clap(long, default_value_t = false)]
+        allow_system: bool,
+
+        /// Write the log in this earlier version of its structure, for tools that haven't caught up with the current one
+        #[clap(long, value_name = "N", default_value_t = output::SCHEMA_VERSION, value_parser = clap::value_parser!(u32).range(1..=output::SCHEMA_VERSION as i64))]
+        schema_version: u32,
+    },
+
+    /// Measure discovery speed, hashing throughput per algorithm and scaling with thread count on your storage
+    Bench {
+        /// Directory to benchmark, searched recursively
+        #[clap(default_value = "./")]
+        directory: PathBuf,
+
+        /// Amount of file content in bytes to benchmark hashing with
+        #[clap(long, default_value_t = 256 * 1024 * 1024)]
+        sample: u64,
+    },
+
+    /// List every file found with its size and hash as JSON lines, run on the other machine by --remote
+    Hash {
+        #[clap(flatten)]
+        args: Args,
+    },
+
+    /// List the files in the first directory whose content exists nowhere in the other directories or manifests given
+    Missing {
+        #[clap(flatten)]
+        args: Box<Args>,
+    },
+
+    /// Confirm every file in the first directory has a copy in the others before deleting it, exiting with code 1 and
+    /// listing the files without one otherwise
+    VerifyBackup {
+        #[clap(flatten)]
+        args: Box<Args>,
+    },
+
+    /// Show the settings a scan would use and where each comes from, or write a starter configuration file
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Write a man page for rupes to stdout, in roff, such as for `rupes man > /usr/share/man/man1/rupes.1`
+    Man,
+
+    /// Scan again and again in the background, as a systemd user service or launchd agent written by rupes
+    Service {
+        #[clap(subcommand)]
+        action: ServiceAction,
+    },
+
+    /// Look after the hashes kept by --cache, or move them to another machine scanning the same files
+    Cache {
+        #[clap(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Hash the files of a report written with --output jdupes-json again, stating which groups are still valid and which
+    /// files changed or vanished, exiting with code 1 if any did
+    Recheck {
+        /// Report to check, as written with --output jdupes-json
+        report: PathBuf,
+
+        /// Hash algorithm for groups reported without --show-hash
+        #[clap(short = 'H', long, value_enum, default_value_t = HashAlgorithm::Sha256)]
+        hash: HashAlgorithm,
+    },
+
+    /// List files stored more than once across the layers of container images, OCI image layouts or `docker save`
+    /// archives, each shown as <image>@<layer>/<path>
+    ScanImage {
+        /// OCI image layout directories, or tar archives of one or written by `docker save`
+        #[clap(required = true)]
+        images: Vec<PathBuf>,
+
+        /// Hash algorithm to compare files with
+        #[clap(short = 'H', long, value_enum, default_value_t = HashAlgorithm::Sha256)]
+        hash: HashAlgorithm,
+
+        /// Character to separate duplicate file paths with
+        #[clap(short = '1', long, default_value = "\n")]
+        separator: String,
+
+        /// Display the amount of space wasted by each group of duplicate files
+        #[clap(short, long, default_value_t = false)]
+        size: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Write the cached hashes of every file in a directory to a file, with paths relative to the directory
+    Export {
+        /// File to write the hashes to
+        file: PathBuf,
+
+        /// Directory whose files are exported
+        #[clap(long, value_name = "DIRECTORY", default_value = ".")]
+        under: PathBuf,
+
+        /// The cache to export from, as given to --cache when scanning
+        #[clap(long, value_name = "FILE")]
+        cache: PathBuf,
+    },
+
+    /// Add the hashes written by `rupes cache export` to a cache, for the same files found in another directory
+    Import {
+        /// File of exported hashes
+        file: PathBuf,
+
+        /// Directory the exported files are in on this machine
+        #[clap(long, value_name = "DIRECTORY", default_value = ".")]
+        under: PathBuf,
+
+        /// The cache to add them to, as given to --cache when scanning
+        #[clap(long, value_name = "FILE")]
+        cache: PathBuf,
+    },
+
+    /// Drop the hashes of files that were removed or changed since they were hashed
+    Prune {
+        /// The cache to prune, as given to --cache when scanning
+        #[clap(long, value_name = "FILE")]
+        cache: PathBuf,
+    },
+
+    /// Show how many hashes are cached, how much space they take and how often the last scan found what it needed
+    Stats {
+        /// The cache to describe, as given to --cache when scanning
+        #[clap(long, value_name = "FILE")]
+        cache: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Write a systemd user unit, or a launchd agent on macOS, that keeps `rupes service run` going with these options
+    Install {
+        /// Where to write it, defaults to where the current user's services are found
+        #[clap(long)]
+        path: Option<PathBuf>,
+
+        /// Seconds to wait after each scan before starting the next
+        #[clap(long, value_name = "SECONDS", default_value_t = 3600)]
+        every: u64,
+
+        /// Directories and options for every scan, as given to rupes itself
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        options: Vec<String>,
+    },
+
+    /// Scan, wait and scan again until stopped, recording every scan in the system log
+    Run {
+        /// Seconds to wait after each scan before starting the next
+        #[clap(long, value_name = "SECONDS", default_value_t = 3600)]
+        every: u64,
+
+        #[clap(flatten)]
+        args: Box<Args>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print every option as resolved from the defaults, configuration file, profile, environment and command line
+    Show {
+        #[clap(flatten)]
+        args: Box<Args>,
+    },
+
+    /// Write a configuration file listing every option, commented out, to edit as a starting point
+    Init {
+        /// Where to write it, defaults to the file read by rupes, such as ~/.config/rupes/config.toml
+        path: Option<PathBuf>,
+
+        /// Replace the file if it already exists
+        #[clap(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
 #[derive(Parser)]
 struct Args {
-    /// Directory to scan for duplicates
-    #[clap(default_value = "./")]
-    directory: PathBuf,
+    /// Directories to scan for duplicates, several directories are scanned concurrently, s3://bucket/prefix scans objects
+    /// in a bucket with the aws command line tool
+    #[clap(default_value = "./", value_name = "DIRECTORY")]
+    directories: Vec<PathBuf>,
 
     /// Recursively search directory
     #[clap(short, long, default_value_t = false)]
@@ -36,18 +301,178 @@ struct Args {
     #[clap(short = 'e', long, default_value_t = false)]
     exclude_dots: bool,
 
-    /// Filter files by pattern, only files with names matching this pattern will be included
+    /// Filter files by pattern, only files with names matching this pattern will be included, can be given several times
+    /// to include files matching any of the patterns
     #[clap(short = 'f', long)]
-    filter: Option<Regex>,
+    filter: Vec<Regex>,
+
+    /// Only include common kinds of files, recognised by their extensions, can be given several times
+    #[clap(long, value_enum)]
+    preset: Vec<Preset>,
+
+    /// Exclude files with names matching this pattern, can be given several times
+    #[clap(short = 'x', long, value_name = "PATTERN")]
+    exclude: Vec<Regex>,
+
+    /// Don't descend into directories with names matching this pattern, can be given several times
+    #[clap(long, value_name = "PATTERN")]
+    exclude_dir: Vec<Regex>,
+
+    /// Exclude files and directories with names matching this shell glob, such as '*.tmp', can be given several times
+    #[clap(long, value_name = "GLOB", value_parser = parse_glob)]
+    exclude_glob: Vec<Regex>,
+
+    /// Only descend this many levels of directories below each directory scanned, implies --recursive
+    #[clap(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Don't descend into well-known cache directories, such as ~/.cache, browser caches and those of pip, npm and cargo
+    #[clap(long, default_value_t = false)]
+    skip_caches: bool,
+
+    /// Don't descend into the internals of version control, .git, .hg and .svn directories, which is the default
+    #[clap(long, default_value_t = false, overrides_with = "no_skip_vcs")]
+    skip_vcs: bool,
+
+    /// Also scan .git, .hg and .svn directories, which are skipped by default
+    #[clap(long, default_value_t = false, overrides_with = "skip_vcs")]
+    no_skip_vcs: bool,
+
+    /// Also scan ZFS, snapper and Timeshift snapshot directories, listing copies of the same file in several snapshots
+    /// separately as they share their storage
+    #[clap(long, default_value_t = false)]
+    include_snapshots: bool,
+
+    /// Also scan directories tagged as caches with a CACHEDIR.TAG file, which are skipped by default
+    #[clap(long, default_value_t = false)]
+    no_cachedir_tag: bool,
+
+    /// Skip directories holding a file with this name, such as .nodedupe, and never act on anything inside them, can be
+    /// given several times
+    #[clap(long, value_name = "FILENAME")]
+    skip_marker: Vec<OsString>,
 
     /// Follow symlinks, by default symbolic links are ignored
     #[clap(short = 'l', long, default_value_t = false)]
     follow_symlinks: bool,
 
-    /// Use Md5 instead of Sha256, speeds up duplication detection but increases risk of collision drastically
+    /// Use Md5 instead of Sha256, the same as --hash md5, speeds up duplication detection but increases risk of
+    /// collision drastically
     #[clap(short = '5', long, default_value_t = false)]
     md5: bool,
 
+    /// Hashing algorithm used to identify duplicates, blake3 is the fastest without md5's risk of collisions
+    #[clap(short = 'H', long, alias = "algorithm", value_enum, default_value_t = HashAlgorithm::Sha256, conflicts_with = "md5")]
+    hash: HashAlgorithm,
+
+    /// Number of threads to hash files with, defaults to the number of CPUs
+    #[clap(long)]
+    threads: Option<usize>,
+
+    /// Number of files to read at once, however many threads hash them, spinning disks are read one file at a time
+    /// regardless
+    #[clap(long, value_name = "COUNT")]
+    io_threads: Option<usize>,
+
+    /// Files of at least this many bytes are hashed on all threads at once, only supported by blake3
+    #[clap(long, default_value_t = 128 * 1024 * 1024)]
+    parallel_hash_threshold: u64,
+
+    /// Keep the hashes of files in this file and reuse them on later scans while a file's size and modification time
+    /// stay the same
+    #[clap(long, value_name = "FILE")]
+    cache: Option<PathBuf>,
+
+    /// Also drop the hashes of files removed or changed since they were cached when saving --cache, as `rupes cache
+    /// prune` does, so the cache doesn't keep growing
+    #[clap(long, default_value_t = false, requires = "cache")]
+    cache_prune: bool,
+
+    /// Keep the hash of every file hashed in its extended attributes, in user.rupes.*, and reuse it on later scans while
+    /// the file's size and modification time stay the same
+    #[clap(long, default_value_t = false)]
+    xattr_cache: bool,
+
+    /// Have the kernel forbid rupes from changing any file, with Landlock and seccomp on Linux, other than its own
+    /// outputs such as --cache, and the files in the directories scanned when acting on duplicates
+    #[clap(long, default_value_t = false, conflicts_with = "xattr_cache")]
+    sandbox: bool,
+
+    /// Storage a directory is on, hdd or ssd, for when it isn't detected correctly, such as /mnt/backup=hdd
+    #[clap(long, value_name = "DIRECTORY=KIND", value_parser = parse_storage)]
+    storage: Vec<(PathBuf, Storage)>,
+
+    /// Retry reads failing with transient errors, such as a network share dropping out, this many times
+    #[clap(long, value_name = "COUNT", default_value_t = 2)]
+    retries: u32,
+
+    /// Milliseconds to wait before the first retry of a read, doubled for every retry after it
+    #[clap(long, value_name = "MS", default_value_t = 200)]
+    retry_delay: u64,
+
+    /// Only compare sizes and the first and last 64 KiB of files, much faster but matches are probable rather than confirmed
+    #[clap(long, default_value_t = false)]
+    quick: bool,
+
+    /// Confirm every duplicate with a second, independent hash before reporting or acting on it
+    #[clap(long, default_value_t = false)]
+    double_check: bool,
+
+    /// Compare the files of every group byte by byte once their hashes match, before reporting or acting on them, for
+    /// when matching hashes aren't proof enough
+    #[clap(long, default_value_t = false, conflicts_with_all = ["no_hash", "normalize_text", "documents", "mail"])]
+    verify: bool,
+
+    /// Confirm duplicates by comparing their bytes directly instead of hashing them, faster when few files share a size
+    /// and no digest of any file is computed or written anywhere
+    #[clap(long, default_value_t = false, conflicts_with_all = ["quick", "double_check", "normalize_text", "documents", "mail", "cache", "xattr_cache", "show_hash", "remote", "manifest", "any"])]
+    no_hash: bool,
+
+    /// Compare text files with CRLF line endings and byte order marks normalized, so copies saved on Windows and Linux match
+    #[clap(long, default_value_t = false, conflicts_with_all = ["quick", "action", "any"])]
+    normalize_text: bool,
+
+    /// Also ignore the spaces and tabs ending each line of text files
+    #[clap(long, default_value_t = false, requires = "normalize_text")]
+    ignore_trailing_whitespace: bool,
+
+    /// Compare PDF, docx and odt files by the text in them, so re-saved and re-exported copies of a document match, read with pdftotext and unzip
+    #[clap(long, default_value_t = false, conflicts_with_all = ["quick", "action", "any", "normalize_text"])]
+    documents: bool,
+
+    /// Compare mail messages in maildirs and .eml files without the headers added on delivery, and report messages found more than once in mbox files
+    #[clap(long, default_value_t = false, conflicts_with_all = ["quick", "action", "any", "normalize_text", "documents"])]
+    mail: bool,
+
+    /// List the sidecars of duplicates, such as .xmp and .srt files, and RAW and JPEG pairs, and move the sidecars of deleted duplicates next to the kept copy
+    #[clap(long, default_value_t = false)]
+    sidecars: bool,
+
+    /// Also report files sharing a large part of their content, found with content-defined chunking (experimental)
+    #[clap(long, default_value_t = false)]
+    chunks: bool,
+
+    /// Minimum similarity, as a percentage, of files reported by --chunks
+    #[clap(long, default_value_t = 50.0, requires = "chunks")]
+    chunks_threshold: f64,
+
+    /// Also report videos that look like the same recording in another container or encoding, compared with ffprobe and ffmpeg (experimental)
+    #[clap(long, default_value_t = false)]
+    video: bool,
+
+    /// Largest difference in duration, in seconds, between videos reported by --video
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 2.0,
+        requires = "video"
+    )]
+    video_tolerance: f64,
+
+    /// Also estimate what block-level deduplication, such as ZFS dedup or bees on Btrfs, would save compared to removing duplicate files
+    #[clap(long, default_value_t = false)]
+    estimate_dedup: bool,
+
     /// Maximum file size allowed in bytes, larger files will be skipped
     #[clap(short = 'M', long)]
     max: Option<u64>,
@@ -56,15 +481,89 @@ struct Args {
     #[clap(short, long)]
     min: Option<u64>,
 
-    /// Hide progress information
+    /// Stop discovery after finding this many files, for a quick sample report of an enormous volume
+    #[clap(long, value_name = "N")]
+    max_files: Option<usize>,
+
+    /// Stop discovery once the files found add up to this size, such as 500M or 2GiB
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_bytes: Option<u64>,
+
+    /// Stop at the first file or directory that can't be read, instead of leaving it out and summing up what was skipped
+    /// once the scan is done
+    #[clap(long, default_value_t = false)]
+    strict: bool,
+
+    /// Exit with this code once the scan is done when any file or directory had to be skipped for being unreadable
+    #[clap(long, value_name = "CODE", conflicts_with = "strict")]
+    skipped_exit_code: Option<i32>,
+
+    /// Ask before hashing more than this much, such as 500G, after showing how long it's likely to take
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    confirm_over: Option<u64>,
+
+    /// Delete or link without first asking to go ahead, once the summary of what's about to change is shown
+    #[clap(long, default_value_t = false)]
+    no_prompt: bool,
+
+    /// Allow deleting or linking under / or C:\, or system directories such as /usr and /etc
+    #[clap(long, default_value_t = false)]
+    allow_system: bool,
+
+    /// Record each scan, the duplicates it found and the actions taken on them in the system log, such as under cron
+    #[clap(long, default_value_t = false)]
+    syslog: bool,
+
+    /// Clear the read-only attribute or immutable flag of duplicates before acting on them, rather than skipping them
+    #[clap(long, default_value_t = false)]
+    force_readonly: bool,
+
+    /// Only report groups whose files add up to at least this size, such as 100M, counting every copy
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    min_group_size: Option<u64>,
+
+    /// Only report groups whose files add up to at most this size, counting every copy
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_group_size: Option<u64>,
+
+    /// Only report groups with copies in more than one directory, hiding copies kept side by side like file.bak
+    #[clap(long, default_value_t = false)]
+    across_dirs_only: bool,
+
+    /// Only report copies sitting in the same directory, like IMG_001.jpg and IMG_001 (1).jpg, splitting groups by
+    /// directory
+    #[clap(long, default_value_t = false, conflicts_with = "across_dirs_only")]
+    same_dir_only: bool,
+
+    /// Also scan a directory on another machine over ssh, which needs rupes installed there, can be repeated
+    #[clap(long, value_name = "USER@HOST:PATH")]
+    remote: Vec<Remote>,
+
+    /// Merge files hashed elsewhere, listed as path,size,hash lines or the output of `rupes hash`, can be repeated
+    #[clap(long, value_name = "FILE")]
+    manifest: Vec<PathBuf>,
+
+    /// Command that runs rupes on the machines given to --remote
+    #[clap(long, value_name = "COMMAND", default_value = "rupes")]
+    remote_command: String,
+
+    /// Only print results, hiding progress and notices such as the one when no files are found
     #[clap(short, long, default_value_t = false)]
     quiet: bool,
 
+    /// Hide the progress bars and spinners, keeping everything else
+    #[clap(long, default_value_t = false)]
+    no_progress: bool,
+
+    /// Write a plain status line this often when stderr isn't a terminal, such as in cron or CI logs, 0 to never
+    #[clap(long, value_name = "SECONDS", default_value_t = 60)]
+    status_interval: u64,
+
     /// Character to separate duplicate file paths with
     #[clap(short = '1', long, default_value = "\n")]
     separator: String,
 
-    /// See total execution time of rupes
+    /// See total execution time of rupes, written to stderr with the other notices
     #[clap(short, long, default_value_t = false)]
     time: bool,
 
@@ -72,7 +571,7 @@ struct Args {
     #[clap(short, long, default_value_t = false)]
     size: bool,
 
-    /// Display the total amount of space wasted by duplicate files
+    /// Display the total amount of space wasted by duplicate files, and their share of everything scanned
     #[clap(short = 'S', long, default_value_t = false)]
     total_size: bool,
 
@@ -80,234 +579,2489 @@ struct Args {
     #[clap(short, long, default_value_t = false)]
     details: bool,
 
+    /// Keep the copy under this directory, listing it first in its group, can be repeated with the most preferred
+    /// directory first, groups without a copy under any of them or with several under the same one keep their first path
+    #[clap(long, value_name = "DIRECTORY")]
+    prefer_dir: Vec<PathBuf>,
+
+    /// Choose the copy of each group to keep by running this external program once per group, a Lua script run with the
+    /// lua command, a WebAssembly module run with wasmtime or any executable, given the group's files in the order
+    /// --prefer-dir leaves them, see the README for what it reads and prints
+    #[clap(long, value_name = "PROGRAM", conflicts_with = "compare_to")]
+    keep_command: Option<PathBuf>,
+
+    /// Which copy of each group to keep: first, newest, oldest or path-prefix=DIRECTORY, choosing among the copies under
+    /// the earliest --prefer-dir when there are any
+    #[clap(long, value_name = "POLICY", conflicts_with = "keep_command")]
+    keep: Option<KeepPolicy>,
+
+    /// Delete duplicate files, keeping the first path of each group, or the copy --keep chooses
+    #[clap(long, default_value_t = false, group = "action")]
+    delete: bool,
+
+    /// Replace duplicate files with hardlinks to the first path of each group, or the copy --keep chooses
+    #[clap(long, default_value_t = false, group = "action")]
+    hardlink: bool,
+
+    /// Replace duplicate files with symlinks to the first path of each group, or the copy --keep chooses
+    #[clap(long, default_value_t = false, group = "action")]
+    symlink: bool,
+
+    /// Show what --delete, --hardlink or --symlink would do to every file and how much space it would free, without
+    /// changing anything
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "action",
+        conflicts_with = "log"
+    )]
+    dry_run: bool,
+
+    /// Go through the groups one at a time before --delete, --hardlink or --symlink changes anything, choosing which
+    /// copies are acted on with the keyboard, then confirm the actions left
+    #[clap(
+        long,
+        default_value_t = false,
+        requires = "action",
+        conflicts_with = "no_prompt"
+    )]
+    interactive: bool,
+
+    /// Record every action taken to this file, one JSON object per line
+    #[clap(long, requires = "action")]
+    log: Option<PathBuf>,
+
+    /// Only act on as many groups as it takes to reclaim this much space, such as 50G, starting with those wasting the
+    /// most, which are listed first
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, requires = "action", conflicts_with = "sort")]
+    free: Option<u64>,
+
+    /// Stop at the first group of duplicates found, printing it and exiting with code 1, or 0 when there are none
+    #[clap(long, default_value_t = false, conflicts_with = "action")]
+    any: bool,
+
+    /// Report which files already have a copy in this backup, a directory or a manifest as read by --manifest, and
+    /// which aren't backed up yet, instead of duplicates
+    #[clap(long, value_name = "BACKUP", conflicts_with_all = ["action", "any"])]
+    covered_by: Option<PathBuf>,
+
+    /// Only report the files in the directories scanned that already have a copy in this reference directory, one copy
+    /// of it kept first in each group, never duplicates found only inside it, which is scanned along with the others
+    #[clap(long, value_name = "REFERENCE", conflicts_with_all = ["covered_by", "unique", "same_dir_only"])]
+    compare_to: Option<PathBuf>,
+
+    /// List the files with no copy of their content anywhere in the directories scanned, instead of duplicates
+    #[clap(long, default_value_t = false, conflicts_with_all = ["action", "any", "covered_by"])]
+    unique: bool,
+
+    /// Only print the number of duplicate groups, or of redundant files with --count files
+    #[clap(long, value_enum, value_name = "WHAT", num_args = 0..=1, default_missing_value = "groups", conflicts_with = "action")]
+    count: Option<Count>,
+
+    /// Order of the groups in the report, score puts first the groups that look safest and most worthwhile to act on:
+    /// copies named like `Copy of` or `(1)`, in Downloads or temporary directories, modified far apart and wasting the
+    /// most space, each preceded by its score
+    #[clap(long, value_enum, value_name = "ORDER", default_value_t = GroupOrder::Size)]
+    sort: GroupOrder,
+
+    /// Also export the space wasted by redundant copies in each directory, only as ncdu (for `ncdu -f FILE`) for now
+    #[clap(long, num_args = 2, value_names = ["FORMAT", "FILE"])]
+    export: Vec<String>,
+
+    /// Show the N largest files that have at least one duplicate, with how many copies of each exist
+    #[clap(long, value_name = "N")]
+    top_files: Option<usize>,
+
+    /// Show which pairs of directories hold copies of the same files, and how much they share
+    #[clap(long, default_value_t = false)]
+    dir_pairs: bool,
+
+    /// Show the space wasted by the redundant copies within each directory and its subdirectories, largest first
+    #[clap(long, default_value_t = false)]
+    by_dir: bool,
+
+    /// Also list directories that are empty, or hold nothing but redundant copies that --delete removes
+    #[clap(long, default_value_t = false)]
+    find_empty_dirs: bool,
+
+    /// Display statistics about the duplicates found, including a histogram of the space wasted by each group and a
+    /// breakdown by file extension
+    #[clap(long, default_value_t = false)]
+    stats: bool,
+
+    /// Write the final statistics of the scan as a single JSON object to this file descriptor, such as 3 with `3>stats.json`
+    #[cfg(unix)]
+    #[clap(long, value_name = "FD", conflicts_with = "stats_file")]
+    stats_fd: Option<i32>,
+
+    /// Write the final statistics of the scan as a single JSON object to this file
+    #[clap(long, value_name = "FILE")]
+    stats_file: Option<PathBuf>,
+
+    /// List paths that are already hardlinks to each other separately, they don't count towards wasted space, which is
+    /// the default
+    #[clap(long, default_value_t = false, overrides_with = "count_hardlinks")]
+    report_hardlinks: bool,
+
+    /// Count every hardlink to a file as a copy of its own, towards wasted space and the groups reported, rather than
+    /// the paths linked to each other as a single copy listed separately
+    #[clap(long, default_value_t = false)]
+    count_hardlinks: bool,
+
+    /// Write a line per file of every group in this format instead, with the placeholders {group}, {id}, {hash},
+    /// {size}, {waste}, {mtime}, {path} and {role}, original or redundant, such as '{hash}\t{size}\t{path}'
+    #[clap(long, value_name = "TEMPLATE")]
+    format_template: Option<Template>,
+
+    /// Run this command for every group of duplicates as it's reported, before any action, with the placeholders
+    /// {kept}, {dupes...}, {size}, {waste}, {hash}, {id} and {group}, such as 'tag-copies {kept} {dupes...}'
+    #[clap(long, value_name = "COMMAND")]
+    exec: Option<GroupCommand>,
+
+    /// Show the size, modification time (UTC) and owner of every path, like ls -l, to help choose which copy to keep
+    #[clap(short = 'L', long, default_value_t = false)]
+    long: bool,
+
+    /// Print the digest of each group's content, with its algorithm, before its paths and in JSON output
+    #[clap(long, default_value_t = false)]
+    show_hash: bool,
+
+    /// Only write the paths of redundant copies, every path of each group but the one that would be kept, a line each and
+    /// nothing else, for piping into xargs rm or other tools
+    #[clap(long, default_value_t = false, conflicts_with_all = ["format_template", "long", "show_hash", "size", "details", "count", "output"])]
+    omit_first: bool,
+
+    /// Format of the report written to stdout, jdupes-json matches the JSON written by jdupes -j and fdupes the output
+    /// of fdupes
+    #[clap(long, alias = "format", value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// End every path with a NUL rather than a line break, and groups with another NUL, with --output fdupes and
+    /// --omit-first
+    #[clap(long, default_value_t = false)]
+    print0: bool,
+
+    /// Write JSON outputs, the report, --stats-file, --log, plans and `rupes hash`, and CSV reports in this earlier
+    /// version of their structure, for tools that haven't caught up with the current one
+    #[clap(long, value_name = "N", default_value_t = output::SCHEMA_VERSION, value_parser = clap::value_parser!(u32).range(1..=output::SCHEMA_VERSION as i64))]
+    schema_version: u32,
+
+    /// Only write plain ASCII to the terminal, for serial consoles and old terminal emulators
+    #[clap(long, default_value_t = false)]
+    ascii: bool,
+
+    /// Language of progress labels, prompts and summaries, defaults to the one set by LANG
+    #[clap(long, value_enum)]
+    lang: Option<Lang>,
+
+    /// Read defaults from this configuration file instead of $RUPES_CONFIG or ~/.config/rupes/config.toml
+    #[clap(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Apply this profile of the configuration file over its other settings, also read from $RUPES_PROFILE
+    #[clap(long, value_name = "NAME")]
+    profile: Option<String>,
+
     /// Print rupes version
     #[clap(short = 'V', long, default_value_t = false)]
     version: bool,
 }
 
-#[derive(Debug)]
-struct IdenticalFiles {
-    paths: Vec<PathBuf>,
+/// Names of the directories version control systems keep their history and internals in
+fn vcs_directories() -> Regex {
+    Regex::new(r"^\.(git|hg|svn)$").unwrap()
 }
 
-fn hash_file(path: &Path, args: &Args) -> Result<String> {
-    let mut file = File::open(path)?;
+impl Args {
+    fn local_directories(&self) -> Vec<PathBuf> {
+        self.directories
+            .iter()
+            .filter(|directory| !S3Location::is_s3(directory))
+            .cloned()
+            .collect()
+    }
 
-    if args.md5 {
-        let mut hasher = Md5::new();
+    fn s3_locations(&self) -> Result<Vec<S3Location>> {
+        self.directories
+            .iter()
+            .filter(|directory| S3Location::is_s3(directory))
+            .map(|location| {
+                location
+                    .to_string_lossy()
+                    .parse()
+                    .map_err(|error| Error::new(ErrorKind::InvalidInput, error))
+            })
+            .collect()
+    }
 
-        io::copy(&mut file, &mut hasher)?;
+    /// Patterns given to --filter and those of the presets given to --preset, files matching any of them are included
+    fn filters(&self) -> Vec<Regex> {
+        let presets = self.preset.iter().map(|preset| preset.pattern());
+        self.filter.iter().cloned().chain(presets).collect()
+    }
 
-        let hash = hasher.finalize();
-        let hash = base16ct::lower::encode_string(&hash);
+    fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            recursive: self.recursive || self.max_depth.is_some(),
+            exclude_dots: self.exclude_dots,
+            filters: self.filters(),
+            exclude: self
+                .exclude
+                .iter()
+                .chain(&self.exclude_glob)
+                .cloned()
+                .collect(),
+            exclude_dirs: self
+                .exclude_dir
+                .iter()
+                .chain(&self.exclude_glob)
+                .cloned()
+                .chain(self.skip_caches.then(caches::names))
+                .chain((!self.no_skip_vcs).then(vcs_directories))
+                .chain((!self.include_snapshots).then(snapshot::names))
+                .collect(),
+            exclude_paths: if self.skip_caches {
+                caches::directories()
+            } else {
+                Vec::new()
+            },
+            cachedir_tags: !self.no_cachedir_tag,
+            skip_markers: self.skip_marker.clone(),
+            follow_symlinks: self.follow_symlinks,
+            min: self.min,
+            max: self.max,
+            max_files: self.max_files,
+            max_bytes: self.max_bytes,
+            max_depth: self.max_depth,
+            skip_unreadable: !self.strict,
+            directory_overrides: Some(config::directory_overrides()),
+        }
+    }
 
-        Ok(hash)
-    } else {
-        let mut hasher = Sha256::new();
+    fn algorithm(&self) -> HashAlgorithm {
+        if self.md5 {
+            HashAlgorithm::Md5
+        } else {
+            self.hash
+        }
+    }
 
-        io::copy(&mut file, &mut hasher)?;
+    /// Whether a group of duplicates passes the group filters, such as --min-group-size
+    fn reports_group(&self, group: &DuplicateGroup<(u64, Digest)>) -> bool {
+        let total = group.key.0 * group.files.len() as u64;
 
-        let hash = hasher.finalize();
-        let hash = base16ct::lower::encode_string(&hash);
+        if self.min_group_size.is_some_and(|min| total < min) {
+            return false;
+        }
+        if self.max_group_size.is_some_and(|max| total > max) {
+            return false;
+        }
+        if self.across_dirs_only {
+            let parent = group.files[0].path.parent();
+            if group.files.iter().all(|file| file.path.parent() == parent) {
+                return false;
+            }
+        }
 
-        Ok(hash)
+        true
     }
-}
 
-fn handle_file(path: PathBuf, paths: &mut Vec<(u64, PathBuf)>, args: &Args) -> Result<()> {
-    let metadata = path.metadata()?;
-    let size = metadata.len();
-    let file_type = metadata.file_type();
-    let file_name = path.file_name().unwrap().to_string_lossy();
+    /// The file given to --export ncdu
+    fn ncdu_export(&self) -> Result<Option<PathBuf>> {
+        match self.export.as_slice() {
+            [format, path] if format == "ncdu" => Ok(Some(PathBuf::from(path))),
+            [format, _] => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown export format '{}', only ncdu is supported", format),
+            )),
+            _ => Ok(None),
+        }
+    }
 
-    // Guard against dot files/directories (if they are excluded)
-    if args.exclude_dots && file_name.starts_with('.') {
-        return Ok(());
+    /// The files --sandbox lets rupes write, and nothing else in their directories
+    fn writable_files(&self) -> Result<Vec<PathBuf>> {
+        let outputs = [
+            &self.cache,
+            &self.log,
+            &self.stats_file,
+            &self.ncdu_export()?,
+        ];
+        Ok(outputs.into_iter().flatten().cloned().collect())
     }
 
-    // Handle files
-    if file_type.is_file() {
-        if let Some(filter) = args.filter.as_ref() {
-            if !filter.is_match(&file_name) {
-                return Ok(());
-            }
+    /// The directories --sandbox lets rupes change files in, those scanned when acting on duplicates
+    fn writable_directories(&self) -> Vec<PathBuf> {
+        if self.operation().is_some() {
+            self.local_directories()
+        } else {
+            Vec::new()
         }
+    }
 
-        if let Some(min) = args.min {
-            if min > size {
-                return Ok(());
+    /// Where --stats-fd or --stats-file write the statistics, opened before scanning so a mistake doesn't waste one
+    fn stats_output(&self) -> Result<Option<File>> {
+        #[cfg(unix)]
+        if let Some(fd) = self.stats_fd {
+            use std::os::fd::FromRawFd;
+
+            // SAFETY: fcntl only queries the descriptor, which is owned by the returned file from then on
+            if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("--stats-fd {} isn't an open file descriptor", fd),
+                ));
             }
+            return Ok(Some(unsafe { File::from_raw_fd(fd) }));
+        }
+
+        self.stats_file.as_ref().map(File::create).transpose()
+    }
+
+    /// The reference directory of --compare-to, always kept, then the directories of --prefer-dir, the most preferred
+    /// first, then that of --keep path-prefix
+    fn preferred_dirs(&self) -> impl Iterator<Item = &Path> {
+        self.compare_to
+            .as_deref()
+            .into_iter()
+            .chain(self.prefer_dir.iter().map(PathBuf::as_path))
+            .chain(self.keep.as_ref().and_then(KeepPolicy::prefix))
+    }
+
+    /// Whether `path` is under the reference directory of --compare-to
+    fn in_reference(&self, path: &Path) -> bool {
+        self.compare_to.is_some() && self.preferred_rank(path) == Some(0)
+    }
+
+    /// The index of the earliest of [`Args::preferred_dirs`] `path` is under, `None` when it isn't under any
+    fn preferred_rank(&self, path: &Path) -> Option<usize> {
+        // ./photos/a and photos are compared without their leading ./
+        let without_dot = |path: &Path| -> PathBuf {
+            path.components()
+                .skip_while(|c| *c == Component::CurDir)
+                .collect()
+        };
+        let path = without_dot(path);
+        self.preferred_dirs()
+            .position(|dir| path.starts_with(without_dot(dir)))
+    }
+
+    /// Move the copy to keep to the front of `group`, the one --keep chooses among those under the earliest
+    /// --prefer-dir, by default the first of them
+    fn put_preferred_first(&self, group: &mut DuplicateGroup<(u64, Digest)>) {
+        let unranked = self.preferred_dirs().count();
+        let rank = |file: &FileEntry| self.preferred_rank(&file.path).unwrap_or(unranked);
+
+        let policy = self.keep.as_ref().unwrap_or(&KeepPolicy::First);
+        if let Some(index) = policy.choose(&group.files, rank) {
+            let kept = group.files.remove(index);
+            group.files.insert(0, kept);
         }
+    }
 
-        if let Some(max) = args.max {
-            if max < size {
-                return Ok(());
+    /// Move the copy to keep to the front of `group`, the one --keep-command chooses or else the one preferred by
+    /// [`Args::put_preferred_first`]
+    fn put_kept_first(&self, group: &mut DuplicateGroup<(u64, Digest)>) -> Result<()> {
+        self.put_preferred_first(group);
+        if let Some(program) = &self.keep_command {
+            if let Some(index) = keep::choose(program, &group.files)? {
+                let kept = group.files.remove(index);
+                group.files.insert(0, kept);
             }
         }
+        Ok(())
+    }
 
-        paths.push((size, path));
-        return Ok(());
+    /// Why `kept`, put first by [`Args::put_kept_first`], is the copy of its group to keep
+    fn keep_reason(&self, kept: &Path) -> String {
+        if let Some(program) = &self.keep_command {
+            return format!("chosen by --keep-command {}", program.display());
+        }
+        let under = match self.preferred_rank(kept) {
+            Some(rank) if rank < self.prefer_dir.len() => Some(format!(
+                "under --prefer-dir {}",
+                self.prefer_dir[rank].display()
+            )),
+            Some(_) => self
+                .keep
+                .as_ref()
+                .and_then(KeepPolicy::prefix)
+                .map(|directory| format!("under --keep path-prefix={}", directory.display())),
+            None => None,
+        };
+        match (self.keep.as_ref().and_then(KeepPolicy::reason), under) {
+            (Some(policy), Some(under)) => format!("{} {}", policy, under),
+            (Some(policy), None) => policy.to_string(),
+            (None, Some(under)) => under,
+            (None, None) => "first path".to_string(),
+        }
     }
 
-    if args.recursive && file_type.is_dir() {
-        get_files(path, paths, args)?;
+    /// Whether reported duplicates are only probable, found by --quick without confirming them
+    fn probable_matches(&self) -> bool {
+        self.quick && !self.double_check && !self.verify
     }
 
-    Ok(())
-}
+    /// Whether some files are compared by normalized content, which files hashed elsewhere can't be
+    fn compares_normalized(&self) -> bool {
+        self.normalize_text || self.documents || self.mail
+    }
 
-fn get_files(path: PathBuf, paths: &mut Vec<(u64, PathBuf)>, args: &Args) -> Result<()> {
-    for entry in read_dir(path)? {
-        let dir = entry?;
-        let path = dir.path();
+    /// The files among `files` that may have a duplicate, with their full paths, those of the others are never put
+    /// together
+    fn candidates(&self, files: &FileList) -> Vec<FileEntry> {
+        // Normalized content can match between files of any sizes
+        if self.compares_normalized() {
+            files.iter().collect()
+        } else {
+            files.same_size_entries()
+        }
+    }
 
-        if !args.follow_symlinks && dir.metadata()?.file_type().is_symlink() {
-            continue;
+    /// Total number of progress steps shown while scanning
+    fn steps(&self) -> usize {
+        2 + self.double_check as usize
+            + self.verify as usize
+            + self.chunks as usize
+            + self.video as usize
+            + self.mail as usize
+            + self.estimate_dedup as usize
+    }
+
+    /// Where files are stored, to read each device at the depth that suits it
+    fn storage_map(&self) -> Option<Arc<StorageMap>> {
+        Some(Arc::new(StorageMap::new(self.storage.iter().cloned())))
+    }
+
+    /// The hash cache chosen with --cache, as read from its file
+    fn hash_cache(&self) -> Result<Option<Arc<HashCache>>> {
+        self.cache
+            .as_deref()
+            .map(|path| HashCache::open(path).map(Arc::new))
+            .transpose()
+    }
+
+    /// Write `cache` back to the file given to --cache, pruned first with --cache-prune
+    fn save_cache(&self, cache: Option<&Arc<HashCache>>) -> Result<()> {
+        let (Some(cache), Some(path)) = (cache, &self.cache) else {
+            return Ok(());
+        };
+        if self.cache_prune {
+            let pruned = cache.prune();
+            if pruned > 0 && !self.quiet {
+                eprintln!(
+                    "{}",
+                    locale::text(
+                        Message::StaleHashes,
+                        &[("hashes", &pruned), ("cache", &path.display())],
+                    )
+                );
+            }
         }
+        if self.sandbox {
+            cache.save_in_place(path)
+        } else {
+            cache.save(path)
+        }
+    }
 
-        handle_file(path, paths, args)?;
+    /// The limit on concurrent reads shared by every comparator of a step, if there is one
+    fn read_limit(&self) -> Option<Arc<ReadLimit>> {
+        self.io_threads.map(|reads| Arc::new(ReadLimit::new(reads)))
     }
 
-    Ok(())
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            delay: Duration::from_millis(self.retry_delay),
+        }
+    }
+
+    /// The comparator hashing whole files with --hash, or with the independent algorithm of --double-check, which
+    /// neither reads nor fills the caches since their hashes were made with another algorithm
+    fn hash_comparator(&self, independent: bool, cache: Option<&Arc<HashCache>>) -> HashComparator {
+        HashComparator {
+            algorithm: if independent {
+                self.algorithm().independent()
+            } else {
+                self.algorithm()
+            },
+            parallel_threshold: Some(self.parallel_hash_threshold),
+            retry: self.retry_policy(),
+            read_limit: self.read_limit(),
+            storage: self.storage_map(),
+            cache: cache.filter(|_| !independent).cloned(),
+            xattrs: self.xattr_cache && !independent,
+        }
+    }
+
+    /// What the actions on duplicates found in the directories scanned are checked against
+    fn guard(&self) -> action::Guard<'_> {
+        action::Guard {
+            roots: &self.directories,
+            markers: &self.skip_marker,
+            allow_system: self.allow_system,
+        }
+    }
+
+    fn operation(&self) -> Option<Operation> {
+        if self.delete {
+            Some(Operation::Delete)
+        } else if self.hardlink {
+            Some(Operation::Hardlink)
+        } else if self.symlink {
+            Some(Operation::Symlink)
+        } else {
+            None
+        }
+    }
 }
 
-fn find_duplicates(
-    paths: Vec<(u64, PathBuf)>,
-    hashes_by_file_size: &mut HashMap<u64, HashMap<String, IdenticalFiles>>,
-    progress: &ProgressBar,
-    args: &Args,
-) -> Result<()> {
-    let hashes_by_file_size = Mutex::new(hashes_by_file_size);
+fn parse_glob(value: &str) -> std::result::Result<Regex, String> {
+    rupes::glob_pattern(value).map_err(|error| error.to_string())
+}
 
-    paths.par_iter().enumerate().for_each(|(_, (size, path))| {
-        let hash = hash_file(path, args).unwrap();
+/// Parse a DIRECTORY=KIND pair given to --storage
+fn parse_storage(value: &str) -> std::result::Result<(PathBuf, Storage), String> {
+    let (directory, kind) = value.rsplit_once('=').ok_or_else(|| {
+        format!(
+            "Expected DIRECTORY=KIND, such as /mnt/backup=hdd, not {}",
+            value
+        )
+    })?;
+    let kind = kind.parse().map_err(|error: Error| error.to_string())?;
+
+    Ok((PathBuf::from(directory), kind))
+}
 
-        let mut hashes_by_file_size = hashes_by_file_size.lock().unwrap();
-        let hashes = hashes_by_file_size.entry(*size).or_default();
+/// Parse a size in bytes with an optional decimal (K, M, G, T) or binary (KiB, MiB, GiB, TiB) suffix
+fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' doesn't start with a number of bytes", value))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        unit => return Err(format!("unknown size unit '{}'", unit)),
+    };
 
-        hashes
-            .entry(hash)
-            .and_modify(|identical_files| identical_files.paths.push(path.to_path_buf()))
-            .or_insert(IdenticalFiles {
-                paths: vec![path.to_path_buf()],
-            });
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("'{}' is too large", value))
+}
 
-        progress.inc(1);
-    });
+/// Every progress widget, drawn together so the discovery counter stays up while files are hashed
+fn progress_display() -> &'static MultiProgress {
+    static DISPLAY: OnceLock<MultiProgress> = OnceLock::new();
+    DISPLAY.get_or_init(MultiProgress::new)
+}
 
-    Ok(())
+/// The style of a spinner, turning with plain ASCII characters under --ascii
+fn spinner_style(template: &str) -> ProgressStyle {
+    let style = ProgressStyle::with_template(template).unwrap();
+    if locale::ascii() {
+        style.tick_chars("|/-\\ ")
+    } else {
+        style
+    }
 }
 
-fn scan_directory(
-    hashes_by_file_size: &mut HashMap<u64, HashMap<String, IdenticalFiles>>,
-    _term: &Term,
-    args: &Args,
-) -> Result<()> {
-    if !args.directory.is_dir() {
-        eprintln!("Please specify a valid directory to search");
-        return Err(Error::new(ErrorKind::InvalidInput, "Please specify a valid directory to search"));
+/// Draw `bar` below the other progress widgets, or nothing with --quiet or --no-progress
+///
+/// Without a terminal to draw on, its state is written as a plain line every --status-interval instead.
+fn show_progress(bar: ProgressBar, args: &Args) -> ProgressBar {
+    if args.quiet || args.no_progress {
+        return ProgressBar::hidden();
     }
 
-    let get_files_spinner = if args.quiet {
-        ProgressBar::hidden()
-    } else {
-        ProgressBar::new_spinner()
-    };
+    let bar = progress_display().add(bar);
+    if args.status_interval > 0 && status::wanted() {
+        status::watch(&bar, Duration::from_secs(args.status_interval));
+    }
+    bar
+}
+
+fn scan_directory(args: &Args, skipped: &Skipped) -> Result<Discovery> {
+    let get_files_spinner = show_progress(ProgressBar::new_spinner(), args);
     get_files_spinner.enable_steady_tick(Duration::from_millis(100));
-    get_files_spinner.set_style(ProgressStyle::with_template("{prefix} {spinner}").unwrap());
-    get_files_spinner.set_prefix(format!("{} Scanning files", style("[1/2]").white()));
+    get_files_spinner.set_style(spinner_style("{prefix} {spinner} {msg}"));
+    get_files_spinner.set_prefix(format!(
+        "{} {}",
+        style(format!("[1/{}]", args.steps())).white(),
+        locale::text(Message::ScanningFiles, &[])
+    ));
+
+    let mut directories = args.local_directories();
+    if directories.is_empty() && !args.directories.is_empty() {
+        get_files_spinner.finish_and_clear();
+        return Ok(Discovery::default());
+    }
+    directories.extend(args.compare_to.clone());
+
+    let mut discovery = rupes::discover_roots(&directories, &args.scan_options(), |progress| {
+        let elapsed = get_files_spinner.elapsed().as_secs_f64().max(0.001);
+        get_files_spinner.set_message(locale::text(
+            Message::DiscoveryProgress,
+            &[
+                ("directories", &progress.directories),
+                ("files", &progress.files),
+                ("bytes", &DecimalBytes(progress.bytes)),
+                ("rate", &format!("{:.0}", progress.files as f64 / elapsed)),
+            ],
+        ))
+    })
+    .inspect_err(|error| {
+        if error.kind() == ErrorKind::InvalidInput {
+            eprintln!("{}", error);
+        }
+    })?;
 
-    let mut paths = Vec::new();
-    get_files(args.directory.to_path_buf(), &mut paths, args)?;
+    // Left up, with its totals, while files are hashed
+    get_files_spinner.finish();
+    skipped.record(std::mem::take(&mut discovery.skipped));
 
-    get_files_spinner.finish_and_clear();
+    Ok(discovery)
+}
 
-    let progress = if args.quiet {
-        ProgressBar::hidden()
-    } else {
-        ProgressBar::new(paths.len() as u64)
-    };
+/// A progress bar over `bytes`, advanced by the size of each file as it's done so the ETA holds up when a few files
+/// are much larger than the rest
+fn hashing_progress(bytes: u64, step: usize, message: Message, args: &Args) -> ProgressBar {
+    let progress = show_progress(ProgressBar::new(bytes), args);
     progress.set_style(
-        ProgressStyle::with_template("{prefix} {pos:>7}/{len:7}\n[{bar:40.green/white}]")
-            .unwrap()
-            .progress_chars("=> "),
+        ProgressStyle::with_template(
+            "{prefix} {decimal_bytes:>10}/{decimal_total_bytes:10} {decimal_bytes_per_sec:>12} ETA {eta}\n[{bar:40.green/white}] {msg}",
+        )
+        .unwrap()
+        .progress_chars("=> "),
     );
 
-    progress.set_prefix(format!("{} Finding duplicates", style("[2/2]").white()));
+    progress.set_prefix(format!(
+        "{} {}",
+        style(format!("[{}/{}]", step, args.steps())).white(),
+        locale::text(message, &[])
+    ));
+
+    progress
+}
+
+/// Split a group into the copies in each directory, dropping directories holding a single copy
+fn split_by_directory<K: Clone>(group: DuplicateGroup<K>) -> Vec<DuplicateGroup<K>> {
+    let mut by_directory: Vec<DuplicateGroup<K>> = Vec::new();
+
+    for file in group.files {
+        let parent = file.path.parent();
+        match by_directory
+            .iter_mut()
+            .find(|split| split.files[0].path.parent() == parent)
+        {
+            Some(split) => split.files.push(file),
+            None => by_directory.push(DuplicateGroup {
+                key: group.key.clone(),
+                files: vec![file],
+            }),
+        }
+    }
+
+    by_directory.retain(|split| split.files.len() >= 2);
+    by_directory
+}
+
+/// The files of `group` outside the reference directory of --compare-to, after a single copy in it, `None` when there is
+/// no copy in it or nothing outside of it
+fn against_reference<K>(group: DuplicateGroup<K>, args: &Args) -> Option<DuplicateGroup<K>> {
+    let (reference, compared): (Vec<_>, Vec<_>) = group
+        .files
+        .into_iter()
+        .partition(|file| args.in_reference(&file.path));
+    let kept = reference.into_iter().next()?;
+    if compared.is_empty() {
+        return None;
+    }
+
+    Some(DuplicateGroup {
+        key: group.key,
+        files: std::iter::once(kept).chain(compared).collect(),
+    })
+}
+
+/// The groups left after comparing them to the reference directory of --compare-to, splitting them with
+/// --same-dir-only and applying the group filters
+fn reported_groups(
+    mut groups: Vec<DuplicateGroup<(u64, Digest)>>,
+    args: &Args,
+) -> Vec<DuplicateGroup<(u64, Digest)>> {
+    if args.compare_to.is_some() {
+        groups = groups
+            .into_iter()
+            .filter_map(|group| against_reference(group, args))
+            .collect();
+    }
+    if args.same_dir_only {
+        groups = groups.into_iter().flat_map(split_by_directory).collect();
+    }
+    groups.retain(|group| args.reports_group(group));
+    groups
+}
+
+/// The first group of duplicates confirmed that passes the group filters, for --any
+///
+/// Files of each size are compared on their own, and no more sizes are started once a group is found, so a scan
+/// with duplicates ends long before every file is read.
+fn any_duplicate(
+    files: Vec<FileEntry>,
+    hashes: HashMap<PathBuf, Digest>,
+    args: &Args,
+    skipped: &Skipped,
+) -> Result<Option<DuplicateGroup<(u64, Digest)>>> {
+    let cache = args.hash_cache()?;
+    let bytes = files.iter().map(|file| file.size).sum();
+    let progress = hashing_progress(bytes, 2, Message::FindingDuplicates, args);
+    let inc = |file: &FileEntry| progress.inc(file.size);
+
+    let first = Comparison::first(args, hashes, cache.as_ref());
+    let confirming = Comparison::confirming(args);
+
+    let first_group = |files: Vec<FileEntry>| -> Result<Option<DuplicateGroup<(u64, Digest)>>> {
+        let mut groups = first.group(files, None, skipped, inc)?;
+        if args.double_check {
+            groups = confirming.confirm(groups, None, skipped, inc)?;
+        }
+        if args.verify {
+            groups = verified(groups, &args.retry_policy(), skipped, inc)?;
+        }
+
+        let mut groups = reported_groups(groups, args);
+        groups.sort_by(|a, b| a.files[0].path.cmp(&b.files[0].path));
+        Ok(groups.into_iter().next())
+    };
+
+    let mut by_size: HashMap<u64, Vec<FileEntry>> = HashMap::new();
+    for file in files {
+        by_size.entry(file.size).or_default().push(file);
+    }
 
-    find_duplicates(paths, hashes_by_file_size, &progress, args)?;
+    let found = by_size
+        .into_par_iter()
+        .filter(|(_, files)| files.len() >= 2)
+        .find_map_any(|(_, files)| first_group(files).transpose())
+        .transpose();
 
     progress.finish_and_clear();
+    args.save_cache(cache.as_ref())?;
+    found
+}
 
-    Ok(())
+/// [`rupes::find_duplicates`] with `comparator`, obeying the hotkeys when there are `controls` and leaving out files
+/// that can't be read in `skipped`
+fn duplicates_with<C>(
+    files: Vec<FileEntry>,
+    comparator: &C,
+    controls: Option<&Controls>,
+    skipped: &Skipped,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<DuplicateGroup<(u64, Digest)>>>
+where
+    C: ContentComparator<Key = (u64, Digest)>,
+    for<'a> Controlled<'a, C>: ContentComparator<Key = (u64, Digest)>,
+{
+    match controls {
+        Some(controls) => rupes::find_duplicates(
+            files,
+            &Tolerant {
+                comparator: &Controlled {
+                    comparator,
+                    controls,
+                },
+                skipped,
+            },
+            progress,
+        ),
+        None => rupes::find_duplicates(
+            files,
+            &Tolerant {
+                comparator,
+                skipped,
+            },
+            progress,
+        ),
+    }
 }
 
-fn main() -> Result<()> {
-    let now = Instant::now();
-    let args = Args::parse();
+/// [`rupes::confirm_duplicates`] with `comparator`, obeying the hotkeys when there are `controls` and leaving out
+/// files that can't be read in `skipped`
+fn confirmed_with<C>(
+    groups: Vec<DuplicateGroup<(u64, Digest)>>,
+    comparator: &C,
+    controls: Option<&Controls>,
+    skipped: &Skipped,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<DuplicateGroup<(u64, Digest)>>>
+where
+    C: ContentComparator,
+    for<'a> Controlled<'a, C>: ContentComparator,
+{
+    match controls {
+        Some(controls) => rupes::confirm_duplicates(
+            groups,
+            &Tolerant {
+                comparator: &Controlled {
+                    comparator,
+                    controls,
+                },
+                skipped,
+            },
+            progress,
+        ),
+        None => rupes::confirm_duplicates(
+            groups,
+            &Tolerant {
+                comparator,
+                skipped,
+            },
+            progress,
+        ),
+    }
+}
 
-    let term: Term = Term::stdout();
+/// Split every group by comparing its files byte by byte, for --verify, leaving out files whose hash matched another's
+/// but whose content doesn't, and those that can't be read in `skipped`
+fn verified(
+    groups: Vec<DuplicateGroup<(u64, Digest)>>,
+    retry: &RetryPolicy,
+    skipped: &Skipped,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<DuplicateGroup<(u64, Digest)>>> {
+    let mut verified = Vec::new();
+    let skip = |path: &Path, error| skipped.skip(path, error);
+
+    for group in groups {
+        for split in rupes::compare_bytes_skipping(group.files, retry, skip, &progress)? {
+            verified.push(DuplicateGroup {
+                key: group.key.clone(),
+                files: split.files,
+            });
+        }
+    }
 
-    if args.version {
-        term.write_line(&format!("Rupes version {}", env!("CARGO_PKG_VERSION")))?;
-        return Ok(());
+    Ok(verified)
+}
+
+/// The comparator the arguments choose for a step of the search, built once and shared by every size compared
+enum Comparison {
+    /// Byte by byte, for --no-hash
+    Bytes(RetryPolicy),
+    Quick(QuickComparator),
+    Text(TextComparator),
+    Document(DocumentComparator),
+    Mail(MailComparator),
+    Hash(PrecomputedComparator),
+}
+
+impl Comparison {
+    /// The comparator grouping the files found, which takes the hashes already known in `hashes` as they are
+    fn first(
+        args: &Args,
+        hashes: HashMap<PathBuf, Digest>,
+        cache: Option<&Arc<HashCache>>,
+    ) -> Comparison {
+        if args.no_hash {
+            Comparison::Bytes(args.retry_policy())
+        } else if args.quick {
+            Comparison::Quick(QuickComparator {
+                algorithm: args.algorithm(),
+                retry: args.retry_policy(),
+                read_limit: args.read_limit(),
+                storage: args.storage_map(),
+                ..QuickComparator::default()
+            })
+        } else {
+            Comparison::hashing(args, args.hash_comparator(false, cache), hashes)
+        }
     }
 
-    let mut hashes_by_file_size = HashMap::new();
-    scan_directory(&mut hashes_by_file_size, &term, &args)?;
+    /// The comparator confirming the groups found, for --double-check
+    fn confirming(args: &Args) -> Comparison {
+        Comparison::hashing(args, args.hash_comparator(true, None), HashMap::new())
+    }
 
-    if hashes_by_file_size.is_empty() {
-        term.write_line("No files to scan, rupes will now exit")?;
-        return Ok(());
+    /// `hashing`, reading content the way --normalize-text, --documents or --mail ask
+    fn hashing(
+        args: &Args,
+        hashing: HashComparator,
+        hashes: HashMap<PathBuf, Digest>,
+    ) -> Comparison {
+        if args.normalize_text {
+            Comparison::Text(TextComparator {
+                comparator: hashing,
+                trailing_whitespace: args.ignore_trailing_whitespace,
+            })
+        } else if args.documents {
+            Comparison::Document(DocumentComparator {
+                comparator: hashing,
+            })
+        } else if args.mail {
+            Comparison::Mail(MailComparator {
+                comparator: hashing,
+            })
+        } else {
+            Comparison::Hash(PrecomputedComparator {
+                comparator: hashing,
+                hashes,
+            })
+        }
     }
 
-    let term: Term = Term::buffered_stdout();
+    /// Group `files` of the same content, see [`duplicates_with`]
+    fn group(
+        &self,
+        files: Vec<FileEntry>,
+        controls: Option<&Controls>,
+        skipped: &Skipped,
+        progress: impl Fn(&FileEntry) + Sync,
+    ) -> Result<Vec<DuplicateGroup<(u64, Digest)>>> {
+        match self {
+            Comparison::Bytes(retry) => {
+                let skip = |path: &Path, error| skipped.skip(path, error);
+                Ok(rupes::compare_bytes_skipping(files, retry, skip, progress)?
+                    .into_iter()
+                    .map(|group| DuplicateGroup {
+                        key: (group.key, path_digest(&group.files[0].path)),
+                        files: group.files,
+                    })
+                    .collect())
+            }
+            Comparison::Quick(comparator) => {
+                duplicates_with(files, comparator, controls, skipped, progress)
+            }
+            Comparison::Text(comparator) => {
+                duplicates_with(files, comparator, controls, skipped, progress)
+            }
+            Comparison::Document(comparator) => {
+                duplicates_with(files, comparator, controls, skipped, progress)
+            }
+            Comparison::Mail(comparator) => {
+                duplicates_with(files, comparator, controls, skipped, progress)
+            }
+            Comparison::Hash(comparator) => {
+                duplicates_with(files, comparator, controls, skipped, progress)
+            }
+        }
+    }
 
-    // Final output
+    /// Split each of `groups` by comparing its files again, see [`confirmed_with`]
+    fn confirm(
+        &self,
+        groups: Vec<DuplicateGroup<(u64, Digest)>>,
+        controls: Option<&Controls>,
+        skipped: &Skipped,
+        progress: impl Fn(&FileEntry) + Sync,
+    ) -> Result<Vec<DuplicateGroup<(u64, Digest)>>> {
+        match self {
+            // Nothing is surer than their bytes
+            Comparison::Bytes(_) => Ok(groups),
+            Comparison::Quick(comparator) => {
+                confirmed_with(groups, comparator, controls, skipped, progress)
+            }
+            Comparison::Text(comparator) => {
+                confirmed_with(groups, comparator, controls, skipped, progress)
+            }
+            Comparison::Document(comparator) => {
+                confirmed_with(groups, comparator, controls, skipped, progress)
+            }
+            Comparison::Mail(comparator) => {
+                confirmed_with(groups, comparator, controls, skipped, progress)
+            }
+            Comparison::Hash(comparator) => {
+                confirmed_with(groups, comparator, controls, skipped, progress)
+            }
+        }
+    }
+}
+
+/// Duplicate groups passing the group filters, ordered by file size, then hash, then path, each group's paths sorted
+/// after the copy preferred by --prefer-dir
+///
+/// With `controls`, hashing obeys the hotkeys pressed during the scan.
+fn find_duplicates(
+    files: Vec<FileEntry>,
+    hashes: HashMap<PathBuf, Digest>,
+    cache: Option<&Arc<HashCache>>,
+    args: &Args,
+    controls: Option<&Controls>,
+    skipped: &Skipped,
+) -> Result<Vec<DuplicateGroup<(u64, Digest)>>> {
+    let bytes = files.iter().map(|file| file.size).sum();
+    let progress = hashing_progress(bytes, 2, Message::FindingDuplicates, args);
+    if let Some(controls) = controls {
+        controls.attach(&progress);
+    }
+    let inc = |file: &FileEntry| progress.inc(file.size);
 
-    let mut hashes_by_file_size: Vec<(u64, HashMap<String, IdenticalFiles>)> =
-        hashes_by_file_size.into_iter().collect();
-    hashes_by_file_size.sort_by_key(|pair| pair.0);
+    let mut groups = Comparison::first(args, hashes, cache).group(files, controls, skipped, inc)?;
 
-    term.write_line("")?;
+    progress.finish_and_clear();
+    args.save_cache(cache)?;
+
+    if args.double_check {
+        let candidates = groups
+            .iter()
+            .flat_map(|group| &group.files)
+            .map(|file| file.size)
+            .sum();
+        let progress = hashing_progress(candidates, 3, Message::DoubleChecking, args);
+        if let Some(controls) = controls {
+            controls.attach(&progress);
+        }
+        let inc = |file: &FileEntry| progress.inc(file.size);
 
-    let mut total_size: u64 = 0;
-    for (size, hashes) in hashes_by_file_size {
-        for (_hash, mut identical_files) in hashes {
-            if identical_files.paths.len() < 2 {
-                continue;
-            };
+        groups = Comparison::confirming(args).confirm(groups, controls, skipped, inc)?;
 
-            let dupe_size = size * (identical_files.paths.len() - 1) as u64;
-            total_size += dupe_size;
+        progress.finish_and_clear();
+    }
 
-            identical_files.paths.sort();
+    if args.verify {
+        let candidates = groups
+            .iter()
+            .flat_map(|group| &group.files)
+            .map(|file| file.size)
+            .sum();
+        let step = 3 + args.double_check as usize;
+        let progress = hashing_progress(candidates, step, Message::Verifying, args);
+        if let Some(controls) = controls {
+            controls.attach(&progress);
+        }
+        groups = verified(groups, &args.retry_policy(), skipped, |file| {
+            progress.inc(file.size)
+        })?;
+        progress.finish_and_clear();
+    }
 
-            let paths: Vec<String> = identical_files
-                .paths
-                .iter()
-                .map(|path_buf: &PathBuf| path_buf.display().to_string())
-                .collect();
+    let mut groups = reported_groups(groups, args);
+    for group in &mut groups {
+        args.put_kept_first(group)?;
+    }
+    // Groups of the same size come out of a HashMap, sort them fully so two runs can be diffed
+    groups.sort_by(|a, b| {
+        a.key
+            .cmp(&b.key)
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+    });
+
+    Ok(groups)
+}
+
+/// What stands in for the hash of a group compared with --no-hash, the digest of its first path, so groups still have
+/// an id of their own while that file stays where it is
+fn path_digest(path: &Path) -> Digest {
+    HashAlgorithm::Sha256
+        .digest_reader(&mut path.as_os_str().as_encoded_bytes())
+        .unwrap_or_else(|_| Digest::from_bytes(&[]))
+}
+
+/// The files of a backup to compare against, a directory searched recursively or a manifest as read by --manifest,
+/// with the hashes known for them
+fn backup_files(
+    backup: &Path,
+    args: &Args,
+    skipped: &Skipped,
+) -> Result<(FileList, HashMap<PathBuf, Digest>)> {
+    if backup.is_dir() {
+        let options = ScanOptions {
+            recursive: true,
+            ..args.scan_options()
+        };
+        let discovery = rupes::discover(backup, &options, |_| {})?;
+        skipped.record(discovery.skipped);
+        return Ok((discovery.files, HashMap::new()));
+    }
+
+    if args.quick || args.double_check || args.no_hash || args.compares_normalized() {
+        eprintln!(
+            "A manifest can't be compared with --quick, --double-check, --no-hash, --normalize-text, --documents or --mail, only the hashes of whole files are known"
+        );
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Can't compare a manifest quickly",
+        ));
+    }
 
-            let concatenated_paths = paths.join(&args.separator.to_string());
+    let mut hashes = HashMap::new();
+    let mut files = FileList::default();
+    for (file, hash) in manifest::read(backup)? {
+        hashes.insert(file.path.clone(), hash.parse()?);
+        files.push(file);
+    }
 
-            term.write_line(&concatenated_paths)?;
+    Ok((files, hashes))
+}
 
-            if args.size || args.details {
-                term.write_line(&format!("^ {} of wasted space", DecimalBytes(dupe_size)))?;
+/// `files` split into those with a copy among `backups` and those without one, each sorted by path
+///
+/// A hardlink into a backup isn't a copy, it's the same file.
+fn coverage(
+    files: Vec<FileEntry>,
+    backups: &[FileList],
+    hashes: HashMap<PathBuf, Digest>,
+    cache: Option<&Arc<HashCache>>,
+    args: &Args,
+    skipped: &Skipped,
+) -> Result<(Vec<FileEntry>, Vec<FileEntry>)> {
+    // A backup inside the scanned directories is only the backup
+    let local: HashSet<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+    let inside: HashSet<PathBuf> = backups
+        .iter()
+        .flat_map(FileList::iter)
+        .map(|file| file.path)
+        .filter(|path| local.contains(path.as_path()))
+        .collect();
+    let mut files: Vec<FileEntry> = files
+        .into_iter()
+        .filter(|file| !inside.contains(&file.path))
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    // Only backup files the size of one here can be a copy of it, unless content is normalized
+    let sizes: HashSet<u64> = files.iter().map(|file| file.size).collect();
+    let backup: Vec<FileEntry> = backups
+        .iter()
+        .flat_map(|backup| {
+            backup.entries_where(|size| args.compares_normalized() || sizes.contains(&size))
+        })
+        .collect();
+    let in_backup: HashSet<PathBuf> = backup.iter().map(|file| file.path.clone()).collect();
+
+    let compared = files.iter().cloned().chain(backup).collect();
+    let mut covered = HashSet::new();
+    for group in find_duplicates(compared, hashes, cache, args, None, skipped)? {
+        let (copies, local): (Vec<_>, Vec<_>) = group
+            .files
+            .iter()
+            .partition(|file| in_backup.contains(&file.path));
+
+        for file in local {
+            if copies
+                .iter()
+                .any(|copy| copy.id.is_none() || copy.id != file.id)
+            {
+                covered.insert(file.path.clone());
             }
-            term.write_line("")?
         }
     }
 
-    if args.time || args.details {
-        term.write_line(&format!("Took {:.2?} to complete", now.elapsed()))?;
+    Ok(files
+        .into_iter()
+        .partition(|file| covered.contains(&file.path)))
+}
+
+/// The files among `files` with no copy of their content anywhere else among them, sorted by path
+///
+/// Hardlinks to the same file are a single copy, losing it loses all of them.
+fn unique_files(
+    files: &FileList,
+    hashes: HashMap<PathBuf, Digest>,
+    cache: Option<&Arc<HashCache>>,
+    args: &Args,
+    skipped: &Skipped,
+) -> Result<Vec<FileEntry>> {
+    let mut copied = HashSet::new();
+    for group in find_duplicates(args.candidates(files), hashes, cache, args, None, skipped)? {
+        if group.physical_files().len() >= 2 {
+            copied.extend(group.files.into_iter().map(|file| file.path));
+        }
     }
-    if args.total_size || args.details {
-        term.write_line(&format!("{} total wasted space", DecimalBytes(total_size)))?;
+
+    let mut unique: Vec<FileEntry> = files
+        .iter()
+        .filter(|file| !copied.contains(&file.path))
+        .collect();
+    unique.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(unique)
+}
+
+/// Bytes read per file while sampling hashing throughput
+const SAMPLE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Roughly how many bytes per second `files` can be hashed at, from hashing the start of some of them for up to half a
+/// second on every thread
+fn sample_throughput(files: &[FileEntry], algorithm: HashAlgorithm) -> Option<f64> {
+    let started = Instant::now();
+
+    let sampled: u64 = files
+        .par_iter()
+        .map(|file| {
+            if started.elapsed() > Duration::from_millis(500) {
+                return 0;
+            }
+
+            let Ok(file) = File::open(&file.path) else {
+                return 0;
+            };
+            let mut start = io::Read::take(file, SAMPLE_BYTES);
+            let mut counted = CountingReader(&mut start, 0);
+            let _ = algorithm.hash_reader(&mut counted);
+            counted.1
+        })
+        .sum();
+
+    let elapsed = started.elapsed().as_secs_f64();
+    (sampled > 0 && elapsed > 0.0).then(|| sampled as f64 / elapsed)
+}
+
+/// Counts the bytes read through it
+struct CountingReader<R>(R, u64);
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.0.read(buf)?;
+        self.1 += read as u64;
+        Ok(read)
     }
+}
 
-    term.flush()?;
+/// Show how much is about to be hashed and ask whether to go ahead when it's over `threshold` bytes
+fn confirm_hashing(files: &[FileEntry], threshold: u64, args: &Args) -> Result<bool> {
+    let bytes: u64 = files.iter().map(|file| file.size).sum();
+    if bytes <= threshold {
+        return Ok(true);
+    }
 
-    Ok(())
+    progress_display().clear()?;
+
+    let estimate = match sample_throughput(files, args.algorithm()) {
+        Some(throughput) => locale::text(
+            Message::HashingEstimate,
+            &[(
+                "duration",
+                &locale::duration(Duration::from_secs_f64(bytes as f64 / throughput)),
+            )],
+        ),
+        None => String::new(),
+    };
+    eprint!(
+        "{}",
+        locale::text(
+            Message::ConfirmHashing,
+            &[
+                ("files", &files.len()),
+                ("bytes", &DecimalBytes(bytes)),
+                ("estimate", &estimate),
+            ],
+        )
+    );
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(locale::is_yes(&answer))
+}
+
+/// Show what the planned actions change and ask once whether to go ahead, unless --no-prompt is given
+fn confirm_actions(planned: &[Vec<PlannedAction>], args: &Args) -> Result<bool> {
+    if !(args.quiet && args.no_prompt) {
+        action::write_summary(&mut io::stderr(), planned, &args.directories)?;
+    }
+    if args.no_prompt {
+        return Ok(true);
+    }
+
+    eprint!("{}", locale::text(Message::ConfirmActions, &[]));
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(locale::is_yes(&answer))
+}
+
+/// Refuse options that need to read files which are only known by their hash, such as those on a remote machine
+fn check_precomputed(args: &Args) -> Result<()> {
+    let s3_locations = args
+        .s3_locations()
+        .inspect_err(|error| eprintln!("{}", error))?;
+    if args.remote.is_empty() && args.manifest.is_empty() && s3_locations.is_empty() {
+        return Ok(());
+    }
+
+    if args.quick
+        || args.double_check
+        || args.no_hash
+        || args.verify
+        || args.compares_normalized()
+        || args.operation().is_some()
+    {
+        eprintln!(
+            "--remote, --manifest and s3:// sources can't be combined with --quick, --double-check, --no-hash, --verify, --normalize-text, --documents, --mail or actions, their files can only be compared by hash"
+        );
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Remote files can't be read locally",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Add the files of every --remote, --manifest and s3:// source to `files`, returning the hashes computed for them elsewhere
+///
+/// Objects in a bucket that can't be duplicates, by their size and ETag, are left out rather than downloaded to hash
+/// them, and of those with the same size and ETag only one is downloaded.
+fn add_precomputed(args: &Args, files: &mut FileList) -> Result<HashMap<PathBuf, Digest>> {
+    let mut hashes = HashMap::new();
+
+    for remote in &args.remote {
+        let spinner = show_progress(ProgressBar::new_spinner(), args);
+        spinner.set_style(spinner_style("{spinner} {msg}"));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        spinner.set_message(locale::text(
+            Message::HashingRemote,
+            &[("destination", &remote.destination), ("path", &remote.path)],
+        ));
+
+        for (file, hash) in remote::list(remote, args)? {
+            hashes.insert(file.path.clone(), hash.parse()?);
+            files.push(file);
+        }
+
+        spinner.finish_and_clear();
+    }
+
+    for manifest in &args.manifest {
+        for (file, hash) in manifest::read(manifest)? {
+            hashes.insert(file.path.clone(), hash.parse()?);
+            files.push(file);
+        }
+    }
+
+    let locations = args.s3_locations()?;
+    if locations.is_empty() {
+        return Ok(hashes);
+    }
+
+    let mut objects = Vec::new();
+    for location in locations {
+        objects.extend(s3::list(&location)?);
+    }
+
+    let local_sizes: HashSet<u64> = files.sizes().collect();
+    let groups = s3::candidates(objects, &local_sizes);
+
+    let bytes = groups.iter().map(|group| group[0].size).sum();
+    let progress = hashing_progress(bytes, 2, Message::HashingObjects, args);
+    let hashed = groups
+        .into_par_iter()
+        .map(|group| {
+            let hash = group[0].hash(args.algorithm())?;
+            progress.inc(group[0].size);
+            Ok((group, hash))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    progress.finish_and_clear();
+
+    for (group, hash) in hashed {
+        for object in group {
+            let file = object.entry();
+            hashes.insert(file.path.clone(), hash.clone());
+            files.push(file);
+        }
+    }
+
+    Ok(hashes)
+}
+
+/// Write every file found with its size and hash to stdout, one JSON object per line
+fn hash(args: &Args, skipped: &Skipped) -> Result<()> {
+    let cache = args.hash_cache()?;
+    let discovery = scan_directory(args, skipped)?;
+    if discovery.truncated && !args.quiet {
+        eprintln!("{}", locale::text(Message::Truncated, &[]));
+    }
+    for (path, first) in discovery.aliases.iter().filter(|_| !args.quiet) {
+        eprintln!(
+            "{}",
+            locale::text(
+                Message::SameDirectory,
+                &[("path", &path.display()), ("first", &first.display())],
+            )
+        );
+    }
+
+    let bytes = discovery.files.sizes().sum();
+    let progress = hashing_progress(bytes, 2, Message::HashingFiles, args);
+    let comparator = args.hash_comparator(false, cache.as_ref());
+
+    let hashed = discovery
+        .files
+        .par_iter()
+        .filter_map(|file| {
+            let key = comparator.key(&file.path, file.size);
+            progress.inc(file.size);
+
+            match key {
+                Ok((size, hash)) => Some(Ok(HashedFile {
+                    schema_version: output::versioned(args.schema_version),
+                    path: file.path.display().to_string(),
+                    size,
+                    hash: hash.to_string(),
+                })),
+                Err(error) => skipped.skip(&file.path, error).err().map(Err),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    progress.finish_and_clear();
+    progress_display().clear()?;
+    args.save_cache(cache.as_ref())?;
+
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    for file in hashed {
+        serde_json::to_writer(&mut stdout, &file)?;
+        writeln!(stdout)?;
+    }
+    stdout.flush()
+}
+
+/// The files in the first directory, searched with `options`, and those of them with no copy in any of the others,
+/// whatever their names or paths
+///
+/// The other directories are searched recursively, like a backup given to --covered-by.
+fn missing_files(
+    args: &Args,
+    options: &ScanOptions,
+    skipped: &Skipped,
+) -> Result<(usize, Vec<FileEntry>)> {
+    let Some((source, others)) = args
+        .directories
+        .split_first()
+        .filter(|(_, others)| !others.is_empty())
+    else {
+        eprintln!("{}", locale::text(Message::NoCoverageSources, &[]));
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Nothing to compare with",
+        ));
+    };
+
+    let discovery = rupes::discover(source, options, |_| {})?;
+    skipped.record(discovery.skipped);
+    // Every one of them may be missing and listed
+    let files = discovery.files.into_entries();
+    let checked = files.len();
+    let mut copies = Vec::new();
+    let mut hashes = HashMap::new();
+    for other in others {
+        let (files, known) = backup_files(other, args, skipped)?;
+        copies.push(files);
+        hashes.extend(known);
+    }
+
+    let cache = args.hash_cache()?;
+    let (_, missing) = coverage(files, &copies, hashes, cache.as_ref(), args, skipped)?;
+    progress_display().clear()?;
+
+    Ok((checked, missing))
+}
+
+/// Write the files in the first directory with no copy in any of the others to stdout
+fn missing(args: &Args, skipped: &Skipped) -> Result<()> {
+    let (_, missing) = missing_files(args, &args.scan_options(), skipped)?;
+
+    let mut stdout = io::stdout().lock();
+    for file in missing {
+        writeln!(stdout, "{}", file.path.display())?;
+    }
+    Ok(())
+}
+
+/// Confirm every file in the first directory has a copy in the others, exiting with code 1 and listing those without
+/// one otherwise
+///
+/// The first directory is searched recursively whatever --recursive says, a check skipping its subdirectories would
+/// pass far too easily.
+fn verify_backup(args: &Args, skipped: &Skipped) -> Result<()> {
+    let options = ScanOptions {
+        recursive: true,
+        ..args.scan_options()
+    };
+    let (checked, missing) = missing_files(args, &options, skipped)?;
+    let source = args.directories[0].display();
+
+    if missing.is_empty() {
+        return Term::stdout().write_line(&locale::text(
+            Message::AllCovered,
+            &[("files", &checked), ("directory", &source)],
+        ));
+    }
+
+    let mut stdout = io::stdout().lock();
+    for file in &missing {
+        writeln!(stdout, "{}", file.path.display())?;
+    }
+    stdout.flush()?;
+    eprintln!(
+        "Not verified: {} of the {} files in {} have no copy",
+        missing.len(),
+        checked,
+        source
+    );
+    std::process::exit(1)
+}
+
+fn plan(args: &Args, skipped: &Skipped) -> Result<()> {
+    let Some(operation) = args.operation() else {
+        eprintln!("{}", locale::text(Message::NoPlanAction, &[]));
+        return Err(Error::new(ErrorKind::InvalidInput, "No action to plan"));
+    };
+
+    if args.quick {
+        eprintln!("{}", locale::text(Message::NoQuickPlans, &[]));
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Can't plan quick matches",
+        ));
+    }
+    if args.no_hash {
+        eprintln!("{}", locale::text(Message::NoByteComparePlans, &[]));
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Can't plan byte-for-byte matches",
+        ));
+    }
+
+    let guard = args.guard();
+    guard.check_roots()?;
+    check_precomputed(args)?;
+
+    let discovery = scan_directory(args, skipped)?;
+    if discovery.truncated {
+        eprintln!("{}", locale::text(Message::Truncated, &[]));
+    }
+
+    let cache = args.hash_cache()?;
+    let actions = find_duplicates(
+        args.candidates(&discovery.files),
+        HashMap::new(),
+        cache.as_ref(),
+        args,
+        None,
+        skipped,
+    )?
+    .iter()
+    .flat_map(|group| {
+        let (size, hash) = &group.key;
+        let paths: Vec<PathBuf> = group.files.iter().map(|file| file.path.clone()).collect();
+        action::plan(
+            operation,
+            *size,
+            &hash.to_string(),
+            &paths,
+            &args.keep_reason(&paths[0]),
+        )
+    })
+    .filter(|action| guard.allows(action))
+    .collect();
+    let actions = if args.sidecars {
+        let mut planned = vec![actions];
+        action::take_sidecars(&mut planned);
+        planned.into_iter().flatten().collect()
+    } else {
+        actions
+    };
+    progress_display().clear()?;
+
+    let plan = Plan {
+        schema_version: output::versioned(args.schema_version),
+        algorithm: args.algorithm().name().to_string(),
+        directories: args.local_directories(),
+        actions,
+    };
+
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &plan)?;
+    writeln!(stdout)?;
+
+    Ok(())
+}
+
+/// Check that a file still has the size and hash recorded in a plan
+fn verify_file(path: &Path, size: u64, hash: &str, algorithm: HashAlgorithm) -> Result<()> {
+    if path.metadata()?.len() != size || algorithm.hash_file(path)? != hash {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} has changed since the plan was made", path.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Refuse a planned action whose kept copy is gone, or is the very file acted on, which would lose the only copy
+fn check_target(planned: &PlannedAction) -> Result<()> {
+    if !planned.target.exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{}, the copy kept, no longer exists",
+                planned.target.display()
+            ),
+        ));
+    }
+    if action::same_file(&planned.source, &planned.target) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} is the same file as {}, its only copy",
+                planned.source.display(),
+                planned.target.display()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn apply(
+    plan_path: &Path,
+    log_path: Option<&Path>,
+    force_readonly: bool,
+    allow_system: bool,
+    schema_version: u32,
+) -> Result<()> {
+    let plan: Plan = serde_json::from_reader(io::BufReader::new(File::open(plan_path)?))?;
+    let version = plan.schema_version.unwrap_or(1);
+    if version > output::SCHEMA_VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} was written by a newer rupes, in version {} of the plan format",
+                plan_path.display(),
+                version
+            ),
+        ));
+    }
+
+    if plan.directories.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} doesn't list the directories it was made in, make it again with this version of rupes plan",
+                plan_path.display()
+            ),
+        ));
+    }
+    // A plan can be edited by hand, it's checked as carefully as a scan
+    let guard = action::Guard {
+        roots: &plan.directories,
+        markers: &[],
+        allow_system,
+    };
+    guard.check_roots()?;
+
+    let algorithm: HashAlgorithm = plan.algorithm.parse()?;
+
+    let mut log = match log_path {
+        Some(path) => Some(ActionLog::open(path, schema_version)?),
+        None => None,
+    };
+
+    let mut applied = 0;
+    for planned in &plan.actions {
+        if let Some(refused) = guard.refused(planned) {
+            action::record(planned, Err(refused), log.as_mut())?;
+            continue;
+        }
+        let verified = check_target(planned)
+            .and_then(|_| verify_file(&planned.source, planned.size, &planned.hash, algorithm))
+            .and_then(|_| verify_file(&planned.target, planned.size, &planned.hash, algorithm));
+
+        let succeeded = match verified {
+            Ok(()) => action::execute(planned, force_readonly, log.as_mut())?,
+            Err(error) => action::record(planned, Err(error), log.as_mut())?,
+        };
+
+        if succeeded {
+            applied += 1;
+        }
+    }
+
+    let term: Term = Term::stdout();
+    term.write_line(&locale::text(
+        Message::AppliedSummary,
+        &[("applied", &applied), ("planned", &plan.actions.len())],
+    ))?;
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let raw_args: Vec<_> = std::env::args_os().collect();
+
+    // Neither depends on the configuration, which may not even parse, and the man page documents the real defaults
+    match Cli::try_parse_from(&raw_args) {
+        Ok(Cli {
+            command:
+                Some(Command::Config {
+                    action: ConfigAction::Init { path, force },
+                }),
+            ..
+        }) => return init_config(path.or_else(|| config::path(&raw_args)), force),
+        Ok(Cli {
+            command: Some(Command::Man),
+            ..
+        }) => return man(),
+        _ => {}
+    }
+
+    let settings = config::load(&Cli::command(), &raw_args)?;
+    let matches = config::apply(Cli::command(), &settings).get_matches_from(&raw_args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    match cli.command {
+        Some(Command::Plan { args }) => {
+            configure(&args)?;
+            let skipped = Skipped::new(args.strict);
+            plan(&args, &skipped)?;
+            report_skipped(&args, &skipped);
+            Ok(())
+        }
+        Some(Command::Apply {
+            plan,
+            log,
+            force_readonly,
+            allow_system,
+            schema_version,
+        }) => {
+            // Neither takes --lang, their messages are in the language of the environment
+            locale::select(None, false);
+            apply(
+                &plan,
+                log.as_deref(),
+                force_readonly,
+                allow_system,
+                schema_version,
+            )
+        }
+        Some(Command::Bench { directory, sample }) => bench::run(directory, sample),
+        Some(Command::Hash { args }) => {
+            configure(&args)?;
+            let skipped = Skipped::new(args.strict);
+            hash(&args, &skipped)?;
+            report_skipped(&args, &skipped);
+            Ok(())
+        }
+        Some(Command::Missing { args }) => {
+            configure(&args)?;
+            let skipped = Skipped::new(args.strict);
+            missing(&args, &skipped)?;
+            report_skipped(&args, &skipped);
+            Ok(())
+        }
+        Some(Command::VerifyBackup { args }) => {
+            configure(&args)?;
+            let skipped = Skipped::new(args.strict);
+            verify_backup(&args, &skipped)?;
+            report_skipped(&args, &skipped);
+            Ok(())
+        }
+        Some(Command::Config {
+            action: ConfigAction::Show { .. },
+        }) => {
+            let matches = matches
+                .subcommand_matches("config")
+                .and_then(|matches| matches.subcommand_matches("show"))
+                .unwrap();
+            config::show(
+                &mut io::stdout().lock(),
+                &Cli::command(),
+                matches,
+                &settings,
+            )
+        }
+        Some(Command::Service {
+            action:
+                ServiceAction::Install {
+                    path,
+                    every,
+                    options,
+                },
+        }) => {
+            // Caught now rather than when the service first starts
+            let run = ["rupes", "service", "run"].map(String::from);
+            Cli::try_parse_from(run.iter().chain(&options)).unwrap_or_else(|error| error.exit());
+
+            let (path, start) = service::install(path, every, &options)?;
+            locale::select(None, false);
+            Term::stdout().write_line(&locale::text(
+                Message::ServiceWritten,
+                &[("path", &path.display()), ("command", &start)],
+            ))
+        }
+        Some(Command::Service {
+            action: ServiceAction::Run { every, args },
+        }) => {
+            configure(&args)?;
+            serve(&args, Duration::from_secs(every))
+        }
+        Some(Command::Cache { action }) => {
+            locale::select(None, false);
+            cache(action)
+        }
+        Some(Command::Recheck { report, hash }) => recheck(&report, hash),
+        Some(Command::ScanImage {
+            images,
+            hash,
+            separator,
+            size,
+        }) => scan_image(&images, hash, &separator, size),
+        Some(Command::Config {
+            action: ConfigAction::Init { .. },
+        })
+        | Some(Command::Man) => unreachable!("handled before reading the configuration"),
+        None => {
+            configure(&cli.args)?;
+            let skipped = Skipped::new(cli.args.strict);
+            run(&cli.args, &skipped).inspect_err(|error| {
+                syslog::log(syslog::Level::Error, &format!("Scan failed: {}", error))
+            })?;
+            report_skipped(&cli.args, &skipped);
+            Ok(())
+        }
+    }
+}
+
+/// Sum up the files and directories left out for being unreadable, exiting with --skipped-exit-code if there were any
+fn report_skipped(args: &Args, skipped: &Skipped) {
+    let Some(summary) = skipped.summary() else {
+        return;
+    };
+    syslog::info(&summary);
+    if !args.quiet {
+        eprintln!("{}", summary);
+    }
+    if let Some(code) = args.skipped_exit_code {
+        std::process::exit(code)
+    }
+}
+
+fn recheck(report: &Path, algorithm: HashAlgorithm) -> Result<()> {
+    let sets = output::read_jdupes_json(io::BufReader::new(File::open(report)?))
+        .map_err(|error| Error::new(error.kind(), format!("{}: {}", report.display(), error)))?;
+    let checks = recheck::recheck(&sets, algorithm)?;
+
+    let mut stdout = io::stdout().lock();
+    output::write_rechecks(&mut stdout, &checks)?;
+    stdout.flush()?;
+
+    let valid = checks.iter().filter(|check| check.valid()).count();
+    let changed: usize = checks.iter().map(|check| check.changed.len()).sum();
+    let vanished: usize = checks.iter().map(|check| check.vanished.len()).sum();
+    eprintln!(
+        "{} of {} groups still valid, {} files changed and {} vanished",
+        valid,
+        checks.len(),
+        changed,
+        vanished
+    );
+    if changed + vanished > 0 {
+        std::process::exit(1)
+    }
+    Ok(())
+}
+
+fn scan_image(
+    images: &[PathBuf],
+    algorithm: HashAlgorithm,
+    separator: &str,
+    size: bool,
+) -> Result<()> {
+    let groups = image::duplicates(image::files(images, algorithm)?);
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    let mut wasted = 0;
+    for (file_size, paths) in &groups {
+        let group_wasted = output::wasted_space(*file_size, paths.len());
+        output::write_group(&mut out, paths, group_wasted, separator, size)?;
+        wasted += group_wasted;
+    }
+    out.flush()?;
+
+    eprintln!(
+        "{} groups of files stored more than once across layers, wasting {}",
+        groups.len(),
+        DecimalBytes(wasted)
+    );
+    Ok(())
+}
+
+fn cache(action: CacheAction) -> Result<()> {
+    let term = Term::stdout();
+
+    match action {
+        CacheAction::Export { file, under, cache } => {
+            let exported = HashCache::open(&cache)?.under(&under);
+            exported.write(io::BufWriter::new(File::create(&file)?))?;
+            term.write_line(&format!(
+                "Exported the hashes of {} files in {} to {}",
+                exported.len(),
+                under.display(),
+                file.display()
+            ))
+        }
+        CacheAction::Import { file, under, cache } => {
+            let exported =
+                HashCache::read(io::BufReader::new(File::open(&file)?)).map_err(|error| {
+                    Error::new(error.kind(), format!("{}: {}", file.display(), error))
+                })?;
+            let hashes = HashCache::open(&cache)?;
+            let imported = hashes.import(exported, &under);
+            hashes.save(&cache)?;
+            term.write_line(&format!(
+                "Imported the hashes of {} files in {} into {}",
+                imported,
+                under.display(),
+                cache.display()
+            ))
+        }
+        CacheAction::Prune { cache: path } => {
+            let cache = HashCache::open(&path)?;
+            let pruned = cache.prune();
+            cache.save(&path)?;
+            term.write_line(&locale::text(
+                Message::StaleHashesLeft,
+                &[
+                    ("hashes", &pruned),
+                    ("cache", &path.display()),
+                    ("left", &cache.len()),
+                ],
+            ))
+        }
+        CacheAction::Stats { cache: path } => {
+            let cache = HashCache::open(&path)?;
+            let size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+
+            term.write_line(&locale::text(
+                Message::CacheHashes,
+                &[("hashes", &cache.len())],
+            ))?;
+            term.write_line(&locale::text(
+                Message::CacheSize,
+                &[("size", &DecimalBytes(size))],
+            ))?;
+            match cache.last_scan() {
+                Some(stats) => term.write_line(&locale::text(
+                    Message::CacheLastScan,
+                    &[
+                        ("hits", &stats.hits),
+                        ("hashes", &(stats.hits + stats.misses)),
+                        ("rate", &format!("{:.0}", stats.hit_rate())),
+                        ("saved", &DecimalBytes(stats.bytes_saved)),
+                    ],
+                )),
+                None => term.write_line(&locale::text(Message::CacheNoScan, &[])),
+            }
+        }
+    }
+}
+
+/// Scan with `args`, waiting `every` after each scan, until the process is stopped
+fn serve(args: &Args, every: Duration) -> Result<()> {
+    // Nobody watches the terminal of a service, its scans are followed in the system log
+    if !args.syslog {
+        let _ = syslog::enable();
+    }
+
+    loop {
+        // Counted afresh for every scan, a file unreadable once may not be the next time
+        let skipped = Skipped::new(args.strict);
+        if let Err(error) = run(args, &skipped) {
+            eprintln!("{}", error);
+            syslog::log(syslog::Level::Error, &format!("Scan failed: {}", error));
+        }
+        if let Some(summary) = skipped.summary() {
+            syslog::info(&summary);
+        }
+        std::thread::sleep(every);
+    }
+}
+
+fn man() -> Result<()> {
+    // The version is left off the command itself so it doesn't clash with -V
+    let command = Cli::command()
+        .version(env!("CARGO_PKG_VERSION"))
+        .disable_version_flag(true);
+    clap_mangen::Man::new(command).render(&mut io::stdout().lock())
+}
+
+fn init_config(path: Option<PathBuf>, force: bool) -> Result<()> {
+    let path = path.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            "No configuration path, give one to write to",
+        )
+    })?;
+
+    config::init(&Cli::command(), &path, force)?;
+    locale::select(None, false);
+    Term::stdout().write_line(&locale::text(
+        Message::ConfigWritten,
+        &[("path", &path.display())],
+    ))
+}
+
+/// Apply the options that hold for the whole run, such as the language of messages and the number of threads
+fn configure(args: &Args) -> Result<()> {
+    locale::select(args.lang, args.ascii);
+
+    if args.syslog {
+        syslog::enable()?;
+    }
+
+    if args.xattr_cache && !xattr::SUPPORTED {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "--xattr-cache is only supported on Linux and macOS",
+        ));
+    }
+
+    // Before any thread is started, since threads already running wouldn't be sandboxed
+    if args.sandbox {
+        sandbox::enter(
+            &args.writable_directories(),
+            &args.writable_files()?,
+            args.operation().is_some(),
+        )?;
+    }
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(Error::other)?;
+    }
+
+    Ok(())
+}
+
+fn run(args: &Args, skipped: &Skipped) -> Result<()> {
+    let now = Instant::now();
+
+    let term: Term = Term::stdout();
+
+    if args.version {
+        term.write_line(&format!("Rupes version {}", env!("CARGO_PKG_VERSION")))?;
+        return Ok(());
+    }
+
+    if args.operation().is_some() && args.probable_matches() {
+        eprintln!("{}", locale::text(Message::NoQuickActions, &[]));
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Can't act on unconfirmed quick matches",
+        ));
+    }
+
+    if args.operation().is_some() {
+        args.guard().check_roots()?;
+    }
+
+    check_precomputed(args)?;
+    let ncdu_export = args.ncdu_export()?;
+    let stats_output = args.stats_output()?;
+
+    if syslog::enabled() {
+        let directories: Vec<String> = args
+            .directories
+            .iter()
+            .map(|directory| directory.display().to_string())
+            .collect();
+        syslog::info(&format!("Scanning {}", directories.join(", ")));
+    }
+
+    let Discovery {
+        mut files,
+        truncated,
+        aliases,
+        ..
+    } = scan_directory(args, skipped)?;
+    let hashes = add_precomputed(args, &mut files)?;
+
+    if files.is_empty() {
+        progress_display().clear()?;
+        if !args.quiet {
+            eprintln!("{}", locale::text(Message::NoFiles, &[]));
+        }
+        return Ok(());
+    }
+
+    if let Some(threshold) = args.confirm_over {
+        let local: Vec<FileEntry> = files
+            .iter()
+            .filter(|file| !hashes.contains_key(&file.path))
+            .collect();
+        if !confirm_hashing(&local, threshold, args)? {
+            eprintln!("{}", locale::text(Message::Cancelled, &[]));
+            return Ok(());
+        }
+    }
+
+    if let Some(backup) = &args.covered_by {
+        return report_coverage(backup, files, hashes, args, skipped);
+    }
+    if args.unique {
+        return report_unique(&files, hashes, args, skipped);
+    }
+    if args.any {
+        return report_any(&files, hashes, args, skipped);
+    }
+
+    let extras = report::Extras::find(&files, &hashes, args)?;
+
+    // Hotkeys are read from the terminal only while hashing, dropping them gives it back
+    let hotkeys = if args.quiet || args.no_progress {
+        None
+    } else {
+        Hotkeys::listen()
+    };
+    let controls = hotkeys.as_ref().map(|hotkeys| hotkeys.controls.clone());
+    let cache = args.hash_cache()?;
+    let scanned: u64 = files.sizes().sum();
+    let scanned_files = files.len();
+    let groups = find_duplicates(
+        args.candidates(&files),
+        hashes,
+        cache.as_ref(),
+        args,
+        controls.as_deref(),
+        skipped,
+    )?;
+    drop(hotkeys);
+    progress_display().clear()?;
+
+    let term: Term = Term::buffered_stdout();
+
+    let mut log = match &args.log {
+        Some(path) => Some(ActionLog::open(path, args.schema_version)?),
+        None => None,
+    };
+
+    // Final output
+
+    // Notices go to stderr, stdout only holds results so it can be redirected to a file
+    let text = args.output == OutputFormat::Text
+        && args.format_template.is_none()
+        && args.count.is_none()
+        && !args.omit_first;
+    if !args.quiet {
+        write_notices(
+            truncated,
+            &aliases,
+            controls.as_deref(),
+            cache.as_deref(),
+            args,
+        )?;
+    }
+
+    if text {
+        term.write_line("")?;
+    }
+
+    // Paths already hardlinked or reflinked to each other share their space, only distinct copies are wasted
+    let (groups, groups_copies, shared) = report::distinct_copies(groups, args);
+    let (groups, groups_copies, scores) = report::order(groups, groups_copies, args);
+    let sections = report::Sections::of(&groups, &groups_copies, args);
+
+    if let Some(path) = &ncdu_export {
+        let mut export = io::BufWriter::new(File::create(path)?);
+        output::write_ncdu(&mut export, &groups, &args.local_directories())?;
+        export.flush()?;
+    }
+
+    if let Some(mut stats_output) = stats_output {
+        let cache_stats = cache.as_ref().and_then(|cache| cache.stats());
+        let stats = output::ScanStats {
+            schema_version: output::versioned(args.schema_version),
+            files_scanned: scanned_files,
+            bytes_scanned: scanned,
+            duplicate_groups: groups.len(),
+            duplicate_files: sections.duplicate_files,
+            wasted_bytes: sections.wasted_by_group.iter().sum(),
+            truncated,
+            elapsed_seconds: now.elapsed().as_secs_f64(),
+            cache_hits: cache_stats.as_ref().map(|stats| stats.hits),
+            cache_misses: cache_stats.as_ref().map(|stats| stats.misses),
+        };
+        output::write_stats_json(&mut stats_output, &stats)?;
+    }
+
+    if let Some(count) = args.count {
+        match count {
+            Count::Groups => term.write_line(&groups.len().to_string())?,
+            Count::Files => term.write_line(&sections.duplicate_files.to_string())?,
+        }
+        return term.flush();
+    }
+
+    let mut written =
+        report::write_groups(&term, groups, groups_copies, &scores, &sections, text, args)?;
+    act(
+        std::mem::take(&mut written.planned),
+        args,
+        log.as_mut(),
+        &term,
+    )?;
+
+    // Nothing but the groups, so the output can be piped into other tools
+    if report::groups_only(args) {
+        return term.flush();
+    }
+
+    report::write_sections(&term, &shared, &extras, &sections, &written, args)?;
+
+    let total_size = written.total_size;
+    if args.time || args.details {
+        term.flush()?;
+        eprintln!(
+            "{}",
+            locale::text(
+                Message::Took,
+                &[("duration", &format!("{:.2?}", now.elapsed()))],
+            )
+        );
+    }
+    if args.total_size || args.details {
+        term.write_line(&locale::text(
+            Message::TotalWasted,
+            &[("bytes", &DecimalBytes(total_size))],
+        ))?;
+        if scanned > 0 {
+            term.write_line(&locale::text(
+                Message::WastedShare,
+                &[
+                    (
+                        "percent",
+                        &format!("{:.1}", total_size as f64 * 100.0 / scanned as f64),
+                    ),
+                    ("scanned", &DecimalBytes(scanned)),
+                    (
+                        "remaining",
+                        &DecimalBytes(scanned.saturating_sub(total_size)),
+                    ),
+                ],
+            ))?;
+        }
+    }
+
+    term.flush()?;
+
+    Ok(())
+}
+
+/// Tell on stderr what was left out of the scan, and how much the cache saved
+fn write_notices(
+    truncated: bool,
+    aliases: &[(PathBuf, PathBuf)],
+    controls: Option<&Controls>,
+    cache: Option<&HashCache>,
+    args: &Args,
+) -> Result<()> {
+    let mut stderr = io::stderr();
+
+    if truncated {
+        writeln!(stderr, "{}", locale::text(Message::Truncated, &[]))?;
+    }
+    for (path, first) in aliases {
+        writeln!(
+            stderr,
+            "{}",
+            locale::text(
+                Message::SameDirectory,
+                &[("path", &path.display()), ("first", &first.display())],
+            )
+        )?;
+    }
+
+    if let Some(controls) = controls {
+        let left_out = controls.left_out.load(std::sync::atomic::Ordering::Relaxed);
+        if controls.quitting() {
+            writeln!(
+                stderr,
+                "{}",
+                locale::text(Message::QuitEarly, &[("files", &left_out)])
+            )?;
+        } else if left_out > 0 {
+            writeln!(
+                stderr,
+                "{}",
+                locale::text(Message::Skipped, &[("files", &left_out)])
+            )?;
+        }
+        for path in controls.skipped() {
+            writeln!(stderr, "  {}", path.display())?;
+        }
+    }
+
+    if args.probable_matches() {
+        writeln!(stderr, "{}", locale::text(Message::QuickMode, &[]))?;
+    }
+
+    if let Some(stats) = cache.and_then(|cache| cache.stats()) {
+        writeln!(
+            stderr,
+            "{}",
+            locale::text(
+                Message::CacheUse,
+                &[
+                    ("hits", &stats.hits),
+                    ("misses", &stats.misses),
+                    ("bytes", &DecimalBytes(stats.bytes_saved)),
+                ],
+            )
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Review, then carry out or only show with --dry-run the actions `planned` for the groups found, once confirmed
+fn act(
+    mut planned: Vec<Vec<PlannedAction>>,
+    args: &Args,
+    mut log: Option<&mut ActionLog>,
+    term: &Term,
+) -> Result<()> {
+    if args.sidecars {
+        action::take_sidecars(&mut planned);
+    }
+    if args.interactive && !planned.is_empty() {
+        term.flush()?;
+        planned = review::review(planned)?;
+        if planned.is_empty() {
+            eprintln!("{}", locale::text(Message::ActionsCancelled, &[]));
+        }
+        // A copy kept in review rather than the one planned has sidecars of its own
+        if args.sidecars {
+            action::take_sidecars(&mut planned);
+        }
+    }
+    if planned.is_empty() {
+        return Ok(());
+    }
+
+    term.flush()?;
+    if args.dry_run {
+        action::write_summary(&mut io::stderr(), &planned, &args.directories)?;
+        action::write_dry_run(&mut io::stderr(), &planned)?;
+        eprintln!("{}", locale::text(Message::DryRun, &[]));
+    } else if confirm_actions(&planned, args)? {
+        let mut outcome = action::Outcome::default();
+        for actions in &planned {
+            outcome.add(action::execute_group(
+                actions,
+                args.force_readonly,
+                log.as_deref_mut(),
+            )?);
+        }
+        if let Some(first) = planned.iter().flatten().next().filter(|_| !args.quiet) {
+            let actions = planned.iter().map(Vec::len).sum();
+            action::write_outcome(&mut io::stderr(), first.operation, actions, &outcome)?;
+        }
+    } else {
+        eprintln!("{}", locale::text(Message::ActionsCancelled, &[]));
+    }
+
+    Ok(())
+}
+
+/// With --covered-by, list every file with whether a copy of it is in the backup
+fn report_coverage(
+    backup: &Path,
+    files: FileList,
+    mut hashes: HashMap<PathBuf, Digest>,
+    args: &Args,
+    skipped: &Skipped,
+) -> Result<()> {
+    let (backup, backup_hashes) = backup_files(backup, args, skipped)?;
+    hashes.extend(backup_hashes);
+    let cache = args.hash_cache()?;
+    // Every file is listed, covered or not
+    let (covered, uncovered) = coverage(
+        files.into_entries(),
+        &[backup],
+        hashes,
+        cache.as_ref(),
+        args,
+        skipped,
+    )?;
+    progress_display().clear()?;
+
+    output::write_coverage(&mut io::stdout().lock(), &covered, &uncovered)
+}
+
+/// With --unique, list the files without any copy
+fn report_unique(
+    files: &FileList,
+    hashes: HashMap<PathBuf, Digest>,
+    args: &Args,
+    skipped: &Skipped,
+) -> Result<()> {
+    let cache = args.hash_cache()?;
+    let unique = unique_files(files, hashes, cache.as_ref(), args, skipped)?;
+    progress_display().clear()?;
+
+    let mut stdout = io::stdout().lock();
+    for file in unique {
+        writeln!(stdout, "{}", file.path.display())?;
+    }
+    Ok(())
+}
+
+/// With --any, list the first group of duplicates found and exit with 1, or do nothing when there are none
+fn report_any(
+    files: &FileList,
+    hashes: HashMap<PathBuf, Digest>,
+    args: &Args,
+    skipped: &Skipped,
+) -> Result<()> {
+    let found = any_duplicate(files.same_size_entries(), hashes, args, skipped)?;
+    progress_display().clear()?;
+
+    match found {
+        Some(group) => {
+            let paths: Vec<String> = group
+                .files
+                .iter()
+                .map(|file| file.path.display().to_string())
+                .collect();
+            output::write_group_lines(&mut io::stdout(), &paths, 0, &args.separator, false)?;
+            std::process::exit(1)
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("1234"), Ok(1234));
+        assert_eq!(parse_size("500M"), Ok(500_000_000));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("10 kb"), Ok(10_000));
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("20000000TiB").is_err());
+    }
 }
@@ -0,0 +1,82 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use rupes::FileEntry;
+
+use crate::remote::HashedFile;
+
+/// Parse one `path,size,hash` line, paths may themselves contain commas
+fn parse_csv_line(line: &str) -> Option<HashedFile> {
+    let mut fields = line.rsplitn(3, ',');
+    let hash = fields.next()?.trim();
+    let size = fields.next()?.trim().parse().ok()?;
+    let path = fields.next()?;
+
+    Some(HashedFile {
+        schema_version: None,
+        path: path.to_string(),
+        size,
+        hash: hash.to_ascii_lowercase(),
+    })
+}
+
+/// Read a listing of files hashed elsewhere, as `path,size,hash` lines or the JSON lines written by `rupes hash`
+///
+/// Paths are prefixed with the name of the manifest, such as `server.csv:/srv/file`, so they can't be mistaken for
+/// local files.
+pub fn read(manifest: &Path) -> Result<Vec<(FileEntry, String)>> {
+    let content = fs::read_to_string(manifest)?;
+    let name = manifest.file_name().unwrap_or_default().to_string_lossy();
+
+    let mut files = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || (number == 0 && line == "path,size,hash") {
+            continue;
+        }
+
+        let hashed = if line.starts_with('{') {
+            serde_json::from_str(line).ok()
+        } else {
+            parse_csv_line(line)
+        };
+        let hashed = hashed.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} line {} isn't a path,size,hash listing",
+                    manifest.display(),
+                    number + 1
+                ),
+            )
+        })?;
+
+        files.push((
+            FileEntry {
+                path: PathBuf::from(format!("{}:{}", name, hashed.path)),
+                size: hashed.size,
+                id: None,
+            },
+            hashed.hash,
+        ));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_lines() {
+        let file = parse_csv_line("/home/me/a, b.txt,1234,ABCDEF").unwrap();
+        assert_eq!(file.path, "/home/me/a, b.txt");
+        assert_eq!(file.size, 1234);
+        assert_eq!(file.hash, "abcdef");
+
+        assert!(parse_csv_line("/no/size,abcdef").is_none());
+        assert!(parse_csv_line("/bad/size,big,abcdef").is_none());
+    }
+}
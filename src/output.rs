@@ -0,0 +1,1619 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use indicatif::DecimalBytes;
+use serde::{Deserialize, Serialize};
+
+use rupes::chunks::{DedupEstimate, SimilarPair};
+use rupes::{Digest, DuplicateGroup, FileEntry, HashAlgorithm};
+
+use crate::locale::{self, Message};
+use crate::mail::MboxMessage;
+use crate::recheck::GroupCheck;
+use crate::score::Score;
+use crate::video::SimilarVideos;
+
+/// Version of the structure of the JSON rupes writes, raised whenever a field is added, renamed or removed
+///
+/// The structure of a version never changes once released, --schema-version writes any earlier one exactly as it was.
+/// Version 1 is the structure from before outputs were versioned, without a version field. Version 2 adds it, and the
+/// role and reason of each file of jdupes JSON.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The version field written in outputs of schema `version`, none for version 1, which predates it
+pub fn versioned(version: u32) -> Option<u32> {
+    (version >= 2).then_some(version)
+}
+
+/// What --count counts
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Count {
+    /// Groups of duplicates
+    Groups,
+    /// Redundant copies, not counting the one file of each group that would be kept
+    Files,
+}
+
+/// How the groups of duplicates are ordered in the report
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GroupOrder {
+    /// By file size, then content, then path
+    #[default]
+    Size,
+    /// The groups that look safest and most worthwhile to act on first, see [`crate::score`]
+    Score,
+}
+
+/// How the report of duplicates is written to stdout
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Paths of each group separated by --separator, with the sections asked for after them
+    #[default]
+    #[value(alias = "plain")]
+    Text,
+    /// The JSON written by jdupes -j, for frontends and scripts written for jdupes
+    JdupesJson,
+    /// rupes' own JSON, with the hash, size and waste of every group and the totals
+    Json,
+    /// A row per file of every group, after a header row
+    Csv,
+    /// Paths of each group a line each, groups separated by blank lines and nothing else, like fdupes
+    Fdupes,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JdupesMatchSet {
+    /// Not part of jdupes' output, consumers ignore it
+    #[serde(default)]
+    group_id: String,
+    file_size: u64,
+    /// Not part of jdupes' output, only written with --show-hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    file_list: Vec<JdupesFile>,
+}
+
+/// What becomes of a file of a group, the original being the copy that would be kept
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Original,
+    Redundant,
+}
+
+impl Role {
+    /// The role of the file at `index` in its group, whose first file is the one kept
+    pub fn of(index: usize) -> Role {
+        if index == 0 {
+            Role::Original
+        } else {
+            Role::Redundant
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Original => "original",
+            Role::Redundant => "redundant",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JdupesFile {
+    file_path: String,
+    /// Not part of jdupes' output, consumers ignore it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<Role>,
+    /// Why the original is the copy kept, only written for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JdupesReport {
+    match_sets: Vec<JdupesMatchSet>,
+}
+
+/// Read the groups of a report written with --output jdupes-json, or by jdupes -j itself
+pub fn read_jdupes_json(reader: impl Read) -> Result<Vec<MatchSet>> {
+    let report: JdupesReport = serde_json::from_reader(reader)?;
+    Ok(report
+        .match_sets
+        .into_iter()
+        .map(|set| MatchSet {
+            id: set.group_id,
+            size: set.file_size,
+            hash: set.hash,
+            kept_reason: set.file_list.first().and_then(|file| file.reason.clone()),
+            paths: set
+                .file_list
+                .into_iter()
+                .map(|file| PathBuf::from(file.file_path))
+                .collect(),
+        })
+        .collect())
+}
+
+/// An identifier for the group of files with content hashing to `hash`, the same from one scan to the next however its
+/// files change
+pub fn group_id(hash: &str) -> String {
+    let digest = HashAlgorithm::Sha256
+        .hash_reader(&mut hash.as_bytes())
+        .unwrap_or_default();
+    digest[..digest.len().min(16)].to_string()
+}
+
+/// A group of duplicates as written to the structured outputs
+pub struct MatchSet {
+    /// See [`group_id`]
+    pub id: String,
+    pub size: u64,
+    /// The digest of the group's content, prefixed with its algorithm like `sha256:...`, when asked for
+    pub hash: Option<String>,
+    /// Why the first path is the copy that would be kept, such as `first path`, when known
+    pub kept_reason: Option<String>,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Writes groups in the JSON structure jdupes writes with -j as they come, so they needn't all be held in memory
+///
+/// The version fields identify rupes rather than a jdupes release, since consumers only rely on them being present.
+pub struct JdupesJson {
+    written: usize,
+    /// See [`SCHEMA_VERSION`]
+    version: u32,
+}
+
+impl JdupesJson {
+    /// Write the fields before the groups, in schema `version`, and open the list of groups
+    pub fn begin(out: &mut impl Write, version: u32) -> Result<Self> {
+        let mut fields = vec![];
+        if let Some(version) = versioned(version) {
+            // Not part of jdupes' output, consumers ignore it
+            fields.push(("schemaVersion", serde_json::to_string(&version)?));
+        }
+        let strings = [
+            (
+                "jdupesVersion",
+                format!("rupes {}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("jdupesVersionDate", String::new()),
+            (
+                "commandLine",
+                std::env::args().collect::<Vec<_>>().join(" "),
+            ),
+            ("extensionFlags", "none".to_string()),
+        ];
+        for (name, value) in strings {
+            fields.push((name, serde_json::to_string(&value)?));
+        }
+
+        writeln!(out, "{{")?;
+        for (name, value) in fields {
+            writeln!(out, "  \"{}\": {},", name, value)?;
+        }
+        write!(out, "  \"matchSets\": [")?;
+
+        Ok(JdupesJson {
+            written: 0,
+            version,
+        })
+    }
+
+    pub fn group(&mut self, out: &mut impl Write, set: &MatchSet) -> Result<()> {
+        let set = JdupesMatchSet {
+            group_id: set.id.clone(),
+            file_size: set.size,
+            hash: set.hash.clone(),
+            file_list: set
+                .paths
+                .iter()
+                .enumerate()
+                .map(|(index, path)| JdupesFile {
+                    file_path: path.display().to_string(),
+                    role: Some(Role::of(index)).filter(|_| self.version >= 2),
+                    reason: set
+                        .kept_reason
+                        .clone()
+                        .filter(|_| index == 0 && self.version >= 2),
+                })
+                .collect(),
+        };
+
+        let separator = if self.written == 0 { "" } else { "," };
+        writeln!(out, "{}", separator)?;
+        let json = serde_json::to_string_pretty(&set)?;
+        let indented: Vec<String> = json.lines().map(|line| format!("    {}", line)).collect();
+        write!(out, "{}", indented.join("\n"))?;
+
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Close the list of groups and the report
+    pub fn finish(self, out: &mut impl Write) -> Result<()> {
+        if self.written > 0 {
+            write!(out, "\n  ")?;
+        }
+        writeln!(out, "]")?;
+        writeln!(out, "}}")
+    }
+}
+
+#[derive(Serialize)]
+struct JsonFile {
+    path: String,
+    role: Role,
+    /// Only written for the original
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonGroup {
+    id: String,
+    /// None when compared with --no-hash
+    hash: Option<String>,
+    size: u64,
+    wasted_bytes: u64,
+    files: Vec<JsonFile>,
+}
+
+/// The totals at the end of a [`JsonReport`]
+#[derive(Serialize)]
+pub struct JsonTotals {
+    pub groups: usize,
+    /// Copies beyond the one kept of each group
+    pub duplicate_files: usize,
+    pub wasted_bytes: u64,
+}
+
+/// Writes groups as --output json as they come, so they needn't all be held in memory
+pub struct JsonReport {
+    written: usize,
+}
+
+impl JsonReport {
+    /// Write the fields before the groups, in schema `version`, and open the list of groups
+    pub fn begin(out: &mut impl Write, version: u32, algorithm: HashAlgorithm) -> Result<Self> {
+        write!(out, "{{")?;
+        if let Some(version) = versioned(version) {
+            write!(out, "\"schema_version\":{},", version)?;
+        }
+        write!(
+            out,
+            "\"algorithm\":{},\"groups\":[",
+            serde_json::to_string(algorithm.name())?
+        )?;
+        Ok(JsonReport { written: 0 })
+    }
+
+    /// Write `set`, whose redundant copies waste `wasted` bytes
+    pub fn group(&mut self, out: &mut impl Write, set: &MatchSet, wasted: u64) -> Result<()> {
+        let group = JsonGroup {
+            id: set.id.clone(),
+            hash: set.hash.clone(),
+            size: set.size,
+            wasted_bytes: wasted,
+            files: set
+                .paths
+                .iter()
+                .enumerate()
+                .map(|(index, path)| JsonFile {
+                    path: path.display().to_string(),
+                    role: Role::of(index),
+                    reason: set.kept_reason.clone().filter(|_| index == 0),
+                })
+                .collect(),
+        };
+
+        if self.written > 0 {
+            write!(out, ",")?;
+        }
+        serde_json::to_writer(&mut *out, &group)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Close the list of groups and write the `totals` after it
+    pub fn finish(self, out: &mut impl Write, totals: &JsonTotals) -> Result<()> {
+        write!(out, "],\"totals\":")?;
+        serde_json::to_writer(&mut *out, totals)?;
+        writeln!(out, "}}")
+    }
+}
+
+/// `field` quoted for CSV when it holds a comma, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The header row of --output csv in schema `version`
+pub fn write_csv_header(out: &mut impl Write, version: u32) -> Result<()> {
+    match versioned(version) {
+        Some(_) => writeln!(
+            out,
+            "schema_version,group,id,hash,size,wasted_bytes,role,reason,path"
+        ),
+        None => writeln!(out, "group,id,hash,size,wasted_bytes,role,path"),
+    }
+}
+
+/// A row of --output csv in schema `version` for every file of `set`, the `group`th, whose redundant copies waste
+/// `wasted` bytes
+///
+/// Only the original's row has a reason, why it's the copy kept.
+pub fn write_csv_group(
+    out: &mut impl Write,
+    group: usize,
+    set: &MatchSet,
+    wasted: u64,
+    version: u32,
+) -> Result<()> {
+    for (index, path) in set.paths.iter().enumerate() {
+        if let Some(version) = versioned(version) {
+            write!(out, "{},", version)?;
+        }
+        write!(
+            out,
+            "{},{},{},{},{},{},",
+            group,
+            set.id,
+            set.hash.as_deref().unwrap_or_default(),
+            set.size,
+            wasted,
+            Role::of(index).name(),
+        )?;
+        if versioned(version).is_some() {
+            let reason = set.kept_reason.as_deref().filter(|_| index == 0);
+            write!(out, "{},", csv_field(reason.unwrap_or_default()))?;
+        }
+        writeln!(out, "{}", csv_field(&path.display().to_string()))?;
+    }
+    Ok(())
+}
+
+/// Write the paths of a group as fdupes does, each on a line and a blank line after them, or with --print0 each ended
+/// by a NUL and another after them
+pub fn write_fdupes_group(out: &mut impl Write, paths: &[PathBuf], print0: bool) -> Result<()> {
+    let end = if print0 { "\0" } else { "\n" };
+    for path in paths {
+        write!(out, "{}{}", path.display(), end)?;
+    }
+    write!(out, "{}", end)
+}
+
+/// Values a --format-template placeholder can stand for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Group,
+    Id,
+    Hash,
+    Size,
+    Waste,
+    Mtime,
+    Path,
+    Role,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field(Field),
+}
+
+/// A line written for every file of every group, such as `{hash}\t{size}\t{path}`
+///
+/// Placeholders are `{group}`, the 1-based index of the group, `{id}`, its stable [`group_id`], `{hash}`, `{size}` and `{waste}` in bytes, `{mtime}` as
+/// an RFC 3339 UTC time, `{path}` and `{role}`, original for the copy kept and redundant for the others. `\t`, `\n` and `\\` are unescaped and `{{` and `}}` stand for literal braces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(template: &str) -> std::result::Result<Self, Self::Err> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut characters = template.chars().peekable();
+
+        while let Some(character) = characters.next() {
+            match character {
+                '\\' => match characters.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('\\') | None => text.push('\\'),
+                    Some(other) => {
+                        text.push('\\');
+                        text.push(other);
+                    }
+                },
+                '{' if characters.next_if_eq(&'{').is_some() => text.push('{'),
+                '{' => {
+                    let name: String = characters.by_ref().take_while(|&c| c != '}').collect();
+                    let field = match name.as_str() {
+                        "group" => Field::Group,
+                        "id" => Field::Id,
+                        "hash" => Field::Hash,
+                        "size" => Field::Size,
+                        "waste" => Field::Waste,
+                        "mtime" => Field::Mtime,
+                        "path" => Field::Path,
+                        "role" => Field::Role,
+                        _ => {
+                            return Err(format!(
+                                "unknown placeholder {{{}}}, expected one of {{group}}, {{id}}, {{hash}}, {{size}}, {{waste}}, {{mtime}}, {{path}} or {{role}}",
+                                name
+                            ))
+                        }
+                    };
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Field(field));
+                }
+                '}' => {
+                    characters.next_if_eq(&'}');
+                    text.push('}');
+                }
+                other => text.push(other),
+            }
+        }
+
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+
+        Ok(Template { pieces })
+    }
+}
+
+/// Everything a [`Template`] can show about one file of a group
+pub struct TemplateFile<'a> {
+    pub group: usize,
+    pub hash: &'a str,
+    pub size: u64,
+    pub waste: u64,
+    pub path: &'a Path,
+    pub role: Role,
+}
+
+impl Template {
+    pub fn render(&self, file: &TemplateFile) -> String {
+        let mut line = String::new();
+
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => line.push_str(text),
+                Piece::Field(Field::Group) => line.push_str(&file.group.to_string()),
+                Piece::Field(Field::Id) => line.push_str(&group_id(file.hash)),
+                Piece::Field(Field::Hash) => line.push_str(file.hash),
+                Piece::Field(Field::Size) => line.push_str(&file.size.to_string()),
+                Piece::Field(Field::Waste) => line.push_str(&file.waste.to_string()),
+                Piece::Field(Field::Mtime) => {
+                    let modified = fs::symlink_metadata(file.path).and_then(|m| m.modified());
+                    if let Ok(modified) = modified {
+                        line.push_str(&rfc3339(modified));
+                    }
+                }
+                Piece::Field(Field::Path) => line.push_str(&file.path.display().to_string()),
+                Piece::Field(Field::Role) => line.push_str(file.role.name()),
+            }
+        }
+
+        line
+    }
+}
+
+/// Split seconds since the Unix epoch into a UTC (year, month, day, hour, minute, second)
+pub fn utc(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    let days = seconds.div_euclid(86_400);
+    let of_day = seconds.rem_euclid(86_400) as u32;
+
+    // Civil from days, counting eras of 400 years from 0000-03-01
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let of_era = days.rem_euclid(146_097);
+    let year_of_era = (of_era - of_era / 1460 + of_era / 36_524 - of_era / 146_096) / 365;
+    let day_of_year = of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60,
+    )
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Write one line per file of a group with `template`
+pub fn write_template_group(
+    out: &mut impl Write,
+    template: &Template,
+    group: usize,
+    hash: &str,
+    size: u64,
+    waste: u64,
+    paths: &[PathBuf],
+) -> Result<()> {
+    for (index, path) in paths.iter().enumerate() {
+        let file = TemplateFile {
+            group,
+            hash,
+            size,
+            waste,
+            path,
+            role: Role::of(index),
+        };
+        writeln!(out, "{}", template.render(&file))?;
+    }
+    Ok(())
+}
+
+/// Upper bounds, in bytes, of the wasted space histogram buckets, the last bucket has no upper bound
+const HISTOGRAM_BOUNDS: [u64; 5] = [
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+    10_000_000_000,
+];
+const HISTOGRAM_LABELS: [&str; 6] = [
+    "< 1 MB",
+    "1 - 10 MB",
+    "10 - 100 MB",
+    "100 MB - 1 GB",
+    "1 - 10 GB",
+    ">= 10 GB",
+];
+
+/// Space wasted by the redundant copies in a group of `copies` files of `size` bytes
+pub fn wasted_space(size: u64, copies: usize) -> u64 {
+    size * (copies as u64 - 1)
+}
+
+/// Write one group of duplicates, its paths joined by `separator`, followed by its wasted space if `show_wasted`
+pub fn write_group(
+    out: &mut impl Write,
+    paths: &[PathBuf],
+    wasted: u64,
+    separator: &str,
+    show_wasted: bool,
+) -> Result<()> {
+    let paths_display: Vec<String> = paths
+        .iter()
+        .map(|path_buf: &PathBuf| path_buf.display().to_string())
+        .collect();
+
+    write_group_lines(out, &paths_display, wasted, separator, show_wasted)
+}
+
+/// Like [`write_group`], with a line already made for each path, such as by [`long_line`]
+pub fn write_group_lines(
+    out: &mut impl Write,
+    lines: &[String],
+    wasted: u64,
+    separator: &str,
+    show_wasted: bool,
+) -> Result<()> {
+    writeln!(out, "{}", lines.join(separator))?;
+
+    if show_wasted {
+        writeln!(out, "^ {} of wasted space", DecimalBytes(wasted))?;
+    }
+    writeln!(out)
+}
+
+/// The name of the user with id `uid`, or the id itself if it has no name
+#[cfg(unix)]
+fn user_name(uid: u32) -> String {
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    let mut user = std::mem::MaybeUninit::<libc::passwd>::uninit();
+    let mut found = std::ptr::null_mut();
+
+    // SAFETY: every pointer is valid for the lengths given, the name is only read if a user was found
+    unsafe {
+        let status = libc::getpwuid_r(
+            uid,
+            user.as_mut_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut found,
+        );
+        if status == 0 && !found.is_null() {
+            let name = std::ffi::CStr::from_ptr((*found).pw_name);
+            return name.to_string_lossy().into_owned();
+        }
+    }
+
+    uid.to_string()
+}
+
+fn owner(metadata: &fs::Metadata) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        user_name(metadata.uid())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        "-".to_string()
+    }
+}
+
+/// The line written before a group with --sort score, like `score 72: named like copies, wasting a lot of space`
+pub fn score_line(score: &Score) -> String {
+    if score.reasons.is_empty() {
+        return format!("score {}", score.points);
+    }
+    format!("score {}: {}", score.points, score.reasons.join(", "))
+}
+
+/// A path with its size, modification time in UTC and owner before it, like `ls -l`
+pub fn long_line(path: &Path) -> String {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return format!("{:>12}  {:16}  {:8}  {}", "?", "?", "?", path.display());
+    };
+
+    let modified = match metadata.modified() {
+        Ok(modified) => {
+            let (year, month, day, hour, minute, _) = utc(modified);
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}",
+                year, month, day, hour, minute
+            )
+        }
+        Err(_) => "?".to_string(),
+    };
+
+    format!(
+        "{:>12}  {:16}  {:8}  {}",
+        DecimalBytes(metadata.len()).to_string(),
+        modified,
+        owner(&metadata),
+        path.display()
+    )
+}
+
+/// Write sets of paths that already share their storage, such as hardlinks, under `heading`
+pub fn write_shared(
+    out: &mut impl Write,
+    heading: &str,
+    shared: &[Vec<PathBuf>],
+    separator: &str,
+) -> Result<()> {
+    writeln!(out, "{}", heading)?;
+    writeln!(out)?;
+
+    for paths in shared {
+        write_group(out, paths, 0, separator, false)?;
+    }
+
+    Ok(())
+}
+
+pub fn write_empty_dirs(out: &mut impl Write, directories: &[PathBuf]) -> Result<()> {
+    writeln!(
+        out,
+        "Empty directories, or holding nothing but redundant copies"
+    )?;
+
+    for directory in directories {
+        writeln!(out, "{}", directory.display())?;
+    }
+    writeln!(out)
+}
+
+/// The `count` largest duplicated files as (size, copies, first path), ties broken by path so the view is stable
+pub fn top_files(
+    groups: &[DuplicateGroup<(u64, Digest)>],
+    count: usize,
+) -> Vec<(u64, usize, PathBuf)> {
+    let mut top_files: Vec<(u64, usize, PathBuf)> = groups
+        .iter()
+        .map(|group| (group.key.0, group.files.len(), group.files[0].path.clone()))
+        .collect();
+    top_files.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(&b.2)));
+    top_files.truncate(count);
+    top_files
+}
+
+pub fn write_top_files(out: &mut impl Write, top_files: &[(u64, usize, PathBuf)]) -> Result<()> {
+    writeln!(out, "Largest files with duplicates")?;
+
+    for (size, copies, path) in top_files {
+        writeln!(
+            out,
+            "{:>12}  {:>4} copies  {}",
+            DecimalBytes(*size).to_string(),
+            copies,
+            path.display()
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Files with a copy in the backup given to --covered-by, which are safe to delete locally, then files without one
+pub fn write_coverage(
+    out: &mut impl Write,
+    covered: &[FileEntry],
+    uncovered: &[FileEntry],
+) -> Result<()> {
+    for (heading, files) in [
+        (Message::BackedUp, covered),
+        (Message::NotBackedUp, uncovered),
+    ] {
+        let bytes: u64 = files.iter().map(|file| file.size).sum();
+        writeln!(
+            out,
+            "{}",
+            locale::text(
+                heading,
+                &[("files", &files.len()), ("bytes", &DecimalBytes(bytes))],
+            )
+        )?;
+        for file in files {
+            writeln!(out, "{}", file.path.display())?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Directories holding copies of the same files, as (directory, other directory, files shared, bytes shared), the pairs
+/// sharing the most bytes first
+pub fn dir_pairs(groups: &[DuplicateGroup<(u64, Digest)>]) -> Vec<(PathBuf, PathBuf, usize, u64)> {
+    let mut pairs: HashMap<(PathBuf, PathBuf), (usize, u64)> = HashMap::new();
+
+    for group in groups {
+        let mut directories: Vec<&Path> = group
+            .files
+            .iter()
+            .filter_map(|file| file.path.parent())
+            .collect();
+        directories.sort();
+        directories.dedup();
+
+        for (index, first) in directories.iter().enumerate() {
+            for second in &directories[index + 1..] {
+                let shared = pairs
+                    .entry((first.to_path_buf(), second.to_path_buf()))
+                    .or_default();
+                shared.0 += 1;
+                shared.1 += group.key.0;
+            }
+        }
+    }
+
+    let mut pairs: Vec<_> = pairs
+        .into_iter()
+        .map(|((first, second), (files, bytes))| (first, second, files, bytes))
+        .collect();
+    pairs.sort_by(|a, b| {
+        b.3.cmp(&a.3)
+            .then_with(|| a.0.cmp(&b.0))
+            .then_with(|| a.1.cmp(&b.1))
+    });
+    pairs
+}
+
+pub fn write_dir_pairs(
+    out: &mut impl Write,
+    pairs: &[(PathBuf, PathBuf, usize, u64)],
+) -> Result<()> {
+    writeln!(out, "Directories sharing duplicates")?;
+
+    for (first, second, files, bytes) in pairs {
+        writeln!(
+            out,
+            "{} and {} share {} files / {}",
+            first.display(),
+            second.display(),
+            files,
+            DecimalBytes(*bytes)
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Space wasted within each directory, counting redundant copies, every path but the first of each group, towards
+/// their directory and its ancestors up to the scanned root they were found in
+///
+/// Returned as (directory, redundant files, wasted bytes), the directories wasting the most first.
+pub fn by_dir(
+    groups: &[DuplicateGroup<(u64, Digest)>],
+    roots: &[PathBuf],
+) -> Vec<(PathBuf, usize, u64)> {
+    let mut wasted: HashMap<&Path, (usize, u64)> = HashMap::new();
+
+    for group in groups {
+        for file in &group.files[1..] {
+            for directory in file.path.ancestors().skip(1) {
+                if directory.as_os_str().is_empty() {
+                    break;
+                }
+
+                let total = wasted.entry(directory).or_default();
+                total.0 += 1;
+                total.1 += group.key.0;
+
+                if roots.iter().any(|root| root == directory) {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut directories: Vec<_> = wasted
+        .into_iter()
+        .map(|(directory, (files, bytes))| (directory.to_path_buf(), files, bytes))
+        .collect();
+    directories.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    directories
+}
+
+/// A directory of the tree exported for ncdu, holding redundant copies and the directories leading to them
+#[derive(Default)]
+struct NcduDirectory {
+    files: Vec<(String, u64)>,
+    directories: BTreeMap<String, NcduDirectory>,
+}
+
+impl NcduDirectory {
+    fn to_json(&self, name: String) -> serde_json::Value {
+        let mut entries = vec![serde_json::json!({ "name": name })];
+
+        for (name, directory) in &self.directories {
+            entries.push(directory.to_json(name.clone()));
+        }
+        for (name, size) in &self.files {
+            entries.push(serde_json::json!({ "name": name, "asize": size, "dsize": size }));
+        }
+
+        serde_json::Value::Array(entries)
+    }
+}
+
+fn component_names(path: &Path) -> Vec<String> {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Write the redundant copies of every group, all paths but the first, as an ncdu JSON export
+///
+/// Each copy's size is the space it wastes, so browsing the export with `ncdu -f` shows how much every directory
+/// would get back. The tree starts at the deepest directory holding every root.
+pub fn write_ncdu(
+    out: &mut impl Write,
+    groups: &[DuplicateGroup<(u64, Digest)>],
+    roots: &[PathBuf],
+) -> Result<()> {
+    let mut prefix = roots
+        .first()
+        .map(|root| component_names(root))
+        .unwrap_or_default();
+    for root in roots {
+        let names = component_names(root);
+        let common = prefix
+            .iter()
+            .zip(&names)
+            .take_while(|(a, b)| a == b)
+            .count();
+        prefix.truncate(common);
+    }
+
+    let mut tree = NcduDirectory::default();
+    for group in groups {
+        for file in &group.files[1..] {
+            let names = component_names(&file.path);
+            let Some(relative) = names.strip_prefix(prefix.as_slice()) else {
+                continue;
+            };
+            let Some((name, directories)) = relative.split_last() else {
+                continue;
+            };
+
+            let directory = directories.iter().fold(&mut tree, |directory, name| {
+                directory.directories.entry(name.clone()).or_default()
+            });
+            directory.files.push((name.clone(), group.key.0));
+        }
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let root_name = if prefix.is_empty() {
+        ".".to_string()
+    } else {
+        prefix.iter().collect::<PathBuf>().display().to_string()
+    };
+
+    let export = serde_json::json!([
+        1,
+        2,
+        {
+            "progname": "rupes",
+            "progver": env!("CARGO_PKG_VERSION"),
+            "timestamp": timestamp,
+        },
+        tree.to_json(root_name),
+    ]);
+    serde_json::to_writer(&mut *out, &export)?;
+    writeln!(out)
+}
+
+pub fn write_by_dir(out: &mut impl Write, directories: &[(PathBuf, usize, u64)]) -> Result<()> {
+    writeln!(out, "Wasted space by directory")?;
+
+    for (directory, files, bytes) in directories {
+        writeln!(
+            out,
+            "{:>12}  {:>6} files  {}",
+            DecimalBytes(*bytes).to_string(),
+            files,
+            directory.display()
+        )?;
+    }
+    writeln!(out)
+}
+
+pub fn write_similar(out: &mut impl Write, similar: &[SimilarPair], separator: &str) -> Result<()> {
+    writeln!(
+        out,
+        "Similar files (experimental, found with content-defined chunking)"
+    )?;
+    writeln!(out)?;
+
+    for pair in similar {
+        writeln!(
+            out,
+            "{}{}{}",
+            pair.first.path.display(),
+            separator,
+            pair.second.path.display()
+        )?;
+        writeln!(
+            out,
+            "^ {:.1}% similar, {} shared",
+            pair.similarity * 100.0,
+            DecimalBytes(pair.shared_bytes)
+        )?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Write how much removing duplicate files would save next to what block-level deduplication would, for --estimate-dedup
+pub fn write_dedup_estimate(
+    out: &mut impl Write,
+    estimate: &DedupEstimate,
+    file_savings: u64,
+) -> Result<()> {
+    writeln!(
+        out,
+        "Deduplication estimate, of {} scanned",
+        DecimalBytes(estimate.total)
+    )?;
+    writeln!(out)?;
+
+    let [blocks, records] = estimate.unique_blocks;
+    let rows = [
+        ("Removing duplicate files", file_savings),
+        ("4 KiB blocks, as bees on Btrfs", estimate.total - blocks),
+        ("128 KiB records, as ZFS dedup", estimate.total - records),
+        (
+            "Content-defined chunks, as backup tools",
+            estimate.total - estimate.unique_chunks,
+        ),
+    ];
+    for (name, saved) in rows {
+        let percent = if estimate.total == 0 {
+            0.0
+        } else {
+            saved as f64 * 100.0 / estimate.total as f64
+        };
+        writeln!(
+            out,
+            "{:<42}{:>12} ({:.1}%)",
+            name,
+            DecimalBytes(saved).to_string(),
+            percent
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Write what became of each group of a report checked again by `rupes recheck`, numbered from 1 when the report has no
+/// group ids
+pub fn write_rechecks(out: &mut impl Write, checks: &[GroupCheck]) -> Result<()> {
+    for (index, check) in checks.iter().enumerate() {
+        let id = if check.id.is_empty() {
+            (index + 1).to_string()
+        } else {
+            check.id.clone()
+        };
+        let total = check.unchanged.len() + check.changed.len() + check.vanished.len();
+        if check.valid() {
+            writeln!(
+                out,
+                "Group {}: still valid, {} of {} files unchanged",
+                id,
+                check.unchanged.len(),
+                total
+            )?;
+        } else {
+            writeln!(out, "Group {}: no longer valid", id)?;
+        }
+
+        for path in &check.changed {
+            writeln!(out, "  changed   {}", path.display())?;
+        }
+        for path in &check.vanished {
+            writeln!(out, "  vanished  {}", path.display())?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Write the groups of videos found by --video, each followed by their duration and resolution
+pub fn write_videos(out: &mut impl Write, videos: &[SimilarVideos], separator: &str) -> Result<()> {
+    writeln!(
+        out,
+        "Similar videos (experimental, found by duration, resolution and sampled frames)"
+    )?;
+    writeln!(out)?;
+
+    for group in videos {
+        let paths: Vec<String> = group
+            .files
+            .iter()
+            .map(|file| file.path.display().to_string())
+            .collect();
+        writeln!(out, "{}", paths.join(separator))?;
+
+        let seconds = group.duration.round() as u64;
+        writeln!(
+            out,
+            "^ {}:{:02}:{:02}, {}x{}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            group.width,
+            group.height
+        )?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Write the messages found more than once in mbox files by --mail, each group followed by its subject
+pub fn write_messages(
+    out: &mut impl Write,
+    messages: &[Vec<MboxMessage>],
+    separator: &str,
+) -> Result<()> {
+    writeln!(
+        out,
+        "Duplicate messages in mbox files, by mailbox and message number"
+    )?;
+    writeln!(out)?;
+
+    for group in messages {
+        let locations: Vec<String> = group
+            .iter()
+            .map(|message| format!("{}#{}", message.mailbox.display(), message.number))
+            .collect();
+        writeln!(out, "{}", locations.join(separator))?;
+        writeln!(out, "^ {}", group[0].subject)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Write the sidecars and RAW or JPEG companions found next to duplicates by --sidecars
+pub fn write_companions(
+    out: &mut impl Write,
+    companions: &[(PathBuf, Vec<PathBuf>)],
+) -> Result<()> {
+    writeln!(out, "Sidecars and companions of duplicates")?;
+    writeln!(out)?;
+
+    for (path, found) in companions {
+        let found: Vec<String> = found
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        writeln!(out, "{}", path.display())?;
+        writeln!(out, "^ {}", found.join(", "))?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Count the groups, and the space they waste, in each bucket of the wasted space histogram
+pub fn histogram(wasted_by_group: &[u64]) -> [(usize, u64); HISTOGRAM_LABELS.len()] {
+    let mut buckets = [(0usize, 0u64); HISTOGRAM_LABELS.len()];
+    for &wasted in wasted_by_group {
+        let bucket = HISTOGRAM_BOUNDS
+            .iter()
+            .position(|&bound| wasted < bound)
+            .unwrap_or(HISTOGRAM_BOUNDS.len());
+        buckets[bucket].0 += 1;
+        buckets[bucket].1 += wasted;
+    }
+    buckets
+}
+
+/// The extension of `path` in lowercase with its dot, such as ".jpg", or "(none)"
+fn extension(path: &Path) -> String {
+    match path.extension() {
+        Some(extension) => format!(".{}", extension.to_string_lossy().to_lowercase()),
+        None => "(none)".to_string(),
+    }
+}
+
+/// The number of groups and space wasted by the files of each extension, largest first
+///
+/// A group counts towards the extension of its first path, the copy that would be kept.
+pub fn wasted_by_extension(
+    groups: &[DuplicateGroup<(u64, Digest)>],
+    wasted_by_group: &[u64],
+) -> Vec<(String, usize, u64)> {
+    let mut by_extension: HashMap<String, (usize, u64)> = HashMap::new();
+    for (group, &wasted) in groups.iter().zip(wasted_by_group) {
+        let totals = by_extension
+            .entry(extension(&group.files[0].path))
+            .or_default();
+        totals.0 += 1;
+        totals.1 += wasted;
+    }
+
+    let mut by_extension: Vec<(String, usize, u64)> = by_extension
+        .into_iter()
+        .map(|(extension, (groups, wasted))| (extension, groups, wasted))
+        .collect();
+    by_extension.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    by_extension
+}
+
+/// The statistics of a scan written by --stats-fd and --stats-file
+#[derive(Serialize)]
+pub struct ScanStats {
+    /// See [`SCHEMA_VERSION`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub files_scanned: usize,
+    pub bytes_scanned: u64,
+    pub duplicate_groups: usize,
+    /// Copies beyond the first of each group
+    pub duplicate_files: usize,
+    pub wasted_bytes: u64,
+    /// Whether discovery stopped early, at --max-files or --max-bytes
+    pub truncated: bool,
+    pub elapsed_seconds: f64,
+    /// Only written with --cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_hits: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_misses: Option<u64>,
+}
+
+/// Write `stats` as a single line of JSON
+pub fn write_stats_json(out: &mut impl Write, stats: &ScanStats) -> Result<()> {
+    serde_json::to_writer(&mut *out, stats)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+pub fn write_stats(
+    out: &mut impl Write,
+    wasted_by_group: &[u64],
+    duplicate_files: usize,
+    by_extension: &[(String, usize, u64)],
+) -> Result<()> {
+    let total: u64 = wasted_by_group.iter().sum();
+
+    writeln!(out, "Statistics")?;
+    writeln!(
+        out,
+        "{} duplicate groups, {} redundant files, {} wasted",
+        wasted_by_group.len(),
+        duplicate_files,
+        DecimalBytes(total)
+    )?;
+    writeln!(out)?;
+
+    let buckets = histogram(wasted_by_group);
+    let most_groups = buckets.iter().map(|bucket| bucket.0).max().unwrap_or(0);
+
+    writeln!(out, "Groups by wasted space")?;
+    for (label, (groups, wasted)) in HISTOGRAM_LABELS.iter().zip(buckets) {
+        let bar = if most_groups == 0 {
+            0
+        } else {
+            (groups * 30).div_ceil(most_groups)
+        };
+
+        let line = format!(
+            "{:>13} {:>8} groups {:>12} {}",
+            label,
+            groups,
+            DecimalBytes(wasted).to_string(),
+            "#".repeat(bar)
+        );
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "Wasted space by extension")?;
+    for (extension, groups, wasted) in by_extension {
+        writeln!(
+            out,
+            "{:<13} {:>12} {:>8} groups",
+            extension,
+            DecimalBytes(*wasted).to_string(),
+            groups
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rupes::FileEntry;
+    use std::time::Duration;
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn group(size: u64, names: &[&str]) -> DuplicateGroup<(u64, Digest)> {
+        DuplicateGroup {
+            key: (size, Digest::from_bytes(&[])),
+            files: names
+                .iter()
+                .map(|name| FileEntry {
+                    path: PathBuf::from(name),
+                    size,
+                    id: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn group_output() {
+        let paths = [PathBuf::from("a"), PathBuf::from("b")];
+
+        assert_eq!(
+            written(|out| write_group(out, &paths, 1500, "\n", true)),
+            "a\nb\n^ 1.50 kB of wasted space\n\n"
+        );
+        assert_eq!(
+            written(|out| write_group(out, &paths, 1500, " ", false)),
+            "a b\n\n"
+        );
+    }
+
+    #[test]
+    fn report_formats() {
+        let set = MatchSet {
+            id: "1".to_string(),
+            size: 3,
+            hash: Some("sha256:abc".to_string()),
+            kept_reason: Some("first path".to_string()),
+            paths: vec![PathBuf::from("a"), PathBuf::from("b, \"c\"")],
+        };
+
+        assert_eq!(
+            written(|out| write_csv_group(out, 1, &set, 3, 1)),
+            "1,1,sha256:abc,3,3,original,a\n1,1,sha256:abc,3,3,redundant,\"b, \"\"c\"\"\"\n"
+        );
+        assert_eq!(
+            written(|out| write_csv_group(out, 1, &set, 3, SCHEMA_VERSION)),
+            "2,1,1,sha256:abc,3,3,original,first path,a\n2,1,1,sha256:abc,3,3,redundant,,\"b, \"\"c\"\"\"\n"
+        );
+        assert_eq!(
+            written(|out| write_fdupes_group(out, &set.paths[..1], true)),
+            "a\0\0"
+        );
+
+        let json = written(|out| {
+            let mut report = JsonReport::begin(out, SCHEMA_VERSION, HashAlgorithm::Sha256)?;
+            report.group(out, &set, 3)?;
+            report.finish(
+                out,
+                &JsonTotals {
+                    groups: 1,
+                    duplicate_files: 1,
+                    wasted_bytes: 3,
+                },
+            )
+        });
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["groups"][0]["files"][1]["role"], "redundant");
+        assert_eq!(json["groups"][0]["wasted_bytes"], 3);
+        assert_eq!(json["totals"]["duplicate_files"], 1);
+    }
+
+    #[test]
+    fn largest_files_first() {
+        let groups = [
+            group(10, &["small", "small2"]),
+            group(30, &["large", "large2", "large3"]),
+            group(20, &["b-medium", "b-medium2"]),
+            group(20, &["a-medium", "a-medium2"]),
+        ];
+
+        let top = top_files(&groups, 3);
+        let names: Vec<_> = top
+            .iter()
+            .map(|file| file.2.display().to_string())
+            .collect();
+        assert_eq!(names, ["large", "a-medium", "b-medium"]);
+        assert_eq!(top[0].1, 3);
+    }
+
+    #[test]
+    fn directory_pairs() {
+        let groups = [
+            group(10, &["backup/a", "photos/a"]),
+            group(30, &["backup/b", "photos/b", "other/b"]),
+            group(5, &["backup/c", "backup/c2"]),
+        ];
+
+        let pairs = dir_pairs(&groups);
+        let pair = |first: &str, second: &str| {
+            pairs
+                .iter()
+                .find(|pair| pair.0 == Path::new(first) && pair.1 == Path::new(second))
+                .map(|pair| (pair.2, pair.3))
+        };
+
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pair("backup", "photos"), Some((2, 40)));
+        assert_eq!(pair("backup", "other"), Some((1, 30)));
+        assert_eq!(pairs[0].0, Path::new("backup"));
+        assert_eq!(pairs[0].1, Path::new("photos"));
+    }
+
+    #[test]
+    fn wasted_by_directory() {
+        let groups = [
+            group(10, &["root/a/x", "root/b/x", "root/b/deep/x"]),
+            group(5, &["root/a/y", "root/a/y2"]),
+        ];
+
+        let directories = by_dir(&groups, &[PathBuf::from("root")]);
+        let names: Vec<_> = directories
+            .iter()
+            .map(|directory| (directory.0.display().to_string(), directory.1, directory.2))
+            .collect();
+
+        assert_eq!(
+            names,
+            [
+                ("root".to_string(), 3, 25),
+                ("root/b".to_string(), 2, 20),
+                ("root/b/deep".to_string(), 1, 10),
+                ("root/a".to_string(), 1, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn stable_group_ids() {
+        assert_eq!(group_id("abc"), group_id("abc"));
+        assert_ne!(group_id("abc"), group_id("abd"));
+        assert_eq!(group_id("abc").len(), 16);
+    }
+
+    #[test]
+    fn templates() {
+        let template: Template = r"{group}\t{hash}\t{size}:{waste} {{{path}}} {role}"
+            .parse()
+            .unwrap();
+        let file = TemplateFile {
+            group: 2,
+            hash: "abc",
+            size: 10,
+            waste: 20,
+            path: Path::new("dir/a"),
+            role: Role::Redundant,
+        };
+        assert_eq!(template.render(&file), "2\tabc\t10:20 {dir/a} redundant");
+
+        assert!("{nope}".parse::<Template>().is_err());
+        assert_eq!(
+            r"a\\b\n".parse::<Template>().unwrap().render(&file),
+            "a\\b\n"
+        );
+    }
+
+    #[test]
+    fn utc_dates() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_827_696)),
+            "2000-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            rfc3339(UNIX_EPOCH - Duration::from_secs(86_400)),
+            "1969-12-31T00:00:00Z"
+        );
+    }
+
+    fn write_jdupes_json(out: &mut Vec<u8>, match_sets: &[MatchSet], version: u32) -> Result<()> {
+        let mut json = JdupesJson::begin(out, version)?;
+        for set in match_sets {
+            json.group(out, set)?;
+        }
+        json.finish(out)
+    }
+
+    #[test]
+    fn jdupes_json() {
+        let sets = [MatchSet {
+            id: group_id("abc"),
+            size: 3,
+            hash: None,
+            kept_reason: Some("first path".to_string()),
+            paths: vec![PathBuf::from("dir/a"), PathBuf::from("dir/b")],
+        }];
+        let json = written(|out| write_jdupes_json(out, &sets, SCHEMA_VERSION));
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(json["matchSets"][0]["fileSize"], 3);
+        assert_eq!(json["matchSets"][0]["fileList"][1]["filePath"], "dir/b");
+        assert_eq!(json["matchSets"][0]["fileList"][0]["role"], "original");
+        assert_eq!(json["matchSets"][0]["fileList"][0]["reason"], "first path");
+        assert_eq!(json["matchSets"][0]["fileList"][1]["role"], "redundant");
+        assert!(json["matchSets"][0]["fileList"][1].get("reason").is_none());
+        assert!(json["jdupesVersion"].is_string());
+        assert!(json["commandLine"].is_string());
+        assert!(json["matchSets"][0].get("hash").is_none());
+        assert_eq!(json["matchSets"][0]["groupId"], group_id("abc"));
+        assert_eq!(json["schemaVersion"], SCHEMA_VERSION);
+
+        let json = written(|out| write_jdupes_json(out, &[], SCHEMA_VERSION));
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["matchSets"], serde_json::json!([]));
+
+        // Exactly as written before outputs were versioned
+        let json = written(|out| write_jdupes_json(out, &sets, 1));
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(json.get("schemaVersion").is_none());
+        assert_eq!(
+            json["matchSets"][0]["fileList"][0],
+            serde_json::json!({"filePath": "dir/a"})
+        );
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        let buckets = histogram(&[0, 999_999, 1_000_000, 20_000_000_000]);
+
+        assert_eq!(buckets[0], (2, 999_999));
+        assert_eq!(buckets[1], (1, 1_000_000));
+        assert_eq!(buckets[5], (1, 20_000_000_000));
+        assert_eq!(wasted_space(100, 3), 200);
+    }
+
+    #[test]
+    fn ncdu_export() {
+        let groups = [
+            group(
+                100,
+                &["/data/a/film.mp4", "/data/b/film.mp4", "/data/b/c/film.mp4"],
+            ),
+            group(10, &["/data/a/x", "/data/a/y"]),
+        ];
+
+        let export = written(|out| write_ncdu(out, &groups, &[PathBuf::from("/data")]));
+        let export: serde_json::Value = serde_json::from_str(&export).unwrap();
+
+        assert_eq!(export[0], 1);
+        assert_eq!(export[2]["progname"], "rupes");
+        assert_eq!(
+            export[3],
+            serde_json::json!([
+                { "name": "/data" },
+                [
+                    { "name": "a" },
+                    { "name": "y", "asize": 10, "dsize": 10 },
+                ],
+                [
+                    { "name": "b" },
+                    [
+                        { "name": "c" },
+                        { "name": "film.mp4", "asize": 100, "dsize": 100 },
+                    ],
+                    { "name": "film.mp4", "asize": 100, "dsize": 100 },
+                ],
+            ])
+        );
+
+        let export = written(|out| {
+            write_ncdu(
+                out,
+                &groups,
+                &[PathBuf::from("/data/a"), PathBuf::from("/data/b")],
+            )
+        });
+        assert!(export.contains(r#"[{"name":"/data"}"#));
+    }
+
+    #[test]
+    fn extension_breakdown() {
+        let groups = [
+            group(100, &["a/film.MP4", "b/film.mp4"]),
+            group(10, &["a/photo.jpg", "b/photo.jpg", "c/photo.jpg"]),
+            group(50, &["a/clip.mp4", "b/clip.mov"]),
+            group(5, &["a/README", "b/README"]),
+        ];
+        let wasted = [100, 20, 50, 5];
+
+        assert_eq!(
+            wasted_by_extension(&groups, &wasted),
+            [
+                (".mp4".to_string(), 2, 150),
+                (".jpg".to_string(), 1, 20),
+                ("(none)".to_string(), 1, 5),
+            ]
+        );
+
+        let stats =
+            written(|out| write_stats(out, &wasted, 5, &wasted_by_extension(&groups, &wasted)));
+        assert!(stats
+            .contains("Wasted space by extension\n.mp4                 150 B        2 groups\n"));
+    }
+}
@@ -0,0 +1,264 @@
+//! Holding the paths of very many files compactly, each directory's path once rather than once for every file in it
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::walk::{FileEntry, FileId};
+
+/// Index of a directory in a [`FileList`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct DirectoryId(u32);
+
+/// A directory, named relative to its parent, or by its full path when it has none
+#[derive(Debug)]
+pub(crate) struct Directory {
+    pub parent: Option<DirectoryId>,
+    pub name: Box<OsStr>,
+}
+
+/// A file, named relative to the directory it's in
+#[derive(Debug)]
+pub(crate) struct CompactFile {
+    pub directory: DirectoryId,
+    pub name: Box<OsStr>,
+    pub size: u64,
+    pub id: Option<FileId>,
+}
+
+/// Files as found by [`discover`](crate::discover), in the order they were found
+///
+/// Every directory is stored once as its parent and its name, and every file as its directory and its name, so a deep
+/// tree of millions of files takes a fraction of the memory a [`PathBuf`] per file would. [`FileEntry`]s, with full
+/// paths, are only put together as they're needed, for every file by [`FileList::iter`] and [`FileList::into_entries`], or
+/// for those that may have a duplicate by [`FileList::same_size_entries`].
+#[derive(Debug, Default)]
+pub struct FileList {
+    directories: Vec<Directory>,
+    files: Vec<CompactFile>,
+}
+
+impl FileList {
+    pub(crate) fn from_parts(directories: Vec<Directory>, files: Vec<CompactFile>) -> Self {
+        FileList { directories, files }
+    }
+
+    /// Add `directory` to `directories`, parents must be added before their children
+    pub(crate) fn add_directory(
+        directories: &mut Vec<Directory>,
+        directory: Directory,
+    ) -> DirectoryId {
+        directories.push(directory);
+        DirectoryId(directories.len() as u32 - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The size of every file, in order
+    pub fn sizes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.files.iter().map(|file| file.size)
+    }
+
+    /// Every file with its full path, in order
+    pub fn iter(&self) -> impl Iterator<Item = FileEntry> + '_ {
+        let directories = self.directory_paths();
+        self.files.iter().map(move |file| entry(&directories, file))
+    }
+
+    /// Every file with its full path, in order, each put together by the thread it's handed to
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = FileEntry> + '_ {
+        let directories = self.directory_paths();
+        self.files
+            .par_iter()
+            .map(move |file| entry(&directories, file))
+    }
+
+    /// The files whose size `keep` accepts with their full paths, in order, the paths of the others are never put
+    /// together
+    pub fn entries_where(&self, keep: impl Fn(u64) -> bool) -> Vec<FileEntry> {
+        let directories = self.directory_paths();
+        self.files
+            .iter()
+            .filter(|file| keep(file.size))
+            .map(|file| entry(&directories, file))
+            .collect()
+    }
+
+    /// The files sharing their size with another, with their full paths, in order
+    ///
+    /// Only these can have a duplicate by a comparator matching [files of the same
+    /// size](crate::ContentComparator::same_size), most files of a large tree have a size of their own.
+    pub fn same_size_entries(&self) -> Vec<FileEntry> {
+        let mut by_size: HashMap<u64, usize> = HashMap::new();
+        for size in self.sizes() {
+            *by_size.entry(size).or_default() += 1;
+        }
+        self.entries_where(|size| by_size[&size] >= 2)
+    }
+
+    /// Every file with its full path, in order
+    pub fn into_entries(self) -> Vec<FileEntry> {
+        let directories = self.directory_paths();
+        self.files
+            .iter()
+            .map(|file| entry(&directories, file))
+            .collect()
+    }
+
+    /// Add `file` after the files already in the list
+    pub fn push(&mut self, file: FileEntry) {
+        let parent = file.path.parent().unwrap_or(Path::new(""));
+        let name = file.path.file_name().unwrap_or(file.path.as_os_str());
+
+        // Files added one at a time mostly come from the same directory as the one before
+        let directory = match self.files.last() {
+            Some(last) if self.directory_path(last.directory) == parent => last.directory,
+            _ => FileList::add_directory(
+                &mut self.directories,
+                Directory {
+                    parent: None,
+                    name: parent.as_os_str().into(),
+                },
+            ),
+        };
+
+        self.files.push(CompactFile {
+            directory,
+            name: name.into(),
+            size: file.size,
+            id: file.id,
+        });
+    }
+
+    fn directory_path(&self, DirectoryId(id): DirectoryId) -> PathBuf {
+        let directory = &self.directories[id as usize];
+        match directory.parent {
+            Some(parent) => self.directory_path(parent).join(&*directory.name),
+            None => PathBuf::from(&directory.name),
+        }
+    }
+
+    /// The full path of every directory, by id
+    fn directory_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = Vec::with_capacity(self.directories.len());
+        for directory in &self.directories {
+            let path = match directory.parent {
+                Some(DirectoryId(parent)) => paths[parent as usize].join(&*directory.name),
+                None => PathBuf::from(&directory.name),
+            };
+            paths.push(path);
+        }
+
+        paths
+    }
+}
+
+fn entry(directories: &[PathBuf], file: &CompactFile) -> FileEntry {
+    FileEntry {
+        path: directories[file.directory.0 as usize].join(&*file.name),
+        size: file.size,
+        id: file.id,
+    }
+}
+
+impl FromIterator<FileEntry> for FileList {
+    fn from_iter<I: IntoIterator<Item = FileEntry>>(files: I) -> Self {
+        let mut list = FileList::default();
+        list.extend(files);
+        list
+    }
+}
+
+impl Extend<FileEntry> for FileList {
+    fn extend<I: IntoIterator<Item = FileEntry>>(&mut self, files: I) {
+        for file in files {
+            self.push(file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_put_back_together() {
+        let mut directories = Vec::new();
+        let root = FileList::add_directory(
+            &mut directories,
+            Directory {
+                parent: None,
+                name: OsStr::new("root").into(),
+            },
+        );
+        let sub = FileList::add_directory(
+            &mut directories,
+            Directory {
+                parent: Some(root),
+                name: OsStr::new("sub").into(),
+            },
+        );
+        let file = |directory, name: &str, size| CompactFile {
+            directory,
+            name: OsStr::new(name).into(),
+            size,
+            id: None,
+        };
+        let mut list =
+            FileList::from_parts(directories, vec![file(sub, "b", 2), file(root, "a", 1)]);
+
+        list.push(FileEntry {
+            path: PathBuf::from("elsewhere/c"),
+            size: 3,
+            id: None,
+        });
+        list.push(FileEntry {
+            path: PathBuf::from("elsewhere/d"),
+            size: 4,
+            id: None,
+        });
+
+        list.push(FileEntry {
+            path: PathBuf::from("elsewhere/e"),
+            size: 2,
+            id: None,
+        });
+
+        assert_eq!(list.len(), 5);
+        assert_eq!(list.sizes().collect::<Vec<_>>(), [2, 1, 3, 4, 2]);
+        let same_size: Vec<PathBuf> = list
+            .same_size_entries()
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(same_size, ["root/sub/b", "elsewhere/e"].map(PathBuf::from));
+        assert_eq!(
+            list.par_iter().collect::<Vec<_>>(),
+            list.iter().collect::<Vec<_>>()
+        );
+        let paths: Vec<PathBuf> = list
+            .into_entries()
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "root/sub/b",
+                "root/a",
+                "elsewhere/c",
+                "elsewhere/d",
+                "elsewhere/e"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use clap::ValueEnum;
+use regex::Regex;
+
+/// Common kinds of files, recognised by their extensions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Photos and pictures, including camera raw formats
+    Images,
+    Videos,
+    Audio,
+    /// Office documents, PDFs and ebooks
+    Documents,
+    /// Compressed archives and disk images
+    Archives,
+}
+
+impl Preset {
+    /// Extensions of the files in this preset, in lowercase
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Preset::Images => &[
+                "jpg", "jpeg", "jpe", "jfif", "png", "gif", "bmp", "tif", "tiff", "webp", "heic",
+                "heif", "avif", "jxl", "svg", "ico", "psd", "raw", "dng", "cr2", "cr3", "nef",
+                "nrw", "arw", "srf", "sr2", "orf", "rw2", "raf", "pef", "srw", "x3f", "3fr", "erf",
+                "kdc", "mrw",
+            ],
+            Preset::Videos => &[
+                "mp4", "m4v", "mov", "avi", "mkv", "webm", "wmv", "flv", "mpg", "mpeg", "m2ts",
+                "mts", "ts", "3gp", "3g2", "ogv", "vob", "mxf",
+            ],
+            Preset::Audio => &[
+                "mp3", "m4a", "aac", "flac", "wav", "aif", "aiff", "ogg", "oga", "opus", "wma",
+                "alac", "ape", "wv", "mid", "midi",
+            ],
+            Preset::Documents => &[
+                "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "xls", "xlsx", "ods", "csv",
+                "ppt", "pptx", "odp", "pages", "numbers", "key", "epub", "mobi", "azw3", "djvu",
+                "tex",
+            ],
+            Preset::Archives => &[
+                "zip", "7z", "rar", "tar", "gz", "tgz", "bz2", "tbz2", "xz", "txz", "zst", "lz",
+                "lzma", "cab", "iso", "dmg", "img",
+            ],
+        }
+    }
+
+    /// A --filter pattern matching the names of files in this preset, whatever the case of their extension
+    pub fn pattern(self) -> Regex {
+        Regex::new(&format!("(?i)[.]({})$", self.extensions().join("|"))).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_patterns() {
+        let images = Preset::Images.pattern();
+        assert!(images.is_match("IMG_0001.HEIC"));
+        assert!(images.is_match("holiday.jpeg"));
+        assert!(images.is_match("DSC_1234.NEF"));
+        assert!(!images.is_match("notes.txt"));
+        assert!(!images.is_match("jpg"));
+
+        assert!(Preset::Archives.pattern().is_match("backup.tar.gz"));
+        assert!(!Preset::Videos.pattern().is_match("song.mp3"));
+    }
+}
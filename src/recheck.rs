@@ -0,0 +1,150 @@
+//! Checking that the groups of an earlier report still hold duplicates, for `rupes recheck`, since files may have
+//! changed or gone in the days between a report and acting on it
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+
+use rayon::prelude::*;
+
+use rupes::HashAlgorithm;
+
+use crate::output::MatchSet;
+
+/// What became of the files of a group since it was reported
+#[derive(Debug, PartialEq, Eq)]
+pub struct GroupCheck {
+    pub id: String,
+    /// Files with the content they were reported with
+    pub unchanged: Vec<PathBuf>,
+    /// Files whose size or hash is no longer that of the group
+    pub changed: Vec<PathBuf>,
+    pub vanished: Vec<PathBuf>,
+}
+
+impl GroupCheck {
+    /// Whether the group still holds duplicates to act on
+    pub fn valid(&self) -> bool {
+        self.unchanged.len() >= 2
+    }
+}
+
+/// The algorithm and digest of a hash written with --show-hash, such as `sha256:...`
+fn reported_hash(hash: &str) -> Result<(HashAlgorithm, &str)> {
+    let (algorithm, digest) = hash.split_once(':').ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("'{}' isn't a hash written by --show-hash", hash),
+        )
+    })?;
+    Ok((algorithm.parse()?, digest))
+}
+
+/// Hash the files of `set` again, with the algorithm of its hash when the report has one and `algorithm` otherwise
+///
+/// Without a hash to compare with, the files sharing the content most of them have are taken as unchanged.
+fn check(set: &MatchSet, algorithm: HashAlgorithm) -> Result<GroupCheck> {
+    let (algorithm, expected) = match &set.hash {
+        Some(hash) => {
+            let (algorithm, digest) = reported_hash(hash)?;
+            (algorithm, Some(digest))
+        }
+        None => (algorithm, None),
+    };
+
+    let mut vanished = Vec::new();
+    let mut hashed: Vec<(PathBuf, Option<String>)> = Vec::new();
+    for path in &set.paths {
+        match path.metadata() {
+            Err(error) if error.kind() == ErrorKind::NotFound => vanished.push(path.clone()),
+            Err(error) => {
+                return Err(Error::new(
+                    error.kind(),
+                    format!("{}: {}", path.display(), error),
+                ))
+            }
+            Ok(metadata) if metadata.len() != set.size => hashed.push((path.clone(), None)),
+            Ok(_) => hashed.push((path.clone(), Some(algorithm.hash_file(path)?))),
+        }
+    }
+
+    let kept = match expected {
+        Some(expected) => Some(expected.to_string()),
+        None => {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for hash in hashed.iter().filter_map(|(_, hash)| hash.as_deref()) {
+                *counts.entry(hash).or_default() += 1;
+            }
+            // The earliest listed of the most common contents
+            let most = counts.values().copied().max().unwrap_or(0);
+            hashed
+                .iter()
+                .filter_map(|(_, hash)| hash.as_deref())
+                .find(|hash| counts[hash] == most && most >= 2)
+                .map(str::to_string)
+        }
+    };
+
+    let (unchanged, changed): (Vec<_>, Vec<_>) = hashed
+        .into_iter()
+        .partition(|(_, hash)| hash.is_some() && *hash == kept);
+    Ok(GroupCheck {
+        id: set.id.clone(),
+        unchanged: unchanged.into_iter().map(|(path, _)| path).collect(),
+        changed: changed.into_iter().map(|(path, _)| path).collect(),
+        vanished,
+    })
+}
+
+/// Check every group in `sets` again, hashing with `algorithm` those reported without a hash
+pub fn recheck(sets: &[MatchSet], algorithm: HashAlgorithm) -> Result<Vec<GroupCheck>> {
+    sets.par_iter().map(|set| check(set, algorithm)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn changed_and_vanished() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paths: Vec<PathBuf> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        for path in &paths[..3] {
+            fs::write(path, "same").unwrap();
+        }
+        fs::write(&paths[2], "diff").unwrap();
+
+        let set = MatchSet {
+            id: "1".to_string(),
+            size: 4,
+            hash: None,
+            kept_reason: None,
+            paths: paths.clone(),
+        };
+        let checked = check(&set, HashAlgorithm::Sha256).unwrap();
+        assert_eq!(checked.unchanged, &paths[..2]);
+        assert_eq!(checked.changed, &paths[2..3]);
+        assert_eq!(checked.vanished, &paths[3..]);
+        assert!(checked.valid());
+
+        // With the hash reported, the content it was reported with is the one that counts
+        let hash = format!(
+            "sha256:{}",
+            HashAlgorithm::Sha256.hash_file(&paths[2]).unwrap()
+        );
+        let checked = check(
+            &MatchSet {
+                hash: Some(hash),
+                ..set
+            },
+            HashAlgorithm::Md5,
+        )
+        .unwrap();
+        assert_eq!(checked.unchanged, &paths[2..3]);
+        assert!(!checked.valid());
+    }
+}
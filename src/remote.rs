@@ -0,0 +1,244 @@
+use std::io::{BufRead, Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use rupes::FileEntry;
+
+use crate::tool::SSH;
+use crate::Args;
+
+/// A file with its size and hash, one JSON line of the output of `rupes hash`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HashedFile {
+    /// See [`output::SCHEMA_VERSION`](crate::output::SCHEMA_VERSION)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    pub path: String,
+    pub size: u64,
+    pub hash: String,
+}
+
+/// A directory on another machine, given as `user@host:/path`
+#[derive(Clone, Debug)]
+pub struct Remote {
+    /// Where to connect to with ssh, such as `user@host`
+    pub destination: String,
+    pub path: String,
+}
+
+impl FromStr for Remote {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some((destination, path)) if !destination.is_empty() && !path.is_empty() => {
+                Ok(Remote {
+                    destination: destination.to_string(),
+                    path: path.to_string(),
+                })
+            }
+            _ => Err(format!("expected user@host:/path, got '{}'", value)),
+        }
+    }
+}
+
+/// Quote `value` for the POSIX shell ssh runs the remote command with
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Arguments of the remote `rupes hash` that make it find and hash files the same way as the local scan
+fn forwarded_args(args: &Args) -> Vec<String> {
+    let mut forwarded = vec![
+        "hash".to_string(),
+        "--quiet".to_string(),
+        "--hash".to_string(),
+        args.algorithm().name().to_string(),
+    ];
+
+    if args.recursive {
+        forwarded.push("--recursive".to_string());
+    }
+    if args.exclude_dots {
+        forwarded.push("--exclude-dots".to_string());
+    }
+    if args.follow_symlinks {
+        forwarded.push("--follow-symlinks".to_string());
+    }
+    for filter in &args.filters() {
+        forwarded.extend(["--filter".to_string(), filter.to_string()]);
+    }
+    for exclude in &args.exclude {
+        forwarded.extend(["--exclude".to_string(), exclude.to_string()]);
+    }
+    for exclude in &args.exclude_dir {
+        forwarded.extend(["--exclude-dir".to_string(), exclude.to_string()]);
+    }
+    // Already turned into patterns, which exclude both files and directories
+    for exclude in &args.exclude_glob {
+        forwarded.extend(["--exclude".to_string(), exclude.to_string()]);
+        forwarded.extend(["--exclude-dir".to_string(), exclude.to_string()]);
+    }
+    if let Some(depth) = args.max_depth {
+        forwarded.extend(["--max-depth".to_string(), depth.to_string()]);
+    }
+    if args.skip_caches {
+        forwarded.push("--skip-caches".to_string());
+    }
+    if args.no_skip_vcs {
+        forwarded.push("--no-skip-vcs".to_string());
+    }
+    if args.include_snapshots {
+        forwarded.push("--include-snapshots".to_string());
+    }
+    if args.no_cachedir_tag {
+        forwarded.push("--no-cachedir-tag".to_string());
+    }
+    for marker in &args.skip_marker {
+        forwarded.extend([
+            "--skip-marker".to_string(),
+            marker.to_string_lossy().into_owned(),
+        ]);
+    }
+    if let Some(min) = args.min {
+        forwarded.extend(["--min".to_string(), min.to_string()]);
+    }
+    if let Some(max) = args.max {
+        forwarded.extend(["--max".to_string(), max.to_string()]);
+    }
+    if let Some(files) = args.max_files {
+        forwarded.extend(["--max-files".to_string(), files.to_string()]);
+    }
+    if let Some(bytes) = args.max_bytes {
+        forwarded.extend(["--max-bytes".to_string(), bytes.to_string()]);
+    }
+    if args.strict {
+        forwarded.push("--strict".to_string());
+    }
+
+    forwarded
+}
+
+/// List and hash the files in `remote` by running `rupes hash` on it over ssh
+///
+/// Returns every file found, with paths of the form `user@host:/path/to/file`, and its hash. The ssh program can be
+/// changed with the `RUPES_SSH` environment variable.
+pub fn list(remote: &Remote, args: &Args) -> Result<Vec<(FileEntry, String)>> {
+    let mut command: Vec<String> = vec![quote(&args.remote_command)];
+    command.extend(forwarded_args(args).iter().map(|arg| quote(arg)));
+    command.extend(["--".to_string(), quote(&remote.path)]);
+
+    let output = SSH.output(|ssh| {
+        ssh.arg(&remote.destination)
+            .arg(command.join(" "))
+            .stderr(Stdio::inherit())
+    })?;
+
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "Listing {}:{} failed, {} exited with {}",
+            remote.destination,
+            remote.path,
+            SSH.program().to_string_lossy(),
+            output.status
+        )));
+    }
+
+    let mut files = Vec::new();
+    for line in output.stdout.lines() {
+        let line = line?;
+        let hashed: HashedFile = serde_json::from_str(&line)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+        files.push((
+            FileEntry {
+                path: PathBuf::from(format!("{}:{}", remote.destination, hashed.path)),
+                size: hashed.size,
+                id: None,
+            },
+            hashed.hash,
+        ));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn parse_remote() {
+        let remote: Remote = "me@server:/srv/photos".parse().unwrap();
+        assert_eq!(remote.destination, "me@server");
+        assert_eq!(remote.path, "/srv/photos");
+
+        assert!("/local/path".parse::<Remote>().is_err());
+        assert!("server:".parse::<Remote>().is_err());
+    }
+
+    #[test]
+    fn scan_options_forwarded() {
+        let local = [
+            "rupes",
+            "--exclude-dots",
+            "--follow-symlinks",
+            "--filter",
+            "jpg$",
+            "--preset",
+            "images",
+            "--exclude",
+            "tmp$",
+            "--exclude-dir",
+            "^build$",
+            "--exclude-glob",
+            "*.bak",
+            "--max-depth",
+            "2",
+            "--skip-caches",
+            "--no-skip-vcs",
+            "--include-snapshots",
+            "--no-cachedir-tag",
+            "--skip-marker",
+            ".nodedupe",
+            "--min",
+            "10",
+            "--max",
+            "1000",
+            "--max-files",
+            "50",
+            "--max-bytes",
+            "1M",
+            "--strict",
+            "/local",
+        ];
+        let args = crate::Cli::try_parse_from(local).unwrap().args;
+
+        let remote = ["rupes".to_string()]
+            .into_iter()
+            .chain(forwarded_args(&args))
+            .chain(["--".to_string(), "/remote".to_string()]);
+        let Some(crate::Command::Hash { args: remote }) =
+            crate::Cli::try_parse_from(remote).unwrap().command
+        else {
+            panic!("not the hash subcommand");
+        };
+
+        // Every option of the scan, not only those forwarded so far
+        assert_eq!(
+            format!("{:?}", remote.scan_options()),
+            format!("{:?}", args.scan_options())
+        );
+        assert_eq!(remote.algorithm(), args.algorithm());
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("plain"), "'plain'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,604 @@
+//! The report of a scan for duplicates written to stdout: every group in the format asked for, what's planned for
+//! them, then the sections following the groups such as --stats, --top-files or --chunks
+
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Result, Write};
+use std::path::PathBuf;
+
+use console::Term;
+use indicatif::{DecimalBytes, ProgressBar};
+
+use rupes::chunks::{self, DedupEstimate, SimilarPair};
+use rupes::{extents, Digest, DuplicateGroup, FileEntry, FileList};
+
+use crate::action::{self, PlannedAction};
+use crate::exec::ExecGroup;
+use crate::locale::{self, Message};
+use crate::mail::{self, MboxMessage};
+use crate::output::{self, GroupOrder, JdupesJson, JsonReport, MatchSet, OutputFormat};
+use crate::score::{self, Score};
+use crate::video::{self, SimilarVideos};
+use crate::{empty, hashing_progress, sidecar, snapshot, syslog, Args};
+
+/// Number of groups written to stdout at a time
+const FLUSH_GROUPS: usize = 256;
+
+type Group = DuplicateGroup<(u64, Digest)>;
+
+/// What the analyses asked for besides exact duplicates found, each done in a step of its own before hashing
+#[derive(Default)]
+pub struct Extras {
+    similar: Vec<SimilarPair>,
+    videos: Vec<SimilarVideos>,
+    messages: Vec<Vec<MboxMessage>>,
+    dedup_estimate: Option<DedupEstimate>,
+}
+
+impl Extras {
+    /// Run every analysis asked for on the files among `files` that are on this machine, those without `hashes`
+    pub fn find(
+        files: &FileList,
+        hashes: &HashMap<PathBuf, Digest>,
+        args: &Args,
+    ) -> Result<Extras> {
+        let local = |keep: fn(&FileEntry) -> bool| -> Vec<FileEntry> {
+            files
+                .iter()
+                .filter(|file| !hashes.contains_key(&file.path) && keep(file))
+                .collect()
+        };
+        let progress = |files: &[FileEntry], step: usize, message: Message| -> ProgressBar {
+            let bytes = files.iter().map(|file| file.size).sum();
+            hashing_progress(bytes, step, message, args)
+        };
+
+        let mut extras = Extras::default();
+        let mut step = args.steps();
+
+        if args.chunks {
+            let local = local(|_| true);
+            let progress = progress(&local, step, Message::FindingSimilar);
+            extras.similar = chunks::find_similar(local, args.chunks_threshold / 100.0, |file| {
+                progress.inc(file.size)
+            })?;
+            progress.finish_and_clear();
+            step -= 1;
+        }
+
+        if args.video {
+            let local = local(|file| video::is_video(&file.path));
+            let progress = progress(&local, step, Message::ComparingVideos);
+            extras.videos =
+                video::find_similar(local, args.video_tolerance, |file| progress.inc(file.size))?;
+            progress.finish_and_clear();
+            step -= 1;
+        }
+
+        if args.mail {
+            let local = local(|_| true);
+            let progress = progress(&local, step, Message::ReadingMailboxes);
+            extras.messages =
+                mail::duplicate_messages(local, args.algorithm(), |file| progress.inc(file.size))?;
+            progress.finish_and_clear();
+            step -= 1;
+        }
+
+        if args.estimate_dedup {
+            let local = local(|_| true);
+            let progress = progress(&local, step, Message::EstimatingDedup);
+            extras.dedup_estimate = Some(chunks::estimate_dedup(local, |file| {
+                progress.inc(file.size)
+            })?);
+            progress.finish_and_clear();
+        }
+
+        Ok(extras)
+    }
+}
+
+/// Copies already hardlinked, reflinked or snapshotted to each other, which share their space
+#[derive(Default)]
+pub struct Shared {
+    hardlinked: Vec<Vec<PathBuf>>,
+    reflinked: Vec<Vec<PathBuf>>,
+    snapshotted: Vec<Vec<PathBuf>>,
+}
+
+/// The groups of `groups` holding at least two distinct copies, with their number of copies, since only those are
+/// wasted, and the copies sharing their storage with each other
+pub fn distinct_copies(groups: Vec<Group>, args: &Args) -> (Vec<Group>, Vec<usize>, Shared) {
+    let mut shared = Shared::default();
+    let mut kept = Vec::new();
+    let mut groups_copies = Vec::new();
+
+    for group in groups {
+        let physical = group.physical_files();
+        if !args.count_hardlinks {
+            shared.hardlinked.extend(
+                physical
+                    .iter()
+                    .filter(|links| links.len() >= 2)
+                    .map(|links| links.iter().map(|file| file.path.clone()).collect()),
+            );
+        }
+
+        let storage = extents::by_shared_extents(physical);
+        let mut copies = if args.count_hardlinks {
+            group.files.len()
+        } else {
+            storage.len()
+        };
+
+        for same in storage.iter().filter(|same| same.len() >= 2) {
+            shared.reflinked.push(
+                same.iter()
+                    .flatten()
+                    .map(|file| file.path.clone())
+                    .collect(),
+            );
+            if args.count_hardlinks {
+                copies -= same[1..].iter().map(Vec::len).sum::<usize>();
+            }
+        }
+
+        // Snapshots of a file share its storage even where their extents can't be compared, as on ZFS
+        for same in snapshot::same_files(&storage) {
+            shared.snapshotted.push(
+                same.iter()
+                    .flat_map(|shared| shared.iter().flatten())
+                    .map(|file| file.path.clone())
+                    .collect(),
+            );
+            copies -= same[1..]
+                .iter()
+                .map(|shared| {
+                    if args.count_hardlinks {
+                        shared[0].len()
+                    } else {
+                        1
+                    }
+                })
+                .sum::<usize>();
+        }
+
+        if copies >= 2 {
+            kept.push(group);
+            groups_copies.push(copies);
+        }
+    }
+
+    (kept, groups_copies, shared)
+}
+
+/// `groups` and their numbers of copies in the order --sort asks for, with the score of each group when sorted by it
+///
+/// With --free, the groups wasting the most come first, since reaching the goal with them takes the fewest changes.
+pub fn order(
+    groups: Vec<Group>,
+    groups_copies: Vec<usize>,
+    args: &Args,
+) -> (Vec<Group>, Vec<usize>, Vec<Score>) {
+    if args.sort == GroupOrder::Score {
+        let mut scored: Vec<_> = groups
+            .into_iter()
+            .zip(groups_copies)
+            .map(|(group, copies)| {
+                let waste = output::wasted_space(group.key.0, copies);
+                (score::score(&group.files, waste), group, copies)
+            })
+            .collect();
+        // Stable, groups scoring the same stay ordered by size
+        scored.sort_by_key(|(score, _, _)| std::cmp::Reverse(score.points));
+
+        let (mut scores, mut groups, mut groups_copies) = (Vec::new(), Vec::new(), Vec::new());
+        for (score, group, copies) in scored {
+            scores.push(score);
+            groups.push(group);
+            groups_copies.push(copies);
+        }
+        (groups, groups_copies, scores)
+    } else if args.free.is_some() {
+        let mut by_waste: Vec<_> = groups.into_iter().zip(groups_copies).collect();
+        by_waste.sort_by_key(|(group, copies)| {
+            std::cmp::Reverse(output::wasted_space(group.key.0, *copies))
+        });
+        let (groups, groups_copies) = by_waste.into_iter().unzip();
+        (groups, groups_copies, Vec::new())
+    } else {
+        (groups, groups_copies, Vec::new())
+    }
+}
+
+/// The sections following the groups that are worked out from all of them, before they're written
+pub struct Sections {
+    top_files: Vec<(u64, usize, PathBuf)>,
+    by_dir: Vec<(PathBuf, usize, u64)>,
+    dir_pairs: Vec<(PathBuf, PathBuf, usize, u64)>,
+    by_extension: Vec<(String, usize, u64)>,
+    pub wasted_by_group: Vec<u64>,
+    pub duplicate_files: usize,
+}
+
+impl Sections {
+    pub fn of(groups: &[Group], groups_copies: &[usize], args: &Args) -> Sections {
+        let wasted_by_group: Vec<u64> = groups
+            .iter()
+            .zip(groups_copies)
+            .map(|(group, &copies)| output::wasted_space(group.key.0, copies))
+            .collect();
+
+        Sections {
+            top_files: output::top_files(groups, args.top_files.unwrap_or(0)),
+            by_dir: if args.by_dir {
+                output::by_dir(groups, &args.directories)
+            } else {
+                Vec::new()
+            },
+            dir_pairs: if args.dir_pairs {
+                output::dir_pairs(groups)
+            } else {
+                Vec::new()
+            },
+            by_extension: if args.stats {
+                output::wasted_by_extension(groups, &wasted_by_group)
+            } else {
+                Vec::new()
+            },
+            duplicate_files: groups_copies.iter().map(|copies| copies - 1).sum(),
+            wasted_by_group,
+        }
+    }
+}
+
+/// What writing the groups found and planned for them
+pub struct Written {
+    pub total_size: u64,
+    pub planned: Vec<Vec<PlannedAction>>,
+    /// Copies that aren't kept, for --find-empty-dirs
+    redundant: HashSet<PathBuf>,
+    companions: Vec<(PathBuf, Vec<PathBuf>)>,
+}
+
+/// Write every group to `term` in the output format asked for, running --exec on each and planning the actions asked
+/// for, up to the --free goal
+///
+/// Text is written when `text`, and the JSON formats are finished, so only the text sections are left to write.
+pub fn write_groups(
+    term: &Term,
+    groups: Vec<Group>,
+    groups_copies: Vec<usize>,
+    scores: &[Score],
+    sections: &Sections,
+    text: bool,
+    args: &Args,
+) -> Result<Written> {
+    let operation = args.operation();
+    let mut written = Written {
+        total_size: 0,
+        planned: Vec::new(),
+        redundant: HashSet::new(),
+        companions: Vec::new(),
+    };
+
+    let mut jdupes_json = match args.output {
+        OutputFormat::JdupesJson => Some(JdupesJson::begin(&mut &*term, args.schema_version)?),
+        _ => None,
+    };
+    let mut json_report = match args.output {
+        OutputFormat::Json => Some(JsonReport::begin(
+            &mut &*term,
+            args.schema_version,
+            args.algorithm(),
+        )?),
+        _ => None,
+    };
+    if args.output == OutputFormat::Csv {
+        output::write_csv_header(&mut &*term, args.schema_version)?;
+    }
+    let found = groups.len();
+    let mut reclaiming: u64 = 0;
+    let mut acting_on = 0;
+    for (index, (group, copies)) in groups.into_iter().zip(groups_copies).enumerate() {
+        let (size, hash) = group.key;
+        let hash = hash.to_string();
+        let paths: Vec<PathBuf> = group.files.into_iter().map(|file| file.path).collect();
+
+        if syslog::enabled() {
+            let listed: Vec<String> = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            syslog::info(&format!(
+                "Found {} copies of {}: {}",
+                paths.len(),
+                DecimalBytes(size),
+                listed.join(", ")
+            ));
+        }
+
+        let dupe_size = output::wasted_space(size, copies);
+        written.total_size += dupe_size;
+        let labelled_hash = format!("{}:{}", args.algorithm().name(), hash);
+        let kept_reason = args.keep_reason(&paths[0]);
+
+        if let Some(template) = &args.format_template {
+            output::write_template_group(
+                &mut &*term,
+                template,
+                index + 1,
+                &hash,
+                size,
+                dupe_size,
+                &paths,
+            )?;
+        } else if args.omit_first {
+            for path in &paths[1..] {
+                if args.print0 {
+                    write!(&*term, "{}\0", path.display())?;
+                } else {
+                    term.write_line(&path.display().to_string())?;
+                }
+            }
+        } else if args.output == OutputFormat::Fdupes {
+            output::write_fdupes_group(&mut &*term, &paths, args.print0)?;
+        } else if text {
+            if let Some(score) = scores.get(index) {
+                term.write_line(&output::score_line(score))?;
+            }
+            if args.show_hash {
+                term.write_line(&labelled_hash)?;
+            }
+            if args.long {
+                let lines: Vec<String> = paths.iter().map(|path| output::long_line(path)).collect();
+                output::write_group_lines(
+                    &mut &*term,
+                    &lines,
+                    dupe_size,
+                    &args.separator,
+                    args.size || args.details,
+                )?;
+            } else {
+                output::write_group(
+                    &mut &*term,
+                    &paths,
+                    dupe_size,
+                    &args.separator,
+                    args.size || args.details,
+                )?;
+            }
+        }
+
+        if let Some(command) = &args.exec {
+            term.flush()?;
+            let group = ExecGroup {
+                group: index + 1,
+                hash: &hash,
+                size,
+                waste: dupe_size,
+                paths: &paths,
+            };
+            match command.run(&group) {
+                Ok(()) => {}
+                Err(error) if error.kind() == ErrorKind::NotFound => {
+                    eprintln!("{}", error);
+                    return Err(error);
+                }
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+
+        let goal_reached = args.free.is_some_and(|goal| reclaiming >= goal);
+        if let Some(operation) = operation.filter(|_| !goal_reached) {
+            let guard = args.guard();
+            let actions: Vec<PlannedAction> =
+                action::plan(operation, size, &hash, &paths, &kept_reason)
+                    .into_iter()
+                    .filter(|action| guard.allows(action))
+                    .collect();
+            if !actions.is_empty() {
+                reclaiming += dupe_size.min(size * actions.len() as u64);
+                acting_on += 1;
+                written.planned.push(actions);
+            }
+        }
+
+        if args.find_empty_dirs {
+            written.redundant.extend(paths[1..].iter().cloned());
+        }
+        if args.sidecars {
+            for path in &paths {
+                let found = sidecar::companions(path);
+                if !found.is_empty() {
+                    written.companions.push((path.clone(), found));
+                }
+            }
+        }
+        if matches!(
+            args.output,
+            OutputFormat::JdupesJson | OutputFormat::Json | OutputFormat::Csv
+        ) {
+            // jdupes JSON only has the hash when asked for, to stay as close to jdupes' as it can
+            let show_hash = match args.output {
+                OutputFormat::JdupesJson => args.show_hash,
+                _ => !args.no_hash,
+            };
+            let set = MatchSet {
+                id: output::group_id(&hash),
+                size,
+                hash: show_hash.then(|| labelled_hash.clone()),
+                kept_reason: Some(kept_reason),
+                paths,
+            };
+            if let Some(json) = &mut jdupes_json {
+                json.group(&mut &*term, &set)?;
+            }
+            if let Some(json) = &mut json_report {
+                json.group(&mut &*term, &set, dupe_size)?;
+            }
+            if args.output == OutputFormat::Csv {
+                output::write_csv_group(
+                    &mut &*term,
+                    index + 1,
+                    &set,
+                    dupe_size,
+                    args.schema_version,
+                )?;
+            }
+        }
+
+        // Written as they go, rather than holding every group's output until the end
+        if index % FLUSH_GROUPS == FLUSH_GROUPS - 1 {
+            term.flush()?;
+        }
+    }
+
+    if let Some(json) = jdupes_json {
+        json.finish(&mut &*term)?;
+    }
+    if let Some(json) = json_report {
+        let totals = output::JsonTotals {
+            groups: found,
+            duplicate_files: sections.duplicate_files,
+            wasted_bytes: written.total_size,
+        };
+        json.finish(&mut &*term, &totals)?;
+    }
+
+    syslog::info(&format!(
+        "Scan finished, {} groups of duplicates wasting {}",
+        found,
+        DecimalBytes(written.total_size)
+    ));
+
+    if let Some(goal) = args.free.filter(|_| !args.quiet) {
+        if reclaiming < goal {
+            eprintln!(
+                "{}",
+                locale::text(
+                    Message::FreeShort,
+                    &[
+                        ("bytes", &DecimalBytes(reclaiming)),
+                        ("goal", &DecimalBytes(goal)),
+                    ],
+                )
+            );
+        } else {
+            eprintln!(
+                "{}",
+                locale::text(
+                    Message::FreeActing,
+                    &[
+                        ("groups", &acting_on),
+                        ("found", &found),
+                        ("bytes", &DecimalBytes(reclaiming)),
+                    ],
+                )
+            );
+        }
+    }
+
+    Ok(written)
+}
+
+/// Whether nothing but the groups is written, so the output can be read by other tools
+pub fn groups_only(args: &Args) -> bool {
+    args.omit_first
+        || matches!(
+            args.output,
+            OutputFormat::JdupesJson
+                | OutputFormat::Json
+                | OutputFormat::Csv
+                | OutputFormat::Fdupes
+        )
+}
+
+/// Write the text sections following the groups
+pub fn write_sections(
+    term: &Term,
+    shared: &Shared,
+    extras: &Extras,
+    sections: &Sections,
+    written: &Written,
+    args: &Args,
+) -> Result<()> {
+    let out = &mut &*term;
+
+    if !shared.hardlinked.is_empty() {
+        output::write_shared(
+            out,
+            "Already hardlinked, no space to reclaim",
+            &shared.hardlinked,
+            &args.separator,
+        )?;
+    }
+
+    if !shared.reflinked.is_empty() {
+        output::write_shared(
+            out,
+            "Already sharing extents (reflinked), no space to reclaim",
+            &shared.reflinked,
+            &args.separator,
+        )?;
+    }
+
+    if !shared.snapshotted.is_empty() {
+        output::write_shared(
+            out,
+            "The same file in several snapshots, no space to reclaim",
+            &shared.snapshotted,
+            &args.separator,
+        )?;
+    }
+
+    if args.find_empty_dirs {
+        let empty = empty::find(
+            &args.local_directories(),
+            &written.redundant,
+            &args.scan_options(),
+        )?;
+        output::write_empty_dirs(out, &empty)?;
+    }
+
+    if args.top_files.is_some() {
+        output::write_top_files(out, &sections.top_files)?;
+    }
+
+    if args.by_dir {
+        output::write_by_dir(out, &sections.by_dir)?;
+    }
+
+    if args.dir_pairs {
+        output::write_dir_pairs(out, &sections.dir_pairs)?;
+    }
+
+    if args.chunks {
+        output::write_similar(out, &extras.similar, &args.separator)?;
+    }
+
+    if args.video {
+        output::write_videos(out, &extras.videos, &args.separator)?;
+    }
+
+    if args.mail {
+        output::write_messages(out, &extras.messages, &args.separator)?;
+    }
+
+    if args.sidecars {
+        output::write_companions(out, &written.companions)?;
+    }
+
+    if let Some(estimate) = &extras.dedup_estimate {
+        output::write_dedup_estimate(out, estimate, written.total_size)?;
+    }
+
+    if args.stats {
+        output::write_stats(
+            out,
+            &sections.wasted_by_group,
+            sections.duplicate_files,
+            &sections.by_extension,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! Retrying reads that fail for reasons likely to pass, such as a network share dropping out for a moment
+
+use std::io::{Error, ErrorKind, Result};
+use std::thread;
+use std::time::Duration;
+
+/// How many more times to try a failed read, and how long to wait before the first retry
+///
+/// The wait doubles with every retry. Only [transient](is_transient) errors are retried, the default policy doesn't
+/// retry at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Call `operation` until it succeeds, fails with an error that isn't transient, or runs out of retries
+    pub fn run<T>(&self, mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut delay = self.delay;
+
+        for _ in 0..self.retries {
+            match operation() {
+                Err(error) if is_transient(&error) => {
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+
+        operation()
+    }
+}
+
+/// Whether `error` may well not happen again on the next attempt, as with a busy file or a brief network outage
+pub fn is_transient(error: &Error) -> bool {
+    #[cfg(unix)]
+    if error.raw_os_error() == Some(libc::EIO) {
+        // Network filesystems such as SMB report most dropped connections as plain I/O errors
+        return true;
+    }
+
+    matches!(
+        error.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy
+            | ErrorKind::StaleNetworkFileHandle
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NetworkDown
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::HostUnreachable
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_transient_errors() {
+        let policy = RetryPolicy {
+            retries: 2,
+            delay: Duration::from_millis(1),
+        };
+
+        let mut attempts = 0;
+        let result = policy.run(|| {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(Error::from(ErrorKind::WouldBlock)),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<()> = policy.run(|| {
+            attempts += 1;
+            Err(Error::from(ErrorKind::TimedOut))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result: Result<()> = policy.run(|| {
+            attempts += 1;
+            Err(Error::from(ErrorKind::NotFound))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
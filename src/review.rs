@@ -0,0 +1,313 @@
+//! Going through the planned actions a group at a time with --interactive, choosing which copies are acted on before
+//! anything is touched
+//!
+//! Every duplicate starts out marked, as the plan has it, and the copy kept stays kept whatever is unmarked, unless
+//! another copy is chosen to be kept instead. Groups never shown, when the review is ended early, are left alone.
+//! Nothing is changed here, the actions left are confirmed and executed like any others.
+
+use std::io::{Error, ErrorKind, Result};
+
+use console::{Key, Term};
+
+use crate::action::{self, PlannedAction};
+use crate::locale::{self, Message};
+use crate::output;
+
+/// What reviewing is left to do after a key
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    Continue,
+    Done,
+    Cancelled,
+}
+
+/// The groups being reviewed, with which of their actions are still marked, which groups were shown, and where the
+/// cursor is
+struct Review {
+    groups: Vec<Vec<PlannedAction>>,
+    marked: Vec<Vec<bool>>,
+    shown: Vec<bool>,
+    group: usize,
+    cursor: usize,
+}
+
+impl Review {
+    fn new(groups: Vec<Vec<PlannedAction>>) -> Self {
+        let marked = groups
+            .iter()
+            .map(|actions| vec![true; actions.len()])
+            .collect();
+        let mut shown = vec![false; groups.len()];
+        shown[0] = true;
+        Review {
+            groups,
+            marked,
+            shown,
+            group: 0,
+            cursor: 0,
+        }
+    }
+
+    fn press(&mut self, key: &Key) -> Step {
+        let marked = &mut self.marked[self.group];
+        match key {
+            Key::ArrowUp | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => {
+                self.cursor = (self.cursor + 1).min(marked.len() - 1)
+            }
+            Key::Char(' ') => marked[self.cursor] ^= true,
+            Key::Char('a') => marked.fill(true),
+            Key::Char('n') => marked.fill(false),
+            Key::Char('c') => self.keep_next(),
+            Key::Enter | Key::ArrowRight => {
+                if self.group + 1 == self.groups.len() {
+                    return Step::Done;
+                }
+                self.group += 1;
+                self.shown[self.group] = true;
+                self.cursor = 0;
+            }
+            Key::ArrowLeft | Key::Char('b') => {
+                self.group = self.group.saturating_sub(1);
+                self.cursor = 0;
+            }
+            Key::Char('q') => return Step::Done,
+            Key::Escape => return Step::Cancelled,
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    /// Keep the first copy acted on in the current group instead of the one kept so far, which is acted on in its
+    /// place, so pressing it again goes through every copy and back
+    fn keep_next(&mut self) {
+        let actions = &mut self.groups[self.group];
+        let marked = &mut self.marked[self.group];
+        let next = actions.remove(0);
+        marked.remove(0);
+
+        let reason = "identical content, kept copy: chosen in review".to_string();
+        for action in actions.iter_mut() {
+            action.target = next.source.clone();
+            action.reason = reason.clone();
+        }
+        actions.push(PlannedAction {
+            source: next.target.clone(),
+            target: next.source.clone(),
+            reason,
+            // Found again for the copies deleted once reviewing is done
+            sidecars: Vec::new(),
+            ..next
+        });
+        marked.push(true);
+        self.cursor = self.cursor.min(marked.len() - 1);
+    }
+
+    /// The lines showing the current group
+    fn lines(&self) -> Vec<String> {
+        let actions = &self.groups[self.group];
+        let mut lines = vec![
+            locale::text(
+                Message::ReviewGroup,
+                &[
+                    ("group", &(self.group + 1)),
+                    ("groups", &self.groups.len()),
+                    ("operation", &actions[0].operation.name()),
+                ],
+            ),
+            format!("      {}", output::long_line(&actions[0].target)),
+        ];
+        for (index, (action, marked)) in actions.iter().zip(&self.marked[self.group]).enumerate() {
+            lines.push(format!(
+                "{} [{}] {}",
+                if index == self.cursor { ">" } else { " " },
+                if *marked { "x" } else { " " },
+                output::long_line(&action.source)
+            ));
+        }
+        lines.push(locale::text(Message::ReviewKeys, &[]));
+        lines
+    }
+
+    /// The actions still marked in the groups shown, dropping groups left without any
+    ///
+    /// A hardlink to the copy now kept is the same file, it's never acted on.
+    fn finish(self) -> Vec<Vec<PlannedAction>> {
+        self.groups
+            .into_iter()
+            .zip(self.marked)
+            .zip(self.shown)
+            .filter_map(|(group, shown)| shown.then_some(group))
+            .map(|(actions, marked)| {
+                actions
+                    .into_iter()
+                    .zip(marked)
+                    .filter_map(|(action, marked)| marked.then_some(action))
+                    .filter(|action| !action::same_file(&action.target, &action.source))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|actions| !actions.is_empty())
+            .collect()
+    }
+}
+
+/// Show each group of `planned` on the terminal and let the copies acted on be chosen with the keyboard, returning the
+/// actions still marked, or none when the review is cancelled
+pub fn review(planned: Vec<Vec<PlannedAction>>) -> Result<Vec<Vec<PlannedAction>>> {
+    let term = Term::stderr();
+    if !term.is_term() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--interactive needs a terminal to read keys from",
+        ));
+    }
+
+    let mut review = Review::new(planned);
+    let mut shown = 0;
+    loop {
+        term.clear_last_lines(shown)?;
+        let lines = review.lines();
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        shown = lines.len();
+
+        match review.press(&term.read_key()?) {
+            Step::Continue => {}
+            Step::Done => break,
+            Step::Cancelled => return Ok(Vec::new()),
+        }
+    }
+    term.clear_last_lines(shown)?;
+
+    Ok(review.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Operation;
+    use std::path::PathBuf;
+
+    #[test]
+    fn keys_choose_the_copies_acted_on() {
+        let paths = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let mut review = Review::new(vec![
+            action::plan(
+                Operation::Delete,
+                1,
+                "a",
+                &paths(&["a1", "a2", "a3"]),
+                "first path",
+            ),
+            action::plan(
+                Operation::Delete,
+                1,
+                "b",
+                &paths(&["b1", "b2"]),
+                "first path",
+            ),
+        ]);
+
+        assert_eq!(review.press(&Key::ArrowDown), Step::Continue);
+        review.press(&Key::Char(' '));
+        review.press(&Key::Enter);
+        review.press(&Key::Char('n'));
+        // Going back keeps what was chosen
+        review.press(&Key::Char('b'));
+        assert_eq!(review.marked[0], [true, false]);
+        assert_eq!(review.press(&Key::Char('q')), Step::Done);
+
+        let left = review.finish();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].len(), 1);
+        assert_eq!(left[0][0].source, PathBuf::from("a2"));
+        assert_eq!(left[0][0].target, PathBuf::from("a1"));
+
+        let mut review = Review::new(vec![action::plan(
+            Operation::Delete,
+            1,
+            "a",
+            &paths(&["a1", "a2"]),
+            "first path",
+        )]);
+        assert_eq!(review.press(&Key::Escape), Step::Cancelled);
+        assert_eq!(review.press(&Key::Enter), Step::Done);
+    }
+
+    #[test]
+    fn done_leaves_groups_not_shown_alone() {
+        let paths = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let mut review = Review::new(vec![
+            action::plan(
+                Operation::Delete,
+                1,
+                "a",
+                &paths(&["a1", "a2"]),
+                "first path",
+            ),
+            action::plan(
+                Operation::Delete,
+                1,
+                "b",
+                &paths(&["b1", "b2"]),
+                "first path",
+            ),
+            action::plan(
+                Operation::Delete,
+                1,
+                "c",
+                &paths(&["c1", "c2"]),
+                "first path",
+            ),
+        ]);
+
+        review.press(&Key::Enter);
+        assert_eq!(review.press(&Key::Char('q')), Step::Done);
+
+        let left = review.finish();
+        let sources: Vec<&PathBuf> = left.iter().flatten().map(|action| &action.source).collect();
+        assert_eq!(sources, [&PathBuf::from("a2"), &PathBuf::from("b2")]);
+    }
+
+    #[test]
+    fn another_copy_kept() {
+        let paths = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let mut review = Review::new(vec![action::plan(
+            Operation::Delete,
+            1,
+            "a",
+            &paths(&["a1", "a2", "a3"]),
+            "first path",
+        )]);
+        let pairs = |review: &Review| -> Vec<(PathBuf, PathBuf)> {
+            review.groups[0]
+                .iter()
+                .map(|action| (action.source.clone(), action.target.clone()))
+                .collect()
+        };
+
+        review.press(&Key::Char('c'));
+        assert_eq!(
+            pairs(&review),
+            [("a3", "a2"), ("a1", "a2")].map(|(source, target)| (source.into(), target.into()))
+        );
+        assert_eq!(
+            review.groups[0][1].reason,
+            "identical content, kept copy: chosen in review"
+        );
+
+        // The copy no longer kept is marked, like every duplicate starts out
+        review.press(&Key::Char(' '));
+        review.press(&Key::Char('c'));
+        assert_eq!(review.groups[0][0].target, PathBuf::from("a3"));
+        assert_eq!(review.marked[0], [true, true]);
+        review.press(&Key::Char(' '));
+
+        assert_eq!(review.press(&Key::Enter), Step::Done);
+        let left = review.finish();
+        assert_eq!(left[0].len(), 1);
+        assert_eq!(left[0][0].target, PathBuf::from("a3"));
+        assert_eq!(left[0][0].source, PathBuf::from("a2"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use rupes::{Digest, FileEntry, HashAlgorithm};
+
+use crate::tool::AWS;
+
+/// Objects in a bucket, given as `s3://bucket/prefix`
+#[derive(Clone, Debug)]
+pub struct S3Location {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl FromStr for S3Location {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let location = value
+            .strip_prefix("s3://")
+            .ok_or_else(|| format!("expected s3://bucket/prefix, got '{}'", value))?;
+
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(format!("no bucket given in '{}'", value));
+        }
+
+        Ok(S3Location {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+impl S3Location {
+    /// Whether `path` names objects in a bucket rather than a local directory
+    pub fn is_s3(path: &Path) -> bool {
+        path.to_str().is_some_and(|path| path.starts_with("s3://"))
+    }
+}
+
+/// An object listed in a bucket
+#[derive(Debug)]
+pub struct S3Object {
+    pub bucket: String,
+    pub key: String,
+    pub size: u64,
+    /// The object's ETag, without quotes
+    ///
+    /// Objects with the same ETag and size have the same content, but the opposite doesn't hold: an object uploaded in
+    /// several parts has an ETag of the MD5s of its parts, followed by `-` and their number, which differs from that
+    /// of the same content uploaded in one part or in parts of another size.
+    pub etag: String,
+    /// The MD5 of the object's content, when its ETag is one
+    ///
+    /// Objects uploaded in several parts or encrypted with KMS have ETags that aren't a hash of their content.
+    pub md5: Option<String>,
+}
+
+impl S3Object {
+    pub fn url(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.key)
+    }
+
+    pub fn entry(&self) -> FileEntry {
+        FileEntry {
+            path: PathBuf::from(self.url()),
+            size: self.size,
+            id: None,
+        }
+    }
+
+    /// Hash the content of the object with `algorithm`, only downloading it if its ETag can't be used instead
+    pub fn hash(&self, algorithm: HashAlgorithm) -> Result<Digest> {
+        if let (HashAlgorithm::Md5, Some(md5)) = (algorithm, &self.md5) {
+            return md5.parse();
+        }
+
+        let mut download = AWS.spawn(|command| {
+            command
+                .args(["s3", "cp", "--quiet", &self.url(), "-"])
+                .stdout(Stdio::piped())
+        })?;
+
+        let hash = algorithm.digest_reader(download.stdout.as_mut().unwrap());
+        let status = download.wait()?;
+        if !status.success() {
+            return Err(Error::other(format!(
+                "Downloading {} failed, aws exited with {}",
+                self.url(),
+                status
+            )));
+        }
+
+        hash
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Listing {
+    #[serde(default)]
+    contents: Vec<ListedObject>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedObject {
+    key: String,
+    size: u64,
+    e_tag: String,
+}
+
+/// List every object under `location`, skipping folder placeholders, with the aws command line tool or the program
+/// named by the `RUPES_AWS` environment variable
+pub fn list(location: &S3Location) -> Result<Vec<S3Object>> {
+    let output = AWS.output(|command| {
+        command
+            .args(["s3api", "list-objects-v2", "--output", "json"])
+            .args(["--bucket", &location.bucket, "--prefix", &location.prefix])
+            .stderr(Stdio::inherit())
+    })?;
+
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "Listing s3://{}/{} failed, aws exited with {}",
+            location.bucket, location.prefix, output.status
+        )));
+    }
+
+    // An empty listing prints nothing at all
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+
+    let listing: Listing = serde_json::from_slice(&output.stdout)
+        .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+    Ok(listing
+        .contents
+        .into_iter()
+        .filter(|object| !object.key.ends_with('/'))
+        .map(|object| {
+            let etag = object.e_tag.trim_matches('"').to_ascii_lowercase();
+            let md5 = (etag.len() == 32 && etag.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .then(|| etag.clone());
+
+            S3Object {
+                bucket: location.bucket.clone(),
+                key: object.key,
+                size: object.size,
+                etag,
+                md5,
+            }
+        })
+        .collect())
+}
+
+/// Group `objects` by size and ETag, the objects of a group having the same content so only the first has to be
+/// downloaded to hash them all, leaving out those that can't be duplicates of anything
+///
+/// An object can only have the same content as a local file of its size, given in `local_sizes`, or another object
+/// of its size. Among objects alone, one whose ETag is the MD5 of its content can only be a copy of another with the
+/// same ETag, unless one of them was uploaded in parts and has another kind of ETag.
+pub fn candidates(objects: Vec<S3Object>, local_sizes: &HashSet<u64>) -> Vec<Vec<S3Object>> {
+    let mut by_size: HashMap<u64, HashMap<String, Vec<S3Object>>> = HashMap::new();
+    for object in objects {
+        by_size
+            .entry(object.size)
+            .or_default()
+            .entry(object.etag.clone())
+            .or_default()
+            .push(object);
+    }
+
+    let mut candidates = Vec::new();
+    for (size, by_etag) in by_size {
+        let mut groups: Vec<Vec<S3Object>> = by_etag.into_values().collect();
+        if !local_sizes.contains(&size) {
+            if groups.iter().all(|group| group[0].md5.is_some()) {
+                groups.retain(|group| group.len() >= 2);
+            } else if groups.len() == 1 && groups[0].len() == 1 {
+                groups.clear();
+            }
+        }
+        candidates.extend(groups);
+    }
+
+    for group in &mut candidates {
+        group.sort_by(|a, b| a.key.cmp(&b.key));
+    }
+    candidates.sort_by(|a, b| a[0].key.cmp(&b[0].key));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_location() {
+        let location: S3Location = "s3://backups/photos/2023".parse().unwrap();
+        assert_eq!(location.bucket, "backups");
+        assert_eq!(location.prefix, "photos/2023");
+
+        let location: S3Location = "s3://backups".parse().unwrap();
+        assert_eq!(location.prefix, "");
+
+        assert!("backups/photos".parse::<S3Location>().is_err());
+        assert!("s3:///photos".parse::<S3Location>().is_err());
+    }
+
+    fn object(key: &str, size: u64, etag: &str) -> S3Object {
+        S3Object {
+            bucket: "backups".to_string(),
+            key: key.to_string(),
+            size,
+            etag: etag.to_string(),
+            md5: (etag.len() == 32).then(|| etag.to_string()),
+        }
+    }
+
+    #[test]
+    fn candidates_grouped_by_etag() {
+        let md5 = |digit: &str| digit.repeat(32);
+        let objects = vec![
+            // A local file has this size, so any of them could be its copy
+            object("local/a", 1, &md5("a")),
+            object("local/b", 1, &md5("b")),
+            // Copies of each other, and an object of their size with another MD5 that can't be
+            object("md5/copy", 2, &md5("c")),
+            object("md5/original", 2, &md5("c")),
+            object("md5/other", 2, &md5("d")),
+            // Uploaded in parts, so possibly a copy of the other object of its size whatever its ETag
+            object("parts/a", 3, &md5("e")),
+            object("parts/b", 3, "ffffffffffffffffffffffffffffffff-2"),
+            // No other object or file has this size
+            object("unique", 4, "ffffffffffffffffffffffffffffffff-2"),
+        ];
+
+        let candidates = candidates(objects, &HashSet::from([1]));
+        let groups: Vec<Vec<&str>> = candidates
+            .iter()
+            .map(|group| group.iter().map(|object| object.key.as_str()).collect())
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                vec!["local/a"],
+                vec!["local/b"],
+                vec!["md5/copy", "md5/original"],
+                vec!["parts/a"],
+                vec!["parts/b"],
+            ]
+        );
+    }
+}
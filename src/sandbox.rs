@@ -0,0 +1,306 @@
+//! Kernel enforced read-only scanning for --sandbox, with Landlock and seccomp on Linux
+//!
+//! Landlock takes away the right to create, write, rename or delete anything outside the files rupes writes and the
+//! directories it acts on, and seccomp the right to change the permissions, owner, timestamps or extended attributes of any file,
+//! which Landlock doesn't cover. Both apply to rupes and everything it runs from then on, and can't be lifted.
+
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "linux")]
+const WRITE_FILE: u64 = 1 << 1;
+#[cfg(target_os = "linux")]
+const TRUNCATE: u64 = 1 << 14;
+
+/// Devices anything may write to, even sandboxed, such as the /dev/null programs get as stdin
+#[cfg(target_os = "linux")]
+const HARMLESS_DEVICES: [&str; 2] = ["/dev/null", "/dev/tty"];
+
+/// Rights to change files taken away by Landlock, for each version of its ABI: writing, truncating, removing files and
+/// directories, making every kind of file, and linking or renaming files into other directories
+#[cfg(target_os = "linux")]
+fn write_access(abi: i64) -> u64 {
+    const REMOVE_AND_MAKE: u64 = (1 << 4) | (1 << 5) | (0b111_1111 << 6);
+    const REFER: u64 = 1 << 13;
+
+    let mut access = WRITE_FILE | REMOVE_AND_MAKE;
+    if abi >= 2 {
+        access |= REFER;
+    }
+    if abi >= 3 {
+        access |= TRUNCATE;
+    }
+    access
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+#[cfg(target_os = "linux")]
+fn unavailable(what: &str) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!("--sandbox needs {}, {}", what, Error::last_os_error()),
+    )
+}
+
+/// System calls changing the metadata of files, failed with EPERM by the seccomp filter
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const METADATA_CALLS: [libc::c_long; 18] = [
+    libc::SYS_chmod,
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    libc::SYS_fchmodat2,
+    libc::SYS_chown,
+    libc::SYS_fchown,
+    libc::SYS_lchown,
+    libc::SYS_fchownat,
+    libc::SYS_utime,
+    libc::SYS_utimes,
+    libc::SYS_utimensat,
+    libc::SYS_futimesat,
+    libc::SYS_setxattr,
+    libc::SYS_lsetxattr,
+    libc::SYS_fsetxattr,
+    libc::SYS_removexattr,
+    libc::SYS_lremovexattr,
+    libc::SYS_fremovexattr,
+];
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const METADATA_CALLS: [libc::c_long; 12] = [
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    452, // fchmodat2
+    libc::SYS_fchown,
+    libc::SYS_fchownat,
+    libc::SYS_utimensat,
+    libc::SYS_setxattr,
+    libc::SYS_lsetxattr,
+    libc::SYS_fsetxattr,
+    libc::SYS_removexattr,
+    libc::SYS_lremovexattr,
+    libc::SYS_fremovexattr,
+];
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Fail every system call changing the metadata of files for this thread and those it starts from then on
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn deny_metadata_changes() -> Result<()> {
+    let statement = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let jump = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    let ret = libc::BPF_RET | libc::BPF_K;
+
+    // Offsets of the system call number and architecture in struct seccomp_data. Calls made for another architecture,
+    // which would get past the numbers below, kill the process instead
+    let mut filter = vec![
+        statement(load, 4, 0, 0),
+        statement(jump, AUDIT_ARCH, 1, 0),
+        statement(ret, libc::SECCOMP_RET_KILL_PROCESS, 0, 0),
+        statement(load, 0, 0, 0),
+    ];
+    // As do x32 calls, which share the architecture of x86_64 but number their calls from this bit
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        statement(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            0x4000_0000,
+            0,
+            1,
+        ),
+        statement(ret, libc::SECCOMP_RET_KILL_PROCESS, 0, 0),
+    ]);
+    for call in METADATA_CALLS {
+        filter.push(statement(jump, call as u32, 0, 1));
+        filter.push(statement(
+            ret,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+            0,
+            0,
+        ));
+    }
+    filter.push(statement(ret, libc::SECCOMP_RET_ALLOW, 0, 0));
+
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    // SAFETY: the program points to a filter of the length given, which the kernel copies
+    let installed = unsafe {
+        libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &program as *const libc::sock_fprog,
+        )
+    };
+    if installed != 0 {
+        return Err(unavailable("seccomp"));
+    }
+    Ok(())
+}
+
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_arch = "x86_64", target_arch = "aarch64"))
+))]
+fn deny_metadata_changes() -> Result<()> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "--sandbox only knows the system calls of x86_64 and aarch64",
+    ))
+}
+
+/// Take away the right to change anything but what's in `directories` and the contents of `files`, and the metadata
+/// of any file unless `metadata`, for this thread and those it starts from then on, so it has to be called before any
+/// other thread is started
+///
+/// Directories and files that don't exist are created first, since they couldn't be once sandboxed: files can only be
+/// written and truncated, not replaced.
+#[cfg(target_os = "linux")]
+pub fn enter(directories: &[PathBuf], files: &[PathBuf], metadata: bool) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::ptr;
+
+    const CREATE_RULESET_VERSION: libc::c_uint = 1;
+    const RULE_PATH_BENEATH: libc::c_uint = 1;
+
+    // SAFETY: asking for the ABI version takes no attributes
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            ptr::null::<RulesetAttr>(),
+            0,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 1 {
+        return Err(unavailable("Landlock, in Linux 5.13 and later"));
+    }
+
+    let access = write_access(abi);
+    let attr = RulesetAttr {
+        handled_access_fs: access,
+    };
+    // SAFETY: the attributes are valid for the size given
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            size_of::<RulesetAttr>(),
+            0,
+        )
+    } as libc::c_int;
+    if ruleset < 0 {
+        return Err(unavailable("Landlock"));
+    }
+
+    let result = (|| {
+        for directory in directories {
+            std::fs::create_dir_all(directory)?;
+        }
+        for file in files {
+            if let Some(parent) = file
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)?;
+        }
+        let devices = HARMLESS_DEVICES
+            .iter()
+            .map(Path::new)
+            .filter(|device| device.exists());
+        let rules = directories
+            .iter()
+            .map(|directory| (directory.as_path(), access))
+            .chain(
+                files
+                    .iter()
+                    .map(PathBuf::as_path)
+                    .chain(devices)
+                    .map(|file| (file, access & (WRITE_FILE | TRUNCATE))),
+            );
+
+        for (path, access) in rules {
+            let path = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: the path is NUL terminated
+            let fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if fd < 0 {
+                return Err(Error::last_os_error());
+            }
+
+            let rule = PathBeneathAttr {
+                allowed_access: access,
+                parent_fd: fd,
+            };
+            // SAFETY: the rule is valid, and the descriptors open until they're closed here
+            let added = unsafe {
+                let added = libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset,
+                    RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0,
+                );
+                libc::close(fd);
+                added
+            };
+            if added != 0 {
+                return Err(unavailable("Landlock"));
+            }
+        }
+
+        // SAFETY: only takes privileges away
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        if !metadata {
+            deny_metadata_changes()?;
+        }
+        // SAFETY: the ruleset descriptor is open
+        if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) } != 0 {
+            return Err(unavailable("Landlock"));
+        }
+        Ok(())
+    })();
+
+    // SAFETY: the ruleset descriptor is open, and not used again
+    unsafe { libc::close(ruleset) };
+    result
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enter(_directories: &[PathBuf], _files: &[PathBuf], _metadata: bool) -> Result<()> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "--sandbox is only supported on Linux",
+    ))
+}
@@ -0,0 +1,173 @@
+//! Scanning directories for duplicates in a single call, for applications embedding rupes rather than running it
+//!
+//! A [`Scanner`] walks the directories with [`discover_roots`](crate::discover_roots) and compares the files found
+//! with a [`DuplicateFinder`], reporting both steps to one progress callback. With a comparator that only matches
+//! files of the same size, such as the default [`HashComparator`], groups are yielded as soon as they are found.
+
+use std::io::{Error, Result};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rayon::ThreadPool;
+
+use crate::group::{ContentComparator, DuplicateFinder, DuplicateGroup, HashComparator};
+use crate::walk::{self, DiscoveryProgress, FileEntry, ScanOptions};
+
+/// What a [`Scanner`] is doing, passed to its progress callback
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanProgress {
+    /// Walking the directories, with the totals found so far
+    Discovering(DiscoveryProgress),
+    /// Comparing the files found, `files` and `bytes` of them done so far
+    Comparing {
+        files: usize,
+        bytes: u64,
+        total_files: usize,
+        total_bytes: u64,
+    },
+}
+
+type Progress = Arc<dyn Fn(ScanProgress) + Send + Sync>;
+
+type Groups<K> = Box<dyn Iterator<Item = Result<DuplicateGroup<K>>> + Send>;
+
+/// Finds duplicates in directories, configured with [`ScanOptions`] and optionally a comparator, thread pool and
+/// progress callback of its own
+///
+/// ```no_run
+/// let scanner = rupes::Scanner::new(rupes::ScanOptions::default());
+/// for group in scanner.scan(&["photos".into()])? {
+///     println!("{:?}", group?.files);
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Scanner<C = HashComparator> {
+    options: ScanOptions,
+    comparator: C,
+    pool: Option<Arc<ThreadPool>>,
+    progress: Option<Progress>,
+}
+
+impl Scanner {
+    /// A scanner comparing files by size and hash with the default [`HashComparator`]
+    pub fn new(options: ScanOptions) -> Self {
+        Scanner {
+            options,
+            comparator: HashComparator::default(),
+            pool: None,
+            progress: None,
+        }
+    }
+}
+
+impl<C: ContentComparator + Send + Sync + 'static> Scanner<C>
+where
+    C::Key: 'static,
+{
+    /// Compare files with `comparator` instead
+    pub fn comparator<D: ContentComparator>(self, comparator: D) -> Scanner<D> {
+        Scanner {
+            options: self.options,
+            comparator,
+            pool: self.pool,
+            progress: self.progress,
+        }
+    }
+
+    /// Walk directories and compare files on `pool` rather than the global rayon thread pool
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Call `progress` while directories are walked and files compared, from whichever thread did the work
+    pub fn progress(mut self, progress: impl Fn(ScanProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Find the files in `roots` and start comparing them, returning once they're found, or once they're compared for
+    /// comparators that match files of different sizes
+    ///
+    /// Errors walking the directories are returned here, and errors comparing files by the iterator, which ends with
+    /// the first of them.
+    pub fn scan(self, roots: &[PathBuf]) -> Result<Scan<C::Key>> {
+        let progress = self.progress.clone();
+        let discover = || {
+            walk::discover_roots(roots, &self.options, |found| {
+                if let Some(progress) = &progress {
+                    progress(ScanProgress::Discovering(*found));
+                }
+            })
+        };
+        let discovery = match &self.pool {
+            Some(pool) => pool.install(discover),
+            None => discover(),
+        }?;
+
+        let total_files = discovery.files.len();
+        let total_bytes = discovery.files.sizes().sum();
+        let streams = self.comparator.same_size();
+        // Files of a size of their own are done without their paths ever being put together
+        let files = if streams {
+            discovery.files.same_size_entries()
+        } else {
+            discovery.files.into_entries()
+        };
+        let left_out = total_files - files.len();
+        let left_out_bytes = total_bytes - files.iter().map(|file| file.size).sum::<u64>();
+
+        let mut finder = DuplicateFinder::new(files, self.comparator);
+        if let Some(pool) = self.pool {
+            finder = finder.thread_pool(pool);
+        }
+        if let Some(progress) = self.progress {
+            let (done_files, done_bytes) =
+                (AtomicUsize::new(left_out), AtomicU64::new(left_out_bytes));
+            finder = finder.progress(move |file: &FileEntry| {
+                progress(ScanProgress::Comparing {
+                    files: done_files.fetch_add(1, Ordering::Relaxed) + 1,
+                    bytes: done_bytes.fetch_add(file.size, Ordering::Relaxed) + file.size,
+                    total_files,
+                    total_bytes,
+                })
+            });
+        }
+
+        // Streaming only compares files of the same size to each other
+        let groups: Groups<C::Key> = if streams {
+            Box::new(finder.run_streaming())
+        } else {
+            Box::new(finder.run()?.into_iter().map(Ok))
+        };
+
+        Ok(Scan {
+            files: total_files,
+            bytes: total_bytes,
+            truncated: discovery.truncated,
+            skipped: discovery.skipped,
+            groups,
+        })
+    }
+}
+
+/// A scan started by [`Scanner::scan`], iterating over the groups of duplicates it finds in no particular order
+pub struct Scan<K> {
+    /// How many files were found, and their size together
+    pub files: usize,
+    pub bytes: u64,
+    /// Whether discovery stopped early because [`ScanOptions::max_files`] or [`ScanOptions::max_bytes`] was reached
+    pub truncated: bool,
+    /// The files and directories left out for being unreadable with [`ScanOptions::skip_unreadable`], and why
+    pub skipped: Vec<(PathBuf, Error)>,
+    groups: Groups<K>,
+}
+
+impl<K> Iterator for Scan<K> {
+    type Item = Result<DuplicateGroup<K>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.groups.next()
+    }
+}
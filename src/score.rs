@@ -0,0 +1,190 @@
+//! How safe and worthwhile acting on a group of duplicates looks, for --sort score
+//!
+//! A guess from what usually tells leftovers from the files people keep: copies named like `Copy of report.pdf` or
+//! `report (1).pdf` next to one that isn't, copies in Downloads or temporary directories while another is kept
+//! elsewhere, copies left untouched for months, and the space they waste.
+
+use std::fs;
+use std::path::{Component, Path};
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use rupes::FileEntry;
+
+/// Points of each heuristic, adding up to 100
+const NAME_POINTS: f64 = 35.0;
+const LOCATION_POINTS: f64 = 25.0;
+const AGE_POINTS: f64 = 15.0;
+const SIZE_POINTS: f64 = 25.0;
+
+/// Names of the directories files are downloaded or put aside in, compared ignoring case
+const SCRATCH_DIRECTORIES: [&str; 9] = [
+    "downloads",
+    "download",
+    "tmp",
+    "temp",
+    "trash",
+    ".trash",
+    ".cache",
+    "cache",
+    "$recycle.bin",
+];
+
+/// How many days apart the oldest and newest copies are modified for the full points of their age
+const AGE_DAYS: f64 = 365.0;
+
+/// Bytes wasted for the full points of the size, 1 GiB, scored on a logarithmic scale
+const SIZE_BITS: f64 = 30.0;
+
+/// Names given to copies by file managers, browsers and editors
+fn copy_names() -> Regex {
+    Regex::new(
+        r"(?i)(^copy of |[ _-]copy( \d+)?(\.[^.]*)?$| \(\d+\)(\.[^.]*)?$|~$|\.(bak|orig|old)$)",
+    )
+    .unwrap()
+}
+
+/// The score of a group and what it was given for
+#[derive(Debug)]
+pub struct Score {
+    /// From 0 to 100, higher for groups that are safer and more worthwhile to act on
+    pub points: u32,
+    pub reasons: Vec<&'static str>,
+}
+
+/// Whether `path` is in a scratch directory below its first `shared` components, which every file of the group is in
+/// and so tell nothing about which to keep
+fn in_scratch_directory(path: &Path, shared: usize) -> bool {
+    path.components()
+        .skip(shared)
+        .any(|component| match component {
+            Component::Normal(name) => name.to_str().is_some_and(|name| {
+                SCRATCH_DIRECTORIES
+                    .iter()
+                    .any(|scratch| scratch.eq_ignore_ascii_case(name))
+            }),
+            _ => false,
+        })
+}
+
+/// Points for the share of `files` matching, all of them when some but not every file does, since the one that doesn't
+/// is then clearly the file to keep, and half of them when every file does
+fn telling(files: &[FileEntry], matches: impl Fn(&Path) -> bool, points: f64) -> f64 {
+    let matching = files.iter().filter(|file| matches(&file.path)).count();
+    if matching == 0 {
+        0.0
+    } else if matching < files.len() {
+        points
+    } else {
+        points / 2.0
+    }
+}
+
+/// Score the group of duplicates `files`, wasting `wasted` bytes
+pub fn score(files: &[FileEntry], wasted: u64) -> Score {
+    let mut reasons = Vec::new();
+    let mut points = 0.0;
+
+    let names = copy_names();
+    let named = telling(
+        files,
+        |path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| names.is_match(name))
+        },
+        NAME_POINTS,
+    );
+    if named > 0.0 {
+        reasons.push("named like copies");
+    }
+    points += named;
+
+    let shared = files
+        .iter()
+        .map(|file| {
+            file.path
+                .components()
+                .zip(files[0].path.components())
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .min()
+        .unwrap_or(0);
+    let located = telling(
+        files,
+        |path| in_scratch_directory(path, shared),
+        LOCATION_POINTS,
+    );
+    if located > 0.0 {
+        reasons.push("in downloads or temporary directories");
+    }
+    points += located;
+
+    let modified: Vec<SystemTime> = files
+        .iter()
+        .filter_map(|file| fs::metadata(&file.path).and_then(|m| m.modified()).ok())
+        .collect();
+    if let (Some(oldest), Some(newest)) = (modified.iter().min(), modified.iter().max()) {
+        let days = newest
+            .duration_since(*oldest)
+            .unwrap_or_default()
+            .as_secs_f64()
+            / 86_400.0;
+        if days >= 30.0 {
+            reasons.push("modified months apart");
+        }
+        points += AGE_POINTS * (days / AGE_DAYS).min(1.0);
+    }
+
+    let bits = (wasted as f64).max(1.0).log2();
+    if bits >= SIZE_BITS - 4.0 {
+        reasons.push("wasting a lot of space");
+    }
+    points += SIZE_POINTS * (bits / SIZE_BITS).min(1.0);
+
+    Score {
+        points: points.round() as u32,
+        reasons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entries(paths: &[&str]) -> Vec<FileEntry> {
+        paths
+            .iter()
+            .map(|path| FileEntry {
+                path: PathBuf::from(path),
+                size: 1,
+                id: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn telling_groups_score_higher() {
+        let plain = score(
+            &entries(&["/tmp/photos/a.jpg", "/tmp/backup/a.jpg"]),
+            1 << 20,
+        );
+        let copies = score(
+            &entries(&["/photos/a.jpg", "/home/ann/Downloads/a (1).jpg"]),
+            1 << 20,
+        );
+        let all_copies = score(&entries(&["/x/Copy of a.jpg", "/y/a - Copy.jpg"]), 1 << 20);
+
+        assert!(plain.reasons.is_empty());
+        assert_eq!(
+            copies.reasons,
+            ["named like copies", "in downloads or temporary directories"]
+        );
+        assert_eq!(copies.points, plain.points + 60);
+        assert!(all_copies.points > plain.points && all_copies.points < copies.points);
+        assert!(score(&entries(&["/a", "/b"]), 1 << 30).points > plain.points);
+    }
+}
@@ -0,0 +1,169 @@
+//! Running scans unattended, again and again, as a systemd user service or a launchd agent that rupes writes itself
+
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// Where the service definition is installed for the current user, and the command that starts it
+fn default_location() -> Result<(PathBuf, String)> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+
+    if cfg!(target_os = "macos") {
+        let path = home
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "$HOME isn't set"))?
+            .join("Library/LaunchAgents/rupes.plist");
+        let start = format!("launchctl load {}", path.display());
+        return Ok((path, start));
+    }
+
+    if cfg!(unix) {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .filter(|config| !config.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".config")))
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    "Neither $XDG_CONFIG_HOME nor $HOME is set",
+                )
+            })?;
+        let path = config.join("systemd/user/rupes.service");
+        return Ok((path, "systemctl --user enable --now rupes".to_string()));
+    }
+
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "Services can only be installed for systemd and launchd, run `rupes service run` from a scheduled task instead",
+    ))
+}
+
+/// The arguments that start `rupes service run` with `options`, scanning every `every` seconds
+fn command(executable: &Path, every: u64, options: &[String]) -> Vec<String> {
+    let mut command = vec![
+        executable.display().to_string(),
+        "service".to_string(),
+        "run".to_string(),
+        "--every".to_string(),
+        every.to_string(),
+    ];
+    command.extend(options.iter().cloned());
+    command
+}
+
+/// A word of a systemd command line, quoted so spaces, quotes and specifiers in it are kept as they are
+fn systemd_quoted(word: &str) -> String {
+    let mut quoted = String::from('"');
+    for character in word.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '%' => quoted.push_str("%%"),
+            '$' => quoted.push_str("$$"),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A systemd user unit running `command`, restarting it if it fails
+pub fn systemd_unit(command: &[String]) -> String {
+    let exec: Vec<String> = command.iter().map(|word| systemd_quoted(word)).collect();
+
+    format!(
+        "[Unit]
+Description=Duplicate file scans by rupes
+
+[Service]
+ExecStart={}
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+",
+        exec.join(" ")
+    )
+}
+
+fn xml_escaped(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A launchd agent property list running `command` from login, restarting it if it exits
+pub fn launch_agent(command: &[String]) -> String {
+    let arguments: String = command
+        .iter()
+        .map(|word| format!("        <string>{}</string>\n", xml_escaped(word)))
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>rupes</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+</dict>
+</plist>
+"#,
+        arguments
+    )
+}
+
+/// Write the service definition running `rupes service run` with `options` to `path`, or where it's installed for the
+/// current user, returning where it was written and how to start it
+pub fn install(path: Option<PathBuf>, every: u64, options: &[String]) -> Result<(PathBuf, String)> {
+    let (default, start) = default_location()?;
+    let path = path.unwrap_or(default);
+
+    let command = command(&env::current_exe()?, every, options);
+    let definition = if cfg!(target_os = "macos") {
+        launch_agent(&command)
+    } else {
+        systemd_unit(&command)
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, definition)?;
+
+    Ok((path, start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions() {
+        let command = command(
+            Path::new("/usr/bin/rupes"),
+            600,
+            &[
+                "/srv/my files".to_string(),
+                "--format-template=%p $x".to_string(),
+            ],
+        );
+
+        let unit = systemd_unit(&command);
+        assert!(unit.contains(
+            r#"ExecStart="/usr/bin/rupes" "service" "run" "--every" "600" "/srv/my files" "--format-template=%%p $$x""#
+        ));
+
+        let agent = launch_agent(&command);
+        assert!(agent.contains("<string>/srv/my files</string>"));
+        assert!(agent.contains("<string>600</string>"));
+    }
+}
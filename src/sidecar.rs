@@ -0,0 +1,178 @@
+//! Files that belong with another one next to it, such as the .xmp metadata of a photo, the subtitles of a video or the
+//! RAW half of a RAW and JPEG pair
+//!
+//! Sidecars are named after their primary file, either its stem (IMG_1.xmp) or its whole name (IMG_1.CR2.xmp), with an
+//! extension of their own. When a duplicate is deleted its sidecars would be left without it, so they're moved next to
+//! the copy that's kept instead, unless it already has one of its own.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::locale::{self, Message};
+use crate::syslog;
+
+/// Extensions of the files holding metadata or subtitles for the file they're named after
+const SIDECAR_EXTENSIONS: [&str; 5] = ["xmp", "aae", "thm", "srt", "vtt"];
+
+/// Extensions of camera RAW files, which cameras save next to a JPEG of the same picture
+const RAW_EXTENSIONS: [&str; 10] = [
+    "cr2", "cr3", "nef", "arw", "raf", "orf", "rw2", "dng", "pef", "srw",
+];
+
+/// Extensions of the JPEGs cameras save next to RAW files
+const JPEG_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "heic"];
+
+/// The files in the directory of `path` named like `path` with one of `extensions`, in lower or upper case
+fn with_extensions(path: &Path, names: &[&str], extensions: &[&str]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+
+    for name in names {
+        for extension in extensions {
+            for extension in [extension.to_string(), extension.to_ascii_uppercase()] {
+                let candidate = path.with_file_name(format!("{}.{}", name, extension));
+                if candidate != path && candidate.is_file() && !found.contains(&candidate) {
+                    found.push(candidate);
+                }
+            }
+        }
+    }
+
+    found
+}
+
+fn extension_in(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// The sidecars of the file at `path` that exist, named after its stem or its whole name
+pub fn sidecars(path: &Path) -> Vec<PathBuf> {
+    if extension_in(path, &SIDECAR_EXTENSIONS) {
+        return Vec::new();
+    }
+    let (Some(name), Some(stem)) = (
+        path.file_name().and_then(|name| name.to_str()),
+        path.file_stem().and_then(|stem| stem.to_str()),
+    ) else {
+        return Vec::new();
+    };
+
+    with_extensions(path, &[stem, name], &SIDECAR_EXTENSIONS)
+}
+
+/// The sidecars of the file at `path` and the other half of its RAW and JPEG pair, if it's part of one
+pub fn companions(path: &Path) -> Vec<PathBuf> {
+    let mut companions = sidecars(path);
+
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return companions;
+    };
+    if extension_in(path, &JPEG_EXTENSIONS) {
+        companions.extend(with_extensions(path, &[stem], &RAW_EXTENSIONS));
+    } else if extension_in(path, &RAW_EXTENSIONS) {
+        companions.extend(with_extensions(path, &[stem], &JPEG_EXTENSIONS));
+    }
+
+    companions
+}
+
+/// Where the sidecar of `source` at `sidecar` goes for `kept`, named after it the way it was named after `source`
+fn counterpart(sidecar: &Path, source: &Path, kept: &Path) -> Option<PathBuf> {
+    let name = sidecar.file_name()?.to_str()?;
+    let (source_name, source_stem) = (source.file_name()?.to_str()?, source.file_stem()?.to_str()?);
+    let (kept_name, kept_stem) = (kept.file_name()?.to_str()?, kept.file_stem()?.to_str()?);
+
+    let renamed = match name.strip_prefix(source_name) {
+        Some(rest) => format!("{}{}", kept_name, rest),
+        None => format!("{}{}", kept_stem, name.strip_prefix(source_stem)?),
+    };
+    Some(kept.with_file_name(renamed))
+}
+
+/// Take the sidecars of the deleted duplicate `source` to `kept`: each moves next to it, or is deleted when `kept`
+/// already has an identical one, and is left where it is with a warning when `kept` has a different one
+pub fn carry(sidecars: &[PathBuf], source: &Path, kept: &Path) {
+    for sidecar in sidecars {
+        // Already taken along under another spelling on filesystems ignoring case
+        if !sidecar.exists() {
+            continue;
+        }
+        let Some(destination) = counterpart(sidecar, source, kept) else {
+            continue;
+        };
+
+        let result = if !destination.exists() {
+            fs::rename(sidecar, &destination).map(|()| Message::SidecarMoved)
+        } else if fs::read(sidecar).ok() == fs::read(&destination).ok() {
+            fs::remove_file(sidecar).map(|()| Message::SidecarDeleted)
+        } else {
+            eprintln!(
+                "{}",
+                locale::text(
+                    Message::SidecarLeft,
+                    &[("path", &sidecar.display()), ("kept", &kept.display())],
+                )
+            );
+            continue;
+        };
+
+        match result {
+            Ok(done) => syslog::info(&locale::text(
+                done,
+                &[
+                    ("path", &sidecar.display()),
+                    ("kept", &destination.display()),
+                ],
+            )),
+            Err(error) => {
+                let message = locale::text(
+                    Message::FailedMove,
+                    &[("path", &sidecar.display()), ("error", &error)],
+                );
+                eprintln!("{}", message);
+                syslog::log(syslog::Level::Error, &message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn found_and_carried() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (kept, duplicate) = (dir.path().join("a"), dir.path().join("b"));
+        fs::create_dir_all(&kept).unwrap();
+        fs::create_dir_all(&duplicate).unwrap();
+        for file in [
+            "a/IMG_1.jpg",
+            "a/IMG_1.CR2",
+            "b/IMG_1.jpg",
+            "b/IMG_1.XMP",
+            "b/IMG_1.jpg.aae",
+        ] {
+            fs::write(dir.path().join(file), file).unwrap();
+        }
+
+        let source = duplicate.join("IMG_1.jpg");
+        let mut found = sidecars(&source);
+        found.sort();
+        assert_eq!(
+            found,
+            [duplicate.join("IMG_1.XMP"), duplicate.join("IMG_1.jpg.aae")]
+        );
+        assert_eq!(
+            companions(&kept.join("IMG_1.jpg")),
+            [kept.join("IMG_1.CR2")]
+        );
+
+        fs::remove_file(&source).unwrap();
+        carry(&found, &source, &kept.join("IMG_1.jpg"));
+        assert!(kept.join("IMG_1.XMP").is_file());
+        assert!(kept.join("IMG_1.jpg.aae").is_file());
+        assert!(fs::read_dir(&duplicate).unwrap().next().is_none());
+    }
+}
@@ -0,0 +1,201 @@
+//! Files and directories left out of a scan because they couldn't be read, rather than failing the scan, unless
+//! --strict is given, summed up by why once the scan is done
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rupes::ContentComparator;
+
+use crate::locale::{self, Message};
+
+/// Why a file or directory was left out
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Reason {
+    /// A symlink followed with --follow-symlinks to nothing
+    Dangling,
+    /// Removed since it was found
+    Vanished,
+    Other(ErrorKind),
+}
+
+impl Reason {
+    fn of(path: &Path, error: &Error) -> Reason {
+        match error.kind() {
+            ErrorKind::NotFound
+                if path
+                    .symlink_metadata()
+                    .is_ok_and(|metadata| metadata.file_type().is_symlink()) =>
+            {
+                Reason::Dangling
+            }
+            ErrorKind::NotFound => Reason::Vanished,
+            kind => Reason::Other(kind),
+        }
+    }
+
+    /// How `count` files left out for this reason are described
+    fn describe(&self, count: usize) -> String {
+        match self {
+            Reason::Dangling if count == 1 => locale::text(Message::DanglingSymlink, &[]),
+            Reason::Dangling => locale::text(Message::DanglingSymlinks, &[]),
+            Reason::Vanished => locale::text(Message::Vanished, &[]),
+            Reason::Other(kind) => kind.to_string(),
+        }
+    }
+}
+
+/// The files and directories left out by one scan, gathered from discovery and every comparator reading files
+#[derive(Debug, Default)]
+pub struct Skipped {
+    /// Fail the scan on the first file that can't be read instead, unless it only vanished, as it always has
+    strict: bool,
+    skipped: Mutex<Vec<(PathBuf, Reason)>>,
+}
+
+impl Skipped {
+    pub fn new(strict: bool) -> Self {
+        Skipped {
+            strict,
+            ..Skipped::default()
+        }
+    }
+
+    /// Leave out the file at `path` that failed with `error`, or when strict fail the scan with it
+    pub fn skip(&self, path: &Path, error: Error) -> Result<()> {
+        if self.strict {
+            return rupes::skip_vanished(error);
+        }
+        let reason = Reason::of(path, &error);
+        self.skipped
+            .lock()
+            .unwrap()
+            .push((path.to_path_buf(), reason));
+        Ok(())
+    }
+
+    /// Count the files and directories discovery left out
+    pub fn record(&self, skipped: Vec<(PathBuf, Error)>) {
+        let reasons = skipped.into_iter().map(|(path, error)| {
+            let reason = Reason::of(&path, &error);
+            (path, reason)
+        });
+        self.skipped.lock().unwrap().extend(reasons);
+    }
+
+    /// Such as `Skipped 37 files and directories that couldn't be read: 30 permission denied, 7 vanished`, the most
+    /// common reasons first, `None` when nothing was skipped
+    pub fn summary(&self) -> Option<String> {
+        summarize(&self.skipped.lock().unwrap())
+    }
+}
+
+fn summarize(skipped: &[(PathBuf, Reason)]) -> Option<String> {
+    if skipped.is_empty() {
+        return None;
+    }
+
+    let mut by_reason: HashMap<&Reason, usize> = HashMap::new();
+    for (_, reason) in skipped.iter() {
+        *by_reason.entry(reason).or_default() += 1;
+    }
+    let mut by_reason: Vec<(String, usize)> = by_reason
+        .into_iter()
+        .map(|(reason, count)| (reason.describe(count), count))
+        .collect();
+    by_reason.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let reasons: Vec<String> = by_reason
+        .into_iter()
+        .map(|(reason, count)| format!("{} {}", count, reason))
+        .collect();
+
+    Some(locale::text(
+        Message::SkippedUnreadable,
+        &[("count", &skipped.len()), ("reasons", &reasons.join(", "))],
+    ))
+}
+
+/// A comparator that leaves out the files it can't read, counting them in `skipped`
+pub struct Tolerant<'a, C> {
+    pub comparator: &'a C,
+    pub skipped: &'a Skipped,
+}
+
+impl<C: ContentComparator> ContentComparator for Tolerant<'_, C> {
+    type Key = C::Key;
+
+    fn key(&self, path: &Path, size: u64) -> Result<Self::Key> {
+        self.comparator.key(path, size)
+    }
+
+    fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.comparator.read_depth(path)
+    }
+
+    fn same_size(&self) -> bool {
+        self.comparator.same_size()
+    }
+
+    fn same_start(&self, path: &Path) -> bool {
+        self.comparator.same_start(path)
+    }
+
+    fn skip_unreadable(&self, path: &Path, error: Error) -> Result<()> {
+        self.skipped.skip(path, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasons_summed_up() {
+        assert_eq!(summarize(&[]), None);
+
+        let skipped = [
+            (PathBuf::from("/a"), Reason::Vanished),
+            (
+                PathBuf::from("/b"),
+                Reason::Other(ErrorKind::PermissionDenied),
+            ),
+            (
+                PathBuf::from("/c"),
+                Reason::Other(ErrorKind::PermissionDenied),
+            ),
+        ];
+        assert_eq!(
+            summarize(&skipped).unwrap(),
+            "Skipped 3 files and directories that couldn't be read: 2 permission denied, 1 vanished"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_told_apart() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dangling = dir.path().join("dangling");
+        std::os::unix::fs::symlink(dir.path().join("missing"), &dangling).unwrap();
+
+        let skipped = Skipped::new(false);
+        for path in [dangling, dir.path().join("removed")] {
+            skipped
+                .skip(&path, Error::from(ErrorKind::NotFound))
+                .unwrap();
+        }
+        assert_eq!(
+            skipped.summary().unwrap(),
+            "Skipped 2 files and directories that couldn't be read: 1 dangling symlink, 1 vanished"
+        );
+
+        let strict = Skipped::new(true);
+        assert!(strict
+            .skip(Path::new("/a"), Error::from(ErrorKind::NotFound))
+            .is_ok());
+        assert!(strict
+            .skip(Path::new("/a"), Error::from(ErrorKind::PermissionDenied))
+            .is_err());
+        assert_eq!(strict.summary(), None);
+    }
+}
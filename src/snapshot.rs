@@ -0,0 +1,99 @@
+//! Snapshots of ZFS datasets and Btrfs subvolumes, left out of scans unless --include-snapshots is given
+//!
+//! A file in a snapshot shares its storage with the same file in every other snapshot of the dataset and with the live
+//! file, so counting them as duplicates would inflate the wasted space enormously while nothing could be reclaimed.
+//! Snapshots are recognised by where ZFS, snapper and Timeshift keep them. Other Btrfs snapshots share their extents
+//! with the subvolume they were taken of, and are reported as reflinked instead.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use regex::Regex;
+
+use rupes::FileEntry;
+
+/// Names of the directories snapshots are kept in: .zfs of every ZFS dataset, .snapshots of snapper and the
+/// timeshift-btrfs directory of Timeshift
+pub fn names() -> Regex {
+    Regex::new(r"^(\.zfs|\.snapshots|timeshift-btrfs)$").unwrap()
+}
+
+/// Where the file at `path` is in the dataset or subvolume its snapshot was taken of, `None` if it isn't in a snapshot
+///
+/// Those are `<dataset>/.zfs/snapshot/<name>/<path>` for ZFS, `<subvolume>/.snapshots/<number>/snapshot/<path>` for
+/// snapper, and `timeshift-btrfs/snapshots/<name>/@/<path>` or `.../@home/<path>` for Timeshift, whose @ and @home
+/// subvolumes are mounted at / and /home.
+pub fn live_path(path: &Path) -> Option<PathBuf> {
+    let components: Vec<Component> = path.components().collect();
+    let name = |i: usize| components.get(i).map(|component| component.as_os_str());
+
+    for i in 0..components.len() {
+        let before = || components[..i].iter().collect::<PathBuf>();
+        let (mut live, rest) = match name(i)?.to_str() {
+            Some(".zfs") if name(i + 1)? == "snapshot" && i + 3 <= components.len() => {
+                (before(), i + 3)
+            }
+            Some(".snapshots") if name(i + 2).is_some_and(|name| name == "snapshot") => {
+                (before(), i + 3)
+            }
+            Some("timeshift-btrfs") if name(i + 1)? == "snapshots" => {
+                let subvolume = name(i + 3)?.to_str()?.strip_prefix('@')?;
+                (Path::new("/").join(subvolume), i + 4)
+            }
+            _ => continue,
+        };
+
+        live.extend(&components[rest..]);
+        return Some(live);
+    }
+
+    None
+}
+
+/// The storage of a group of duplicates, each a list of files sharing it, gathered by the file they are in the live
+/// dataset or subvolume, snapshots of the same file being the same storage
+///
+/// `storage` is as returned by [`rupes::extents::by_shared_extents`], and only those holding more than one are returned.
+pub fn same_files(storage: &[Vec<Vec<FileEntry>>]) -> Vec<Vec<&Vec<Vec<FileEntry>>>> {
+    let mut by_live_path: HashMap<PathBuf, Vec<&Vec<Vec<FileEntry>>>> = HashMap::new();
+    for shared in storage {
+        let path = &shared[0][0].path;
+        let live = live_path(path).unwrap_or_else(|| path.clone());
+        by_live_path.entry(live).or_default().push(shared);
+    }
+
+    let mut same: Vec<_> = by_live_path
+        .into_values()
+        .filter(|same| same.len() >= 2)
+        .collect();
+    same.sort_by(|a, b| a[0][0][0].path.cmp(&b[0][0][0].path));
+    same
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_paths() {
+        assert_eq!(
+            live_path(Path::new(
+                "/tank/home/.zfs/snapshot/daily-2024-05-01/ann/notes.txt"
+            )),
+            Some(PathBuf::from("/tank/home/ann/notes.txt"))
+        );
+        assert_eq!(
+            live_path(Path::new("/.snapshots/42/snapshot/etc/fstab")),
+            Some(PathBuf::from("/etc/fstab"))
+        );
+        assert_eq!(
+            live_path(Path::new(
+                "/run/timeshift/backup/timeshift-btrfs/snapshots/2024-05-01_10-00-01/@home/ann/notes.txt"
+            )),
+            Some(PathBuf::from("/home/ann/notes.txt"))
+        );
+        assert_eq!(live_path(Path::new("/tank/home/ann/notes.txt")), None);
+        assert_eq!(live_path(Path::new("/srv/.snapshots/notes.txt")), None);
+        assert!(names().is_match(".zfs"));
+    }
+}
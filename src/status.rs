@@ -0,0 +1,85 @@
+//! Plain status lines in place of progress bars when stderr is a log rather than a terminal, such as under cron or CI
+
+use std::io::IsTerminal;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use indicatif::{DecimalBytes, ProgressBar};
+
+use crate::locale;
+
+/// Whether progress bars would go unseen, because stderr doesn't go to a terminal
+pub fn wanted() -> bool {
+    !std::io::stderr().is_terminal()
+}
+
+/// Bars still being reported on
+fn watched() -> &'static Mutex<Vec<ProgressBar>> {
+    static WATCHED: OnceLock<Mutex<Vec<ProgressBar>>> = OnceLock::new();
+    WATCHED.get_or_init(Mutex::default)
+}
+
+/// Write a line about `bar` to stderr every `interval` until it's finished, the last line showing where it finished
+///
+/// Every bar is reported at the interval of the first one watched.
+pub fn watch(bar: &ProgressBar, interval: Duration) {
+    static REPORTER: OnceLock<()> = OnceLock::new();
+
+    watched().lock().unwrap().push(bar.clone());
+    REPORTER.get_or_init(|| {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            report();
+        });
+    });
+}
+
+fn report() {
+    let mut bars = watched().lock().unwrap();
+    for bar in bars.iter() {
+        eprintln!("{}", line(bar));
+    }
+    bars.retain(|bar| !bar.is_finished());
+}
+
+/// The state of `bar` as one line of plain text, bars of a known length counting bytes
+fn line(bar: &ProgressBar) -> String {
+    let prefix = console::strip_ansi_codes(&bar.prefix()).into_owned();
+
+    match bar.length() {
+        Some(total) => format!(
+            "{} {}/{} ({}%), {}/s, ETA {}",
+            prefix,
+            DecimalBytes(bar.position()),
+            DecimalBytes(total),
+            (bar.position() * 100).checked_div(total).unwrap_or(100),
+            DecimalBytes(bar.per_sec() as u64),
+            locale::duration(bar.eta())
+        ),
+        None => format!("{} {}", prefix, bar.message()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_lines() {
+        let bar = ProgressBar::hidden();
+        bar.set_prefix(format!("{} Hashing", console::style("[2/2]").white()));
+        bar.set_length(4_000_000);
+        bar.set_position(1_000_000);
+        assert!(line(&bar).starts_with("[2/2] Hashing 1.00 MB/4.00 MB (25%), "));
+
+        let empty = ProgressBar::hidden();
+        empty.set_length(0);
+        assert!(line(&empty).contains("(100%)"));
+
+        let spinner = ProgressBar::new_spinner();
+        spinner.set_prefix("[1/2] Scanning");
+        spinner.set_message("3 files");
+        assert_eq!(line(&spinner), "[1/2] Scanning 3 files");
+    }
+}
@@ -0,0 +1,154 @@
+//! Telling spinning disks from solid-state storage, so each can be read at a concurrency that suits it
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The kind of storage a file lives on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Storage {
+    /// A spinning disk, where concurrent reads make the heads seek back and forth between files
+    Rotational,
+    /// An SSD or NVMe drive, which only reaches its full speed with many reads in flight
+    SolidState,
+}
+
+impl Storage {
+    /// Detect the kind of storage `path` is on, if the platform tells
+    ///
+    /// Only Linux exposes it, for block devices in /sys. Network shares, device mapper volumes and other virtual
+    /// devices aren't recognised.
+    #[cfg(target_os = "linux")]
+    pub fn detect(path: &Path) -> Option<Storage> {
+        use std::os::unix::fs::MetadataExt;
+
+        let device = std::fs::metadata(path).ok()?.dev();
+        let block = format!(
+            "/sys/dev/block/{}:{}",
+            libc::major(device),
+            libc::minor(device)
+        );
+
+        // Partitions don't have a queue of their own, the disk they're on does
+        let rotational = std::fs::read_to_string(format!("{}/queue/rotational", block))
+            .or_else(|_| std::fs::read_to_string(format!("{}/../queue/rotational", block)))
+            .ok()?;
+
+        match rotational.trim() {
+            "1" => Some(Storage::Rotational),
+            "0" => Some(Storage::SolidState),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn detect(_path: &Path) -> Option<Storage> {
+        None
+    }
+
+    /// How many files to read from storage of this kind at once, `None` for as many as there are threads to hash them
+    pub fn read_concurrency(&self) -> Option<usize> {
+        match self {
+            // One reader keeps the heads moving through a file at a time
+            Storage::Rotational => Some(1),
+            Storage::SolidState => None,
+        }
+    }
+}
+
+/// The storage files are on, as detected or as given for the directories it isn't detected correctly for
+#[derive(Clone, Debug, Default)]
+pub struct StorageMap {
+    overrides: Vec<(PathBuf, Storage)>,
+}
+
+impl StorageMap {
+    /// Use `overrides` for the files below each of their directories, and detect the storage of every other file
+    pub fn new(overrides: impl IntoIterator<Item = (PathBuf, Storage)>) -> Self {
+        let mut overrides: Vec<_> = overrides
+            .into_iter()
+            .map(|(directory, storage)| {
+                (fs::canonicalize(&directory).unwrap_or(directory), storage)
+            })
+            .collect();
+        // The innermost directory decides
+        overrides.sort_by_key(|(directory, _)| std::cmp::Reverse(directory.components().count()));
+
+        StorageMap { overrides }
+    }
+
+    /// The storage `path` is on, if it was given or can be detected
+    pub fn storage(&self, path: &Path) -> Option<Storage> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        self.overrides
+            .iter()
+            .find(|(directory, _)| canonical.starts_with(directory))
+            .map(|(_, storage)| *storage)
+            .or_else(|| Storage::detect(path))
+    }
+
+    /// How many files on the same device as `path` to read at once, see [`Storage::read_concurrency`]
+    pub fn read_depth(&self, path: &Path) -> Option<usize> {
+        self.storage(path)?.read_concurrency()
+    }
+}
+
+impl FromStr for Storage {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "hdd" => Ok(Storage::Rotational),
+            "ssd" => Ok(Storage::SolidState),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown storage {}, expected hdd or ssd", name),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!("hdd".parse::<Storage>().unwrap(), Storage::Rotational);
+        assert_eq!("ssd".parse::<Storage>().unwrap(), Storage::SolidState);
+        assert!("tape".parse::<Storage>().is_err());
+    }
+
+    #[test]
+    fn concurrency() {
+        assert_eq!(Storage::Rotational.read_concurrency(), Some(1));
+        assert_eq!(Storage::SolidState.read_concurrency(), None);
+
+        // Whatever the machine, detection never fails outright
+        let _ = Storage::detect(Path::new("/"));
+        assert_eq!(Storage::detect(Path::new("/does/not/exist")), None);
+    }
+
+    #[test]
+    fn overrides() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("fast")).unwrap();
+        let map = StorageMap::new([
+            (dir.path().to_path_buf(), Storage::Rotational),
+            (dir.path().join("fast"), Storage::SolidState),
+        ]);
+
+        assert_eq!(
+            map.storage(&dir.path().join("a")),
+            Some(Storage::Rotational)
+        );
+        assert_eq!(map.read_depth(&dir.path().join("a")), Some(1));
+        assert_eq!(
+            map.storage(&dir.path().join("fast/b")),
+            Some(Storage::SolidState)
+        );
+        assert_eq!(map.read_depth(&dir.path().join("fast/b")), None);
+    }
+}
@@ -0,0 +1,72 @@
+//! Recording scans and what was done about their duplicates in the system log, for machines nobody is watching
+//!
+//! Messages go through the C library's syslog, which journald also collects on systemd machines. Other platforms have
+//! no system log rupes can write to yet.
+
+use std::io::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// How serious a message is, mapped onto the syslog priorities
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Send every later message to the system log, as `rupes`
+#[cfg(unix)]
+pub fn enable() -> Result<()> {
+    // openlog keeps the pointer, so the name has to live for the rest of the process
+    static IDENT: &std::ffi::CStr = c"rupes";
+    unsafe { libc::openlog(IDENT.as_ptr(), libc::LOG_PID, libc::LOG_USER) };
+
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn enable() -> Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "--syslog is only supported on Unix",
+    ))
+}
+
+/// Whether messages are sent to the system log, to skip putting together ones that wouldn't be
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Write `message` to the system log, if it was enabled
+pub fn log(level: Level, message: &str) {
+    if enabled() {
+        write(level, message);
+    }
+}
+
+#[cfg(unix)]
+fn write(level: Level, message: &str) {
+    let priority = match level {
+        Level::Info => libc::LOG_INFO,
+        Level::Warning => libc::LOG_WARNING,
+        Level::Error => libc::LOG_ERR,
+    };
+    // Paths can't hold a NUL byte, anything else that does is cut off there
+    let message = message.split('\0').next().unwrap_or_default();
+    let Ok(message) = std::ffi::CString::new(message) else {
+        return;
+    };
+
+    unsafe { libc::syslog(priority, c"%s".as_ptr(), message.as_ptr()) };
+}
+
+#[cfg(not(unix))]
+fn write(_level: Level, _message: &str) {}
+
+/// Shorthand for [`log`] at [`Level::Info`]
+pub fn info(message: &str) {
+    log(Level::Info, message);
+}
@@ -0,0 +1,123 @@
+//! Text content normalized so copies of a file saved on different platforms compare equal
+
+use std::io::{BufRead, Read, Result};
+
+/// Leading bytes some editors, mostly on Windows, start UTF-8 files with
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Whether content starting with `head` looks like text, which is what it is unless it holds a NUL byte
+pub fn is_text(head: &[u8]) -> bool {
+    !head.contains(&0)
+}
+
+/// Reads text with CRLF line endings turned into LF and a leading byte order mark dropped, and optionally the spaces
+/// and tabs ending each line
+#[derive(Debug)]
+pub struct NormalizedText<R> {
+    reader: R,
+    trailing_whitespace: bool,
+    line: Vec<u8>,
+    position: usize,
+    started: bool,
+    length: u64,
+}
+
+impl<R: BufRead> NormalizedText<R> {
+    /// Normalize what's read from `reader`, dropping trailing whitespace too when `trailing_whitespace` is set
+    pub fn new(reader: R, trailing_whitespace: bool) -> Self {
+        NormalizedText {
+            reader,
+            trailing_whitespace,
+            line: Vec::new(),
+            position: 0,
+            started: false,
+            length: 0,
+        }
+    }
+
+    /// Number of normalized bytes read so far
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Read and normalize the next line, returning false at the end of the content
+    fn next_line(&mut self) -> Result<bool> {
+        self.line.clear();
+        self.position = 0;
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+
+        if !self.started {
+            self.started = true;
+            if self.line.starts_with(BOM) {
+                self.line.drain(..BOM.len());
+            }
+        }
+
+        let newline = self.line.last() == Some(&b'\n');
+        if newline {
+            self.line.pop();
+            if self.line.last() == Some(&b'\r') {
+                self.line.pop();
+            }
+        }
+        if self.trailing_whitespace {
+            while matches!(self.line.last(), Some(b' ' | b'\t')) {
+                self.line.pop();
+            }
+        }
+        if newline {
+            self.line.push(b'\n');
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for NormalizedText<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // A line can normalize to nothing, such as a lone byte order mark
+        while self.position == self.line.len() {
+            if !self.next_line()? {
+                return Ok(0);
+            }
+        }
+
+        let count = buf.len().min(self.line.len() - self.position);
+        buf[..count].copy_from_slice(&self.line[self.position..self.position + count]);
+        self.position += count;
+        self.length += count as u64;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(text: &[u8], trailing_whitespace: bool) -> Vec<u8> {
+        let mut reader = NormalizedText::new(text, trailing_whitespace);
+        let mut normalized = Vec::new();
+        reader.read_to_end(&mut normalized).unwrap();
+        assert_eq!(reader.length(), normalized.len() as u64);
+        normalized
+    }
+
+    #[test]
+    fn line_endings_and_bom() {
+        assert_eq!(
+            normalized(b"\xEF\xBB\xBFone\r\ntwo\r\n", false),
+            b"one\ntwo\n"
+        );
+        assert_eq!(normalized(b"one\ntwo", false), b"one\ntwo");
+        assert_eq!(normalized(b"\xEF\xBB\xBF", false), b"");
+        assert_eq!(normalized(b"a \t\r\nb  ", false), b"a \t\nb  ");
+        assert_eq!(normalized(b"a \t\r\nb  ", true), b"a\nb");
+        assert_eq!(normalized(b"lone\rreturn", false), b"lone\rreturn");
+
+        assert!(is_text(b"plain text\n"));
+        assert!(!is_text(b"\x7fELF\0\0"));
+    }
+}
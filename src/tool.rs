@@ -0,0 +1,135 @@
+//! External programs rupes hands work to, such as pdftotext, ffmpeg or aws, each run from the PATH unless an
+//! environment variable names another program to run instead
+
+use std::env;
+use std::ffi::OsString;
+use std::io::{Error, ErrorKind, Result};
+use std::process::{Child, Command, Output, Stdio};
+
+/// An external program, `default` or the one named by the environment variable `variable`
+#[derive(Clone, Copy, Debug)]
+pub struct Tool {
+    pub variable: &'static str,
+    pub default: &'static str,
+    /// The error given when the program isn't installed, saying what needs it
+    pub missing: &'static str,
+}
+
+pub const PDFTOTEXT: Tool = Tool {
+    variable: "RUPES_PDFTOTEXT",
+    default: "pdftotext",
+    missing: "--documents needs pdftotext to read documents, install it first",
+};
+
+pub const UNZIP: Tool = Tool {
+    variable: "RUPES_UNZIP",
+    default: "unzip",
+    missing: "--documents needs unzip to read documents, install it first",
+};
+
+pub const FFPROBE: Tool = Tool {
+    variable: "RUPES_FFPROBE",
+    default: "ffprobe",
+    missing: "--video needs ffprobe and ffmpeg, install FFmpeg to compare videos",
+};
+
+pub const FFMPEG: Tool = Tool {
+    variable: "RUPES_FFMPEG",
+    default: "ffmpeg",
+    missing: "--video needs ffprobe and ffmpeg, install FFmpeg to compare videos",
+};
+
+pub const GZIP: Tool = Tool {
+    variable: "RUPES_GZIP",
+    default: "gzip",
+    missing: "Reading compressed layers needs gzip, install it first",
+};
+
+pub const ZSTD: Tool = Tool {
+    variable: "RUPES_ZSTD",
+    default: "zstd",
+    missing: "Reading compressed layers needs zstd, install it first",
+};
+
+pub const LUA: Tool = Tool {
+    variable: "RUPES_LUA",
+    default: "lua",
+    missing: "--keep-command needs lua to run .lua scripts, install it first",
+};
+
+pub const WASMTIME: Tool = Tool {
+    variable: "RUPES_WASMTIME",
+    default: "wasmtime",
+    missing: "--keep-command needs wasmtime to run WebAssembly modules, install it first",
+};
+
+pub const AWS: Tool = Tool {
+    variable: "RUPES_AWS",
+    default: "aws",
+    missing: "S3 sources need the aws command line tool, install it and configure your credentials",
+};
+
+pub const SSH: Tool = Tool {
+    variable: "RUPES_SSH",
+    default: "ssh",
+    missing: "--remote needs ssh, install OpenSSH first",
+};
+
+impl Tool {
+    /// The program run
+    pub fn program(&self) -> OsString {
+        env::var_os(self.variable).unwrap_or_else(|| self.default.into())
+    }
+
+    /// A command running the program, reading nothing from stdin unless `configure` says otherwise
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(self.program());
+        command.stdin(Stdio::null());
+        command
+    }
+
+    /// Run the program with the arguments and pipes `configure` sets, waiting for it to exit
+    pub fn output(&self, configure: impl FnOnce(&mut Command) -> &mut Command) -> Result<Output> {
+        configure(&mut self.command())
+            .output()
+            .map_err(|error| self.not_found(error))
+    }
+
+    /// Start the program with the arguments and pipes `configure` sets
+    pub fn spawn(&self, configure: impl FnOnce(&mut Command) -> &mut Command) -> Result<Child> {
+        configure(&mut self.command())
+            .spawn()
+            .map_err(|error| self.not_found(error))
+    }
+
+    /// `error` running the program, telling what needs it when it isn't installed
+    pub fn not_found(&self, error: Error) -> Error {
+        if error.kind() == ErrorKind::NotFound {
+            Error::new(ErrorKind::NotFound, self.missing)
+        } else {
+            error
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_program_explained() {
+        let tool = Tool {
+            variable: "RUPES_TEST_NONEXISTENT_TOOL",
+            default: "rupes-nonexistent-tool",
+            missing: "testing needs a tool that isn't there",
+        };
+        assert_eq!(tool.program(), "rupes-nonexistent-tool");
+
+        let error = tool.output(|command| command.arg("-v")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(error.to_string(), tool.missing);
+
+        let other = tool.not_found(Error::from(ErrorKind::PermissionDenied));
+        assert_eq!(other.kind(), ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,260 @@
+//! Videos that look like the same recording, whatever container or encoding they were saved in
+//!
+//! Exact hashing never matches a movie remuxed from .mkv to .mp4, so videos are compared by what survives that: their
+//! duration, their resolution, and a few frames sampled across them. Videos are probed with ffprobe and their frames
+//! read with ffmpeg, or the programs named by the `RUPES_FFPROBE` and `RUPES_FFMPEG` environment variables.
+
+use std::collections::HashMap;
+use std::io::Result;
+use std::path::Path;
+use std::process::Stdio;
+
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use rupes::FileEntry;
+
+use crate::tool::{FFMPEG, FFPROBE};
+
+/// Extensions of the files compared as videos
+const EXTENSIONS: [&str; 15] = [
+    "3gp", "avi", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "mts", "ogv", "ts",
+    "webm", "wmv",
+];
+
+/// How many frames are sampled from each video, spread evenly across it
+const FRAMES: usize = 5;
+
+/// Frames whose hashes differ in more than this many of their 64 bits are different pictures
+const MAX_FRAME_DISTANCE: u32 = 10;
+
+/// Videos that look like the same recording
+#[derive(Debug)]
+pub struct SimilarVideos {
+    pub files: Vec<FileEntry>,
+    /// Duration of the first video, in seconds
+    pub duration: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What a video is compared by
+#[derive(Debug)]
+struct Signature {
+    duration: f64,
+    width: u32,
+    height: u32,
+    /// Difference hashes of the sampled frames
+    frames: Vec<u64>,
+}
+
+impl Signature {
+    fn matches(&self, other: &Signature, tolerance: f64) -> bool {
+        (self.duration - other.duration).abs() <= tolerance
+            && (self.width, self.height) == (other.width, other.height)
+            && self
+                .frames
+                .iter()
+                .zip(&other.frames)
+                .all(|(a, b)| (a ^ b).count_ones() <= MAX_FRAME_DISTANCE)
+    }
+}
+
+#[derive(Deserialize)]
+struct Probe {
+    #[serde(default)]
+    streams: Vec<ProbedStream>,
+    format: ProbedFormat,
+}
+
+#[derive(Deserialize)]
+struct ProbedStream {
+    width: u32,
+    height: u32,
+}
+
+#[derive(Deserialize)]
+struct ProbedFormat {
+    duration: String,
+}
+
+/// Whether `path` is named like a video
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// Duration in seconds and resolution of the video at `path`, `None` if ffprobe can't read it
+fn probe(path: &Path) -> Result<Option<(f64, u32, u32)>> {
+    let output = FFPROBE.output(|command| {
+        command
+            .args(["-v", "error", "-select_streams", "v:0", "-of", "json"])
+            .args(["-show_entries", "stream=width,height:format=duration"])
+            .arg(path)
+            .stderr(Stdio::null())
+    })?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let Ok(probe) = serde_json::from_slice::<Probe>(&output.stdout) else {
+        return Ok(None);
+    };
+    let (Some(stream), Ok(duration)) = (probe.streams.first(), probe.format.duration.parse())
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some((duration, stream.width, stream.height)))
+}
+
+/// Difference hash of a 9 by 8 grayscale picture: one bit for whether each pixel is brighter than the one right of it
+fn difference_hash(pixels: &[u8]) -> u64 {
+    let mut hash = 0;
+    for row in pixels.chunks_exact(9) {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[0] > pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+/// Hash of the frame `at` seconds into the video at `path`, `None` if ffmpeg can't decode it
+fn frame_hash(path: &Path, at: f64) -> Result<Option<u64>> {
+    let output = FFMPEG.output(|command| {
+        command
+            .args(["-v", "error", "-ss", &format!("{:.3}", at), "-i"])
+            .arg(path)
+            .args(["-frames:v", "1", "-vf", "scale=9:8,format=gray"])
+            .args(["-f", "rawvideo", "-"])
+            .stderr(Stdio::null())
+    })?;
+
+    Ok((output.status.success() && output.stdout.len() == 9 * 8)
+        .then(|| difference_hash(&output.stdout)))
+}
+
+fn signature(path: &Path) -> Result<Option<Signature>> {
+    let Some((duration, width, height)) = probe(path)? else {
+        return Ok(None);
+    };
+
+    let mut frames = Vec::with_capacity(FRAMES);
+    for i in 0..FRAMES {
+        let at = duration * (i + 1) as f64 / (FRAMES + 1) as f64;
+        let Some(hash) = frame_hash(path, at)? else {
+            return Ok(None);
+        };
+        frames.push(hash);
+    }
+
+    Ok(Some(Signature {
+        duration,
+        width,
+        height,
+        frames,
+    }))
+}
+
+/// Group the videos among `files` that look like the same recording, durations differing by at most `tolerance`
+/// seconds, calling `progress` as each file is probed
+///
+/// Files that aren't named like videos, and videos ffprobe or ffmpeg can't read, are left out. Groups are sorted by the
+/// path of their first file.
+pub fn find_similar(
+    files: Vec<FileEntry>,
+    tolerance: f64,
+    progress: impl Fn(&FileEntry) + Sync,
+) -> Result<Vec<SimilarVideos>> {
+    let mut videos: Vec<(FileEntry, Signature)> = files
+        .into_par_iter()
+        .filter(|file| is_video(&file.path))
+        .filter_map(|file| {
+            let signature = signature(&file.path);
+            progress(&file);
+            signature
+                .map(|signature| signature.map(|signature| (file, signature)))
+                .transpose()
+        })
+        .collect::<Result<_>>()?;
+    videos.sort_by(|a, b| a.1.duration.total_cmp(&b.1.duration));
+
+    // Each video joins the group of the closest shorter one it matches, only those close enough in duration are tried
+    let mut group_of: Vec<usize> = (0..videos.len()).collect();
+    for i in 0..videos.len() {
+        for j in (0..i).rev() {
+            if videos[i].1.duration - videos[j].1.duration > tolerance {
+                break;
+            }
+            if videos[i].1.matches(&videos[j].1, tolerance) {
+                group_of[i] = group_of[j];
+                break;
+            }
+        }
+    }
+
+    let mut groups: Vec<SimilarVideos> = Vec::new();
+    let mut group_index: HashMap<usize, usize> = HashMap::new();
+    for (i, (file, signature)) in videos.into_iter().enumerate() {
+        match group_index.get(&group_of[i]) {
+            Some(&index) => groups[index].files.push(file),
+            None => {
+                group_index.insert(i, groups.len());
+                groups.push(SimilarVideos {
+                    files: vec![file],
+                    duration: signature.duration,
+                    width: signature.width,
+                    height: signature.height,
+                });
+            }
+        }
+    }
+
+    groups.retain(|group| group.files.len() >= 2);
+    for group in &mut groups {
+        group.files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    groups.sort_by(|a, b| a.files[0].path.cmp(&b.files[0].path));
+
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures() {
+        let mut gradient = [0u8; 9 * 8];
+        for (i, pixel) in gradient.iter_mut().enumerate() {
+            *pixel = 255 - (i % 9) as u8 * 20;
+        }
+        assert_eq!(difference_hash(&gradient), u64::MAX);
+        assert_eq!(difference_hash(&[7; 9 * 8]), 0);
+
+        let movie = Signature {
+            duration: 5400.0,
+            width: 1920,
+            height: 1080,
+            frames: vec![0; FRAMES],
+        };
+        let remux = Signature {
+            duration: 5401.5,
+            frames: vec![0b1011; FRAMES],
+            ..movie
+        };
+        assert!(movie.matches(&remux, 2.0));
+        assert!(!movie.matches(&remux, 1.0));
+        assert!(!movie.matches(
+            &Signature {
+                width: 1280,
+                ..remux
+            },
+            2.0
+        ));
+
+        assert!(is_video(Path::new("movie.MKV")));
+        assert!(!is_video(Path::new("notes.txt")));
+    }
+}
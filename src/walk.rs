@@ -0,0 +1,784 @@
+//! Discovering the files to compare
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, read_dir, DirEntry, File, Metadata};
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+use regex::Regex;
+
+use crate::paths::{CompactFile, Directory, DirectoryId, FileList};
+
+/// Which files [`find_files`] includes
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// Recursively search directories
+    pub recursive: bool,
+    /// Exclude files and directories that begin with '.'
+    pub exclude_dots: bool,
+    /// Only include files with names matching any of these patterns, every name is included when there are none
+    pub filters: Vec<Regex>,
+    /// Exclude files with names matching any of these patterns, even if they match [`ScanOptions::filters`]
+    pub exclude: Vec<Regex>,
+    /// Skip directories with names matching any of these patterns, and everything inside them
+    pub exclude_dirs: Vec<Regex>,
+    /// Skip these directories, and everything inside them, whatever path they are reached under, unless given as roots
+    pub exclude_paths: Vec<PathBuf>,
+    /// Skip directories holding a valid `CACHEDIR.TAG`, see [`has_cache_tag`], unless given as roots
+    pub cachedir_tags: bool,
+    /// Skip directories holding a file with any of these names, and everything inside them, unless given as roots
+    pub skip_markers: Vec<OsString>,
+    /// Follow symlinks, by default symbolic links are ignored
+    pub follow_symlinks: bool,
+    /// Minimum file size in bytes, smaller files are skipped
+    pub min: Option<u64>,
+    /// Maximum file size in bytes, larger files are skipped
+    pub max: Option<u64>,
+    /// Files inside the tree that change these options for their directory and everything below it
+    pub directory_overrides: Option<DirectoryOverrides>,
+    /// Stop discovery once this many files have been found
+    pub max_files: Option<usize>,
+    /// Stop discovery once the files found add up to this many bytes
+    pub max_bytes: Option<u64>,
+    /// Only descend this many levels of directories below each root when searching recursively, 0 for none
+    pub max_depth: Option<usize>,
+    /// Leave out files and directories that can't be read, listing them in [`Discovery::skipped`], rather than failing
+    /// the discovery on the first of them
+    pub skip_unreadable: bool,
+}
+
+/// Reads options for a directory from a file in it, such as a `.rupes.toml` tightening the filters for its subtree
+///
+/// Only what a directory holds is affected, [`ScanOptions::max_files`] and [`ScanOptions::max_bytes`] stay shared by
+/// the whole discovery.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectoryOverrides {
+    /// Name of the file, looked for in every directory walked
+    pub file_name: &'static str,
+    /// Turns the file at the given path, and the options in force in the directory above, into the options for its
+    /// directory
+    pub read: fn(&Path, &ScanOptions) -> Result<ScanOptions>,
+}
+
+impl DirectoryOverrides {
+    /// The options for the directory at `path`, read from its file if `has_file` says it holds one
+    fn apply<'a>(
+        options: &'a ScanOptions,
+        path: &Path,
+        has_file: impl FnOnce(&str) -> bool,
+    ) -> Result<Cow<'a, ScanOptions>> {
+        match options.directory_overrides {
+            Some(overrides) if has_file(overrides.file_name) => Ok(Cow::Owned((overrides.read)(
+                &path.join(overrides.file_name),
+                options,
+            )?)),
+            _ => Ok(Cow::Borrowed(options)),
+        }
+    }
+}
+
+/// The options for the directory at `path`, given those in force around it, for walks of its own such as looking for
+/// directories left empty
+pub fn directory_options<'a>(
+    path: &Path,
+    options: &'a ScanOptions,
+) -> Result<Cow<'a, ScanOptions>> {
+    DirectoryOverrides::apply(options, path, |file_name| path.join(file_name).is_file())
+}
+
+/// A file found by [`find_files`], or listed by a [`FileList`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// The physical file behind this path, shared by every hardlink to it, if the platform exposes one
+    pub id: Option<FileId>,
+}
+
+/// Identifies a physical file, two paths with the same id are hardlinks to each other
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileId {
+    pub device: u64,
+    pub inode: u64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    pub fn of(metadata: &Metadata) -> Option<FileId> {
+        use std::os::unix::fs::MetadataExt;
+
+        Some(FileId {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn of(_metadata: &Metadata) -> Option<FileId> {
+        None
+    }
+}
+
+/// Whether a file or directory called `name` is excluded from the scan regardless of its type
+pub fn excludes_name(name: &str, options: &ScanOptions) -> bool {
+    options.exclude_dots && name.starts_with('.')
+}
+
+/// Whether a file called `name` that is `size` bytes long passes the file filters in `options`
+pub fn includes_file(name: &str, size: u64, options: &ScanOptions) -> bool {
+    if !options.filters.is_empty() && !options.filters.iter().any(|filter| filter.is_match(name)) {
+        return false;
+    }
+
+    if options.exclude.iter().any(|exclude| exclude.is_match(name)) {
+        return false;
+    }
+
+    if let Some(min) = options.min {
+        if min > size {
+            return false;
+        }
+    }
+
+    if let Some(max) = options.max {
+        if max < size {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Whether a directory called `name` is walked rather than pruned by [`ScanOptions::exclude_dirs`]
+pub fn includes_directory(name: &str, options: &ScanOptions) -> bool {
+    !options
+        .exclude_dirs
+        .iter()
+        .any(|exclude| exclude.is_match(name))
+}
+
+/// The pattern matching the names the shell glob `glob` does, with `*`, `?` and `[...]` classes, `[!...]` for the
+/// names not in a class
+///
+/// A `[` without a `]` closing it only matches itself.
+pub fn glob_pattern(glob: &str) -> std::result::Result<Regex, regex::Error> {
+    let characters: Vec<char> = glob.chars().collect();
+    let mut pattern = String::from("^");
+    let mut index = 0;
+
+    while index < characters.len() {
+        match characters[index] {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' if characters[index + 1..].contains(&']') => {
+                let end = index
+                    + 1
+                    + characters[index + 1..]
+                        .iter()
+                        .position(|&c| c == ']')
+                        .unwrap();
+                let mut class = &characters[index + 1..end];
+                pattern.push('[');
+                if let Some(('!' | '^', rest)) = class.split_first() {
+                    pattern.push('^');
+                    class = rest;
+                }
+                for &character in class {
+                    if matches!(character, '\\' | '[' | '&' | '~') {
+                        pattern.push('\\');
+                    }
+                    pattern.push(character);
+                }
+                pattern.push(']');
+                index = end;
+            }
+            character => pattern.push_str(&regex::escape(&character.to_string())),
+        }
+        index += 1;
+    }
+    pattern.push('$');
+
+    Regex::new(&pattern)
+}
+
+/// Name of the file marking a cache directory, from the Cache Directory Tagging Specification
+pub const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+
+/// What a `CACHEDIR.TAG` has to start with to count, so files that just happen to have the name don't
+const CACHEDIR_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// Whether `directory` is tagged as a cache, with a `CACHEDIR.TAG` file starting with the signature of the spec
+pub fn has_cache_tag(directory: &Path) -> bool {
+    let Ok(file) = File::open(directory.join(CACHEDIR_TAG)) else {
+        return false;
+    };
+
+    let mut start = Vec::with_capacity(CACHEDIR_SIGNATURE.len());
+    file.take(CACHEDIR_SIGNATURE.len() as u64)
+        .read_to_end(&mut start)
+        .is_ok_and(|_| start == CACHEDIR_SIGNATURE)
+}
+
+/// Whether the directory at `path` is skipped for what it holds, a valid `CACHEDIR.TAG` or one of
+/// [`ScanOptions::skip_markers`]
+pub fn is_marked(path: &Path, options: &ScanOptions) -> bool {
+    (options.cachedir_tags && has_cache_tag(path))
+        || options
+            .skip_markers
+            .iter()
+            .any(|marker| path.join(marker).exists())
+}
+
+/// The directories of [`ScanOptions::exclude_paths`], recognised under any path they are reached by
+///
+/// Directories are told apart by their [`FileId`] where the platform has one, and by their canonical path otherwise.
+/// Those that don't exist are left out.
+#[derive(Clone, Debug, Default)]
+pub struct ExcludedDirectories(Vec<(Option<FileId>, PathBuf)>);
+
+impl ExcludedDirectories {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let excluded = paths
+            .iter()
+            .filter_map(|path| {
+                let id = fs::metadata(path).ok().as_ref().and_then(FileId::of);
+                Some((id, fs::canonicalize(path).ok()?))
+            })
+            .collect();
+
+        ExcludedDirectories(excluded)
+    }
+
+    /// Whether the directory at `path` is one of them
+    pub fn contains(&self, path: &Path) -> bool {
+        if self.0.is_empty() {
+            return false;
+        }
+
+        self.contains_directory(path, fs::metadata(path).ok().as_ref().and_then(FileId::of))
+    }
+
+    /// Like [`contains`](Self::contains), for a directory whose [`FileId`] is already known
+    pub fn contains_directory(&self, path: &Path, id: Option<FileId>) -> bool {
+        if self.0.is_empty() {
+            return false;
+        }
+
+        match id {
+            Some(id) => self.0.iter().any(|(excluded, _)| *excluded == Some(id)),
+            None => fs::canonicalize(path)
+                .is_ok_and(|path| self.0.iter().any(|(_, excluded)| *excluded == path)),
+        }
+    }
+}
+
+/// What a directory entry is, following symlinks when asked to
+enum Kind {
+    File(Metadata),
+    Directory(Option<FileId>),
+    Other,
+}
+
+/// The files found by [`discover`]
+#[derive(Debug, Default)]
+pub struct Discovery {
+    pub files: FileList,
+    /// Whether discovery stopped early because [`ScanOptions::max_files`] or [`ScanOptions::max_bytes`] was reached
+    pub truncated: bool,
+    /// Directories that weren't walked because they are another directory walked under a different path, such as a
+    /// bind mount of it, each with the path it was walked under
+    pub aliases: Vec<(PathBuf, PathBuf)>,
+    /// Files and directories left out because they couldn't be read, with why, see [`ScanOptions::skip_unreadable`]
+    pub skipped: Vec<(PathBuf, Error)>,
+}
+
+/// Running totals of a discovery in progress, passed to the callback of [`discover`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiscoveryProgress {
+    pub directories: usize,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// State shared by every thread of a walk
+struct Walk<'a> {
+    options: &'a ScanOptions,
+    progress: &'a (dyn Fn(&DiscoveryProgress) + Sync),
+    directories: AtomicUsize,
+    files: AtomicUsize,
+    bytes: AtomicU64,
+    truncated: AtomicBool,
+    /// The path each directory was first walked under, by the physical directory behind it
+    walked: Mutex<HashMap<FileId, PathBuf>>,
+    aliases: Mutex<Vec<(PathBuf, PathBuf)>>,
+    /// Every directory walked, the files found refer to them rather than holding full paths
+    tree: Mutex<Vec<Directory>>,
+    excluded: ExcludedDirectories,
+    skipped: Mutex<Vec<(PathBuf, Error)>>,
+}
+
+impl Walk<'_> {
+    /// Whether the directory at `path`, with the [`FileId`] `id`, is walked under this path, rather than already under
+    /// another one
+    fn claim(&self, path: &Path, id: Option<FileId>) -> bool {
+        let Some(id) = id else {
+            return true;
+        };
+
+        let mut walked = self.walked.lock().unwrap();
+        match walked.get(&id) {
+            Some(first) if first != path => {
+                self.aliases
+                    .lock()
+                    .unwrap()
+                    .push((path.to_path_buf(), first.clone()));
+                false
+            }
+            Some(_) => true,
+            None => {
+                walked.insert(id, path.to_path_buf());
+                true
+            }
+        }
+    }
+
+    /// Leave out the file or directory at `path` that failed with `error`, or fail the walk with it unless skipping
+    /// unreadable files
+    fn skip(&self, path: PathBuf, error: Error) -> Result<Vec<CompactFile>> {
+        if !self.options.skip_unreadable {
+            return Err(error);
+        }
+        self.skipped.lock().unwrap().push((path, error));
+        Ok(Vec::new())
+    }
+
+    /// Like [`is_marked`], for the directory at `path` holding `entries`, without looking for files it doesn't hold
+    fn skips(&self, path: &Path, entries: &[DirEntry], options: &ScanOptions) -> bool {
+        entries.iter().any(|entry| {
+            let name = entry.file_name();
+            options.skip_markers.contains(&name)
+                || (options.cachedir_tags && name == CACHEDIR_TAG && has_cache_tag(path))
+        })
+    }
+
+    /// Count a file towards the limits, returning whether it still fits within them
+    fn admit(&self, size: u64) -> bool {
+        if self.truncated.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let files = self.files.fetch_add(1, Ordering::Relaxed);
+        let bytes = self.bytes.fetch_add(size, Ordering::Relaxed);
+
+        let over_files = self.options.max_files.is_some_and(|max| files >= max);
+        let over_bytes = self.options.max_bytes.is_some_and(|max| bytes >= max);
+
+        if over_files || over_bytes {
+            self.truncated.store(true, Ordering::Relaxed);
+            return false;
+        }
+
+        (self.progress)(&DiscoveryProgress {
+            directories: self.directories.load(Ordering::Relaxed),
+            files: files + 1,
+            bytes: bytes + size,
+        });
+
+        true
+    }
+
+    fn visit_directory(&self) {
+        let directories = self.directories.fetch_add(1, Ordering::Relaxed);
+
+        (self.progress)(&DiscoveryProgress {
+            directories: directories + 1,
+            files: self.files.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        });
+    }
+}
+
+/// Work out what `entry` is with as few metadata calls as possible
+///
+/// `read_dir` already knows the type of most entries, so files and directories need one call on the entry, which
+/// doesn't resolve its path again, for their size or their [`FileId`]. Symlinks being followed need one call to find
+/// out what they point to.
+fn kind(entry: &DirEntry, options: &ScanOptions) -> Result<Kind> {
+    let file_type = entry.file_type()?;
+
+    let metadata = if file_type.is_symlink() {
+        if !options.follow_symlinks {
+            return Ok(Kind::Other);
+        }
+        entry.path().metadata()?
+    } else if file_type.is_file() || file_type.is_dir() {
+        entry.metadata()?
+    } else {
+        return Ok(Kind::Other);
+    };
+
+    Ok(if metadata.is_file() {
+        Kind::File(metadata)
+    } else if metadata.is_dir() {
+        Kind::Directory(FileId::of(&metadata))
+    } else {
+        Kind::Other
+    })
+}
+
+fn handle_file(
+    entry: DirEntry,
+    directory: DirectoryId,
+    depth: usize,
+    options: &ScanOptions,
+    walk: &Walk,
+) -> Result<Vec<CompactFile>> {
+    let file_name = entry.file_name();
+    let file_name = file_name.to_string_lossy();
+
+    // Guard against dot files/directories (if they are excluded)
+    if excludes_name(&file_name, options) {
+        return Ok(Vec::new());
+    }
+
+    let kind = match kind(&entry, options) {
+        Ok(kind) => kind,
+        Err(error) => return walk.skip(entry.path(), error),
+    };
+    match kind {
+        Kind::File(metadata)
+            if includes_file(&file_name, metadata.len(), options) && walk.admit(metadata.len()) =>
+        {
+            Ok(vec![CompactFile {
+                directory,
+                name: entry.file_name().into_boxed_os_str(),
+                size: metadata.len(),
+                id: FileId::of(&metadata),
+            }])
+        }
+        Kind::Directory(id)
+            if options.recursive
+                && options.max_depth.is_none_or(|max| depth < max)
+                && includes_directory(&file_name, options)
+                && !walk.excluded.contains_directory(&entry.path(), id) =>
+        {
+            let subdirectory = Directory {
+                parent: Some(directory),
+                name: entry.file_name().into_boxed_os_str(),
+            };
+            get_files(entry.path(), id, subdirectory, depth + 1, options, walk)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// List the files in the directory at `path`, descending into subdirectories on the rayon thread pool
+///
+/// Results keep the order `read_dir` returned the entries in, so a scan is no less repeatable than a sequential walk.
+/// `id` is the [`FileId`] of the directory at `path`. `directory` names `path` in the tree of the walk, it's only added
+/// once the directory is claimed. `options` are the ones in force in the directory above, or the options of the walk
+/// for a root. `depth` counts the directories between `path` and its root.
+fn get_files(
+    path: PathBuf,
+    id: Option<FileId>,
+    directory: Directory,
+    depth: usize,
+    options: &ScanOptions,
+    walk: &Walk,
+) -> Result<Vec<CompactFile>> {
+    if walk.truncated.load(Ordering::Relaxed) || !walk.claim(&path, id) {
+        return Ok(Vec::new());
+    }
+
+    let entries = match read_dir(&path).and_then(|entries| entries.collect::<Result<Vec<_>>>()) {
+        Ok(entries) => entries,
+        Err(error) => return walk.skip(path, error),
+    };
+    // Roots are walked whatever they hold, the scan was asked for them
+    if directory.parent.is_some() && walk.skips(&path, &entries, options) {
+        return Ok(Vec::new());
+    }
+
+    let options = DirectoryOverrides::apply(options, &path, |file_name| {
+        entries.iter().any(|entry| entry.file_name() == file_name)
+    })?;
+    let options = options.as_ref();
+    walk.visit_directory();
+    let directory = FileList::add_directory(&mut walk.tree.lock().unwrap(), directory);
+
+    let found = entries
+        .into_par_iter()
+        .map(|entry| handle_file(entry, directory, depth, options, walk))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(found.into_iter().flatten().collect())
+}
+
+/// Find the files in `directory` permitted by `options`, stopping early if one of its limits is reached
+///
+/// `progress` is called with running totals as each directory is visited and each file is found. Directories are
+/// walked in parallel on the current rayon thread pool, so which files make it into a truncated discovery can vary
+/// between runs.
+pub fn discover(
+    directory: &Path,
+    options: &ScanOptions,
+    progress: impl Fn(&DiscoveryProgress) + Sync,
+) -> Result<Discovery> {
+    discover_roots(&[directory.to_path_buf()], options, progress)
+}
+
+/// Drop roots found inside another root, or given twice, so no file is discovered more than once
+fn distinct_roots(roots: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let canonical = roots
+        .iter()
+        .map(|root| root.canonicalize())
+        .collect::<Result<Vec<_>>>()?;
+
+    let distinct = roots
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| {
+            !canonical.iter().enumerate().any(|(other, path)| {
+                other != index
+                    && canonical[index].starts_with(path)
+                    && (canonical[index] != *path || other < index)
+            })
+        })
+        .map(|(_, root)| root.clone())
+        .collect();
+
+    Ok(distinct)
+}
+
+/// Like [`discover`], but walks every directory in `roots` concurrently, sharing the limits in `options` between them
+///
+/// Files of each root are listed together, in the order the roots were given. Roots nested inside another root are
+/// only walked once, as part of the outer root. So are directories reached under two paths, through bind mounts or
+/// symlinks, they are walked under the first path and recorded in [`Discovery::aliases`] under the others. Which path
+/// comes first is only certain for the roots themselves, in the order they were given.
+pub fn discover_roots(
+    roots: &[PathBuf],
+    options: &ScanOptions,
+    progress: impl Fn(&DiscoveryProgress) + Sync,
+) -> Result<Discovery> {
+    if roots.is_empty() || roots.iter().any(|root| !root.is_dir()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Please specify a valid directory to search",
+        ));
+    }
+
+    let walk = Walk {
+        options,
+        progress: &progress,
+        directories: AtomicUsize::new(0),
+        files: AtomicUsize::new(0),
+        bytes: AtomicU64::new(0),
+        truncated: AtomicBool::new(false),
+        walked: Mutex::default(),
+        aliases: Mutex::default(),
+        tree: Mutex::default(),
+        excluded: ExcludedDirectories::new(&options.exclude_paths),
+        skipped: Mutex::default(),
+    };
+
+    // Claimed up front so a directory given twice under different paths is walked under the first
+    let roots: Vec<(PathBuf, Option<FileId>)> = distinct_roots(roots)?
+        .into_iter()
+        .map(|root| {
+            let id = fs::metadata(&root).ok().as_ref().and_then(FileId::of);
+            (root, id)
+        })
+        .filter(|(root, id)| walk.claim(root, *id))
+        .collect();
+
+    let found = roots
+        .into_par_iter()
+        .map(|(root, id)| {
+            let directory = Directory {
+                parent: None,
+                name: root.clone().into_os_string().into_boxed_os_str(),
+            };
+            get_files(root, id, directory, 0, options, &walk)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Discovery {
+        files: FileList::from_parts(
+            walk.tree.into_inner().unwrap(),
+            found.into_iter().flatten().collect(),
+        ),
+        truncated: walk.truncated.into_inner(),
+        aliases: walk.aliases.into_inner().unwrap(),
+        skipped: walk.skipped.into_inner().unwrap(),
+    })
+}
+
+/// Find every file in `directory` permitted by `options`
+///
+/// Directories are walked in parallel on the current rayon thread pool.
+pub fn find_files(directory: &Path, options: &ScanOptions) -> Result<Vec<FileEntry>> {
+    Ok(discover(directory, options, |_| {})?.files.into_entries())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_names_excluded_only_when_asked() {
+        let options = ScanOptions::default();
+        assert!(!excludes_name(".hidden", &options));
+
+        let options = ScanOptions {
+            exclude_dots: true,
+            ..ScanOptions::default()
+        };
+        assert!(excludes_name(".hidden", &options));
+        assert!(!excludes_name("visible.txt", &options));
+    }
+
+    #[test]
+    fn file_filters() {
+        let options = ScanOptions {
+            filters: vec![
+                Regex::new("^.+[.]txt$").unwrap(),
+                Regex::new("^README$").unwrap(),
+            ],
+            min: Some(10),
+            max: Some(20),
+            ..ScanOptions::default()
+        };
+
+        assert!(includes_file("notes.txt", 15, &options));
+        assert!(includes_file("notes.txt", 10, &options));
+        assert!(includes_file("notes.txt", 20, &options));
+        assert!(includes_file("README", 15, &options));
+        assert!(!includes_file("notes.md", 15, &options));
+        assert!(!includes_file("notes.txt", 9, &options));
+        assert!(!includes_file("notes.txt", 21, &options));
+        assert!(includes_file("anything", 0, &ScanOptions::default()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn same_directory_walked_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/file"), "content").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("sub"), dir.path().join("link")).unwrap();
+
+        let options = ScanOptions {
+            recursive: true,
+            follow_symlinks: true,
+            ..ScanOptions::default()
+        };
+        let discovery = discover(dir.path(), &options, |_| {}).unwrap();
+
+        assert_eq!(discovery.files.len(), 1);
+        assert_eq!(discovery.aliases.len(), 1);
+        let (skipped, first) = &discovery.aliases[0];
+        let mut paths = [skipped.clone(), first.clone()];
+        paths.sort();
+        assert_eq!(paths, [dir.path().join("link"), dir.path().join("sub")]);
+    }
+
+    #[test]
+    fn exclude_patterns() {
+        let options = ScanOptions {
+            filters: vec![Regex::new("[.]txt$").unwrap()],
+            exclude: vec![
+                Regex::new("^~").unwrap(),
+                Regex::new("[.]bak[.]txt$").unwrap(),
+            ],
+            exclude_dirs: vec![
+                Regex::new("^node_modules$").unwrap(),
+                Regex::new("^[.]git$").unwrap(),
+            ],
+            ..ScanOptions::default()
+        };
+
+        assert!(includes_file("notes.txt", 0, &options));
+        assert!(!includes_file("~notes.txt", 0, &options));
+        assert!(!includes_file("notes.bak.txt", 0, &options));
+
+        assert!(includes_directory("src", &options));
+        assert!(!includes_directory("node_modules", &options));
+        assert!(!includes_directory(".git", &options));
+        assert!(includes_directory(".github", &options));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let glob = |glob: &str| glob_pattern(glob).unwrap();
+
+        assert!(glob("*.tmp").is_match("notes.tmp"));
+        assert!(!glob("*.tmp").is_match("notes.tmp.txt"));
+        assert!(glob("IMG_????.jpg").is_match("IMG_0042.jpg"));
+        assert!(!glob("IMG_????.jpg").is_match("IMG_42.jpg"));
+        assert!(glob("[ab]*").is_match("backup"));
+        assert!(!glob("[!ab]*").is_match("backup"));
+        assert!(glob("v1.[0-9]").is_match("v1.5"));
+        assert!(!glob("v1.[0-9]").is_match("v1x5"));
+        assert!(glob("a[b").is_match("a[b"));
+    }
+
+    #[test]
+    fn depth_limited() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        for path in ["top", "a/middle", "a/b/bottom"] {
+            fs::write(dir.path().join(path), path).unwrap();
+        }
+
+        let found = |max_depth| {
+            let options = ScanOptions {
+                recursive: true,
+                max_depth,
+                ..ScanOptions::default()
+            };
+            let mut names: Vec<_> = find_files(dir.path(), &options)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.path.file_name().unwrap().to_owned())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(found(Some(0)), ["top"]);
+        assert_eq!(found(Some(1)), ["middle", "top"]);
+        assert_eq!(found(None), ["bottom", "middle", "top"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_entries_skipped_when_asked() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("file"), "content").unwrap();
+        // Following it fails, there's nothing at the other end
+        std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("dangling"))
+            .unwrap();
+
+        let options = ScanOptions {
+            follow_symlinks: true,
+            ..ScanOptions::default()
+        };
+        assert!(discover(dir.path(), &options, |_| {}).is_err());
+
+        let options = ScanOptions {
+            skip_unreadable: true,
+            ..options
+        };
+        let discovery = discover(dir.path(), &options, |_| {}).unwrap();
+        assert_eq!(discovery.files.len(), 1);
+        assert_eq!(discovery.skipped.len(), 1);
+        assert_eq!(discovery.skipped[0].0, dir.path().join("dangling"));
+        assert_eq!(discovery.skipped[0].1.kind(), ErrorKind::NotFound);
+    }
+}
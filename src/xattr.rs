@@ -0,0 +1,139 @@
+//! Hashes kept in the extended attributes of the files they belong to, so they travel with the files when they're
+//! copied or moved by tools that preserve attributes
+//!
+//! Each algorithm has its own `user.rupes.<algorithm>` attribute, holding the file's size and modification time in
+//! nanoseconds when it was hashed followed by the hex digest, separated by spaces. Like [`HashCache`](crate::HashCache)
+//! entries, they are only trusted while the size and modification time are unchanged.
+
+use std::fs::Metadata;
+use std::io::Result;
+use std::path::Path;
+
+use crate::cache::{modified, same_time};
+use crate::hash::{Digest, HashAlgorithm};
+
+/// Whether extended attributes can be read and written on this platform
+pub const SUPPORTED: bool = cfg!(any(target_os = "linux", target_os = "macos"));
+
+/// Longest value read back, far more than any digest needs
+const MAX_VALUE: usize = 256;
+
+fn name(algorithm: HashAlgorithm) -> String {
+    format!("user.rupes.{}", algorithm)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod sys {
+    use std::ffi::CString;
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    fn c_string(bytes: &[u8]) -> Result<CString> {
+        CString::new(bytes).map_err(|error| Error::new(ErrorKind::InvalidInput, error))
+    }
+
+    pub fn get(path: &Path, name: &str, value: &mut [u8]) -> Result<usize> {
+        let path = c_string(path.as_os_str().as_bytes())?;
+        let name = c_string(name.as_bytes())?;
+        let (path, name, buffer) = (path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast());
+
+        #[cfg(target_os = "linux")]
+        let read = unsafe { libc::getxattr(path, name, buffer, value.len()) };
+        #[cfg(target_os = "macos")]
+        let read = unsafe { libc::getxattr(path, name, buffer, value.len(), 0, 0) };
+
+        if read < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(read as usize)
+    }
+
+    pub fn set(path: &Path, name: &str, value: &[u8]) -> Result<()> {
+        let path = c_string(path.as_os_str().as_bytes())?;
+        let name = c_string(name.as_bytes())?;
+        let (path, name, buffer) = (path.as_ptr(), name.as_ptr(), value.as_ptr().cast());
+
+        #[cfg(target_os = "linux")]
+        let result = unsafe { libc::setxattr(path, name, buffer, value.len(), 0) };
+        #[cfg(target_os = "macos")]
+        let result = unsafe { libc::setxattr(path, name, buffer, value.len(), 0, 0) };
+
+        if result < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod sys {
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::Path;
+
+    pub fn get(_path: &Path, _name: &str, _value: &mut [u8]) -> Result<usize> {
+        Err(Error::from(ErrorKind::Unsupported))
+    }
+
+    pub fn set(_path: &Path, _name: &str, _value: &[u8]) -> Result<()> {
+        Err(Error::from(ErrorKind::Unsupported))
+    }
+}
+
+fn parse(value: &str, metadata: &Metadata) -> Option<Digest> {
+    let mut fields = value.split(' ');
+    let size: u64 = fields.next()?.parse().ok()?;
+    let time: u64 = fields.next()?.parse().ok()?;
+    let digest = fields.next()?.parse().ok()?;
+
+    (size == metadata.len() && same_time(time, modified(metadata)?)).then_some(digest)
+}
+
+/// The digest made with `algorithm` kept on the file at `path`, if it has one and is still as `metadata` describes it
+pub fn read(path: &Path, algorithm: HashAlgorithm, metadata: &Metadata) -> Option<Digest> {
+    let mut value = [0; MAX_VALUE];
+    let length = sys::get(path, &name(algorithm), &mut value).ok()?;
+
+    parse(std::str::from_utf8(&value[..length]).ok()?, metadata)
+}
+
+/// Keep `digest` on the file at `path`, `metadata` being how the file was before it was read
+///
+/// Fails for files that can't be written to, and on filesystems without extended attributes.
+pub fn write(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    metadata: &Metadata,
+    digest: &Digest,
+) -> Result<()> {
+    let Some(time) = modified(metadata) else {
+        return Ok(());
+    };
+    let value = format!("{} {} {}", metadata.len(), time, digest);
+
+    sys::set(path, &name(algorithm), value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kept_on_the_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, "content").unwrap();
+        let metadata = std::fs::metadata(&file).unwrap();
+        let digest = HashAlgorithm::Sha256.digest_file(&file).unwrap();
+
+        let value = format!("7 {} {}", modified(&metadata).unwrap(), digest);
+        assert_eq!(parse(&value, &metadata), Some(digest.clone()));
+        assert_eq!(parse(&value.replacen('7', "8", 1), &metadata), None);
+
+        // Not every filesystem tests run on has user attributes, such as tmpfs on older kernels
+        if write(&file, HashAlgorithm::Sha256, &metadata, &digest).is_ok() {
+            assert_eq!(read(&file, HashAlgorithm::Sha256, &metadata), Some(digest));
+            assert_eq!(read(&file, HashAlgorithm::Blake3, &metadata), None);
+        }
+    }
+}
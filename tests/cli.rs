@@ -1,6 +1,30 @@
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
 use std::process::Command;
+use tempfile::TempDir;
+
+/// Copy an example directory (without following symlinks) into a temporary directory so actions can modify it
+fn copy_example(name: &str) -> Result<TempDir, Box<dyn std::error::Error>> {
+    fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+            } else if file_type.is_file() {
+                fs::copy(entry.path(), to.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    }
+
+    let dir = TempDir::new()?;
+    copy_dir(&Path::new("./example_files").join(name), dir.path())?;
+    Ok(dir)
+}
 
 #[test]
 fn bad_directory() -> Result<(), Box<dyn std::error::Error>> {
@@ -20,7 +44,7 @@ fn empty_directory() -> Result<(), Box<dyn std::error::Error>> {
     rupes
         .assert()
         .success()
-        .stdout(predicate::str::contains(
+        .stderr(predicate::str::contains(
             "No files to scan, rupes will now exit",
         ));
 
@@ -159,3 +183,3495 @@ fn comma_separated_scan() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn delete_with_log() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = copy_example("test")?;
+    let log = dir.path().join("actions.log");
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes
+        .arg(dir.path().join("a-dir"))
+        .arg("--delete")
+        .arg("--no-prompt")
+        .arg("--log")
+        .arg(&log);
+    rupes.assert().success();
+
+    assert!(dir.path().join("a-dir/.dot-file").exists());
+    assert!(!dir.path().join("a-dir/c-file.txt").exists());
+    assert!(!dir.path().join("a-dir/d-file.txt").exists());
+
+    let log = fs::read_to_string(log)?;
+    let entries: Vec<&str> = log.lines().collect();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].contains("\"operation\":\"delete\""));
+    assert!(entries[0].contains("c-file.txt"));
+    assert!(entries[0].contains("\"bytes_freed\":9690"));
+    assert!(entries[0].contains("\"result\":\"ok\""));
+
+    Ok(())
+}
+
+#[test]
+fn hardlink_action() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = copy_example("test")?;
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg(dir.path().join("a-dir")).arg("--hardlink").arg("--no-prompt");
+    rupes.assert().success();
+
+    let kept = fs::read(dir.path().join("a-dir/.dot-file"))?;
+    assert_eq!(fs::read(dir.path().join("a-dir/c-file.txt"))?, kept);
+    assert_eq!(fs::read(dir.path().join("a-dir/d-file.txt"))?, kept);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let kept = fs::metadata(dir.path().join("a-dir/.dot-file"))?;
+        let linked = fs::metadata(dir.path().join("a-dir/c-file.txt"))?;
+        assert_eq!(kept.ino(), linked.ino());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn plan_and_apply() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = copy_example("test")?;
+    let plan = dir.path().join("plan.json");
+
+    let output = Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg("--delete")
+        .arg(dir.path().join("a-dir"))
+        .output()?;
+    assert!(output.status.success());
+    fs::write(&plan, &output.stdout)?;
+
+    // Planning never touches the files
+    assert!(dir.path().join("a-dir/c-file.txt").exists());
+    assert!(dir.path().join("a-dir/d-file.txt").exists());
+
+    // A file that changed after planning must be left alone
+    fs::write(dir.path().join("a-dir/d-file.txt"), "changed")?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg("apply").arg(&plan);
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Applied 1 of 2 planned actions"))
+        .stderr(predicate::str::contains("has changed since the plan was made"));
+
+    assert!(dir.path().join("a-dir/.dot-file").exists());
+    assert!(!dir.path().join("a-dir/c-file.txt").exists());
+    assert!(dir.path().join("a-dir/d-file.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn apply_refuses_only_copies() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = copy_example("test")?;
+    let plan = dir.path().join("plan.json");
+
+    let output = Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg("--delete")
+        .arg(dir.path().join("a-dir"))
+        .output()?;
+    assert!(output.status.success());
+
+    // One action edited to keep the very file it deletes, the other to keep a file that's gone
+    let mut edited: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let actions = edited["actions"].as_array_mut().unwrap();
+    actions[0]["target"] = actions[0]["source"].clone();
+    actions[1]["target"] = dir.path().join("a-dir/gone.txt").to_str().unwrap().into();
+    fs::write(&plan, serde_json::to_vec(&edited)?)?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg("apply").arg(&plan);
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Applied 0 of 2 planned actions"))
+        .stderr(predicate::str::contains("its only copy"))
+        .stderr(predicate::str::contains("no longer exists"));
+
+    assert!(dir.path().join("a-dir/c-file.txt").exists());
+    assert!(dir.path().join("a-dir/d-file.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn double_checked_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("./example_files/test").arg("-r").arg("--double-check");
+
+    rupes.assert().success().stdout(predicate::str::contains(
+        "\n./example_files/test/.dot-dir/file-in-dot-dir.txt\n./example_files/test/a-file.txt\n./example_files/test/b-file.specialTXT\n\n./example_files/test/a-dir/.dot-file\n./example_files/test/a-dir/c-file.txt\n./example_files/test/a-dir/d-file.txt\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn xxh3_scan() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rupes")?
+        .arg("./example_files/test")
+        .args(["-r", "--algorithm", "xxh3"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\n./example_files/test/a-dir/.dot-file\n./example_files/test/a-dir/c-file.txt\n./example_files/test/a-dir/d-file.txt\n",
+        ));
+
+    // The same hash as xxhsum -H3
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("abc"), "abc")?;
+    Command::cargo_bin("rupes")?
+        .args(["hash", "--hash", "xxh3"])
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"hash\":\"78af5f94892f3950\""));
+
+    Ok(())
+}
+
+#[test]
+fn crc32c_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("./example_files/test").arg("-r").arg("--hash").arg("crc32c");
+
+    rupes.assert().success().stdout(predicate::str::contains(
+        "\n./example_files/test/a-dir/.dot-file\n./example_files/test/a-dir/c-file.txt\n./example_files/test/a-dir/d-file.txt\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn quick_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("./example_files/test").arg("-r").arg("--quick");
+
+    rupes
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("probable rather than confirmed"))
+        .stdout(predicate::str::contains(
+            "\n./example_files/test/a-dir/.dot-file\n./example_files/test/a-dir/c-file.txt\n./example_files/test/a-dir/d-file.txt\n",
+        ));
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("./example_files/test").arg("--quick").arg("--delete");
+    rupes.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn chunks_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("./example_files/test").arg("-r").arg("--chunks");
+
+    // Exact duplicates aren't repeated as similar files
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Similar files (experimental, found with content-defined chunking)\n\n",
+        ))
+        .stdout(predicate::str::contains("% similar").not());
+
+    Ok(())
+}
+
+#[test]
+fn top_files_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("./example_files/test").arg("-r").arg("--top-files").arg("1");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Largest files with duplicates\n     9.69 kB     3 copies  ./example_files/test/a-dir/.dot-file\n",
+        ))
+        .stdout(predicate::str::contains("copies  ./example_files/test/.dot-dir").not());
+
+    Ok(())
+}
+
+#[test]
+fn stats_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("./example_files/test").arg("-r").arg("--stats");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "2 duplicate groups, 4 redundant files, 19.42 kB wasted",
+        ))
+        .stdout(predicate::str::contains(
+            "       < 1 MB        2 groups     19.42 kB ##############################\n    1 - 10 MB        0 groups          0 B\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn bench_command() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg("bench").arg("./example_files/test");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Discovery: 8 files"))
+        .stdout(predicate::str::contains("Hashing throughput on one thread"))
+        .stdout(predicate::str::contains("blake3"))
+        .stdout(predicate::str::contains("Scaling with thread count"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn report_hardlinks() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "duplicate content")?;
+    fs::hard_link(dir.path().join("a"), dir.path().join("a-link"))?;
+    fs::write(dir.path().join("b"), "duplicate content")?;
+    fs::write(dir.path().join("c"), "only hardlinked")?;
+    fs::hard_link(dir.path().join("c"), dir.path().join("c-link"))?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-S")
+        .arg("--report-hardlinks")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+
+    let (duplicates, hardlinks) = stdout
+        .split_once("Already hardlinked, no space to reclaim")
+        .unwrap();
+    assert!(duplicates.contains(&format!(
+        "{}\n{}\n{}\n",
+        dir.path().join("a").display(),
+        dir.path().join("a-link").display(),
+        dir.path().join("b").display()
+    )));
+    assert!(!duplicates.contains("c-link"));
+    assert!(hardlinks.contains(&format!(
+        "{}\n{}\n",
+        dir.path().join("a").display(),
+        dir.path().join("a-link").display()
+    )));
+    assert!(hardlinks.contains(&format!(
+        "{}\n{}\n",
+        dir.path().join("c").display(),
+        dir.path().join("c-link").display()
+    )));
+    // Only b is a wasted copy, a-link shares its space with a
+    assert!(hardlinks.contains("17 B total wasted space"));
+
+    Ok(())
+}
+
+#[test]
+fn truncated_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes
+        .arg("./example_files/test")
+        .arg("-r")
+        .arg("--max-files")
+        .arg("2");
+
+    rupes
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Truncated: discovery stopped"));
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes
+        .arg("./example_files/test")
+        .arg("-r")
+        .arg("--max-bytes")
+        .arg("1MiB");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Truncated").not());
+
+    Ok(())
+}
+
+#[test]
+fn multiple_roots() -> Result<(), Box<dyn std::error::Error>> {
+    let first = TempDir::new()?;
+    let second = TempDir::new()?;
+    fs::write(first.path().join("original"), "shared between roots")?;
+    fs::write(second.path().join("copy"), "shared between roots")?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes.arg(first.path()).arg(second.path()).arg(first.path());
+
+    let output = rupes.output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+
+    // The first root given twice is only scanned once, so the group has just the two copies
+    let group: Vec<&str> = stdout.trim().lines().collect();
+    assert_eq!(group.len(), 2);
+    assert!(group.contains(&first.path().join("original").to_str().unwrap()));
+    assert!(group.contains(&second.path().join("copy").to_str().unwrap()));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn remote_scan() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let local = TempDir::new()?;
+    let remote = TempDir::new()?;
+    fs::write(local.path().join("laptop.txt"), "backed up to the server")?;
+    fs::write(remote.path().join("server.txt"), "backed up to the server")?;
+    fs::write(remote.path().join("other.txt"), "only on the server")?;
+
+    // Stands in for ssh by running the remote command locally
+    let ssh = local.path().join("fake-ssh");
+    fs::write(&ssh, "#!/bin/sh\nshift\nexec sh -c \"$1\"\n")?;
+    fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755))?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes
+        .env("RUPES_SSH", &ssh)
+        .arg(local.path())
+        .arg("--remote")
+        .arg(format!("me@server:{}", remote.path().display()))
+        .arg("--remote-command")
+        .arg(assert_cmd::cargo::cargo_bin("rupes"));
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            local.path().join("laptop.txt").display().to_string(),
+        ))
+        .stdout(predicate::str::contains(format!(
+            "me@server:{}",
+            remote.path().join("server.txt").display()
+        )))
+        .stdout(predicate::str::contains("other.txt").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn s3_source() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let local = TempDir::new()?;
+    let aws = TempDir::new()?;
+    fs::write(local.path().join("photo.jpg"), "abc")?;
+    fs::write(local.path().join("notes.txt"), "some notes")?;
+
+    // Lists one copy of photo.jpg, one object of a different size, one of the same size that differs, and two copies
+    // uploaded in parts with the same ETag, the second of which is never downloaded
+    let script = aws.path().join("aws");
+    fs::write(
+        &script,
+        r#"#!/bin/sh
+case "$1 $2" in
+    "s3api list-objects-v2")
+        echo '{"Contents": [
+            {"Key": "photos/photo.jpg", "Size": 3, "ETag": "\"900150983cd24fb0d6963f7d28e17f72\""},
+            {"Key": "photos/big.jpg", "Size": 1000, "ETag": "\"0123456789abcdef0123456789abcdef\""},
+            {"Key": "photos/other.jpg", "Size": 3, "ETag": "\"0123456789abcdef0123456789abcdef-2\""},
+            {"Key": "photos/parts.jpg", "Size": 3, "ETag": "\"fedcba9876543210fedcba9876543210-2\""},
+            {"Key": "photos/parts-copy.jpg", "Size": 3, "ETag": "\"fedcba9876543210fedcba9876543210-2\""}
+        ]}' ;;
+    "s3 cp")
+        case "$4" in
+            */photo.jpg) printf abc ;;
+            */other.jpg) printf xyz ;;
+            */parts-copy.jpg) printf xyy ;;
+            *) exit 1 ;;
+        esac ;;
+    *) exit 1 ;;
+esac
+"#,
+    )?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+
+    for hash in ["md5", "sha256"] {
+        let mut rupes = Command::cargo_bin("rupes")?;
+
+        rupes
+            .env("RUPES_AWS", &script)
+            .arg(local.path())
+            .arg("s3://backup/photos")
+            .arg("--hash")
+            .arg(hash);
+
+        rupes
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!(
+                "{}\ns3://backup/photos/photo.jpg\n",
+                local.path().join("photo.jpg").display()
+            )))
+            .stdout(predicate::str::contains(
+                "s3://backup/photos/parts-copy.jpg\ns3://backup/photos/parts.jpg\n",
+            ))
+            .stdout(predicate::str::contains("big.jpg").not())
+            .stdout(predicate::str::contains("other.jpg").not());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn manifest_merge() -> Result<(), Box<dyn std::error::Error>> {
+    let elsewhere = TempDir::new()?;
+    let local = TempDir::new()?;
+    fs::write(elsewhere.path().join("copy.txt"), "hashed on another machine")?;
+    fs::write(local.path().join("original.txt"), "hashed on another machine")?;
+    fs::write(local.path().join("abc.txt"), "abc")?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg("hash")
+        .arg(elsewhere.path())
+        .output()?;
+    assert!(output.status.success());
+
+    let manifests = TempDir::new()?;
+    fs::write(manifests.path().join("hashed.jsonl"), &output.stdout)?;
+    fs::write(
+        manifests.path().join("listing.csv"),
+        "path,size,hash\n/backup/abc.txt,3,ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n",
+    )?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes
+        .arg(local.path())
+        .arg("--manifest")
+        .arg(manifests.path().join("hashed.jsonl"))
+        .arg("--manifest")
+        .arg(manifests.path().join("listing.csv"));
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "hashed.jsonl:{}",
+            elsewhere.path().join("copy.txt").display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}\nlisting.csv:/backup/abc.txt\n",
+            local.path().join("abc.txt").display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn jdupes_json_output() -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin("rupes")?
+        .arg("./example_files/test/a-dir")
+        .arg("--output")
+        .arg("jdupes-json")
+        .arg("-d")
+        .output()?;
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let match_sets = json["matchSets"].as_array().unwrap();
+    assert_eq!(match_sets.len(), 1);
+    assert_eq!(match_sets[0]["fileSize"], 9690);
+    assert_eq!(match_sets[0]["fileList"].as_array().unwrap().len(), 3);
+    assert_eq!(
+        match_sets[0]["fileList"][0]["filePath"],
+        "./example_files/test/a-dir/.dot-file"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn multiple_filters() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for (name, content) in [
+        ("a.txt", "text"),
+        ("b.txt", "text"),
+        ("a.md", "markdown"),
+        ("b.md", "markdown"),
+        ("a.log", "log"),
+        ("b.log", "log"),
+    ] {
+        fs::write(dir.path().join(name), content)?;
+    }
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes
+        .arg(dir.path())
+        .arg("-f")
+        .arg("[.]txt$")
+        .arg("--filter")
+        .arg("[.]md$");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt"))
+        .stdout(predicate::str::contains("a.md"))
+        .stdout(predicate::str::contains("a.log").not());
+
+    Ok(())
+}
+
+#[test]
+fn exclude_patterns() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("cache"))?;
+    fs::create_dir_all(dir.path().join("src"))?;
+    for name in ["src/a.txt", "src/b.txt", "src/a.tmp", "cache/a.txt", "a.bak"] {
+        fs::write(dir.path().join(name), "same")?;
+    }
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+
+    rupes
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--exclude")
+        .arg("[.]tmp$")
+        .arg("-x")
+        .arg("[.]bak$")
+        .arg("--exclude-dir")
+        .arg("^cache$");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt"))
+        .stdout(predicate::str::contains("b.txt"))
+        .stdout(predicate::str::contains("a.tmp").not())
+        .stdout(predicate::str::contains("a.bak").not())
+        .stdout(predicate::str::contains("cache").not());
+
+    Ok(())
+}
+
+#[test]
+fn group_size_bounds() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for (name, content) in [
+        ("small-1", "abc"),
+        ("small-2", "abc"),
+        ("large-1", "0123456789"),
+        ("large-2", "0123456789"),
+        ("large-3", "0123456789"),
+    ] {
+        fs::write(dir.path().join(name), content)?;
+    }
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("--min-group-size").arg("10");
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("large-1"))
+        .stdout(predicate::str::contains("small-1").not());
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("--max-group-size").arg("29B");
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("small-1"))
+        .stdout(predicate::str::contains("large-1").not());
+
+    Ok(())
+}
+
+#[test]
+fn across_dirs_only() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("a"))?;
+    fs::create_dir_all(dir.path().join("b"))?;
+    for (name, content) in [
+        ("a/file", "side by side"),
+        ("a/file.bak", "side by side"),
+        ("a/photo", "scattered"),
+        ("b/photo", "scattered"),
+    ] {
+        fs::write(dir.path().join(name), content)?;
+    }
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("-r").arg("--across-dirs-only");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("photo"))
+        .stdout(predicate::str::contains("file.bak").not());
+
+    Ok(())
+}
+
+#[test]
+fn same_dir_only() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("a"))?;
+    fs::create_dir_all(dir.path().join("b"))?;
+    for (name, content) in [
+        ("a/IMG_001.jpg", "photo"),
+        ("a/IMG_001 (1).jpg", "photo"),
+        ("b/IMG_001.jpg", "photo"),
+        ("a/notes", "scattered"),
+        ("b/notes", "scattered"),
+    ] {
+        fs::write(dir.path().join(name), content)?;
+    }
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("-r").arg("--same-dir-only");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{}\n{}\n",
+            dir.path().join("a/IMG_001 (1).jpg").display(),
+            dir.path().join("a/IMG_001.jpg").display()
+        )))
+        .stdout(predicate::str::contains("b/IMG_001.jpg").not())
+        .stdout(predicate::str::contains("notes").not());
+
+    Ok(())
+}
+
+#[test]
+fn dir_pairs_report() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("backup"))?;
+    fs::create_dir_all(dir.path().join("photos"))?;
+    for name in ["a", "b"] {
+        fs::write(dir.path().join("backup").join(name), name.repeat(500))?;
+        fs::write(dir.path().join("photos").join(name), name.repeat(500))?;
+    }
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("-r").arg("--dir-pairs");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Directories sharing duplicates\n{} and {} share 2 files / 1.00 kB\n",
+            dir.path().join("backup").display(),
+            dir.path().join("photos").display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn by_dir_report() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("copies"))?;
+    fs::write(dir.path().join("a-original"), "x".repeat(2000))?;
+    fs::write(dir.path().join("copies/copy"), "x".repeat(2000))?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("-r").arg("--by-dir");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Wasted space by directory\n     2.00 kB       1 files  {}\n     2.00 kB       1 files  {}\n",
+            dir.path().display(),
+            dir.path().join("copies").display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn find_empty_dirs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for directory in ["empty/nested", "copies", "kept"] {
+        fs::create_dir_all(dir.path().join(directory))?;
+    }
+    fs::write(dir.path().join("a-original"), "same")?;
+    fs::write(dir.path().join("copies/copy"), "same")?;
+    fs::write(dir.path().join("kept/unique"), "unique")?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("-r").arg("--find-empty-dirs");
+
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Empty directories, or holding nothing but redundant copies\n{}\n{}\n\n",
+            dir.path().join("copies").display(),
+            dir.path().join("empty").display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn confirm_over() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same content")?;
+    fs::write(dir.path().join("b"), "same content")?;
+
+    let mut rupes = assert_cmd::Command::cargo_bin("rupes")?;
+    rupes
+        .arg(dir.path())
+        .arg("--confirm-over")
+        .arg("10")
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("About to hash 2 files, 24 B"))
+        .stderr(predicate::str::contains("Cancelled"))
+        .stdout(predicate::str::contains("/a").not());
+
+    let mut rupes = assert_cmd::Command::cargo_bin("rupes")?;
+    rupes
+        .arg(dir.path())
+        .arg("--confirm-over")
+        .arg("10")
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{}\n{}\n",
+            dir.path().join("a").display(),
+            dir.path().join("b").display()
+        )));
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("--confirm-over").arg("1K");
+    rupes
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("About to hash").not());
+
+    Ok(())
+}
+
+#[test]
+fn format_template() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes
+        .arg(dir.path())
+        .arg("--hash")
+        .arg("md5")
+        .arg("--format-template")
+        .arg(r"{group}\t{hash}\t{size}\t{waste}\t{path}");
+
+    rupes.assert().success().stdout(format!(
+        "1\t900150983cd24fb0d6963f7d28e17f72\t3\t3\t{}\n1\t900150983cd24fb0d6963f7d28e17f72\t3\t3\t{}\n",
+        dir.path().join("a").display(),
+        dir.path().join("b").display()
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn show_hash() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("-H").arg("md5").arg("--show-hash");
+    rupes
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "md5:900150983cd24fb0d6963f7d28e17f72\n{}\n",
+            dir.path().join("a").display()
+        )));
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-H")
+        .arg("md5")
+        .arg("--show-hash")
+        .arg("--output")
+        .arg("jdupes-json")
+        .output()?;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        json["matchSets"][0]["hash"],
+        "md5:900150983cd24fb0d6963f7d28e17f72"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn long_listing() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes.arg(dir.path()).arg("--long");
+    rupes.assert().success().stdout(predicate::str::is_match(format!(
+        r"\n +3 B  \d{{4}}-\d{{2}}-\d{{2}} \d{{2}}:\d{{2}}  \S+ +{}\n",
+        regex::escape(&dir.path().join("a").display().to_string())
+    ))?);
+
+    Ok(())
+}
+
+#[test]
+fn deterministic_order() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for index in 0..20 {
+        let content = format!("{:04}", index);
+        fs::write(dir.path().join(format!("{}-a", index)), &content)?;
+        fs::write(dir.path().join(format!("{}-b", index)), &content)?;
+    }
+
+    let run = || -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(Command::cargo_bin("rupes")?
+            .arg(dir.path())
+            .arg("-H")
+            .arg("md5")
+            .arg("--format-template")
+            .arg("{hash}")
+            .output()?
+            .stdout)
+    };
+
+    let first = run()?;
+    assert_eq!(first, run()?);
+
+    let hashes: Vec<&[u8]> = first
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .collect();
+    let mut sorted = hashes.clone();
+    sorted.sort();
+    assert_eq!(hashes, sorted);
+
+    Ok(())
+}
+
+#[test]
+fn stable_group_ids() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+
+    let report = Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--output")
+        .arg("jdupes-json")
+        .output()?;
+    let report: serde_json::Value = serde_json::from_slice(&report.stdout)?;
+    let id = report["matchSets"][0]["groupId"].clone();
+    assert!(id.is_string());
+
+    fs::write(dir.path().join("c"), "abc")?;
+    let plan = Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg(dir.path())
+        .arg("--delete")
+        .output()?;
+    let plan: serde_json::Value = serde_json::from_slice(&plan.stdout)?;
+    let actions = plan["actions"].as_array().unwrap();
+    assert_eq!(actions.len(), 2);
+    assert!(actions.iter().all(|action| action["group"] == id));
+
+    Ok(())
+}
+
+#[test]
+fn config_layers() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+    fs::write(dir.path().join("c"), "abcdef")?;
+    fs::write(dir.path().join("d"), "abcdef")?;
+
+    let config = dir.path().join("config.toml");
+    fs::write(&config, "min = 1\n\n[profile.big]\nmin = 5\n")?;
+
+    Command::cargo_bin("rupes")?
+        .env("RUPES_CONFIG", &config)
+        .arg(dir.path())
+        .arg("--profile")
+        .arg("big")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/c"))
+        .stdout(predicate::str::contains("/a").not());
+
+    Command::cargo_bin("rupes")?
+        .env("RUPES_CONFIG", &config)
+        .env("RUPES_MIN", "2")
+        .arg(dir.path())
+        .arg("--profile")
+        .arg("big")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/a"));
+
+    Command::cargo_bin("rupes")?
+        .env("RUPES_CONFIG", &config)
+        .args(["config", "show", "--profile", "big", "--quick"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"min = 5 +# profile big")?)
+        .stdout(predicate::str::is_match(r"quick = true +# command line")?)
+        .stdout(predicate::str::is_match(r"recursive = false +# default")?);
+
+    Command::cargo_bin("rupes")?
+        .env("RUPES_CONFIG", &config)
+        .arg("--profile")
+        .arg("missing")
+        .assert()
+        .failure();
+
+    let starter = dir.path().join("starter.toml");
+    Command::cargo_bin("rupes")?
+        .args(["config", "init"])
+        .arg(&starter)
+        .assert()
+        .success();
+    assert!(fs::read_to_string(&starter)?.contains("# recursive = false"));
+
+    Ok(())
+}
+
+#[test]
+fn man_page() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin("rupes")?
+        .arg("man")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(".ie"))
+        .stdout(predicate::str::contains(".TH rupes 1"))
+        .stdout(predicate::str::contains(env!("CARGO_PKG_VERSION")))
+        .stdout(predicate::str::contains(r"\-\-double\-check"));
+
+    Ok(())
+}
+
+#[test]
+fn localized_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-S", "--lang", "de"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("verschwendeter Speicherplatz insgesamt"));
+
+    Command::cargo_bin("rupes")?
+        .env("LC_ALL", "")
+        .env("LANG", "fr_FR.UTF-8")
+        .arg(dir.path())
+        .arg("-S")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("d'espace gaspillé au total"));
+
+    // As are the summaries of actions and plans
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--delete", "--dry-run", "--lang", "de"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "Würde {} löschen, eine Kopie von {}",
+            dir.path().join("b").display(),
+            dir.path().join("a").display()
+        )))
+        .stderr(predicate::str::contains("1 Dateien"));
+
+    Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg(dir.path())
+        .args(["--lang", "es"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Indica una acción que planificar"));
+
+    // And the reports of the other commands
+    Command::cargo_bin("rupes")?
+        .env("LC_ALL", "")
+        .env("LANG", "fr_FR.UTF-8")
+        .args(["cache", "stats", "--cache"])
+        .arg(dir.path().join("cache"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Dernière analyse : aucune pour l'instant",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn ascii_output() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-d", "--lang", "fr", "--ascii"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(output.stdout.is_ascii());
+    assert!(String::from_utf8(output.stdout)?.contains("d'espace gaspille au total"));
+
+    Ok(())
+}
+
+#[test]
+fn quiet_and_no_progress() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for name in ["a", "b", "c"] {
+        fs::write(dir.path().join(name), "abc")?;
+    }
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--max-files", "2", "--no-progress"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Truncated"));
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--max-files", "2", "--quiet"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Truncated").not())
+        .stdout(predicate::str::contains(dir.path().to_str().unwrap()));
+
+    let empty = TempDir::new()?;
+    Command::cargo_bin("rupes")?
+        .arg(empty.path())
+        .arg("--quiet")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn results_only_on_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "abc")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--time", "--quick"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Took").not())
+        .stdout(predicate::str::contains("Quick mode").not())
+        .stdout(predicate::str::contains(dir.path().to_str().unwrap()))
+        .stderr(predicate::str::contains("Took"))
+        .stderr(predicate::str::contains("Quick mode"));
+
+    Ok(())
+}
+
+#[test]
+fn any_duplicate() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("b"), "xyz")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--any")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::is_empty());
+
+    fs::write(dir.path().join("c"), "abc")?;
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--any")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("/a\n"))
+        .stdout(predicate::str::contains("/c\n"))
+        .stdout(predicate::str::contains("/b").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--any", "--delete"])
+        .assert()
+        .code(2);
+
+    Ok(())
+}
+
+#[test]
+fn count_only() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for name in ["a", "b", "c"] {
+        fs::write(dir.path().join(name), "abc")?;
+    }
+    fs::write(dir.path().join("d"), "xyz")?;
+    fs::write(dir.path().join("e"), "xyz")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--count", "-S"])
+        .assert()
+        .success()
+        .stdout("2\n");
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--count", "files"])
+        .assert()
+        .success()
+        .stdout("3\n");
+
+    Ok(())
+}
+
+#[test]
+fn ncdu_export() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir(dir.path().join("sub"))?;
+    fs::write(dir.path().join("a"), "abc")?;
+    fs::write(dir.path().join("sub").join("b"), "abc")?;
+    let export = dir.path().join("export.json");
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--export")
+        .arg("ncdu")
+        .arg(&export)
+        .assert()
+        .success();
+
+    let export: serde_json::Value = serde_json::from_str(&fs::read_to_string(&export)?)?;
+    assert_eq!(export[3][1][0]["name"], "sub");
+    assert_eq!(export[3][1][1]["name"], "b");
+    assert_eq!(export[3][1][1]["asize"], 3);
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--export", "du", "out.txt"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn prefer_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for sub in ["downloads", "library", "other"] {
+        fs::create_dir(dir.path().join(sub))?;
+        fs::write(dir.path().join(sub).join("song.mp3"), "abc")?;
+    }
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--prefer-dir")
+        .arg(dir.path().join("library"))
+        .arg("--prefer-dir")
+        .arg(dir.path().join("other"))
+        .arg("--delete")
+        .arg("--no-prompt")
+        .assert()
+        .success();
+
+    assert!(dir.path().join("library/song.mp3").exists());
+    assert!(!dir.path().join("downloads/song.mp3").exists());
+    assert!(!dir.path().join("other/song.mp3").exists());
+
+    Ok(())
+}
+
+#[test]
+fn action_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for sub in ["photos", "backup"] {
+        fs::create_dir(dir.path().join(sub))?;
+        fs::write(dir.path().join(sub).join("a.jpg"), "same content")?;
+        fs::write(dir.path().join(sub).join("b.jpg"), "same content")?;
+    }
+
+    assert_cmd::Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--delete")
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("About to delete 3 files, freeing 36 B"))
+        .stderr(predicate::str::contains("Go ahead? [y/N]"))
+        .stderr(predicate::str::contains("Cancelled, no files were changed"));
+    for sub in ["photos", "backup"] {
+        assert!(dir.path().join(sub).join("a.jpg").exists());
+        assert!(dir.path().join(sub).join("b.jpg").exists());
+    }
+
+    assert_cmd::Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--delete")
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "2 files  {}",
+            dir.path().join("photos").display()
+        )));
+    assert!(!dir.path().join("photos/a.jpg").exists());
+    assert!(!dir.path().join("photos/b.jpg").exists());
+    assert!(dir.path().join("backup/a.jpg").exists());
+    assert!(!dir.path().join("backup/b.jpg").exists());
+
+    Ok(())
+}
+
+#[test]
+fn system_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes
+        .arg("/usr")
+        .arg("--delete")
+        .arg("--no-prompt")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("add --allow-system"));
+
+    // Plans are held to the same checks when they're made and when they're applied
+    Command::cargo_bin("rupes")?
+        .args(["plan", "/usr", "--delete"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("add --allow-system"));
+
+    Ok(())
+}
+
+#[test]
+fn plan_needs_hashes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = copy_example("test")?;
+
+    Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg(dir.path().join("a-dir"))
+        .args(["--no-hash", "--delete"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("Plans can't be made with --no-hash"));
+
+    Ok(())
+}
+
+#[test]
+fn edited_plans_checked() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = copy_example("test")?;
+    let outside = copy_example("test")?;
+    let plan = dir.path().join("plan.json");
+
+    let output = Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg("--delete")
+        .arg(dir.path().join("a-dir"))
+        .output()?;
+    assert!(output.status.success());
+    let planned: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+
+    let mut edited = planned.clone();
+    edited["actions"][0]["source"] = outside
+        .path()
+        .join("a-dir/c-file.txt")
+        .to_str()
+        .unwrap()
+        .into();
+    fs::write(&plan, serde_json::to_vec(&edited)?)?;
+    let log = dir.path().join("actions.log");
+    Command::cargo_bin("rupes")?
+        .arg("apply")
+        .arg(&plan)
+        .arg("--log")
+        .arg(&log)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Applied 1 of 2 planned actions"))
+        .stderr(predicate::str::contains("outside the scanned directories"));
+    assert!(outside.path().join("a-dir/c-file.txt").exists());
+    let logged = fs::read_to_string(&log)?;
+    assert_eq!(logged.lines().count(), 2);
+    assert!(logged.contains("outside the scanned directories"));
+
+    let mut edited = planned;
+    edited["directories"] = serde_json::json!(["/"]);
+    fs::write(&plan, serde_json::to_vec(&edited)?)?;
+    Command::cargo_bin("rupes")?
+        .arg("apply")
+        .arg(&plan)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("add --allow-system"));
+    assert!(dir.path().join("a-dir/c-file.txt").exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn actions_stay_within_roots() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let outside = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same content")?;
+    fs::write(outside.path().join("b"), "same content")?;
+    std::os::unix::fs::symlink(outside.path(), dir.path().join("link"))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--follow-symlinks")
+        .arg("--delete")
+        .arg("--no-prompt")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("outside the scanned directories"));
+
+    assert!(dir.path().join("a").exists());
+    assert!(outside.path().join("b").exists());
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn immutable_files() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same content")?;
+    fs::write(dir.path().join("b"), "same content")?;
+
+    // Setting the immutable flag needs root and a filesystem that has it
+    let chattr = |flag: &str| {
+        std::process::Command::new("chattr")
+            .arg(flag)
+            .arg(dir.path().join("b"))
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+    if !chattr("+i") {
+        return Ok(());
+    }
+
+    let deleted = Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--delete")
+        .arg("--no-prompt")
+        .assert();
+    let still_there = dir.path().join("b").exists();
+    chattr("-i");
+    deleted
+        .success()
+        .stderr(predicate::str::contains("it's read-only or protected"));
+    assert!(still_there);
+
+    chattr("+i");
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--delete")
+        .arg("--no-prompt")
+        .arg("--force-readonly")
+        .assert()
+        .success();
+    assert!(!dir.path().join("b").exists());
+    assert!(dir.path().join("a").exists());
+
+    Ok(())
+}
+
+#[test]
+fn storage_override() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes
+        .arg("./example_files/test")
+        .arg("-r")
+        .arg("--storage")
+        .arg("./example_files=hdd")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("c-file.txt"));
+
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes
+        .arg("./example_files/test")
+        .arg("--storage")
+        .arg("./example_files=tape")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected hdd or ssd"));
+
+    Ok(())
+}
+
+#[test]
+fn io_threads() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes
+        .arg("./example_files/test")
+        .arg("-r")
+        .arg("--io-threads")
+        .arg("1")
+        .arg("--threads")
+        .arg("4")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("c-file.txt"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loop_walked_once() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same content")?;
+    fs::write(dir.path().join("b"), "same content")?;
+    std::os::unix::fs::symlink(dir.path(), dir.path().join("loop"))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--follow-symlinks")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("loop").not())
+        .stderr(predicate::str::contains(format!(
+            "Scanned {} once",
+            dir.path().join("loop").display()
+        )));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn syslog_keeps_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut rupes = Command::cargo_bin("rupes")?;
+    rupes
+        .arg("./example_files/test")
+        .arg("-r")
+        .arg("--syslog")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("c-file.txt"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn service_install() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let unit = dir.path().join("rupes.service");
+
+    Command::cargo_bin("rupes")?
+        .args(["service", "install", "--every", "600", "--path"])
+        .arg(&unit)
+        .args(["/srv/files", "-r"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wrote a service"));
+    let written = fs::read_to_string(&unit)?;
+    assert!(written.contains(r#""service" "run" "--every" "600" "/srv/files" "-r""#));
+
+    Command::cargo_bin("rupes")?
+        .args(["service", "install", "--path"])
+        .arg(dir.path().join("other.service"))
+        .arg("--no-such-option")
+        .assert()
+        .failure();
+    assert!(!dir.path().join("other.service").exists());
+
+    Ok(())
+}
+
+#[test]
+fn skip_caches() -> Result<(), Box<dyn std::error::Error>> {
+    let home = TempDir::new()?;
+    fs::create_dir_all(home.path().join(".cache/thumbnails"))?;
+    fs::create_dir_all(home.path().join("profile/cache2"))?;
+    fs::create_dir(home.path().join("photos"))?;
+    fs::write(home.path().join("photos/a.jpg"), "same content")?;
+    fs::write(home.path().join("photos/b.jpg"), "same content")?;
+    fs::write(home.path().join(".cache/thumbnails/c.jpg"), "same content")?;
+    fs::write(home.path().join("profile/cache2/d.jpg"), "same content")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(home.path())
+        .arg("-r")
+        .arg("--skip-caches")
+        .env("HOME", home.path())
+        .env_remove("XDG_CACHE_HOME")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.jpg"))
+        .stdout(predicate::str::contains("c.jpg").not())
+        .stdout(predicate::str::contains("d.jpg").not());
+
+    Ok(())
+}
+
+#[test]
+fn cachedir_tag() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir(dir.path().join("build"))?;
+    fs::create_dir(dir.path().join("notes"))?;
+    fs::write(dir.path().join("a"), "same content")?;
+    fs::write(dir.path().join("build/b"), "same content")?;
+    fs::write(dir.path().join("notes/c"), "same content")?;
+    fs::write(
+        dir.path().join("build/CACHEDIR.TAG"),
+        "Signature: 8a477f597d28d172789f06886806bc55\n# This file is a cache directory tag.\n",
+    )?;
+    // Not a tag, the signature is missing
+    fs::write(dir.path().join("notes/CACHEDIR.TAG"), "remember the milk")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("notes"))
+        .stdout(predicate::str::contains("build").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--no-cachedir-tag")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("build"));
+
+    Ok(())
+}
+
+#[test]
+fn skip_marker() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir(dir.path().join("mine"))?;
+    fs::create_dir(dir.path().join("common"))?;
+    fs::write(dir.path().join("mine/.nodedupe"), "")?;
+    fs::write(dir.path().join("mine/a"), "same content")?;
+    fs::write(dir.path().join("common/b"), "same content")?;
+    fs::write(dir.path().join("common/c"), "same content")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .arg("--skip-marker")
+        .arg(".nodedupe")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("common"))
+        .stdout(predicate::str::contains("mine").not());
+
+    // A marked root is still scanned, but nothing in it is acted on
+    Command::cargo_bin("rupes")?
+        .arg(dir.path().join("common"))
+        .arg(dir.path().join("mine"))
+        .arg("-r")
+        .arg("--skip-marker")
+        .arg(".nodedupe")
+        .arg("--delete")
+        .arg("--no-prompt")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "a skip marker opts its directory out",
+        ));
+    assert!(dir.path().join("mine/a").exists());
+
+    Ok(())
+}
+
+#[test]
+fn directory_config() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir(dir.path().join("logs"))?;
+    fs::create_dir(dir.path().join("photos"))?;
+    fs::write(dir.path().join("logs/.rupes.toml"), "exclude = '\\.log$'\n")?;
+    fs::write(dir.path().join("logs/a.log"), "same content")?;
+    fs::write(dir.path().join("logs/b.log"), "same content")?;
+    fs::write(dir.path().join("logs/c.txt"), "other content")?;
+    fs::write(dir.path().join("photos/c.txt"), "other content")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("c.txt"))
+        .stdout(predicate::str::contains("a.log").not());
+
+    fs::write(dir.path().join("photos/.rupes.toml"), "protect = true\n")?;
+    Command::cargo_bin("rupes")?
+        .arg(dir.path().join("photos"))
+        .arg(dir.path().join("logs"))
+        .arg("-r")
+        .arg("--delete")
+        .arg("--no-prompt")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "a .rupes.toml protects its directory",
+        ));
+    assert!(dir.path().join("photos/c.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn cache_export_import() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let nas = dir.path().join("nas");
+    let mount = dir.path().join("mount");
+    fs::create_dir(&nas)?;
+    fs::create_dir(&mount)?;
+    fs::write(nas.join("a"), "aaaa")?;
+    fs::write(nas.join("b"), "bbbb")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(&nas)
+        .arg("--cache")
+        .arg(dir.path().join("nas.cache"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("aaaa").not());
+
+    let exported = dir.path().join("exported");
+    Command::cargo_bin("rupes")?
+        .args(["cache", "export"])
+        .arg(&exported)
+        .arg("--under")
+        .arg(&nas)
+        .arg("--cache")
+        .arg(dir.path().join("nas.cache"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hashes of 2 files"));
+
+    // Claiming both files hash the same shows the imported hashes are used rather than the files read
+    let content = fs::read_to_string(&exported)?;
+    let digest = |name: &str| {
+        let line = content
+            .lines()
+            .find(|line| line.ends_with(&format!("\t{}", name)))
+            .unwrap();
+        line.split('\t').nth(3).unwrap().to_string()
+    };
+    fs::write(&exported, content.replace(&digest("b"), &digest("a")))?;
+
+    for name in ["a", "b"] {
+        fs::copy(nas.join(name), mount.join(name))?;
+        let modified = fs::metadata(nas.join(name))?.modified()?;
+        fs::File::options()
+            .write(true)
+            .open(mount.join(name))?
+            .set_modified(modified)?;
+    }
+
+    Command::cargo_bin("rupes")?
+        .args(["cache", "import"])
+        .arg(&exported)
+        .arg("--under")
+        .arg(&mount)
+        .arg("--cache")
+        .arg(dir.path().join("mount.cache"))
+        .assert()
+        .success();
+
+    Command::cargo_bin("rupes")?
+        .arg(&mount)
+        .arg("--cache")
+        .arg(dir.path().join("mount.cache"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("mount/a"))
+        .stdout(predicate::str::contains("mount/b"));
+
+    Ok(())
+}
+
+#[test]
+fn cache_maintenance() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let cache = dir.path().join("hashes");
+    let files = dir.path().join("files");
+    fs::create_dir(&files)?;
+    fs::write(files.join("a"), "same content")?;
+    fs::write(files.join("b"), "same content")?;
+
+    for _ in 0..2 {
+        Command::cargo_bin("rupes")?
+            .arg(&files)
+            .arg("--cache")
+            .arg(&cache)
+            .assert()
+            .success();
+    }
+
+    Command::cargo_bin("rupes")?
+        .args(["cache", "stats", "--cache"])
+        .arg(&cache)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hashes: 2"))
+        .stdout(predicate::str::contains(
+            "Last scan: 2 of 2 hashes from the cache (100%)",
+        ));
+
+    fs::remove_file(files.join("b"))?;
+    Command::cargo_bin("rupes")?
+        .args(["cache", "prune", "--cache"])
+        .arg(&cache)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Dropped 1 stale hashes"));
+
+    Command::cargo_bin("rupes")?
+        .args(["cache", "stats", "--cache"])
+        .arg(&cache)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hashes: 1"));
+
+    Ok(())
+}
+
+#[test]
+fn cache_use_reported() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let cache = dir.path().join("hashes");
+    let files = dir.path().join("files");
+    fs::create_dir(&files)?;
+    fs::write(files.join("a"), "same content")?;
+    fs::write(files.join("b"), "same content")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--cache")
+        .arg(&cache)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Hash cache: 0 hashes reused, 2 computed, 0 B not read",
+        ));
+
+    fs::write(files.join("c"), "same content")?;
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--cache")
+        .arg(&cache)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Hash cache: 2 hashes reused, 1 computed, 24 B not read",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn covered_by() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let local = dir.path().join("local");
+    let backup = dir.path().join("backup");
+    fs::create_dir(&local)?;
+    fs::create_dir_all(backup.join("2024"))?;
+    fs::write(local.join("saved"), "backed up content")?;
+    fs::write(local.join("new"), "fresh content")?;
+    fs::write(backup.join("2024/renamed"), "backed up content")?;
+
+    let expected = || {
+        predicate::str::contains("Backed up, safe to delete locally: 1 files")
+            .and(predicate::str::is_match("locally: [^\n]*\n[^\n]*saved\n").unwrap())
+            .and(predicate::str::is_match("Not backed up yet: 1 files[^\n]*\n[^\n]*new\n").unwrap())
+    };
+
+    Command::cargo_bin("rupes")?
+        .arg(&local)
+        .arg("--covered-by")
+        .arg(&backup)
+        .assert()
+        .success()
+        .stdout(expected());
+
+    let hashes = Command::cargo_bin("rupes")?
+        .arg("hash")
+        .arg(&backup)
+        .arg("-r")
+        .output()?
+        .stdout;
+    fs::write(dir.path().join("backup.jsonl"), hashes)?;
+
+    Command::cargo_bin("rupes")?
+        .arg(&local)
+        .arg("--covered-by")
+        .arg(dir.path().join("backup.jsonl"))
+        .assert()
+        .success()
+        .stdout(expected());
+
+    Ok(())
+}
+
+#[test]
+fn unique() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("copy1"), "same content")?;
+    fs::write(dir.path().join("copy2"), "same content")?;
+    fs::write(dir.path().join("only"), "other content")?;
+    fs::write(dir.path().join("linked"), "linked content")?;
+    fs::hard_link(dir.path().join("linked"), dir.path().join("link"))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--unique")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("only"))
+        .stdout(predicate::str::contains("linked"))
+        .stdout(predicate::str::contains("copy").not());
+
+    Ok(())
+}
+
+#[test]
+fn missing() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let old = dir.path().join("old");
+    let new = dir.path().join("new");
+    fs::create_dir_all(old.join("photos"))?;
+    fs::create_dir_all(new.join("sorted/2019"))?;
+    fs::write(old.join("photos/IMG_0001.jpg"), "copied")?;
+    fs::write(old.join("photos/IMG_0002.jpg"), "forgotten")?;
+    fs::write(new.join("sorted/2019/beach.jpg"), "copied")?;
+
+    Command::cargo_bin("rupes")?
+        .arg("missing")
+        .arg(&old)
+        .arg(&new)
+        .arg("-r")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("IMG_0002.jpg"))
+        .stdout(predicate::str::contains("IMG_0001.jpg").not());
+
+    Command::cargo_bin("rupes")?
+        .arg("missing")
+        .arg(&old)
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn verify_backup() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let source = dir.path().join("source");
+    let backup = dir.path().join("backup");
+    fs::create_dir_all(source.join("deep/down"))?;
+    fs::create_dir(&backup)?;
+    fs::write(source.join("deep/down/a"), "kept")?;
+    fs::write(backup.join("a copy"), "kept")?;
+
+    Command::cargo_bin("rupes")?
+        .arg("verify-backup")
+        .arg(&source)
+        .arg(&backup)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("every one of the 1 files"));
+
+    fs::write(source.join("deep/b"), "not kept")?;
+    Command::cargo_bin("rupes")?
+        .arg("verify-backup")
+        .arg(&source)
+        .arg(&backup)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("deep/b"))
+        .stderr(predicate::str::contains("1 of the 2 files"));
+
+    Ok(())
+}
+
+#[test]
+fn xattr_cache() -> Result<(), Box<dyn std::error::Error>> {
+    use rupes::{xattr, HashAlgorithm};
+
+    let dir = TempDir::new()?;
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    fs::write(&a, "aaaa")?;
+    fs::write(&b, "bbbb")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--xattr-cache")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/a").not());
+
+    // Not every filesystem tests run on has user attributes
+    let Some(digest) = xattr::read(&a, HashAlgorithm::Sha256, &fs::metadata(&a)?) else {
+        return Ok(());
+    };
+
+    // Claiming b hashes like a shows the attribute is trusted rather than the file read
+    xattr::write(&b, HashAlgorithm::Sha256, &fs::metadata(&b)?, &digest)?;
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--xattr-cache")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/a"))
+        .stdout(predicate::str::contains("/b"));
+
+    // Files changed since are hashed again
+    fs::write(&b, "cccc")?;
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--xattr-cache")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/a").not());
+
+    Ok(())
+}
+
+#[test]
+fn normalize_text() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("unix.txt"), "one\ntwo \n")?;
+    fs::write(dir.path().join("windows.txt"), "\u{feff}one\r\ntwo\r\n")?;
+    fs::write(dir.path().join("binary"), b"one\0two\r\n")?;
+    fs::write(dir.path().join("binary copy"), b"one\0two\n")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("unix.txt").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--normalize-text")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("unix.txt").not());
+
+    // Binary files are still compared byte for byte
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args([
+            "--normalize-text",
+            "--ignore-trailing-whitespace",
+            "--double-check",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("unix.txt"))
+        .stdout(predicate::str::contains("windows.txt"))
+        .stdout(predicate::str::contains("binary").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--normalize-text", "--delete"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn video() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new()?;
+    let tools = TempDir::new()?;
+    // The fake tools take each video's duration from its content, d.avi has frames unlike the others
+    fs::write(dir.path().join("a.mkv"), "60.0")?;
+    fs::write(dir.path().join("b.mp4"), "61.0")?;
+    fs::write(dir.path().join("c.mp4"), "300.0")?;
+    fs::write(dir.path().join("d.avi"), "60.5")?;
+    fs::write(dir.path().join("notes.txt"), "60.0")?;
+
+    let ffprobe = tools.path().join("ffprobe");
+    fs::write(
+        &ffprobe,
+        r#"#!/bin/sh
+for last; do :; done
+echo "{\"streams\": [{\"width\": 1920, \"height\": 1080}], \"format\": {\"duration\": \"$(cat "$last")\"}}"
+"#,
+    )?;
+    let ffmpeg = tools.path().join("ffmpeg");
+    fs::write(
+        &ffmpeg,
+        r#"#!/bin/sh
+case "$(cat "$6")" in
+    60.5) printf '\377\000%.0s' $(seq 36) ;;
+    *) printf 'a%.0s' $(seq 72) ;;
+esac
+"#,
+    )?;
+    for tool in [&ffprobe, &ffmpeg] {
+        fs::set_permissions(tool, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Command::cargo_bin("rupes")?
+        .env("RUPES_FFPROBE", &ffprobe)
+        .env("RUPES_FFMPEG", &ffmpeg)
+        .arg(dir.path())
+        .arg("--video")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{}\n{}\n^ 0:01:00, 1920x1080\n",
+            dir.path().join("a.mkv").display(),
+            dir.path().join("b.mp4").display()
+        )))
+        .stdout(predicate::str::contains("c.mp4").not())
+        .stdout(predicate::str::contains("d.avi").not());
+
+    Command::cargo_bin("rupes")?
+        .env("RUPES_FFPROBE", &ffprobe)
+        .env("RUPES_FFMPEG", &ffmpeg)
+        .arg(dir.path())
+        .args(["--video", "--video-tolerance", "0.5"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("b.mp4").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn documents() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new()?;
+    let tools = TempDir::new()?;
+    // The fake tools print the text of documents holding "report", scans have none
+    fs::write(dir.path().join("report.pdf"), "report exported")?;
+    fs::write(dir.path().join("report.docx"), "report saved")?;
+    fs::write(dir.path().join("scan.pdf"), "first scan")?;
+    fs::write(dir.path().join("scan copy.pdf"), "second scan")?;
+
+    let pdftotext = tools.path().join("pdftotext");
+    fs::write(
+        &pdftotext,
+        r#"#!/bin/sh
+grep -q report "$1" && printf 'Quarterly\nreport\n\n\fProfit & loss\n'
+exit 0
+"#,
+    )?;
+    let unzip = tools.path().join("unzip");
+    fs::write(
+        &unzip,
+        r#"#!/bin/sh
+[ "$3" = word/document.xml ] || exit 11
+echo '<w:document><w:body><w:p><w:r><w:t>Quarterly</w:t></w:r></w:p><w:p><w:r><w:t>rep</w:t></w:r><w:r><w:t>ort</w:t></w:r></w:p><w:p><w:r><w:t>Profit &amp; loss</w:t></w:r></w:p></w:body></w:document>'
+"#,
+    )?;
+    for tool in [&pdftotext, &unzip] {
+        fs::set_permissions(tool, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Command::cargo_bin("rupes")?
+        .env("RUPES_PDFTOTEXT", &pdftotext)
+        .env("RUPES_UNZIP", &unzip)
+        .arg(dir.path())
+        .args(["--documents", "--double-check"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("report.docx"))
+        .stdout(predicate::str::contains("report.pdf"))
+        .stdout(predicate::str::contains("scan").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("report").not());
+
+    Ok(())
+}
+
+#[test]
+fn mail() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let (inbox, archive) = (dir.path().join("INBOX/cur"), dir.path().join("Archive/cur"));
+    fs::create_dir_all(&inbox)?;
+    fs::create_dir_all(&archive)?;
+    let message = "From: Ann <ann@example.com>\nSubject: Lunch\nMessage-ID: <1@example.com>\n\nSee you there\n";
+    fs::write(
+        inbox.join("1700000000.M1.host:2,S"),
+        format!(
+            "Return-Path: <ann@example.com>\nDelivered-To: me@example.com\n{}",
+            message
+        ),
+    )?;
+    fs::write(
+        archive.join("1700000500.M7.host:2,RS"),
+        format!(
+            "Delivered-To: me@work.example.com\n{}",
+            message.replace('\n', "\r\n")
+        ),
+    )?;
+    fs::write(
+        dir.path().join("saved.mbox"),
+        format!(
+            "From ann@example.com Mon Jan  1 00:00:00 2024\nStatus: RO\n{}\nFrom bob@example.com Mon Jan  1 00:00:00 2024\nSubject: Other\n\nbody\n\nFrom ann@example.com Tue Jan  2 00:00:00 2024\n{}",
+            message, message
+        ),
+    )?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("INBOX").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-r", "--mail"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("INBOX/cur/1700000000.M1.host:2,S"))
+        .stdout(predicate::str::contains(
+            "Archive/cur/1700000500.M7.host:2,RS",
+        ))
+        .stdout(predicate::str::contains(format!(
+            "{0}#1\n{0}#3\n^ Lunch\n",
+            dir.path().join("saved.mbox").display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn sidecars() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    fs::create_dir_all(&a)?;
+    fs::create_dir_all(&b)?;
+    for (file, content) in [
+        ("a/IMG_1.jpg", "first picture"),
+        ("a/IMG_1.CR2", "first raw"),
+        ("b/IMG_1.jpg", "first picture"),
+        ("b/IMG_1.xmp", "rating 5"),
+        ("a/IMG_2.jpg", "second picture"),
+        ("a/IMG_2.xmp", "rating 3"),
+        ("b/IMG_2.jpg", "second picture"),
+        ("b/IMG_2.xmp", "rating 3"),
+    ] {
+        fs::write(dir.path().join(file), content)?;
+    }
+
+    Command::cargo_bin("rupes")?
+        .arg(&a)
+        .arg(&b)
+        .arg("--sidecars")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{}\n^ {}\n",
+            a.join("IMG_1.jpg").display(),
+            a.join("IMG_1.CR2").display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}\n^ {}\n",
+            b.join("IMG_1.jpg").display(),
+            b.join("IMG_1.xmp").display()
+        )));
+
+    Command::cargo_bin("rupes")?
+        .arg(&a)
+        .arg(&b)
+        .args(["--sidecars", "--delete", "--no-prompt"])
+        .assert()
+        .success();
+
+    // The metadata of deleted duplicates ends up next to the kept copies rather than left behind
+    assert_eq!(fs::read_to_string(a.join("IMG_1.xmp"))?, "rating 5");
+    assert_eq!(fs::read_to_string(a.join("IMG_2.xmp"))?, "rating 3");
+    assert!(a.join("IMG_1.CR2").exists());
+    assert_eq!(fs::read_dir(&b)?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn edited_plan_sidecars_checked() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let outside = TempDir::new()?;
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    fs::create_dir_all(&a)?;
+    fs::create_dir_all(&b)?;
+    fs::write(a.join("IMG_1.jpg"), "first picture")?;
+    fs::write(b.join("IMG_1.jpg"), "first picture")?;
+    fs::write(outside.path().join("IMG_1.xmp"), "rating 5")?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg(&a)
+        .arg(&b)
+        .args(["--delete", "--sidecars"])
+        .output()?;
+    assert!(output.status.success());
+    let mut planned: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    planned["actions"][0]["sidecars"] =
+        serde_json::json!([outside.path().join("IMG_1.xmp").to_str().unwrap()]);
+    let plan = dir.path().join("plan.json");
+    fs::write(&plan, serde_json::to_vec(&planned)?)?;
+
+    Command::cargo_bin("rupes")?
+        .arg("apply")
+        .arg(&plan)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Applied 0 of 1 planned actions"))
+        .stderr(predicate::str::contains("isn't next to it"));
+    assert!(outside.path().join("IMG_1.xmp").exists());
+    assert!(a.join("IMG_1.jpg").exists() && b.join("IMG_1.jpg").exists());
+
+    Ok(())
+}
+
+#[test]
+fn skip_vcs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join(".git/objects/ab"))?;
+    fs::create_dir_all(dir.path().join("src"))?;
+    fs::write(dir.path().join("src/a.rs"), "fn main() {}")?;
+    fs::write(dir.path().join("src/b.rs"), "fn main() {}")?;
+    fs::write(dir.path().join(".git/objects/ab/1"), "blob")?;
+    fs::write(dir.path().join(".git/objects/ab/2"), "blob")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.rs"))
+        .stdout(predicate::str::contains("objects").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-r", "--no-skip-vcs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("objects"));
+
+    // The last of the two wins, so a configured default can be overridden either way
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-r", "--no-skip-vcs", "--skip-vcs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("objects").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn scan_image() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let tar = |archive: &Path, from: &Path| -> Result<(), Box<dyn std::error::Error>> {
+        let status = Command::new("tar")
+            .arg("-cf")
+            .arg(archive)
+            .arg("-C")
+            .arg(from)
+            .arg(".")
+            .status()?;
+        assert!(status.success());
+        Ok(())
+    };
+
+    // A base layer, and a later one copying the same library again and deleting a file with a whiteout
+    let (base, app, image) = (
+        dir.path().join("base"),
+        dir.path().join("app"),
+        dir.path().join("image"),
+    );
+    fs::create_dir_all(base.join("usr/lib"))?;
+    fs::create_dir_all(app.join("opt/app"))?;
+    fs::write(base.join("usr/lib/libfoo.so"), "library")?;
+    fs::write(base.join("usr/lib/README"), "notes")?;
+    fs::write(app.join("opt/app/libfoo.so"), "library")?;
+    fs::write(app.join("opt/app/.wh.README"), "notes")?;
+
+    fs::create_dir_all(image.join("0123456789abcdef"))?;
+    fs::create_dir_all(image.join("fedcba9876543210"))?;
+    tar(&image.join("0123456789abcdef/layer.tar"), &base)?;
+    tar(&image.join("fedcba9876543210/layer.tar"), &app)?;
+    // Layers are usually compressed
+    assert!(Command::new("gzip")
+        .arg(image.join("fedcba9876543210/layer.tar"))
+        .status()?
+        .success());
+    fs::write(
+        image.join("manifest.json"),
+        r#"[{"Config":"config.json","RepoTags":["app:latest"],"Layers":["0123456789abcdef/layer.tar","fedcba9876543210/layer.tar.gz"]}]"#,
+    )?;
+    let archive = dir.path().join("app.tar");
+    tar(&archive, &image)?;
+
+    let prefix = archive.display().to_string();
+    Command::cargo_bin("rupes")?
+        .arg("scan-image")
+        .arg(&archive)
+        .arg("-s")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{}@0123456789ab/usr/lib/libfoo.so\n{}@fedcba987654/opt/app/libfoo.so\n^ 7 B of wasted space",
+            prefix, prefix
+        )))
+        .stdout(predicate::str::contains("README").not())
+        .stderr(predicate::str::contains("1 groups of files stored more than once"));
+
+    Command::cargo_bin("rupes")?
+        .arg("scan-image")
+        .arg(dir.path().join("missing.tar"))
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let snapshot = dir.path().join(".zfs/snapshot/daily");
+    fs::create_dir_all(&snapshot)?;
+    fs::write(dir.path().join("notes.txt"), "meeting notes")?;
+    fs::write(snapshot.join("notes.txt"), "meeting notes")?;
+    fs::write(dir.path().join("report.txt"), "quarterly report")?;
+    fs::write(snapshot.join("report.txt"), "quarterly report")?;
+    fs::write(dir.path().join("report copy.txt"), "quarterly report")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-r")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("report copy.txt"))
+        .stdout(predicate::str::contains(".zfs").not());
+
+    // The notes are only copied by the snapshot, while the report has a copy wasting space besides
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-r", "--include-snapshots", "-S"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "The same file in several snapshots, no space to reclaim",
+        ))
+        .stdout(predicate::str::contains(format!(
+            "{}\n{}",
+            snapshot.join("notes.txt").display(),
+            dir.path().join("notes.txt").display()
+        )))
+        .stdout(predicate::str::contains("16 B"));
+
+    Ok(())
+}
+
+#[test]
+fn estimate_dedup() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let log: Vec<u8> = (0..256 * 1024).map(|i| (i * 31 % 251) as u8).collect();
+    fs::write(dir.path().join("app.log"), &log)?;
+    // The same log appended to, no duplicate as a whole but sharing nearly every block
+    fs::write(
+        dir.path().join("app.log.1"),
+        [&log[..], b"one more line\n"].concat(),
+    )?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--estimate-dedup")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Deduplication estimate, of 524.30 kB scanned",
+        ))
+        .stdout(predicate::str::is_match(
+            r"Removing duplicate files +0 B \(0\.0%\)",
+        )?)
+        .stdout(predicate::str::is_match(
+            r"4 KiB blocks, as bees on Btrfs +262\.14 kB \(50\.0%\)",
+        )?);
+
+    Ok(())
+}
+
+#[test]
+fn wasted_share() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "a".repeat(100))?;
+    fs::write(dir.path().join("b"), "a".repeat(100))?;
+    fs::write(dir.path().join("c"), "c".repeat(200))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-S")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "100 B total wasted space\nDuplicates account for 25.0% of scanned bytes, removing them shrinks the files from 400 B to 300 B",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn stats_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let files = dir.path().join("files");
+    fs::create_dir_all(&files)?;
+    fs::write(files.join("a"), "a".repeat(100))?;
+    fs::write(files.join("b"), "a".repeat(100))?;
+    fs::write(files.join("c"), "c".repeat(200))?;
+
+    let stats = dir.path().join("stats.json");
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--stats-file")
+        .arg(&stats)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("files_scanned").not());
+
+    let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&stats)?)?;
+    assert_eq!(written["files_scanned"], 3);
+    assert_eq!(written["bytes_scanned"], 400);
+    assert_eq!(written["duplicate_groups"], 1);
+    assert_eq!(written["wasted_bytes"], 100);
+    assert!(written.get("cache_hits").is_none());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn stats_fd() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same")?;
+    fs::write(dir.path().join("b"), "same")?;
+    let stats = dir.path().join("stats.json");
+
+    // Results stay on stdout while the statistics go to descriptor 3
+    Command::new("sh")
+        .arg("-c")
+        .arg(r#""$0" "$1" --stats-fd 3 3>"$2""#)
+        .arg(assert_cmd::cargo::cargo_bin("rupes"))
+        .arg(dir.path())
+        .arg(&stats)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("files_scanned").not());
+    assert!(fs::read_to_string(&stats)?.contains(r#""duplicate_files":1"#));
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--stats-fd", "97"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("isn't an open file descriptor"));
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sandbox() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new()?;
+    let docs = dir.path().join("docs");
+    fs::create_dir_all(&docs)?;
+    fs::write(docs.join("a.pdf"), "%PDF a")?;
+    fs::write(docs.join("b.pdf"), "%PDF b")?;
+    let mode = fs::metadata(docs.join("a.pdf"))?.permissions().mode();
+
+    // Programs run by rupes are sandboxed with it, this one tries to change files on the way, even beside the cache
+    // rupes writes
+    let cache = dir.path().join("state").join("cache");
+    let marker = dir.path().join("state").join("marker");
+    let pdftotext = dir.path().join("pdftotext");
+    fs::write(
+        &pdftotext,
+        format!(
+            "#!/bin/sh\ntouch '{}'\nchmod 600 '{}'\necho report\n",
+            marker.display(),
+            docs.join("a.pdf").display()
+        ),
+    )?;
+    fs::set_permissions(&pdftotext, fs::Permissions::from_mode(0o755))?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(&docs)
+        .args(["--documents", "--sandbox", "--cache"])
+        .arg(&cache)
+        .env("RUPES_PDFTOTEXT", &pdftotext)
+        .output()?;
+    if String::from_utf8_lossy(&output.stderr).contains("--sandbox needs Landlock") {
+        // Not available in this kernel
+        return Ok(());
+    }
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("a.pdf"));
+    assert!(!marker.exists());
+    assert_eq!(fs::metadata(docs.join("a.pdf"))?.permissions().mode(), mode);
+    assert!(cache.exists());
+
+    // Acting on duplicates is allowed in the directories scanned
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    fs::create_dir_all(&a)?;
+    fs::create_dir_all(&b)?;
+    fs::write(a.join("photo.jpg"), "photo")?;
+    fs::write(b.join("photo.jpg"), "photo")?;
+    Command::cargo_bin("rupes")?
+        .arg(&a)
+        .arg(&b)
+        .args(["--sandbox", "--delete", "--no-prompt", "--cache"])
+        .arg(&cache)
+        .assert()
+        .success();
+    assert!(a.join("photo.jpg").exists());
+    assert!(!b.join("photo.jpg").exists());
+    assert!(fs::read_to_string(&cache)?.contains("photo.jpg"));
+
+    Ok(())
+}
+
+#[test]
+fn recheck() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let files = dir.path().join("files");
+    fs::create_dir_all(&files)?;
+    for name in ["a", "b", "c", "d", "e"] {
+        fs::write(files.join(name), "same")?;
+    }
+    fs::write(files.join("x"), "other content")?;
+    fs::write(files.join("y"), "other content")?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(&files)
+        .args(["--output", "jdupes-json", "--show-hash"])
+        .output()?;
+    let report = dir.path().join("report.json");
+    fs::write(&report, &output.stdout)?;
+
+    Command::cargo_bin("rupes")?
+        .arg("recheck")
+        .arg(&report)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "2 of 2 groups still valid, 0 files changed and 0 vanished",
+        ));
+
+    // Days later, a copy was edited and another deleted, and one of the other pair as well
+    fs::write(files.join("c"), "edit")?;
+    fs::remove_file(files.join("d"))?;
+    fs::remove_file(files.join("y"))?;
+    Command::cargo_bin("rupes")?
+        .arg("recheck")
+        .arg(&report)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            ": still valid, 3 of 5 files unchanged",
+        ))
+        .stdout(predicate::str::contains(format!(
+            "  changed   {}",
+            files.join("c").display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "  vanished  {}",
+            files.join("d").display()
+        )))
+        .stdout(predicate::str::contains(": no longer valid"))
+        .stderr(predicate::str::contains(
+            "1 of 2 groups still valid, 1 files changed and 2 vanished",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn no_hash() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let content = "x".repeat(100_000);
+    fs::write(dir.path().join("a"), &content)?;
+    fs::write(dir.path().join("b"), &content)?;
+    // Differs only near the end, past the first block compared
+    fs::write(dir.path().join("c"), format!("{}y", &content[1..]))?;
+    fs::write(dir.path().join("d"), "small")?;
+    fs::write(dir.path().join("e"), "small")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--no-hash", "-1", " "])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{} {}",
+            dir.path().join("a").display(),
+            dir.path().join("b").display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{} {}",
+            dir.path().join("d").display(),
+            dir.path().join("e").display()
+        )))
+        .stdout(predicate::str::contains("/c").not());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--no-hash", "--show-hash"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn sort_score() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("photos"))?;
+    fs::create_dir_all(dir.path().join("Downloads"))?;
+    // The larger group comes first by size, the downloaded copy first by score
+    fs::write(dir.path().join("photos/big.raw"), "b".repeat(2000))?;
+    fs::write(dir.path().join("photos/big-backup.raw"), "b".repeat(2000))?;
+    fs::write(dir.path().join("photos/cat.jpg"), "cat")?;
+    fs::write(dir.path().join("Downloads/cat (1).jpg"), "cat")?;
+
+    Command::cargo_bin("rupes")?
+        .arg("-r")
+        .arg(dir.path())
+        .args(["--sort", "score"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(
+            r"(?s)^\nscore \d+: named like copies, in downloads or temporary directories\n.*cat \(1\)\.jpg.*\nscore \d+\n.*big",
+        )?);
+
+    Ok(())
+}
+
+#[test]
+fn free_goal() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for (name, size) in [("big", 3000), ("medium", 2000), ("small", 1000)] {
+        fs::write(dir.path().join(format!("{}-1", name)), name.repeat(size))?;
+        fs::write(dir.path().join(format!("{}-2", name)), name.repeat(size))?;
+    }
+
+    // The medium group wastes the most, 12 kB, a second group is needed for 20 kB
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--delete", "--no-prompt", "--free", "20k"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Acting on 2 of 3 groups to reclaim 21.00 kB",
+        ));
+    assert!(!dir.path().join("medium-2").exists());
+    assert!(!dir.path().join("big-2").exists());
+    assert!(dir.path().join("small-2").exists());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--delete", "--no-prompt", "--free", "1G"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Only 5.00 kB can be reclaimed, short of the 1.00 GB asked for",
+        ));
+    assert!(!dir.path().join("small-2").exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn exec_per_group() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new()?;
+    let files = dir.path().join("files");
+    fs::create_dir_all(&files)?;
+    fs::write(files.join("a one"), "same")?;
+    fs::write(files.join("b"), "same")?;
+    fs::write(files.join("c"), "same")?;
+
+    // Writes each argument on a line of its own
+    let record = dir.path().join("record");
+    let hook = dir.path().join("hook");
+    fs::write(
+        &hook,
+        format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" >> '{}'\n",
+            record.display()
+        ),
+    )?;
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--exec")
+        .arg(format!(
+            "'{}' size={{size}} {{kept}} {{dupes...}}",
+            hook.display()
+        ))
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&record)?,
+        format!(
+            "size=4\n{}\n{}\n{}\n",
+            files.join("a one").display(),
+            files.join("b").display(),
+            files.join("c").display()
+        )
+    );
+
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .args(["--exec", "false {kept}"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("false failed for group 1"));
+
+    Ok(())
+}
+
+#[test]
+fn omit_first() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for name in ["keep/a", "x/a", "y/a", "keep/b", "x/b"] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, name.chars().last().unwrap().to_string().repeat(10))?;
+    }
+    // Groups are ordered by size, then content
+    fs::write(dir.path().join("keep/b"), "bigger b")?;
+    fs::write(dir.path().join("x/b"), "bigger b")?;
+
+    Command::cargo_bin("rupes")?
+        .arg("-r")
+        .arg(dir.path())
+        .arg("--omit-first")
+        .arg("--prefer-dir")
+        .arg(dir.path().join("keep"))
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n{}\n{}\n",
+            dir.path().join("x/b").display(),
+            dir.path().join("x/a").display(),
+            dir.path().join("y/a").display()
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn originals_marked() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("a"))?;
+    fs::create_dir_all(dir.path().join("b"))?;
+    fs::write(dir.path().join("a/file"), "same")?;
+    fs::write(dir.path().join("b/file"), "same")?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg("-r")
+        .arg(dir.path())
+        .args(["--output", "jdupes-json", "--prefer-dir"])
+        .arg(dir.path().join("b"))
+        .output()?;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let files = &json["matchSets"][0]["fileList"];
+    assert_eq!(
+        files[0]["filePath"],
+        dir.path().join("b/file").display().to_string()
+    );
+    assert_eq!(files[0]["role"], "original");
+    assert_eq!(
+        files[0]["reason"],
+        format!("under --prefer-dir {}", dir.path().join("b").display())
+    );
+    assert_eq!(files[1]["role"], "redundant");
+
+    Command::cargo_bin("rupes")?
+        .arg("-r")
+        .arg(dir.path())
+        .args(["--format-template", "{role},{path}"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "original,{}\nredundant,{}\n",
+            dir.path().join("a/file").display(),
+            dir.path().join("b/file").display()
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn schema_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let files = dir.path().join("files");
+    fs::create_dir_all(&files)?;
+    fs::write(files.join("a"), "same")?;
+    fs::write(files.join("b"), "same")?;
+
+    let stats = dir.path().join("stats.json");
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--stats-file")
+        .arg(&stats)
+        .assert()
+        .success();
+    let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&stats)?)?;
+    assert_eq!(written["schema_version"], 2);
+
+    // Older versions are written exactly as they were, without the field
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .args(["--schema-version", "1", "--stats-file"])
+        .arg(&stats)
+        .assert()
+        .success();
+    let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&stats)?)?;
+    assert!(written.get("schema_version").is_none());
+    assert_eq!(written["duplicate_groups"], 1);
+
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .args(["--schema-version", "9"])
+        .assert()
+        .failure();
+
+    // A plan from a newer rupes isn't applied
+    let plan = dir.path().join("plan.json");
+    fs::write(
+        &plan,
+        r#"{"schema_version": 99, "algorithm": "sha256", "actions": []}"#,
+    )?;
+    Command::cargo_bin("rupes")?
+        .arg("apply")
+        .arg(&plan)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("written by a newer rupes"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn keep_command() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, SystemTime};
+
+    let dir = TempDir::new()?;
+    let files = dir.path().join("files");
+    fs::create_dir_all(&files)?;
+    for (name, age) in [("a", 300), ("b", 100), ("c", 200)] {
+        let path = files.join(name);
+        fs::write(&path, "same")?;
+        fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now() - Duration::from_secs(age))?;
+    }
+
+    // Keeps the newest copy
+    let script = dir.path().join("newest");
+    fs::write(
+        &script,
+        "#!/bin/sh\nawk -F '\\t' '$1 > newest { newest = $1; line = NR } END { print line }'\n",
+    )?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--omit-first")
+        .arg("--keep-command")
+        .arg(&script)
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n{}\n",
+            files.join("a").display(),
+            files.join("c").display()
+        ));
+
+    // Lua scripts are run by lua
+    let lua = dir.path().join("lua");
+    fs::write(&lua, "#!/bin/sh\ncat > /dev/null\necho 3\n")?;
+    fs::set_permissions(&lua, fs::Permissions::from_mode(0o755))?;
+    fs::write(dir.path().join("keep.lua"), "print(3)\n")?;
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--omit-first")
+        .arg("--keep-command")
+        .arg(dir.path().join("keep.lua"))
+        .env("RUPES_LUA", &lua)
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n{}\n",
+            files.join("a").display(),
+            files.join("b").display()
+        ));
+
+    // A script that doesn't choose properly stops rupes before anything is done
+    fs::write(&script, "#!/bin/sh\necho 7\n")?;
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .args(["--delete", "--no-prompt", "--keep-command"])
+        .arg(&script)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected a number from 1 to 3"));
+    assert!(files.join("b").exists() && files.join("c").exists());
+
+    Ok(())
+}
+
+#[test]
+fn report_formats() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same")?;
+    fs::write(dir.path().join("b"), "same")?;
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--format", "json"])
+        .output()?;
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(json["groups"][0]["size"], 4);
+    assert_eq!(json["groups"][0]["wasted_bytes"], 4);
+    assert!(json["groups"][0]["hash"]
+        .as_str()
+        .is_some_and(|hash| hash.starts_with("sha256:")));
+    assert_eq!(
+        json["groups"][0]["files"][1]["path"],
+        b.display().to_string()
+    );
+    assert_eq!(json["totals"]["groups"], 1);
+    assert_eq!(json["totals"]["wasted_bytes"], 4);
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--format", "csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "schema_version,group,id,hash,size,wasted_bytes,role,reason,path\n2,1,",
+        ))
+        .stdout(predicate::str::contains(",original,first path,"))
+        .stdout(predicate::str::contains(format!(
+            ",4,4,redundant,,{}\n",
+            b.display()
+        )));
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--format", "csv", "--schema-version", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "group,id,hash,size,wasted_bytes,role,path\n1,",
+        ))
+        .stdout(predicate::str::contains(format!(
+            ",4,4,redundant,{}\n",
+            b.display()
+        )));
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--format", "fdupes"])
+        .assert()
+        .success()
+        .stdout(format!("{}\n{}\n\n", a.display(), b.display()));
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--format", "fdupes", "--print0"])
+        .assert()
+        .success()
+        .stdout(format!("{}\0{}\0\0", a.display(), b.display()));
+
+    Ok(())
+}
+
+#[test]
+fn keep_policies() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let (old, new, other) = (
+        dir.path().join("a-old"),
+        dir.path().join("b-new"),
+        dir.path().join("c-other"),
+    );
+    for (path, modified) in [(&old, 1_000_000), (&new, 3_000_000), (&other, 2_000_000)] {
+        fs::write(path, "same")?;
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(modified))?;
+    }
+    let redundant = |keep: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::cargo_bin("rupes")?
+            .arg(dir.path())
+            .args(["--omit-first", "--keep", keep])
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    assert!(!redundant("first")?.contains("a-old"));
+    assert!(!redundant("newest")?.contains("b-new"));
+    assert!(!redundant("oldest")?.contains("a-old"));
+    assert!(!redundant(&format!("path-prefix={}", other.display()))?.contains("c-other"));
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--keep", "largest"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown keep policy"));
+
+    // Plans give the policy that chose the copy kept
+    let output = Command::cargo_bin("rupes")?
+        .arg("plan")
+        .arg(dir.path())
+        .args(["--delete", "--keep", "newest"])
+        .output()?;
+    assert!(output.status.success());
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        plan["actions"][0]["reason"],
+        "identical content, kept copy: newest copy"
+    );
+
+    // The copy kept is never the one removed
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--delete", "--no-prompt", "--keep", "newest"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Deleted 2 of 2 files, freeing 8 B",
+        ));
+    assert!(new.exists() && !old.exists() && !other.exists());
+
+    Ok(())
+}
+
+#[test]
+fn dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same")?;
+    fs::write(dir.path().join("b"), "same")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--delete", "--dry-run"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "About to delete 1 files, freeing 4 B",
+        ))
+        .stderr(predicate::str::contains(format!(
+            "Would delete {}, a copy of {}, freeing 4 B",
+            dir.path().join("b").display(),
+            dir.path().join("a").display()
+        )))
+        .stderr(predicate::str::contains("Dry run, no files were changed"));
+    assert!(dir.path().join("b").exists());
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--dry-run")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn cache_pruned_on_scan() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let cache = dir.path().join("hashes");
+    let files = dir.path().join("files");
+    fs::create_dir(&files)?;
+    for name in ["a", "b", "c"] {
+        fs::write(files.join(name), "same content")?;
+    }
+
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--cache")
+        .arg(&cache)
+        .assert()
+        .success();
+    fs::remove_file(files.join("c"))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--cache")
+        .arg(&cache)
+        .arg("--cache-prune")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Dropped 1 stale hashes"));
+    Command::cargo_bin("rupes")?
+        .args(["cache", "stats", "--cache"])
+        .arg(&cache)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hashes: 2"));
+
+    Command::cargo_bin("rupes")?
+        .arg(&files)
+        .arg("--cache-prune")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn verify_bytes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    // Different content with the same CRC-32C
+    fs::write(dir.path().join("a"), "01371838")?;
+    fs::write(dir.path().join("b"), "02000402")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--algorithm", "crc32c", "--count"])
+        .assert()
+        .success()
+        .stdout("1\n");
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--algorithm", "crc32c", "--count", "--verify"])
+        .assert()
+        .success()
+        .stdout("0\n");
+
+    fs::write(dir.path().join("b"), "01371838")?;
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--count", "--verify"])
+        .assert()
+        .success()
+        .stdout("1\n");
+
+    Ok(())
+}
+
+#[test]
+fn depth_and_glob_excludes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("one/deep/er"))?;
+    fs::create_dir_all(dir.path().join("two/build"))?;
+    for path in [
+        "one/kept",
+        "one/deep/er/buried",
+        "two/notes.tmp",
+        "two/build/out",
+        "two/copy",
+    ] {
+        fs::write(dir.path().join(path), "same")?;
+    }
+
+    // The nested root is already walked as part of the first, it doesn't add its files a second time
+    let output = Command::cargo_bin("rupes")?
+        .arg(dir.path().join("one"))
+        .arg(dir.path().join("two"))
+        .arg(dir.path().join("one/deep/er"))
+        .args([
+            "--max-depth",
+            "1",
+            "--exclude-glob",
+            "*.tmp",
+            "--exclude-glob",
+            "build",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("kept") && stdout.contains("copy"));
+    assert!(!stdout.contains("buried"));
+    assert!(!stdout.contains("notes.tmp"));
+    assert!(!stdout.contains("build"));
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--max-depth", "3", "--count"])
+        .assert()
+        .success()
+        .stdout("1\n");
+
+    Ok(())
+}
+
+#[test]
+fn compare_to_reference() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let (dump, archive) = (dir.path().join("dump"), dir.path().join("archive"));
+    fs::create_dir_all(&dump)?;
+    fs::create_dir_all(&archive)?;
+    fs::write(archive.join("photo"), "archived")?;
+    fs::write(archive.join("photo copy"), "archived")?;
+    fs::write(archive.join("letter"), "only archived")?;
+    fs::write(archive.join("letter copy"), "only archived")?;
+    fs::write(dump.join("photo"), "archived")?;
+    fs::write(dump.join("new"), "not archived")?;
+    fs::write(dump.join("new copy"), "not archived")?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(&dump)
+        .arg("--compare-to")
+        .arg(&archive)
+        .args(["--output", "csv"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    // A header, then the copy in the archive and the one in the dump
+    assert_eq!(stdout.lines().count(), 3);
+    assert!(stdout.contains(&dump.join("photo").display().to_string()));
+    assert!(!stdout.contains(&archive.join("letter").display().to_string()));
+    assert!(!stdout.contains(&dump.join("new").display().to_string()));
+
+    Command::cargo_bin("rupes")?
+        .arg(&dump)
+        .arg("--compare-to")
+        .arg(&archive)
+        .args(["--delete", "--no-prompt"])
+        .assert()
+        .success();
+    assert!(!dump.join("photo").exists());
+    assert!(dump.join("new").exists() && dump.join("new copy").exists());
+    assert!(archive.join("photo").exists() && archive.join("photo copy").exists());
+
+    Ok(())
+}
+
+#[test]
+fn interactive_needs_terminal() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same")?;
+    fs::write(dir.path().join("b"), "same")?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("--interactive")
+        .assert()
+        .failure();
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--delete", "--interactive"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs a terminal"));
+    assert!(dir.path().join("a").exists() && dir.path().join("b").exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn hardlinks_counted_once() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "duplicate content")?;
+    fs::hard_link(dir.path().join("a"), dir.path().join("a-link"))?;
+    fs::write(dir.path().join("b"), "duplicate content")?;
+    fs::write(dir.path().join("c"), "only hardlinked")?;
+    fs::hard_link(dir.path().join("c"), dir.path().join("c-link"))?;
+
+    let output = Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .arg("-S")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let (duplicates, hardlinks) = stdout
+        .split_once("Already hardlinked, no space to reclaim")
+        .unwrap();
+    assert!(!duplicates.contains("c-link"));
+    assert!(hardlinks.contains("c-link"));
+    assert!(hardlinks.contains("17 B total wasted space"));
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--count", "files", "--count-hardlinks"])
+        .assert()
+        .success()
+        .stdout("3\n");
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-S", "--count-hardlinks"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("49 B total wasted space"))
+        .stdout(predicate::str::contains("Already hardlinked").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn kept_copy_never_acted_on_through_links() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("b_real"), "the only data")?;
+    std::os::unix::fs::symlink(dir.path().join("b_real"), dir.path().join("a_link"))?;
+    fs::hard_link(dir.path().join("b_real"), dir.path().join("c_hardlink"))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["-l", "--count-hardlinks", "--delete", "--no-prompt"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Deleted").not());
+
+    assert_eq!(fs::read(dir.path().join("a_link"))?, b"the only data");
+    assert!(dir.path().join("b_real").exists());
+    assert!(dir.path().join("c_hardlink").exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_files_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same")?;
+    fs::write(dir.path().join("b"), "same")?;
+    // Following it fails, there's nothing at the other end
+    std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("dangling"))?;
+
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--follow-symlinks", "--count"])
+        .assert()
+        .success()
+        .stdout("1\n")
+        .stderr(predicate::str::contains(
+            "Skipped 1 files and directories that couldn't be read: 1 dangling symlink",
+        ));
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--follow-symlinks", "--count", "--skipped-exit-code", "3"])
+        .assert()
+        .code(3)
+        .stdout("1\n");
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--follow-symlinks", "--count", "--strict"])
+        .assert()
+        .failure();
+    Command::cargo_bin("rupes")?
+        .arg(dir.path())
+        .args(["--count", "--skipped-exit-code", "3"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Skipped").not());
+
+    // The hash subcommand leaves them out the same way
+    Command::cargo_bin("rupes")?
+        .arg("hash")
+        .arg(dir.path())
+        .arg("--follow-symlinks")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("1 dangling symlink"));
+    Command::cargo_bin("rupes")?
+        .arg("hash")
+        .arg(dir.path())
+        .args(["--follow-symlinks", "--strict"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
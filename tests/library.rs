@@ -0,0 +1,402 @@
+use rupes::{chunks, extents};
+use rupes::{ContentComparator, HashAlgorithm, HashComparator, QuickComparator, ScanOptions};
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Considers files identical when their content matches ignoring ASCII case
+struct CaseInsensitive;
+
+impl ContentComparator for CaseInsensitive {
+    type Key = Vec<u8>;
+
+    fn key(&self, path: &Path, _size: u64) -> Result<Self::Key> {
+        Ok(fs::read(path)?.to_ascii_lowercase())
+    }
+}
+
+#[test]
+fn custom_comparator() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("lower.txt"), "hello world")?;
+    fs::write(dir.path().join("upper.txt"), "HELLO WORLD")?;
+    fs::write(dir.path().join("other.txt"), "goodbye world")?;
+
+    let files = rupes::find_files(dir.path(), &ScanOptions::default())?;
+    assert_eq!(files.len(), 3);
+
+    let groups = rupes::find_duplicates(files.clone(), &HashComparator::default(), |_| {})?;
+    assert!(groups.is_empty());
+
+    let groups = rupes::find_duplicates(files, &CaseInsensitive, |_| {})?;
+    assert_eq!(groups.len(), 1);
+
+    let names: Vec<_> = groups[0]
+        .files
+        .iter()
+        .map(|file| file.path.file_name().unwrap().to_owned())
+        .collect();
+    assert_eq!(names, ["lower.txt", "upper.txt"]);
+
+    Ok(())
+}
+
+#[test]
+fn parallel_walk_matches_filters() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for branch in 0..8 {
+        let mut path = dir.path().join(format!("branch{}", branch));
+        for depth in 0..4 {
+            fs::create_dir_all(&path)?;
+            fs::write(path.join("keep.txt"), "x".repeat(depth + 1))?;
+            fs::write(path.join("skip.bin"), "x")?;
+            fs::write(path.join(".hidden.txt"), "x")?;
+            path = path.join(format!("depth{}", depth));
+        }
+    }
+
+    let options = ScanOptions {
+        recursive: true,
+        exclude_dots: true,
+        filters: vec![regex::Regex::new("[.]txt$")?],
+        min: Some(2),
+        ..ScanOptions::default()
+    };
+    let files = rupes::find_files(dir.path(), &options)?;
+
+    assert_eq!(files.len(), 8 * 3);
+    assert!(files
+        .iter()
+        .all(|file| file.path.ends_with("keep.txt") && file.size >= 2));
+
+    Ok(())
+}
+
+#[test]
+fn confirm_with_second_comparator() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("lower.txt"), "hello world")?;
+    fs::write(dir.path().join("upper.txt"), "HELLO WORLD")?;
+    fs::write(dir.path().join("copy.txt"), "hello world")?;
+
+    let files = rupes::find_files(dir.path(), &ScanOptions::default())?;
+    let groups = rupes::find_duplicates(files, &CaseInsensitive, |_| {})?;
+    assert_eq!(groups[0].files.len(), 3);
+
+    let groups = rupes::confirm_duplicates(groups, &HashComparator::default(), |_| {})?;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].files.len(), 2);
+    assert!(groups[0]
+        .files
+        .iter()
+        .all(|file| !file.path.ends_with("upper.txt")));
+
+    Ok(())
+}
+
+#[test]
+fn known_digests() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("check.txt");
+    fs::write(&path, "123456789")?;
+
+    let digests = [
+        (HashAlgorithm::Md5, "25f9e794323b453885f5181f1b624d0b"),
+        (
+            HashAlgorithm::Sha1,
+            "f7c3bc1d808e04732adf679965ccc34ca7ae3441",
+        ),
+        (
+            HashAlgorithm::Sha256,
+            "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225",
+        ),
+        (HashAlgorithm::Crc32c, "e3069283"),
+    ];
+
+    for (algorithm, expected) in digests {
+        assert_eq!(algorithm.hash_file(&path)?, expected, "{}", algorithm);
+    }
+    assert_eq!(HashAlgorithm::Sha512.hash_file(&path)?.len(), 128);
+    assert_eq!(HashAlgorithm::Blake3.hash_file(&path)?.len(), 64);
+
+    Ok(())
+}
+
+#[test]
+fn parallel_hash_matches() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("large.bin");
+    let content: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(&path, content)?;
+
+    for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+        assert_eq!(
+            algorithm.hash_file_parallel(&path)?,
+            algorithm.hash_file(&path)?
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn quick_comparator_samples_ends() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let content = vec![0u8; 1024 * 1024];
+
+    let mut middle = content.clone();
+    middle[512 * 1024] = 1;
+    let mut end = content.clone();
+    end[1024 * 1024 - 1] = 1;
+
+    fs::write(dir.path().join("original.bin"), &content)?;
+    fs::write(dir.path().join("middle.bin"), &middle)?;
+    fs::write(dir.path().join("end.bin"), &end)?;
+
+    let files = rupes::find_files(dir.path(), &ScanOptions::default())?;
+    let groups = rupes::find_duplicates(files, &QuickComparator::default(), |_| {})?;
+    assert_eq!(groups.len(), 1);
+
+    let names: Vec<_> = groups[0]
+        .files
+        .iter()
+        .map(|file| file.path.file_name().unwrap().to_owned())
+        .collect();
+    assert_eq!(names, ["middle.bin", "original.bin"]);
+
+    Ok(())
+}
+
+/// Deterministic pseudo-random bytes, so chunk boundaries fall in realistic places
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn chunking_survives_insertions() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let original = noise(200_000, 1);
+    let mut edited = original[..100_000].to_vec();
+    edited.extend_from_slice(b"an insertion in the middle");
+    edited.extend_from_slice(&original[100_000..]);
+
+    let original = chunks::chunk_reader(&mut original.as_slice())?;
+    let edited = chunks::chunk_reader(&mut edited.as_slice())?;
+
+    assert_eq!(
+        original
+            .iter()
+            .map(|chunk| chunk.len as usize)
+            .sum::<usize>(),
+        200_000
+    );
+    assert!(original
+        .iter()
+        .all(|chunk| chunk.len as usize <= chunks::MAX_CHUNK));
+
+    // Only the chunk containing the insertion differs
+    let differing = edited
+        .iter()
+        .filter(|chunk| !original.contains(chunk))
+        .count();
+    assert!(differing <= 2, "{} chunks differ", differing);
+
+    Ok(())
+}
+
+#[test]
+fn similar_files() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let content = noise(200_000, 2);
+    let mut appended = content.clone();
+    appended.extend(noise(20_000, 3));
+
+    fs::write(dir.path().join("log.txt"), &content)?;
+    fs::write(dir.path().join("log-copy.txt"), &content)?;
+    fs::write(dir.path().join("log-appended.txt"), &appended)?;
+    fs::write(dir.path().join("unrelated.txt"), noise(200_000, 4))?;
+
+    let files = rupes::find_files(dir.path(), &ScanOptions::default())?;
+    let pairs = chunks::find_similar(files, 0.5, |_| {})?;
+
+    // Identical and unrelated files aren't reported as similar
+    assert_eq!(pairs.len(), 2);
+    for pair in &pairs {
+        assert!(pair.first.path.ends_with("log-appended.txt"));
+        assert!(pair.similarity > 0.85 && pair.similarity < 1.0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn copies_do_not_share_extents() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    let content = noise(64 * 1024, 3);
+    fs::write(dir.path().join("first"), &content)?;
+    fs::write(dir.path().join("second"), &content)?;
+
+    if let Some(extents) = extents::extents(&dir.path().join("first"))? {
+        let mapped: u64 = extents.iter().map(|extent| extent.length).sum();
+        assert!(mapped >= content.len() as u64);
+    }
+
+    let files = rupes::find_files(dir.path(), &ScanOptions::default())?;
+    let shared = extents::by_shared_extents(rupes::group::by_physical_file(files));
+    assert_eq!(shared.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn discovery_limits() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    for file in 0..10 {
+        fs::write(dir.path().join(file.to_string()), "0123456789")?;
+    }
+
+    let discovery = rupes::discover(dir.path(), &ScanOptions::default(), |_| {})?;
+    assert_eq!(discovery.files.len(), 10);
+    assert!(!discovery.truncated);
+
+    let options = ScanOptions {
+        max_files: Some(4),
+        ..ScanOptions::default()
+    };
+    let discovery = rupes::discover(dir.path(), &options, |_| {})?;
+    assert_eq!(discovery.files.len(), 4);
+    assert!(discovery.truncated);
+
+    let options = ScanOptions {
+        max_bytes: Some(25),
+        ..ScanOptions::default()
+    };
+    let discovery = rupes::discover(dir.path(), &options, |_| {})?;
+    assert_eq!(discovery.files.len(), 3);
+    assert!(discovery.truncated);
+
+    Ok(())
+}
+
+#[test]
+fn discovery_progress() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir_all(dir.path().join("a/b"))?;
+    fs::create_dir(dir.path().join("c"))?;
+    fs::write(dir.path().join("one"), "1")?;
+    fs::write(dir.path().join("a/two"), "22")?;
+    fs::write(dir.path().join("a/b/three"), "333")?;
+
+    let options = ScanOptions {
+        recursive: true,
+        ..ScanOptions::default()
+    };
+    let latest = std::sync::Mutex::new(rupes::DiscoveryProgress::default());
+    rupes::discover(dir.path(), &options, |progress| {
+        let mut latest = latest.lock().unwrap();
+        latest.directories = latest.directories.max(progress.directories);
+        latest.files = latest.files.max(progress.files);
+        latest.bytes = latest.bytes.max(progress.bytes);
+    })?;
+
+    assert_eq!(
+        latest.into_inner().unwrap(),
+        rupes::DiscoveryProgress {
+            directories: 4,
+            files: 3,
+            bytes: 6,
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn nested_roots_walked_once() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::create_dir(dir.path().join("inner"))?;
+    fs::write(dir.path().join("outer.txt"), "outer")?;
+    fs::write(dir.path().join("inner/inner.txt"), "inner")?;
+
+    let options = ScanOptions {
+        recursive: true,
+        ..ScanOptions::default()
+    };
+    let roots = [
+        dir.path().join("inner"),
+        dir.path().to_path_buf(),
+        dir.path().join("inner"),
+    ];
+    let discovery = rupes::discover_roots(&roots, &options, |_| {})?;
+
+    assert_eq!(discovery.files.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn scanner_streams_groups() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same")?;
+    fs::write(dir.path().join("b"), "same")?;
+    fs::write(dir.path().join("c"), "other content")?;
+
+    let steps = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = steps.clone();
+    let scan = rupes::Scanner::new(ScanOptions::default())
+        .progress(move |step| recorded.lock().unwrap().push(step))
+        .scan(&[dir.path().to_path_buf()])?;
+    assert_eq!((scan.files, scan.bytes, scan.truncated), (3, 21, false));
+
+    let groups = scan.collect::<Result<Vec<_>>>()?;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].files.len(), 2);
+
+    let steps = steps.lock().unwrap();
+    assert!(matches!(steps[0], rupes::ScanProgress::Discovering(_)));
+    assert!(steps.contains(&rupes::ScanProgress::Comparing {
+        files: 3,
+        bytes: 21,
+        total_files: 3,
+        total_bytes: 21,
+    }));
+
+    // Comparators matching files of different sizes see every file at once
+    let groups = rupes::Scanner::new(ScanOptions::default())
+        .comparator(CaseInsensitive)
+        .scan(&[dir.path().to_path_buf()])?
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(groups.len(), 1);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn scanner_lists_skipped() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("a"), "same")?;
+    fs::write(dir.path().join("b"), "same")?;
+    std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("dangling"))?;
+
+    let options = ScanOptions {
+        follow_symlinks: true,
+        skip_unreadable: true,
+        ..ScanOptions::default()
+    };
+    let scan = rupes::Scanner::new(options).scan(&[dir.path().to_path_buf()])?;
+    assert_eq!(scan.files, 2);
+    assert_eq!(scan.skipped.len(), 1);
+    assert_eq!(scan.skipped[0].0, dir.path().join("dangling"));
+    assert_eq!(scan.collect::<Result<Vec<_>>>()?.len(), 1);
+
+    Ok(())
+}